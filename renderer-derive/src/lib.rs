@@ -0,0 +1,235 @@
+//! `#[derive(VertexLayout)]` for the `renderer` crate
+//!
+//! Generates the input elements of a `#[repr(C)]` vertex struct, one per
+//! field. Offsets come from `offset_of!` and formats from the field types,
+//! so the layout can't drift from the struct.
+//!
+//! Struct attributes:
+//! - `#[vertex(slot = 1)]` input slot of the elements
+//! - `#[vertex(instance_step_rate = 1)]` per-instance data
+//!
+//! Field attributes:
+//! - `#[vertex(semantic = "TEXCOORD", index = 1)]` defaults to the field name
+//!   in upper case, trailing digits give the index
+//! - `#[vertex(format = "R8G8B8A8Unorm")]` a `Format` variant instead of the
+//!   one of the field type
+//! - `#[vertex(skip)]` no element, e.g. padding
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Ident, LitInt, LitStr, Member, Result,
+};
+
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Options of `#[vertex(...)]` on the struct
+#[derive(Default)]
+struct StructOptions {
+    slot: u32,
+    instance_step_rate: Option<u32>,
+}
+
+/// Options of `#[vertex(...)]` on a field
+#[derive(Default)]
+struct FieldOptions {
+    semantic: Option<LitStr>,
+    index: Option<u32>,
+    format: Option<LitStr>,
+    skip: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "VertexLayout does not support generic structs",
+        ));
+    }
+    if !is_repr_c(&input.attrs)? {
+        return Err(Error::new_spanned(
+            name,
+            "VertexLayout requires #[repr(C)] for a fixed field order",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new_spanned(name, "VertexLayout needs a struct")),
+    };
+
+    let options = struct_options(&input.attrs)?;
+    let slot = options.slot;
+    let (class, step_rate) = match options.instance_step_rate {
+        Some(rate) => (quote!(PerInstanceData), rate),
+        None => (quote!(PerVertexData), 0),
+    };
+
+    let mut elements = Vec::new();
+    let mut checks = Vec::new();
+    let mut semantics: Vec<(String, u32)> = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let field_options = field_options(&field.attrs)?;
+        if field_options.skip {
+            continue;
+        }
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(i.into()),
+        };
+
+        let (semantic, default_index, span) = match (&field_options.semantic, &field.ident) {
+            (Some(lit), _) => (lit.value(), 0, lit.span()),
+            (None, Some(ident)) => {
+                let (semantic, index) = split_index(&ident.to_string().to_uppercase());
+                (semantic, index, ident.span())
+            }
+            (None, None) => {
+                return Err(Error::new_spanned(
+                    field,
+                    "tuple fields need #[vertex(semantic = \"...\")]",
+                ))
+            }
+        };
+        if semantic.is_empty()
+            || !semantic
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(Error::new(span, "semantic must be an identifier"));
+        }
+        let index = field_options.index.unwrap_or(default_index);
+        if semantics.contains(&(semantic.clone(), index)) {
+            return Err(Error::new(
+                span,
+                format!("semantic {}{} is used twice", semantic, index),
+            ));
+        }
+        semantics.push((semantic.clone(), index));
+
+        let ty = &field.ty;
+        let format = match &field_options.format {
+            Some(lit) => {
+                let variant = Ident::new(&lit.value(), lit.span());
+                let format = quote!(::renderer::format::Format::#variant);
+                checks.push(quote! {
+                    assert!(
+                        #format.size_in_bytes() as usize == ::core::mem::size_of::<#ty>(),
+                        "vertex format size differs from the field size"
+                    );
+                });
+                format
+            }
+            None => quote!(<#ty as ::renderer::vertex::VertexFormat>::FORMAT),
+        };
+
+        elements.push(quote! {
+            ::renderer::vertex::InputElement {
+                semantic_name: #semantic,
+                semantic_index: #index,
+                format: #format,
+                input_slot: #slot,
+                aligned_byte_offset: ::core::mem::offset_of!(#name, #member) as u32,
+                input_slot_class: ::renderer::vertex::InputClassification::#class,
+                instance_data_step_rate: #step_rate,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl ::renderer::vertex::VertexLayout for #name {
+            const LAYOUT: &'static [::renderer::vertex::InputElement] = &[#(#elements),*];
+            const STRIDE: u32 = ::core::mem::size_of::<#name>() as u32;
+        }
+
+        const _: () = {
+            #(#checks)*
+        };
+    })
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> Result<bool> {
+    let mut repr_c = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            // Skip arguments of e.g. `align(16)`
+            if meta.input.peek(syn::token::Paren) {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                content.parse::<TokenStream2>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(repr_c)
+}
+
+fn struct_options(attrs: &[Attribute]) -> Result<StructOptions> {
+    let mut options = StructOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("slot") {
+                options.slot = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("instance_step_rate") {
+                options.instance_step_rate = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else {
+                return Err(meta.error("expected `slot` or `instance_step_rate`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn field_options(attrs: &[Attribute]) -> Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("semantic") {
+                options.semantic = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("index") {
+                options.index = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("format") {
+                options.format = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+            } else {
+                return Err(meta.error("expected `semantic`, `index`, `format` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// `TEXCOORD1` is semantic `TEXCOORD` with index 1
+fn split_index(name: &str) -> (String, u32) {
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (semantic, index) = name.split_at(name.len() - digits);
+    match index.parse() {
+        Ok(index) if !semantic.is_empty() => (semantic.trim_end_matches('_').to_string(), index),
+        _ => (name.to_string(), 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_semantic_index() {
+        assert_eq!(split_index("POSITION"), ("POSITION".into(), 0));
+        assert_eq!(split_index("TEXCOORD1"), ("TEXCOORD".into(), 1));
+        assert_eq!(split_index("UV_12"), ("UV".into(), 12));
+        assert_eq!(split_index("42"), ("42".into(), 0));
+    }
+}
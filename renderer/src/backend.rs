@@ -0,0 +1,169 @@
+//! Device, queue, command list and swap chain operations used by the renderer
+use crate::descriptor::{CpuDescriptorHandle, DescriptorHeap, DescriptorHeapDesc};
+use crate::format::Format;
+use crate::image::Image;
+use crate::pipeline::PrimitiveTopology;
+use crate::resource::{Barrier, ResourceId};
+use crate::scene::{Rect, Viewport};
+use std::fmt::Debug;
+
+/// `D3D12_COMMAND_LIST_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum CommandListType {
+    Direct = 0,
+    Bundle = 1,
+    Compute = 2,
+    Copy = 3,
+}
+
+/// `D3D12_COMMAND_QUEUE_PRIORITY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum CommandQueuePriority {
+    Normal = 0,
+    High = 100,
+}
+
+/// `D3D12_COMMAND_QUEUE_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CommandQueueDesc {
+    pub list_type: CommandListType,
+    pub priority: CommandQueuePriority,
+}
+
+/// `DXGI_ALPHA_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum AlphaMode {
+    Unspecified = 0,
+    Premultiplied = 1,
+    Straight = 2,
+    Ignore = 3,
+}
+
+/// `DXGI_SWAP_CHAIN_DESC1` of the composition swap chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SwapChainDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub buffer_count: u32,
+    pub alpha_mode: AlphaMode,
+}
+
+/// `D3D12_VERTEX_BUFFER_VIEW`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VertexBufferView {
+    pub buffer_location: u64,
+    pub size_in_bytes: u32,
+    pub stride_in_bytes: u32,
+}
+
+/// Operations the renderer does on the GPU
+///
+/// The backend owns the single direct queue, command allocator, command list,
+/// pipeline state, fence and swap chain, like the `Window` of the sample.
+/// Back buffers are `ResourceId::back_buffer(i)`.
+pub trait Backend {
+    type Error: Debug;
+
+    /// `CreateCommandQueue`
+    fn create_command_queue(&mut self, desc: &CommandQueueDesc) -> Result<(), Self::Error>;
+
+    /// `CreateSwapChainForComposition`, presents from the queue
+    fn create_swap_chain(&mut self, desc: &SwapChainDesc) -> Result<(), Self::Error>;
+
+    /// `CreateDescriptorHeap`
+    fn create_descriptor_heap(
+        &mut self,
+        desc: &DescriptorHeapDesc,
+    ) -> Result<DescriptorHeap, Self::Error>;
+
+    /// `CreateRenderTargetView`
+    fn create_render_target_view(
+        &mut self,
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    ) -> Result<(), Self::Error>;
+
+    /// `CreateCommandAllocator` for each frame in flight
+    fn create_command_allocators(&mut self, count: usize) -> Result<(), Self::Error>;
+
+    /// `CreateCommandList` on the first allocator, the list is created closed
+    fn create_command_list(&mut self) -> Result<(), Self::Error>;
+
+    /// Upload heap buffer holding the vertex `data`
+    fn create_vertex_buffer(
+        &mut self,
+        data: &[u8],
+        stride: u32,
+    ) -> Result<VertexBufferView, Self::Error>;
+
+    /// Upload heap buffer that stays mapped, returns its GPU address
+    fn create_upload_buffer(&mut self, size: u64) -> Result<u64, Self::Error>;
+
+    /// Mapped memory of the upload buffer from `address` to its end
+    fn upload_memory(&mut self, address: u64) -> Option<&mut [u8]>;
+
+    /// Resets the frame's command allocator and the list on it with the
+    /// pipeline state, the GPU must be done with the allocator
+    fn reset_command_list(&mut self, allocator: usize) -> Result<(), Self::Error>;
+
+    /// `SetGraphicsRootSignature`
+    fn set_graphics_root_signature(&mut self);
+
+    /// `RSSetViewports`
+    fn set_viewports(&mut self, viewports: &[Viewport]);
+
+    /// `RSSetScissorRects`
+    fn set_scissor_rects(&mut self, rects: &[Rect]);
+
+    /// `ResourceBarrier`
+    fn resource_barrier(&mut self, barriers: &[Barrier]);
+
+    /// `OMSetRenderTargets` without depth stencil
+    fn set_render_targets(&mut self, rtvs: &[CpuDescriptorHandle]);
+
+    /// `ClearRenderTargetView` of the whole view
+    fn clear_render_target_view(&mut self, rtv: CpuDescriptorHandle, color: [f32; 4]);
+
+    /// `IASetPrimitiveTopology`
+    fn set_primitive_topology(&mut self, topology: PrimitiveTopology);
+
+    /// `IASetVertexBuffers`
+    fn set_vertex_buffers(&mut self, start_slot: u32, views: &[VertexBufferView]);
+
+    /// `DrawInstanced`
+    fn draw_instanced(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    );
+
+    /// `Close`
+    fn close_command_list(&mut self) -> Result<(), Self::Error>;
+
+    /// `ExecuteCommandLists` with the command list
+    fn execute_command_list(&mut self) -> Result<(), Self::Error>;
+
+    /// `Signal` the fence from the queue
+    fn signal(&mut self, value: u64) -> Result<(), Self::Error>;
+
+    /// `GetCompletedValue` of the fence
+    fn completed_value(&self) -> u64;
+
+    /// Blocks until the fence reaches the value
+    fn wait_for_value(&mut self, value: u64) -> Result<(), Self::Error>;
+
+    /// `GetCurrentBackBufferIndex`
+    fn current_back_buffer_index(&self) -> usize;
+
+    /// `Present`
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<(), Self::Error>;
+
+    /// Copy a back buffer to the CPU, the queue must be idle
+    fn read_back_buffer(&mut self, index: usize) -> Result<Image, Self::Error>;
+}
@@ -0,0 +1,455 @@
+//! PNG decoding of reference images
+//!
+//! Handles 8-bit RGB and RGBA images without interlacing, which covers what
+//! `encode` and common image editors write.
+use crate::image::Image;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    NotPng,
+    Truncated,
+    /// CRC of the chunk or Adler-32 of the zlib stream does not match
+    BadChecksum,
+    Unsupported(&'static str),
+    /// Malformed deflate data
+    Inflate(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::NotPng => write!(f, "not a PNG file"),
+            DecodeError::Truncated => write!(f, "truncated PNG"),
+            DecodeError::BadChecksum => write!(f, "corrupted PNG, checksum mismatch"),
+            DecodeError::Unsupported(what) => write!(f, "unsupported PNG: {}", what),
+            DecodeError::Inflate(what) => write!(f, "invalid compressed data: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decode a PNG to a `B8G8R8A8` image, RGB images get opaque alpha
+pub fn png(data: &[u8]) -> Result<Image, DecodeError> {
+    if data.len() < 8 || &data[..8] != b"\x89PNG\r\n\x1a\n" {
+        return Err(DecodeError::NotPng);
+    }
+    let mut header = None;
+    let mut compressed = Vec::new();
+    let mut pos = 8;
+    loop {
+        let len = be32(data, pos)? as usize;
+        let end = pos + 12 + len;
+        if end > data.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let kind = &data[pos + 4..pos + 8];
+        let body = &data[pos + 8..pos + 8 + len];
+        if crate::encode::crc32(&data[pos + 4..pos + 8 + len]) != be32(data, pos + 8 + len)? {
+            return Err(DecodeError::BadChecksum);
+        }
+        match kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            // Ancillary chunks have a lowercase first letter
+            _ if kind[0] & 0x20 != 0 => {}
+            _ => return Err(DecodeError::Unsupported("critical chunk")),
+        }
+        pos = end;
+    }
+    let header = header.ok_or(DecodeError::Truncated)?;
+
+    let raw = zlib(&compressed)?;
+    let channels = header.channels;
+    let row = header.width as usize * channels;
+    if raw.len() < (row + 1) * header.height as usize {
+        return Err(DecodeError::Truncated);
+    }
+
+    let mut image = Image::new(header.width, header.height);
+    let mut previous = vec![0u8; row];
+    let mut current = vec![0u8; row];
+    for y in 0..header.height as usize {
+        let line = &raw[y * (row + 1)..(y + 1) * (row + 1)];
+        unfilter(line[0], &line[1..], &previous, &mut current, channels)?;
+        for x in 0..header.width as usize {
+            let p = &current[x * channels..(x + 1) * channels];
+            let a = if channels == 4 { p[3] } else { 255 };
+            image.set_pixel(x as u32, y as u32, [p[2], p[1], p[0], a]);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Ok(image)
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    channels: usize,
+}
+
+impl Header {
+    fn parse(body: &[u8]) -> Result<Self, DecodeError> {
+        if body.len() != 13 {
+            return Err(DecodeError::Truncated);
+        }
+        if body[8] != 8 {
+            return Err(DecodeError::Unsupported("bit depth other than 8"));
+        }
+        let channels = match body[9] {
+            2 => 3,
+            6 => 4,
+            _ => {
+                return Err(DecodeError::Unsupported(
+                    "color type other than RGB or RGBA",
+                ))
+            }
+        };
+        if body[12] != 0 {
+            return Err(DecodeError::Unsupported("interlacing"));
+        }
+        Ok(Header {
+            width: be32(body, 0)?,
+            height: be32(body, 4)?,
+            channels,
+        })
+    }
+}
+
+fn be32(data: &[u8], pos: usize) -> Result<u32, DecodeError> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(DecodeError::Truncated)
+}
+
+fn unfilter(
+    filter: u8,
+    line: &[u8],
+    previous: &[u8],
+    out: &mut [u8],
+    bpp: usize,
+) -> Result<(), DecodeError> {
+    for i in 0..line.len() {
+        let a = if i >= bpp { out[i - bpp] } else { 0 };
+        let b = previous[i];
+        let c = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => return Err(DecodeError::Unsupported("filter type")),
+        };
+        out[i] = line[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Decompress a zlib stream
+pub fn zlib(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < 6 {
+        return Err(DecodeError::Truncated);
+    }
+    if data[0] & 0x0f != 8 || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) {
+        return Err(DecodeError::Inflate("bad zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(DecodeError::Unsupported("preset dictionary"));
+    }
+    let mut bits = Bits {
+        data: &data[2..],
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let out = inflate(&mut bits)?;
+    let end = 2 + bits.pos;
+    if be32(data, end)? != crate::encode::adler32(&out) {
+        return Err(DecodeError::BadChecksum);
+    }
+    Ok(out)
+}
+
+/// LSB first bit reader
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, DecodeError> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or(DecodeError::Truncated)?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop the bits left in the current byte
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, DecodeError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecodeError::Inflate("invalid code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of the code length code lengths in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn inflate(bits: &mut Bits) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let header = bits
+                    .data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or(DecodeError::Truncated)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(DecodeError::Inflate("stored length mismatch"));
+                }
+                let start = bits.pos + 4;
+                let block = bits
+                    .data
+                    .get(start..start + len as usize)
+                    .ok_or(DecodeError::Truncated)?;
+                out.extend_from_slice(block);
+                bits.pos = start + len as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|l| *l = 8);
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                lengths[280..].iter_mut().for_each(|l| *l = 8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                codes(bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(bits)?;
+                codes(bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(DecodeError::Inflate("invalid block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), DecodeError> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_count = bits.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[i] = bits.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or(DecodeError::Inflate("repeat without a length"))?;
+                (previous, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.resize(lengths.len() + repeat as usize, value);
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(DecodeError::Inflate("too many lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn codes(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), DecodeError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let i = symbol - 257;
+            if i >= LENGTH_BASE.len() {
+                return Err(DecodeError::Inflate("invalid length"));
+            }
+            let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+            let d = distances.decode(bits)? as usize;
+            if d >= DISTANCE_BASE.len() {
+                return Err(DecodeError::Inflate("invalid distance"));
+            }
+            let distance =
+                DISTANCE_BASE[d] as usize + bits.bits(DISTANCE_EXTRA[d] as u32)? as usize;
+            if distance > out.len() {
+                return Err(DecodeError::Inflate("distance too far back"));
+            }
+            let start = out.len() - distance;
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode;
+
+    #[test]
+    fn round_trips_encoder() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(0, 0, [1, 2, 3, 4]);
+        image.set_pixel(2, 1, [250, 128, 0, 255]);
+        assert_eq!(png(&encode::png(&image, false)), Ok(image));
+    }
+
+    #[test]
+    fn decodes_filters() {
+        // 2x4 image from zlib, rows use the Sub, Up, Average and Paeth filters
+        let data = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x08, 0x06, 0x00, 0x00,
+            0x00, 0xa4, 0xef, 0xee, 0x39, 0x00, 0x00, 0x00, 0x27, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0xda, 0x63, 0x64, 0x38, 0xc1, 0xfa, 0xdf, 0x88, 0x41, 0x8e, 0x81, 0x89, 0xeb, 0x0d,
+            0xc3, 0x0d, 0x10, 0x66, 0xe6, 0x77, 0x63, 0x76, 0x91, 0xfb, 0xc6, 0xff, 0x86, 0x05,
+            0x2c, 0xc2, 0xc0, 0xc0, 0x00, 0x00, 0xc0, 0x6c, 0x0a, 0x46, 0xcb, 0x70, 0x17, 0xea,
+            0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let image = png(&data).unwrap();
+        for y in 0..4 {
+            for x in 0..2 {
+                let [r, g, b, a] = [10 * y + 50 * x, 200 - 20 * y, 30 * x + 5, 255 - 40 * y];
+                assert_eq!(image.pixel(x, y), [b as u8, g as u8, r as u8, a as u8]);
+            }
+        }
+    }
+
+    #[test]
+    fn inflates_dynamic_block() {
+        let data = [
+            0x78, 0xda, 0xad, 0x8c, 0xc7, 0x11, 0x80, 0x20, 0x10, 0x45, 0x5b, 0xf9, 0x05, 0x38,
+            0xf6, 0x04, 0xba, 0x04, 0x03, 0x4b, 0x46, 0xad, 0xde, 0x1d, 0x6b, 0xf0, 0xfc, 0x42,
+            0x75, 0x84, 0xd4, 0xfc, 0xb2, 0x43, 0x67, 0x1e, 0x01, 0x86, 0x2f, 0x6c, 0xed, 0x8c,
+            0x05, 0xdc, 0x29, 0xa3, 0x0a, 0x3e, 0xd4, 0x73, 0x63, 0x65, 0x3b, 0x21, 0x2a, 0xf1,
+            0xce, 0x1b, 0x5a, 0xa4, 0xe1, 0xab, 0x83, 0xf1, 0x9d, 0x04, 0x3d, 0x14, 0x70, 0xf8,
+            0xd4, 0x38, 0x4b, 0x6b, 0xcb, 0xfc, 0x65, 0xff, 0x5f, 0x5f, 0x23, 0x7f, 0x3e, 0x39,
+        ];
+        let text = "the quick brown fox jumps over the lazy dog, \
+                    pack my box with five dozen liquor jugs. ";
+        assert_eq!(zlib(&data).unwrap(), text.repeat(2).as_bytes());
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let mut data = encode::png(&Image::new(2, 2), false);
+        assert_eq!(png(&data[1..]), Err(DecodeError::NotPng));
+        let last = data.len() - 20;
+        data[last] ^= 1;
+        assert_eq!(png(&data), Err(DecodeError::BadChecksum));
+    }
+}
@@ -0,0 +1,556 @@
+//! Descriptor heaps and allocators handing out typed descriptors
+use std::collections::VecDeque;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// `D3D12_DESCRIPTOR_HEAP_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum DescriptorHeapType {
+    CbvSrvUav = 0,
+    Sampler = 1,
+    Rtv = 2,
+    Dsv = 3,
+}
+
+/// `D3D12_DESCRIPTOR_HEAP_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorHeapDesc {
+    pub heap_type: DescriptorHeapType,
+    pub num_descriptors: u32,
+    pub shader_visible: bool,
+}
+
+/// `D3D12_CPU_DESCRIPTOR_HANDLE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CpuDescriptorHandle(pub usize);
+
+/// Descriptor heap created by the backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorHeap {
+    pub desc: DescriptorHeapDesc,
+    /// `GetCPUDescriptorHandleForHeapStart`
+    pub start: CpuDescriptorHandle,
+    /// `GetDescriptorHandleIncrementSize`
+    pub increment: usize,
+}
+
+/// Heap type of the descriptors in a `TypedHeap`
+pub trait HeapKind {
+    const TYPE: DescriptorHeapType;
+}
+
+/// Render target views
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rtv;
+
+/// Depth stencil views
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dsv;
+
+/// Constant buffer, shader resource and unordered access views
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CbvSrvUav;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sampler;
+
+impl HeapKind for Rtv {
+    const TYPE: DescriptorHeapType = DescriptorHeapType::Rtv;
+}
+
+impl HeapKind for Dsv {
+    const TYPE: DescriptorHeapType = DescriptorHeapType::Dsv;
+}
+
+impl HeapKind for CbvSrvUav {
+    const TYPE: DescriptorHeapType = DescriptorHeapType::CbvSrvUav;
+}
+
+impl HeapKind for Sampler {
+    const TYPE: DescriptorHeapType = DescriptorHeapType::Sampler;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorError {
+    WrongHeapType {
+        expected: DescriptorHeapType,
+        actual: DescriptorHeapType,
+    },
+    /// No free run of `requested` descriptors
+    OutOfDescriptors {
+        requested: u32,
+        available: u32,
+    },
+    OutOfBounds {
+        index: u32,
+        count: u32,
+    },
+    /// Range is not allocated from this allocator, or already freed
+    NotAllocated {
+        first: u32,
+        count: u32,
+    },
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptorError::WrongHeapType { expected, actual } => {
+                write!(f, "expected a {:?} heap, got {:?}", expected, actual)
+            }
+            DescriptorError::OutOfDescriptors {
+                requested,
+                available,
+            } => write!(
+                f,
+                "no room for {} descriptors, {} free",
+                requested, available
+            ),
+            DescriptorError::OutOfBounds { index, count } => {
+                write!(f, "descriptor {} is out of range of {}", index, count)
+            }
+            DescriptorError::NotAllocated { first, count } => write!(
+                f,
+                "descriptors {}..{} are not allocated",
+                first,
+                first + count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+/// Descriptor heap known to hold `K` descriptors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypedHeap<K> {
+    heap: DescriptorHeap,
+    kind: PhantomData<K>,
+}
+
+impl<K: HeapKind> TypedHeap<K> {
+    pub fn new(heap: DescriptorHeap) -> Result<Self, DescriptorError> {
+        if heap.desc.heap_type != K::TYPE {
+            return Err(DescriptorError::WrongHeapType {
+                expected: K::TYPE,
+                actual: heap.desc.heap_type,
+            });
+        }
+        Ok(TypedHeap {
+            heap,
+            kind: PhantomData,
+        })
+    }
+
+    pub fn heap(&self) -> &DescriptorHeap {
+        &self.heap
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.heap.desc.num_descriptors
+    }
+
+    fn range(&self, first: u32, count: u32) -> DescriptorRange<K> {
+        DescriptorRange {
+            first,
+            count,
+            start: CpuDescriptorHandle(self.heap.start.0 + self.heap.increment * first as usize),
+            increment: self.heap.increment,
+            kind: PhantomData,
+        }
+    }
+}
+
+/// Single descriptor of a `K` heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Descriptor<K> {
+    /// Index in the heap
+    pub index: u32,
+    pub handle: CpuDescriptorHandle,
+    kind: PhantomData<K>,
+}
+
+/// Contiguous descriptors owned by the caller until given back to the allocator
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorRange<K> {
+    first: u32,
+    count: u32,
+    start: CpuDescriptorHandle,
+    increment: usize,
+    kind: PhantomData<K>,
+}
+
+impl<K> DescriptorRange<K> {
+    /// Index of the first descriptor in the heap
+    pub fn first(&self) -> u32 {
+        self.first
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Descriptor `i` of the range
+    pub fn get(&self, i: u32) -> Result<Descriptor<K>, DescriptorError> {
+        if i >= self.count {
+            return Err(DescriptorError::OutOfBounds {
+                index: i,
+                count: self.count,
+            });
+        }
+        Ok(Descriptor {
+            index: self.first + i,
+            handle: CpuDescriptorHandle(self.start.0 + self.increment * i as usize),
+            kind: PhantomData,
+        })
+    }
+}
+
+/// Bump allocator, everything is freed at once with `reset`
+#[derive(Debug, Clone)]
+pub struct LinearAllocator<K> {
+    heap: TypedHeap<K>,
+    next: u32,
+}
+
+impl<K: HeapKind> LinearAllocator<K> {
+    pub fn new(heap: TypedHeap<K>) -> Self {
+        LinearAllocator { heap, next: 0 }
+    }
+
+    pub fn allocate(&mut self, count: u32) -> Result<DescriptorRange<K>, DescriptorError> {
+        let available = self.available();
+        if count > available {
+            return Err(DescriptorError::OutOfDescriptors {
+                requested: count,
+                available,
+            });
+        }
+        let range = self.heap.range(self.next, count);
+        self.next += count;
+        Ok(range)
+    }
+
+    pub fn available(&self) -> u32 {
+        self.heap.capacity() - self.next
+    }
+
+    /// Free all descriptors, ranges handed out before must not be used anymore
+    pub fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// First fit allocator with freeing, neighbouring free runs are merged
+#[derive(Debug, Clone)]
+pub struct FreeListAllocator<K> {
+    heap: TypedHeap<K>,
+    /// Free runs as `(first, count)`, sorted and never adjacent
+    free: Vec<(u32, u32)>,
+}
+
+impl<K: HeapKind> FreeListAllocator<K> {
+    pub fn new(heap: TypedHeap<K>) -> Self {
+        let capacity = heap.capacity();
+        FreeListAllocator {
+            heap,
+            free: if capacity > 0 {
+                vec![(0, capacity)]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    pub fn allocate(&mut self, count: u32) -> Result<DescriptorRange<K>, DescriptorError> {
+        let i = self
+            .free
+            .iter()
+            .position(|&(_, free)| free >= count)
+            .ok_or(DescriptorError::OutOfDescriptors {
+                requested: count,
+                available: self.largest_free(),
+            })?;
+        let (first, free) = self.free[i];
+        if free == count {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (first + count, free - count);
+        }
+        Ok(self.heap.range(first, count))
+    }
+
+    pub fn free(&mut self, range: DescriptorRange<K>) -> Result<(), DescriptorError> {
+        let (first, count) = (range.first, range.count);
+        if count == 0 {
+            return Ok(());
+        }
+        let end = first + count;
+        let not_allocated = DescriptorError::NotAllocated { first, count };
+        if end > self.heap.capacity() {
+            return Err(not_allocated);
+        }
+        let i = self.free.partition_point(|&(f, _)| f < first);
+        let before = i.checked_sub(1).map(|j| self.free[j]);
+        let after = self.free.get(i).copied();
+        if before.is_some_and(|(f, c)| f + c > first) || after.is_some_and(|(f, _)| f < end) {
+            return Err(not_allocated);
+        }
+        match (
+            before.filter(|&(f, c)| f + c == first),
+            after.filter(|&(f, _)| f == end),
+        ) {
+            (Some((f, c)), Some((_, c2))) => {
+                self.free[i - 1] = (f, c + count + c2);
+                self.free.remove(i);
+            }
+            (Some((f, c)), None) => self.free[i - 1] = (f, c + count),
+            (None, Some((_, c2))) => self.free[i] = (first, count + c2),
+            (None, None) => self.free.insert(i, (first, count)),
+        }
+        Ok(())
+    }
+
+    /// Free descriptors in total
+    pub fn available(&self) -> u32 {
+        self.free.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// Longest run that can be allocated at once
+    pub fn largest_free(&self) -> u32 {
+        self.free.iter().map(|&(_, count)| count).max().unwrap_or(0)
+    }
+
+    /// Number of separate free runs, one or zero when unfragmented
+    pub fn fragments(&self) -> usize {
+        self.free.len()
+    }
+}
+
+/// Ring of descriptors written each frame, freed when the frame's fence
+/// value completes
+///
+/// A range never wraps around the end of the heap, the skipped tail is
+/// freed with the frame.
+#[derive(Debug, Clone)]
+pub struct FrameRingAllocator<K> {
+    heap: TypedHeap<K>,
+    head: u32,
+    tail: u32,
+    used: u32,
+    /// Descriptors used by the current frame, including skipped ones
+    frame_used: u32,
+    /// Ended frames as `(fence value, head, used)`
+    frames: VecDeque<(u64, u32, u32)>,
+}
+
+impl<K: HeapKind> FrameRingAllocator<K> {
+    pub fn new(heap: TypedHeap<K>) -> Self {
+        FrameRingAllocator {
+            heap,
+            head: 0,
+            tail: 0,
+            used: 0,
+            frame_used: 0,
+            frames: VecDeque::new(),
+        }
+    }
+
+    pub fn allocate(&mut self, count: u32) -> Result<DescriptorRange<K>, DescriptorError> {
+        let capacity = self.heap.capacity();
+        let full = DescriptorError::OutOfDescriptors {
+            requested: count,
+            available: capacity - self.used,
+        };
+        // Queued frames, even empty ones, hold a head that `retire` moves
+        // the tail to, so only an idle ring starts over
+        if self.used == 0 && self.frames.is_empty() {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let (first, taken) = if self.head >= self.tail && self.used < capacity {
+            if capacity - self.head >= count {
+                (self.head, count)
+            } else if self.tail >= count {
+                (0, capacity - self.head + count)
+            } else {
+                return Err(full);
+            }
+        } else if self.tail - self.head >= count {
+            (self.head, count)
+        } else {
+            return Err(full);
+        };
+        self.head = first + count;
+        self.used += taken;
+        self.frame_used += taken;
+        Ok(self.heap.range(first, count))
+    }
+
+    /// Close the frame, its descriptors are freed once `fence_value` completes
+    pub fn end_frame(&mut self, fence_value: u64) {
+        self.frames
+            .push_back((fence_value, self.head, self.frame_used));
+        self.frame_used = 0;
+    }
+
+    /// Free the frames whose fence value is at most `completed`
+    pub fn retire(&mut self, completed: u64) {
+        while let Some(&(value, head, used)) = self.frames.front() {
+            if value > completed {
+                break;
+            }
+            self.tail = head;
+            self.used -= used;
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn available(&self) -> u32 {
+        self.heap.capacity() - self.used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heap<K: HeapKind>(num_descriptors: u32) -> TypedHeap<K> {
+        TypedHeap::new(DescriptorHeap {
+            desc: DescriptorHeapDesc {
+                heap_type: K::TYPE,
+                num_descriptors,
+                shader_visible: false,
+            },
+            start: CpuDescriptorHandle(0x1000),
+            increment: 32,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn checks_heap_type_and_bounds() {
+        let rtvs = heap::<Rtv>(4);
+        assert_eq!(
+            TypedHeap::<Dsv>::new(*rtvs.heap()),
+            Err(DescriptorError::WrongHeapType {
+                expected: DescriptorHeapType::Dsv,
+                actual: DescriptorHeapType::Rtv
+            })
+        );
+        let mut linear = LinearAllocator::new(rtvs);
+        linear.allocate(1).unwrap();
+        let range = linear.allocate(2).unwrap();
+        let descriptor = range.get(1).unwrap();
+        assert_eq!(descriptor.index, 2);
+        assert_eq!(descriptor.handle, CpuDescriptorHandle(0x1000 + 2 * 32));
+        assert_eq!(
+            range.get(2),
+            Err(DescriptorError::OutOfBounds { index: 2, count: 2 })
+        );
+    }
+
+    #[test]
+    fn linear_resets() {
+        let mut linear = LinearAllocator::new(heap::<Sampler>(3));
+        linear.allocate(2).unwrap();
+        assert_eq!(
+            linear.allocate(2),
+            Err(DescriptorError::OutOfDescriptors {
+                requested: 2,
+                available: 1
+            })
+        );
+        linear.reset();
+        assert_eq!(linear.allocate(3).unwrap().first(), 0);
+    }
+
+    #[test]
+    fn free_list_fragments_and_coalesces() {
+        let mut list = FreeListAllocator::new(heap::<CbvSrvUav>(8));
+        let a = list.allocate(2).unwrap();
+        let b = list.allocate(2).unwrap();
+        let c = list.allocate(2).unwrap();
+        let d = list.allocate(2).unwrap();
+        assert_eq!(list.available(), 0);
+
+        list.free(a).unwrap();
+        list.free(c).unwrap();
+        assert_eq!((list.available(), list.fragments()), (4, 2));
+        assert_eq!(
+            list.allocate(3),
+            Err(DescriptorError::OutOfDescriptors {
+                requested: 3,
+                available: 2
+            })
+        );
+
+        // First fit reuses the first hole
+        let e = list.allocate(1).unwrap();
+        assert_eq!(e.first(), 0);
+        list.free(e).unwrap();
+
+        // Freeing the middle merges all three runs
+        list.free(b).unwrap();
+        assert_eq!((list.largest_free(), list.fragments()), (6, 1));
+        list.free(d).unwrap();
+        assert_eq!((list.largest_free(), list.fragments()), (8, 1));
+    }
+
+    #[test]
+    fn free_list_rejects_double_free() {
+        let heap = heap::<Dsv>(4);
+        let mut list = FreeListAllocator::new(heap);
+        let range = list.allocate(2).unwrap();
+        let copy = heap.range(range.first(), range.len());
+        list.free(range).unwrap();
+        assert_eq!(
+            list.free(copy),
+            Err(DescriptorError::NotAllocated { first: 0, count: 2 })
+        );
+    }
+
+    #[test]
+    fn ring_frees_retired_frames() {
+        let mut ring = FrameRingAllocator::new(heap::<CbvSrvUav>(8));
+        assert_eq!(ring.allocate(3).unwrap().first(), 0);
+        ring.end_frame(1);
+        assert_eq!(ring.allocate(3).unwrap().first(), 3);
+        ring.end_frame(2);
+
+        // Frame 1 is still in flight
+        assert!(ring.allocate(3).is_err());
+        ring.retire(1);
+
+        // Two descriptors at the end are skipped to keep the range contiguous
+        assert_eq!(ring.allocate(3).unwrap().first(), 0);
+        assert_eq!(ring.available(), 0);
+        ring.end_frame(3);
+        ring.retire(3);
+        assert_eq!(ring.available(), 8);
+    }
+
+    #[test]
+    fn ring_keeps_tail_after_empty_frames() {
+        let mut ring = FrameRingAllocator::new(heap::<CbvSrvUav>(16));
+        ring.allocate(4).unwrap();
+        ring.end_frame(1);
+        ring.end_frame(2);
+        ring.retire(1);
+        assert_eq!(ring.allocate(6).unwrap().first(), 4);
+        ring.end_frame(3);
+        ring.retire(2);
+
+        // Frame 3 holds 4..10
+        assert!(ring.allocate(10).is_err());
+        assert_eq!(ring.allocate(3).unwrap().first(), 10);
+        ring.end_frame(4);
+        ring.retire(4);
+        assert_eq!(ring.available(), 16);
+    }
+}
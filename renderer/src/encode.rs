@@ -0,0 +1,207 @@
+//! PNG and PPM encoding of back buffer images
+//!
+//! PNG data is stored uncompressed, which keeps the encoder dependency free.
+use crate::image::Image;
+use std::path::Path;
+
+/// File format picked from the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    /// Binary `P6`, alpha is dropped
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, image: &Image, unpremultiply: bool) -> Vec<u8> {
+        match self {
+            ImageFormat::Png => png(image, unpremultiply),
+            ImageFormat::Ppm => ppm(image, unpremultiply),
+        }
+    }
+}
+
+/// Swizzle the `B8G8R8A8` data to `R8G8B8A8`
+///
+/// The back buffer holds premultiplied alpha for composition, `unpremultiply`
+/// divides the colors by alpha to get straight alpha.
+pub fn rgba8(image: &Image, unpremultiply: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(image.data.len());
+    for bgra in image.data.chunks_exact(4) {
+        let [b, g, r, a] = [bgra[0], bgra[1], bgra[2], bgra[3]];
+        if unpremultiply {
+            out.extend_from_slice(&[
+                unpremultiplied(r, a),
+                unpremultiplied(g, a),
+                unpremultiplied(b, a),
+                a,
+            ]);
+        } else {
+            out.extend_from_slice(&[r, g, b, a]);
+        }
+    }
+    out
+}
+
+fn unpremultiplied(c: u8, a: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8
+}
+
+/// Binary PPM of the colors
+pub fn ppm(image: &Image, unpremultiply: bool) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    for rgba in rgba8(image, unpremultiply).chunks_exact(4) {
+        out.extend_from_slice(&rgba[..3]);
+    }
+    out
+}
+
+/// 8-bit RGBA PNG
+pub fn png(image: &Image, unpremultiply: bool) -> Vec<u8> {
+    let rgba = rgba8(image, unpremultiply);
+
+    // Every scanline starts with filter type 0
+    let row = image.width as usize * 4;
+    let mut raw = Vec::with_capacity(rgba.len() + image.height as usize);
+    for y in 0..image.height as usize {
+        raw.push(0);
+        raw.extend_from_slice(&rgba[y * row..(y + 1) * row]);
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&image.width.to_be_bytes());
+    ihdr.extend_from_slice(&image.height.to_be_bytes());
+    // Bit depth 8, color type RGBA, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, [0, 0, 128, 128]);
+        image.set_pixel(1, 0, [10, 20, 30, 0]);
+        image
+    }
+
+    #[test]
+    fn swizzles_and_unpremultiplies() {
+        assert_eq!(rgba8(&image(), false), [128, 0, 0, 128, 30, 20, 10, 0]);
+        assert_eq!(rgba8(&image(), true), [255, 0, 0, 128, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn writes_ppm() {
+        let ppm = ppm(&image(), false);
+        assert_eq!(&ppm[..11], b"P6\n2 1\n255\n");
+        assert_eq!(&ppm[11..], [128, 0, 0, 30, 20, 10]);
+    }
+
+    #[test]
+    fn writes_png() {
+        let png = png(&image(), false);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+        // CRC of an IEND chunk
+        assert_eq!(
+            &png[png.len() - 8..],
+            [b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+
+        // Stored block holds the filtered scanline
+        let idat = &png[33 + 8..];
+        assert_eq!(&idat[..7], [0x78, 0x01, 1, 9, 0, 0xf6, 0xff]);
+        assert_eq!(&idat[7..16], [0, 128, 0, 0, 128, 30, 20, 10, 0]);
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("shot.PNG")),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("a/shot.ppm")),
+            Some(ImageFormat::Ppm)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("shot.bmp")), None);
+    }
+}
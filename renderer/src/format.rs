@@ -0,0 +1,178 @@
+/// Subset of `DXGI_FORMAT` used by the renderer
+///
+/// Discriminants are the `DXGI_FORMAT` values, so bindings can cast with `as`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Format {
+    Unknown = 0,
+    R32G32B32A32Float = 2,
+    R32G32B32A32Uint = 3,
+    R32G32B32A32Sint = 4,
+    R32G32B32Float = 6,
+    R32G32B32Uint = 7,
+    R32G32B32Sint = 8,
+    R16G16B16A16Float = 10,
+    R32G32Float = 16,
+    R32G32Uint = 17,
+    R32G32Sint = 18,
+    R10G10B10A2Unorm = 24,
+    R8G8B8A8Unorm = 28,
+    R8G8B8A8UnormSrgb = 29,
+    D32Float = 40,
+    R32Float = 41,
+    R32Uint = 42,
+    R32Sint = 43,
+    D24UnormS8Uint = 45,
+    R16Uint = 57,
+    B8G8R8A8Unorm = 87,
+    B8G8R8A8UnormSrgb = 91,
+}
+
+impl Format {
+    pub const ALL: [Format; 22] = [
+        Format::Unknown,
+        Format::R32G32B32A32Float,
+        Format::R32G32B32A32Uint,
+        Format::R32G32B32A32Sint,
+        Format::R32G32B32Float,
+        Format::R32G32B32Uint,
+        Format::R32G32B32Sint,
+        Format::R16G16B16A16Float,
+        Format::R32G32Float,
+        Format::R32G32Uint,
+        Format::R32G32Sint,
+        Format::R10G10B10A2Unorm,
+        Format::R8G8B8A8Unorm,
+        Format::R8G8B8A8UnormSrgb,
+        Format::D32Float,
+        Format::R32Float,
+        Format::R32Uint,
+        Format::R32Sint,
+        Format::D24UnormS8Uint,
+        Format::R16Uint,
+        Format::B8G8R8A8Unorm,
+        Format::B8G8R8A8UnormSrgb,
+    ];
+
+    /// `DXGI_FORMAT` name without the prefix, e.g. `"R32G32B32_FLOAT"`
+    pub const fn name(self) -> &'static str {
+        match self {
+            Format::Unknown => "UNKNOWN",
+            Format::R32G32B32A32Float => "R32G32B32A32_FLOAT",
+            Format::R32G32B32A32Uint => "R32G32B32A32_UINT",
+            Format::R32G32B32A32Sint => "R32G32B32A32_SINT",
+            Format::R32G32B32Float => "R32G32B32_FLOAT",
+            Format::R32G32B32Uint => "R32G32B32_UINT",
+            Format::R32G32B32Sint => "R32G32B32_SINT",
+            Format::R16G16B16A16Float => "R16G16B16A16_FLOAT",
+            Format::R32G32Float => "R32G32_FLOAT",
+            Format::R32G32Uint => "R32G32_UINT",
+            Format::R32G32Sint => "R32G32_SINT",
+            Format::R10G10B10A2Unorm => "R10G10B10A2_UNORM",
+            Format::R8G8B8A8Unorm => "R8G8B8A8_UNORM",
+            Format::R8G8B8A8UnormSrgb => "R8G8B8A8_UNORM_SRGB",
+            Format::D32Float => "D32_FLOAT",
+            Format::R32Float => "R32_FLOAT",
+            Format::R32Uint => "R32_UINT",
+            Format::R32Sint => "R32_SINT",
+            Format::D24UnormS8Uint => "D24_UNORM_S8_UINT",
+            Format::R16Uint => "R16_UINT",
+            Format::B8G8R8A8Unorm => "B8G8R8A8_UNORM",
+            Format::B8G8R8A8UnormSrgb => "B8G8R8A8_UNORM_SRGB",
+        }
+    }
+
+    /// Format of a [`Format::name`], the `DXGI_FORMAT_` prefix is optional
+    pub fn from_name(name: &str) -> Option<Format> {
+        let name = name.strip_prefix("DXGI_FORMAT_").unwrap_or(name);
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn from_u32(value: u32) -> Option<Format> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| *format as u32 == value)
+    }
+
+    /// Depth stencil view formats
+    pub const fn is_depth(self) -> bool {
+        matches!(self, Format::D32Float | Format::D24UnormS8Uint)
+    }
+
+    /// Size of a single element in bytes, zero for `Unknown`
+    pub const fn size_in_bytes(self) -> u32 {
+        match self {
+            Format::Unknown => 0,
+            Format::R32G32B32A32Float | Format::R32G32B32A32Uint | Format::R32G32B32A32Sint => 16,
+            Format::R32G32B32Float | Format::R32G32B32Uint | Format::R32G32B32Sint => 12,
+            Format::R16G16B16A16Float
+            | Format::R32G32Float
+            | Format::R32G32Uint
+            | Format::R32G32Sint => 8,
+            Format::R10G10B10A2Unorm
+            | Format::R8G8B8A8Unorm
+            | Format::R8G8B8A8UnormSrgb
+            | Format::D32Float
+            | Format::R32Float
+            | Format::R32Uint
+            | Format::R32Sint
+            | Format::D24UnormS8Uint
+            | Format::B8G8R8A8Unorm
+            | Format::B8G8R8A8UnormSrgb => 4,
+            Format::R16Uint => 2,
+        }
+    }
+
+    /// Number of components, e.g. 3 for `R32G32B32Float`
+    pub const fn component_count(self) -> u32 {
+        match self {
+            Format::Unknown => 0,
+            Format::R32Float
+            | Format::R32Uint
+            | Format::R32Sint
+            | Format::D32Float
+            | Format::R16Uint => 1,
+            Format::R32G32Float
+            | Format::R32G32Uint
+            | Format::R32G32Sint
+            | Format::D24UnormS8Uint => 2,
+            Format::R32G32B32Float | Format::R32G32B32Uint | Format::R32G32B32Sint => 3,
+            _ => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dxgi_values() {
+        assert_eq!(Format::R32G32B32Float as u32, 6);
+        assert_eq!(Format::R32G32B32A32Float as u32, 2);
+        assert_eq!(Format::B8G8R8A8Unorm as u32, 87);
+    }
+
+    #[test]
+    fn names() {
+        for format in Format::ALL.iter() {
+            assert_eq!(Format::from_name(format.name()), Some(*format));
+        }
+        assert_eq!(
+            Format::from_name("DXGI_FORMAT_b8g8r8a8_unorm"),
+            Some(Format::B8G8R8A8Unorm)
+        );
+        assert_eq!(Format::from_name("R8G8B8A8"), None);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Format::R32G32B32Float.size_in_bytes(), 12);
+        assert_eq!(Format::R32G32B32A32Float.size_in_bytes(), 16);
+        assert_eq!(Format::B8G8R8A8Unorm.component_count(), 4);
+    }
+}
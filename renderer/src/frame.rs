@@ -0,0 +1,407 @@
+//! Frame flow of `Window::new`, `populate_command_list` and `render`
+use crate::backend::{
+    AlphaMode, Backend, CommandListType, CommandQueueDesc, CommandQueuePriority, SwapChainDesc,
+    VertexBufferView,
+};
+use crate::descriptor::{
+    DescriptorHeapDesc, DescriptorHeapType, DescriptorRange, LinearAllocator, Rtv, TypedHeap,
+};
+use crate::pacing::FramePacer;
+use crate::pipeline::PrimitiveTopology;
+use crate::resource::{ResourceId, ResourceStates, ALL_SUBRESOURCES};
+use crate::scene::{Scene, BACK_BUFFER_FORMAT};
+use crate::state::StateTracker;
+use crate::upload::{Allocation, UploadError, UploadRing, UPLOAD_RING_SIZE};
+use crate::vertex::{self, Vertex, VertexLayout};
+use crate::NUM_OF_FRAMES;
+
+/// Draws the scene with a backend
+pub struct Renderer<B: Backend> {
+    backend: B,
+    scene: Scene,
+    /// Render target view per back buffer
+    rtvs: DescriptorRange<Rtv>,
+    vertex_buffer_view: VertexBufferView,
+    pacer: FramePacer,
+    states: StateTracker,
+    /// Per-frame vertex, index and constant data
+    upload: UploadRing,
+}
+
+impl<B: Backend> Renderer<B> {
+    /// Create the queue, swap chain, render target views, command list,
+    /// vertex buffer and upload ring
+    pub fn new(mut backend: B, scene: Scene) -> Result<Self, B::Error> {
+        backend.create_command_queue(&CommandQueueDesc {
+            list_type: CommandListType::Direct,
+            priority: CommandQueuePriority::High,
+        })?;
+
+        backend.create_swap_chain(&SwapChainDesc {
+            width: scene.width,
+            height: scene.height,
+            format: BACK_BUFFER_FORMAT,
+            buffer_count: NUM_OF_FRAMES as _,
+            alpha_mode: AlphaMode::Premultiplied,
+        })?;
+
+        // Render target view per frame
+        let rtv_heap = backend.create_descriptor_heap(&DescriptorHeapDesc {
+            heap_type: DescriptorHeapType::Rtv,
+            num_descriptors: NUM_OF_FRAMES as _,
+            shader_visible: false,
+        })?;
+        let rtvs = TypedHeap::new(rtv_heap)
+            .and_then(|heap| LinearAllocator::new(heap).allocate(NUM_OF_FRAMES as _))
+            .expect("RTV heap fits the back buffers");
+        let mut states = StateTracker::new();
+        for i in 0..NUM_OF_FRAMES {
+            let rtv = rtvs.get(i as _).expect("RTV per back buffer");
+            backend.create_render_target_view(ResourceId::back_buffer(i), rtv.handle)?;
+            states.register(ResourceId::back_buffer(i), 1, ResourceStates::PRESENT);
+        }
+
+        backend.create_command_allocators(NUM_OF_FRAMES)?;
+        backend.create_command_list()?;
+
+        let vertex_buffer_view =
+            backend.create_vertex_buffer(vertex::as_bytes(&scene.vertices), Vertex::STRIDE)?;
+        let upload_address = backend.create_upload_buffer(UPLOAD_RING_SIZE)?;
+
+        Ok(Renderer {
+            backend,
+            scene,
+            rtvs,
+            vertex_buffer_view,
+            pacer: FramePacer::new(NUM_OF_FRAMES),
+            states,
+            upload: UploadRing::new(upload_address, UPLOAD_RING_SIZE),
+        })
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn upload_ring(&self) -> &UploadRing {
+        &self.upload
+    }
+
+    /// Copy data for the current frame to the upload ring
+    pub fn upload(&mut self, data: &[u8], alignment: u64) -> Result<Allocation, UploadError> {
+        let allocation = self.upload.allocate(data.len() as _, alignment)?;
+        self.write_upload(&allocation, data);
+        Ok(allocation)
+    }
+
+    /// Copy constant buffer data for the current frame to the upload ring
+    pub fn upload_constants(&mut self, data: &[u8]) -> Result<Allocation, UploadError> {
+        let allocation = self.upload.allocate_constants(data.len() as _)?;
+        self.write_upload(&allocation, data);
+        Ok(allocation)
+    }
+
+    fn write_upload(&mut self, allocation: &Allocation, data: &[u8]) {
+        let memory = self
+            .backend
+            .upload_memory(allocation.gpu_address)
+            .expect("Upload ring is mapped");
+        memory[..data.len()].copy_from_slice(data);
+    }
+
+    pub fn populate_command_list(&mut self) -> Result<(), B::Error> {
+        // Get the current backbuffer on which to draw
+        let current_frame = self.backend.current_back_buffer_index();
+        let back_buffer = ResourceId::back_buffer(current_frame);
+        let rtv = self
+            .rtvs
+            .get(current_frame as _)
+            .expect("RTV per back buffer")
+            .handle;
+
+        let b = &mut self.backend;
+        b.reset_command_list(current_frame)?;
+
+        b.set_graphics_root_signature();
+        b.set_viewports(&[self.scene.viewport()]);
+        b.set_scissor_rects(&[self.scene.scissor()]);
+
+        // Direct the draw commands to the render target resource
+        self.states
+            .transition(back_buffer, ALL_SUBRESOURCES, ResourceStates::RENDER_TARGET)
+            .expect("Back buffers are tracked");
+        b.resource_barrier(&self.states.flush());
+
+        b.set_render_targets(&[rtv]);
+        b.clear_render_target_view(rtv, self.scene.clear_color);
+        b.set_primitive_topology(PrimitiveTopology::TriangleList);
+        b.set_vertex_buffers(0, &[self.vertex_buffer_view]);
+        b.draw_instanced(self.scene.vertices.len() as _, 1, 0, 0);
+
+        // Back buffer must be in present state for the swap chain
+        self.states
+            .transition(back_buffer, ALL_SUBRESOURCES, ResourceStates::PRESENT)
+            .expect("Back buffers are tracked");
+        b.resource_barrier(&self.states.flush());
+
+        b.close_command_list()
+    }
+
+    /// Block until the GPU is done with the frame's allocator and back buffer,
+    /// then retire the upload regions of completed frames
+    pub fn wait_for_frame(&mut self, frame: usize) -> Result<(), B::Error> {
+        if let Some(value) = self.pacer.wait_value(frame, self.backend.completed_value()) {
+            self.backend.wait_for_value(value)?;
+        }
+        self.upload.retire(self.backend.completed_value());
+        Ok(())
+    }
+
+    /// Block until all submitted work is done, e.g. before releasing resources
+    pub fn wait_for_gpu(&mut self) -> Result<(), B::Error> {
+        let value = self.pacer.next_value();
+        self.backend.signal(value)?;
+        self.backend.wait_for_value(value)
+    }
+
+    pub fn render(&mut self) -> Result<(), B::Error> {
+        let frame = self.backend.current_back_buffer_index();
+        self.wait_for_frame(frame)?;
+        self.populate_command_list()?;
+        self.backend.execute_command_list()?;
+        self.backend.present(1, 0)?;
+        let value = self.pacer.end_frame(frame);
+        self.upload.end_frame(value);
+        self.backend.signal(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::CpuDescriptorHandle;
+    use crate::mock::{Call, MockBackend};
+    use crate::pacing::SimulatedGpu;
+    use crate::resource::Barrier;
+
+    fn renderer() -> Renderer<MockBackend> {
+        let mut renderer =
+            Renderer::new(MockBackend::default(), Scene::hello_triangle(1024, 1024)).unwrap();
+        renderer.backend_mut().calls.clear();
+        renderer
+    }
+
+    #[test]
+    fn creates_resources_in_order() {
+        let renderer =
+            Renderer::new(MockBackend::default(), Scene::hello_triangle(1024, 1024)).unwrap();
+        let calls = &renderer.backend().calls;
+        assert!(matches!(calls[0], Call::CreateCommandQueue(_)));
+        assert!(matches!(
+            calls[1],
+            Call::CreateSwapChain(SwapChainDesc { width: 1024, .. })
+        ));
+        assert!(matches!(
+            calls[2],
+            Call::CreateDescriptorHeap(DescriptorHeapDesc {
+                heap_type: DescriptorHeapType::Rtv,
+                num_descriptors: 2,
+                ..
+            })
+        ));
+        assert!(matches!(calls[3], Call::CreateRenderTargetView { .. }));
+        assert!(matches!(calls[4], Call::CreateRenderTargetView { .. }));
+        assert_eq!(calls[5], Call::CreateCommandAllocators(2));
+        assert_eq!(calls[6], Call::CreateCommandList);
+        match &calls[7] {
+            Call::CreateVertexBuffer { data, stride } => {
+                assert_eq!(data.len(), 3 * 28);
+                assert_eq!(*stride, 28);
+            }
+            call => panic!("unexpected {:?}", call),
+        }
+        assert_eq!(calls[8], Call::CreateUploadBuffer(UPLOAD_RING_SIZE));
+    }
+
+    #[test]
+    fn populates_command_list() {
+        let mut renderer = renderer();
+        renderer.backend_mut().back_buffer = 1;
+        renderer.populate_command_list().unwrap();
+
+        let rtv = renderer.rtvs.get(1).unwrap().handle;
+        assert_ne!(rtv, renderer.rtvs.get(0).unwrap().handle);
+        let calls = &renderer.backend().calls;
+        assert_eq!(
+            calls.as_slice(),
+            &[
+                Call::ResetCommandList(1),
+                Call::SetGraphicsRootSignature,
+                Call::SetViewports(vec![renderer.scene.viewport()]),
+                Call::SetScissorRects(vec![renderer.scene.scissor()]),
+                Call::ResourceBarrier(vec![Barrier::transition(
+                    ResourceId(1),
+                    ResourceStates::PRESENT,
+                    ResourceStates::RENDER_TARGET,
+                )]),
+                Call::SetRenderTargets(vec![rtv]),
+                Call::ClearRenderTargetView {
+                    rtv,
+                    color: [1.0, 0.2, 0.4, 0.5]
+                },
+                Call::SetPrimitiveTopology(PrimitiveTopology::TriangleList),
+                Call::SetVertexBuffers {
+                    start_slot: 0,
+                    views: vec![renderer.vertex_buffer_view]
+                },
+                Call::DrawInstanced {
+                    vertex_count: 3,
+                    instance_count: 1,
+                    start_vertex: 0,
+                    start_instance: 0
+                },
+                Call::ResourceBarrier(vec![Barrier::transition(
+                    ResourceId(1),
+                    ResourceStates::RENDER_TARGET,
+                    ResourceStates::PRESENT,
+                )]),
+                Call::CloseCommandList,
+            ][..]
+        );
+    }
+
+    fn fence_calls(renderer: &Renderer<MockBackend>) -> Vec<Call> {
+        renderer
+            .backend()
+            .calls
+            .iter()
+            .filter(|c| {
+                matches!(
+                    c,
+                    Call::ResetCommandList(_) | Call::Signal(_) | Call::WaitForValue(_)
+                )
+            })
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn render_waits_only_to_reuse_a_frame() {
+        let mut renderer = renderer();
+        renderer.backend_mut().complete_immediately = false;
+        for _ in 0..3 {
+            renderer.render().unwrap();
+        }
+
+        // The third frame reuses the allocator of the first one
+        assert_eq!(
+            fence_calls(&renderer),
+            vec![
+                Call::ResetCommandList(0),
+                Call::Signal(1),
+                Call::ResetCommandList(1),
+                Call::Signal(2),
+                Call::WaitForValue(1),
+                Call::ResetCommandList(0),
+                Call::Signal(3),
+            ]
+        );
+
+        renderer.backend_mut().calls.clear();
+        renderer.wait_for_gpu().unwrap();
+        assert_eq!(
+            fence_calls(&renderer),
+            vec![Call::Signal(4), Call::WaitForValue(4)]
+        );
+    }
+
+    #[test]
+    fn paces_against_simulated_gpu() {
+        // GPU takes 3 ticks per frame, the CPU 1 tick
+        let mut renderer = renderer();
+        renderer.backend_mut().gpu = Some(SimulatedGpu::new(3));
+        for _ in 0..6 {
+            renderer.render().unwrap();
+            renderer.backend_mut().gpu.as_mut().unwrap().advance(1);
+        }
+        let gpu = renderer.backend().gpu.clone().unwrap();
+
+        // CPU runs two frames ahead, then waits for the GPU each frame
+        assert_eq!(gpu.now(), 13);
+        // First wait is one tick, then each frame stalls for the 2 tick difference
+        assert_eq!(gpu.stalled(), 1 + 2 + 2 + 2);
+        let waits = fence_calls(&renderer)
+            .into_iter()
+            .filter_map(|c| match c {
+                Call::WaitForValue(v) => Some(v),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(waits, vec![1, 2, 3, 4]);
+
+        // A fast GPU never makes the CPU wait
+        let mut renderer = self::renderer();
+        renderer.backend_mut().gpu = Some(SimulatedGpu::new(1));
+        for _ in 0..6 {
+            renderer.render().unwrap();
+            renderer.backend_mut().gpu.as_mut().unwrap().advance(1);
+        }
+        assert_eq!(renderer.backend().gpu.as_ref().unwrap().stalled(), 0);
+    }
+
+    #[test]
+    fn reuses_upload_memory_once_frame_completes() {
+        let mut renderer = renderer();
+        renderer.backend_mut().complete_immediately = false;
+        let constants = renderer.upload_constants(&[7; 64]).unwrap();
+        let memory = renderer
+            .backend_mut()
+            .upload_memory(constants.gpu_address)
+            .unwrap();
+        assert_eq!(&memory[..65], &[[7; 64].as_ref(), &[0]].concat()[..]);
+        renderer.render().unwrap();
+
+        // First frame is still in flight
+        assert_eq!(
+            renderer.upload(&vec![0; UPLOAD_RING_SIZE as usize], 4),
+            Err(UploadError::Overflow {
+                requested: UPLOAD_RING_SIZE,
+                available: UPLOAD_RING_SIZE - 256
+            })
+        );
+        renderer.render().unwrap();
+        assert_eq!(renderer.upload_ring().frames_in_flight(), 2);
+
+        // Third frame waits for the first one which retires its constants
+        renderer.render().unwrap();
+        assert_eq!(renderer.upload_ring().available(), UPLOAD_RING_SIZE);
+        assert!(renderer.upload(&[1; 16], 4).is_ok());
+    }
+
+    #[test]
+    fn render_alternates_back_buffers() {
+        let mut renderer = renderer();
+        for _ in 0..3 {
+            renderer.render().unwrap();
+        }
+        let targets = renderer
+            .backend()
+            .calls
+            .iter()
+            .filter_map(|c| match c {
+                Call::SetRenderTargets(rtvs) => Some(rtvs[0]),
+                _ => None,
+            })
+            .collect::<Vec<CpuDescriptorHandle>>();
+        let rtv = |i| renderer.rtvs.get(i).unwrap().handle;
+        assert_eq!(targets, vec![rtv(0), rtv(1), rtv(0)]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Golden image comparisons for regression tests
+//!
+//! References are PNG files of the premultiplied back buffer. Setting
+//! `UPDATE_GOLDEN=1` writes the rendered images as the new references.
+use crate::decode::{self, DecodeError};
+use crate::encode;
+use crate::image::Image;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How far an image may drift from its reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Largest difference of a channel that is not counted
+    pub channel: u8,
+    /// Number of differing pixels allowed
+    pub max_pixels: usize,
+}
+
+impl Tolerance {
+    pub const EXACT: Tolerance = Tolerance {
+        channel: 0,
+        max_pixels: 0,
+    };
+}
+
+/// Result of comparing two images of the same size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff {
+    /// Pixels with a channel over the tolerance
+    pub differing: usize,
+    /// Largest channel difference of all pixels
+    pub max_delta: u8,
+    /// Dimmed reference, with differing pixels from yellow to red by size
+    pub heatmap: Image,
+}
+
+/// Compare pixel by pixel, `None` when the sizes differ
+pub fn diff(actual: &Image, expected: &Image, channel_tolerance: u8) -> Option<Diff> {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return None;
+    }
+    let mut heatmap = Image::new(expected.width, expected.height);
+    let mut differing = 0;
+    let mut max_delta = 0;
+    for (i, (a, e)) in actual
+        .data
+        .chunks_exact(4)
+        .zip(expected.data.chunks_exact(4))
+        .enumerate()
+    {
+        let delta = (0..4)
+            .map(|c| a[c].max(e[c]) - a[c].min(e[c]))
+            .max()
+            .unwrap();
+        max_delta = max_delta.max(delta);
+        let heat = if delta > channel_tolerance {
+            differing += 1;
+            [0, 255 - delta, 255, 255]
+        } else {
+            // Quarter brightness luma of the reference
+            let luma = (e[0] as u32 * 29 + e[1] as u32 * 150 + e[2] as u32 * 77) >> 10;
+            [luma as u8, luma as u8, luma as u8, 255]
+        };
+        heatmap.data[i * 4..i * 4 + 4].copy_from_slice(&heat);
+    }
+    Some(Diff {
+        differing,
+        max_delta,
+        heatmap,
+    })
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    /// No reference, run with `UPDATE_GOLDEN=1` to create it
+    Missing(PathBuf),
+    Io(PathBuf, io::Error),
+    Decode(PathBuf, DecodeError),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    TooManyDifferences {
+        differing: usize,
+        allowed: usize,
+        max_delta: u8,
+        /// Where the rendered image and the heatmap were written
+        actual: PathBuf,
+        heatmap: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Missing(path) => write!(
+                f,
+                "missing reference {}, run with UPDATE_GOLDEN=1 to create it",
+                path.display()
+            ),
+            GoldenError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            GoldenError::Decode(path, err) => write!(f, "{}: {}", path.display(), err),
+            GoldenError::SizeMismatch { expected, actual } => write!(
+                f,
+                "image is {}x{}, reference is {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            GoldenError::TooManyDifferences {
+                differing,
+                allowed,
+                max_delta,
+                actual,
+                heatmap,
+            } => write!(
+                f,
+                "{} pixels differ, {} allowed, largest difference {}, see {} and {}",
+                differing,
+                allowed,
+                max_delta,
+                actual.display(),
+                heatmap.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Reference directory and where failures are written
+#[derive(Debug, Clone)]
+pub struct Golden {
+    pub references: PathBuf,
+    pub failures: PathBuf,
+    /// Write the images as references instead of comparing
+    pub update: bool,
+}
+
+impl Golden {
+    /// Update mode comes from the `UPDATE_GOLDEN` environment variable
+    pub fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(references: P, failures: Q) -> Self {
+        Golden {
+            references: references.into(),
+            failures: failures.into(),
+            update: std::env::var_os("UPDATE_GOLDEN").is_some_and(|v| v != "0"),
+        }
+    }
+
+    /// Compare against `<references>/<name>.png`, on failure writes
+    /// `<name>.actual.png` and `<name>.diff.png` to the failure directory
+    pub fn check(
+        &self,
+        name: &str,
+        image: &Image,
+        tolerance: Tolerance,
+    ) -> Result<(), GoldenError> {
+        let reference = self.references.join(format!("{}.png", name));
+        if self.update {
+            return write(&reference, image);
+        }
+        let data = match std::fs::read(&reference) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(GoldenError::Missing(reference))
+            }
+            Err(err) => return Err(GoldenError::Io(reference, err)),
+        };
+        let expected = decode::png(&data).map_err(|err| GoldenError::Decode(reference, err))?;
+        let result =
+            diff(image, &expected, tolerance.channel).ok_or(GoldenError::SizeMismatch {
+                expected: (expected.width, expected.height),
+                actual: (image.width, image.height),
+            })?;
+        if result.differing <= tolerance.max_pixels {
+            return Ok(());
+        }
+
+        let actual = self.failures.join(format!("{}.actual.png", name));
+        let heatmap = self.failures.join(format!("{}.diff.png", name));
+        write(&actual, image)?;
+        write(&heatmap, &result.heatmap)?;
+        Err(GoldenError::TooManyDifferences {
+            differing: result.differing,
+            allowed: tolerance.max_pixels,
+            max_delta: result.max_delta,
+            actual,
+            heatmap,
+        })
+    }
+}
+
+fn write(path: &Path, image: &Image) -> Result<(), GoldenError> {
+    let io = |err| GoldenError::Io(path.to_path_buf(), err);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io)?;
+    }
+    std::fs::write(path, encode::png(image, false)).map_err(io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: &[[u8; 4]]) -> Image {
+        let mut image = Image::new(pixels.len() as u32, 1);
+        for (x, pixel) in pixels.iter().enumerate() {
+            image.set_pixel(x as u32, 0, *pixel);
+        }
+        image
+    }
+
+    #[test]
+    fn counts_pixels_over_tolerance() {
+        let expected = image(&[[0, 0, 0, 255], [100, 100, 100, 255], [9, 9, 9, 9]]);
+        let actual = image(&[[0, 0, 0, 255], [102, 100, 100, 255], [9, 9, 9, 109]]);
+        let result = diff(&actual, &expected, 2).unwrap();
+        assert_eq!(result.differing, 1);
+        assert_eq!(result.max_delta, 100);
+        assert_eq!(result.heatmap.pixel(2, 0), [0, 155, 255, 255]);
+        assert_eq!(result.heatmap.pixel(0, 0), [0, 0, 0, 255]);
+        assert!(diff(&actual, &Image::new(1, 1), 0).is_none());
+    }
+
+    #[test]
+    fn writes_failures() {
+        let dir = std::env::temp_dir().join(format!("golden-{}", std::process::id()));
+        let golden = Golden {
+            references: dir.join("references"),
+            failures: dir.join("failures"),
+            update: true,
+        };
+        let expected = image(&[[1, 2, 3, 4], [5, 6, 7, 8]]);
+        golden.check("case", &expected, Tolerance::EXACT).unwrap();
+
+        let golden = Golden {
+            update: false,
+            ..golden
+        };
+        golden.check("case", &expected, Tolerance::EXACT).unwrap();
+        let actual = image(&[[1, 2, 3, 4], [5, 6, 70, 8]]);
+        match golden.check("case", &actual, Tolerance::EXACT) {
+            Err(GoldenError::TooManyDifferences { differing: 1, .. }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(dir.join("failures/case.diff.png").exists());
+        assert!(golden
+            .check(
+                "case",
+                &actual,
+                Tolerance {
+                    channel: 63,
+                    max_pixels: 0
+                }
+            )
+            .is_ok());
+        assert!(matches!(
+            golden.check("other", &actual, Tolerance::EXACT),
+            Err(GoldenError::Missing(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! Rendering frames without a window and saving the last one to disk
+use crate::backend::Backend;
+use crate::encode::ImageFormat;
+use crate::frame::Renderer;
+use crate::image::Image;
+use std::io;
+use std::path::PathBuf;
+
+/// Options of `--headless <file.png|file.ppm> [--frames <n>] [--unpremultiply]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Headless {
+    pub output: PathBuf,
+    pub format: ImageFormat,
+    pub frames: u32,
+    pub unpremultiply: bool,
+}
+
+impl Headless {
+    /// Parse the command line arguments without the program name, `None`
+    /// without `--headless`
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>, String> {
+        let mut output = None;
+        let mut frames = 1;
+        let mut unpremultiply = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    output = Some(PathBuf::from(
+                        args.next().ok_or("--headless requires an output file")?,
+                    ))
+                }
+                "--frames" => {
+                    frames = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|&n| n > 0)
+                        .ok_or("--frames requires a positive number")?
+                }
+                "--unpremultiply" => unpremultiply = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+        let output = match output {
+            Some(output) => output,
+            None => return Ok(None),
+        };
+        let format = ImageFormat::from_path(&output)
+            .ok_or_else(|| format!("{} is not a .png or .ppm file", output.display()))?;
+        Ok(Some(Headless {
+            output,
+            format,
+            frames,
+            unpremultiply,
+        }))
+    }
+
+    /// Render the frames and save the last back buffer
+    pub fn run<B: Backend>(&self, renderer: &mut Renderer<B>) -> Result<Image, B::Error> {
+        render_frames(renderer, self.frames)
+    }
+
+    pub fn save(&self, image: &Image) -> io::Result<()> {
+        std::fs::write(&self.output, self.format.encode(image, self.unpremultiply))
+    }
+}
+
+/// Render `frames` frames and read back the last presented back buffer
+pub fn render_frames<B: Backend>(
+    renderer: &mut Renderer<B>,
+    frames: u32,
+) -> Result<Image, B::Error> {
+    let mut last = renderer.backend().current_back_buffer_index();
+    for _ in 0..frames {
+        last = renderer.backend().current_back_buffer_index();
+        renderer.render()?;
+    }
+    renderer.wait_for_gpu()?;
+    renderer.backend_mut().read_back_buffer(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Call, MockBackend};
+    use crate::scene::Scene;
+
+    fn args(args: &[&str]) -> Result<Option<Headless>, String> {
+        Headless::from_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert_eq!(args(&[]), Ok(None));
+        assert_eq!(
+            args(&["--headless", "out.ppm", "--frames", "3", "--unpremultiply"]),
+            Ok(Some(Headless {
+                output: "out.ppm".into(),
+                format: ImageFormat::Ppm,
+                frames: 3,
+                unpremultiply: true,
+            }))
+        );
+        assert!(args(&["--headless"]).is_err());
+        assert!(args(&["--headless", "out.jpg"]).is_err());
+        assert!(args(&["--headless", "out.png", "--frames", "0"]).is_err());
+    }
+
+    #[test]
+    fn reads_last_presented_buffer() {
+        let mut renderer =
+            Renderer::new(MockBackend::default(), Scene::hello_triangle(4, 2)).unwrap();
+        let image = render_frames(&mut renderer, 3).unwrap();
+        assert_eq!((image.width, image.height), (4, 2));
+        let calls = &renderer.backend().calls;
+        assert_eq!(calls.last(), Some(&Call::ReadBackBuffer(0)));
+        let frames = calls
+            .iter()
+            .filter(|call| matches!(call, Call::Present { .. }))
+            .count();
+        assert_eq!(frames, 3);
+    }
+}
@@ -0,0 +1,99 @@
+//! CPU images in the back buffer format
+use crate::format::Format;
+
+/// `DXGI_FORMAT_B8G8R8A8_UNORM` image, rows are tightly packed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub const FORMAT: Format = Format::B8G8R8A8Unorm;
+
+    /// Image filled with zeros
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    /// Pixel as `[b, g, r, a]`
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.offset(x, y);
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, bgra: [u8; 4]) {
+        let i = self.offset(x, y);
+        self.data[i..i + 4].copy_from_slice(&bgra);
+    }
+
+    /// Pixel as normalized `[r, g, b, a]`
+    pub fn color(&self, x: u32, y: u32) -> [f32; 4] {
+        let [b, g, r, a] = self.pixel(x, y);
+        [unorm(r), unorm(g), unorm(b), unorm(a)]
+    }
+
+    /// Store normalized `[r, g, b, a]`, values are saturated
+    pub fn set_color(&mut self, x: u32, y: u32, rgba: [f32; 4]) {
+        let [r, g, b, a] = rgba;
+        self.set_pixel(x, y, [to_unorm(b), to_unorm(g), to_unorm(r), to_unorm(a)]);
+    }
+
+    /// Fill the whole image with normalized `[r, g, b, a]`
+    pub fn fill(&mut self, rgba: [f32; 4]) {
+        let [r, g, b, a] = rgba;
+        let bgra = [to_unorm(b), to_unorm(g), to_unorm(r), to_unorm(a)];
+        for px in self.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&bgra);
+        }
+    }
+}
+
+/// UNORM8 to float
+pub fn unorm(v: u8) -> f32 {
+    v as f32 / 255.0
+}
+
+/// Float to UNORM8, saturates and rounds to nearest like D3D
+pub fn to_unorm(v: f32) -> u8 {
+    let v = if v.is_nan() { 0.0 } else { v.clamp(0.0, 1.0) };
+    (v * 255.0 + 0.5) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_bgra() {
+        let mut image = Image::new(2, 2);
+        image.set_color(1, 0, [1.0, 0.2, 0.4, 0.5]);
+        assert_eq!(image.pixel(1, 0), [102, 51, 255, 128]);
+        assert_eq!(&image.data[4..8], &[102, 51, 255, 128]);
+        assert_eq!(image.pixel(0, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn unorm_conversion() {
+        assert_eq!(to_unorm(-1.0), 0);
+        assert_eq!(to_unorm(2.0), 255);
+        assert_eq!(to_unorm(f32::NAN), 0);
+        assert_eq!(to_unorm(0.5), 128);
+        assert_eq!(to_unorm(unorm(77)), 77);
+    }
+}
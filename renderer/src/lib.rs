@@ -0,0 +1,35 @@
+//! Platform-neutral parts of the HelloTriangle renderer
+//!
+//! Everything here is plain Rust data and logic, the `winapi-rs` and
+//! `windows-rs` binaries translate it to their own binding types. This way the
+//! frame flow can be type-checked and tested without Windows or a GPU.
+//!
+// Lets the derive macros refer to `::renderer` inside the crate too
+extern crate self as renderer;
+
+pub mod backend;
+pub mod decode;
+pub mod descriptor;
+pub mod encode;
+pub mod format;
+pub mod frame;
+pub mod golden;
+pub mod headless;
+pub mod image;
+pub mod memory;
+pub mod mock;
+pub mod pacing;
+pub mod pipeline;
+pub mod raster;
+pub mod resource;
+pub mod root_signature;
+pub mod scene;
+pub mod shader;
+pub mod software;
+pub mod state;
+pub mod timeline;
+pub mod upload;
+pub mod vertex;
+
+/// Number of back buffers in the swap chain
+pub const NUM_OF_FRAMES: usize = 2;
@@ -0,0 +1,605 @@
+//! Placed resources sub-allocated from large heaps
+//!
+//! Each heap is split with a buddy allocator. Blocks are powers of two and
+//! aligned to their size, so the 64KB and 4MB placement alignments come for
+//! free once the request is rounded up to them. Heaps are created through a
+//! callback, which keeps the algorithm testable without a device.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// `D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT`
+pub const DEFAULT_PLACEMENT_ALIGNMENT: u64 = 64 * 1024;
+
+/// `D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT`
+pub const MSAA_PLACEMENT_ALIGNMENT: u64 = 4 * 1024 * 1024;
+
+/// Size of the heaps shared by placed resources
+pub const DEFAULT_HEAP_SIZE: u64 = 64 * 1024 * 1024;
+
+/// `D3D12_HEAP_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum HeapType {
+    Default = 1,
+    Upload = 2,
+    Readback = 3,
+}
+
+/// Resources a heap may hold, heap tier 1 hardware can't mix them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ResourceKind {
+    /// `D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS`
+    Buffer = 0xc0,
+    /// `D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES`
+    Texture = 0x44,
+    /// `D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES`
+    RenderTarget = 0x84,
+}
+
+/// `D3D12_HEAP_DESC`, `kind` gives the heap flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeapDesc {
+    pub size: u64,
+    pub heap_type: HeapType,
+    pub alignment: u64,
+    pub kind: ResourceKind,
+}
+
+/// `D3D12_RESOURCE_ALLOCATION_INFO` of `GetResourceAllocationInfo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocationInfo {
+    pub size: u64,
+    pub alignment: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    /// Freed twice or never allocated
+    NotAllocated { heap: usize, offset: u64 },
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::NotAllocated { heap, offset } => {
+                write!(f, "no allocation at offset {} of heap {}", offset, heap)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+/// Binary buddy allocator of offsets
+#[derive(Debug, Clone)]
+pub struct BuddyAllocator {
+    min_block: u64,
+    max_order: u32,
+    /// Free block offsets per order, block size is `min_block << order`
+    free: Vec<BTreeSet<u64>>,
+    /// Allocated blocks as `offset -> (order, requested size)`
+    allocated: BTreeMap<u64, (u32, u64)>,
+}
+
+impl BuddyAllocator {
+    /// `size` and `min_block` must be powers of two
+    pub fn new(size: u64, min_block: u64) -> Self {
+        assert!(size.is_power_of_two() && min_block.is_power_of_two());
+        assert!(size >= min_block);
+        let max_order = (size / min_block).trailing_zeros();
+        let mut free = vec![BTreeSet::new(); max_order as usize + 1];
+        free[max_order as usize].insert(0);
+        BuddyAllocator {
+            min_block,
+            max_order,
+            free,
+            allocated: BTreeMap::new(),
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.min_block << self.max_order
+    }
+
+    fn order(&self, size: u64, alignment: u64) -> Option<u32> {
+        let block = size
+            .max(alignment)
+            .max(self.min_block)
+            .checked_next_power_of_two()?;
+        let order = (block / self.min_block).trailing_zeros();
+        if order > self.max_order {
+            None
+        } else {
+            Some(order)
+        }
+    }
+
+    /// Offset of the lowest free block that fits, aligned to the block size
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let order = self.order(size, alignment)?;
+        let found = (order..=self.max_order).find(|&o| !self.free[o as usize].is_empty())?;
+        let offset = *self.free[found as usize].iter().next()?;
+        self.free[found as usize].remove(&offset);
+
+        // Split down, keeping the lower half
+        for o in (order..found).rev() {
+            self.free[o as usize].insert(offset + (self.min_block << o));
+        }
+        self.allocated.insert(offset, (order, size));
+        Some(offset)
+    }
+
+    /// Free the block and merge it with its free buddies
+    pub fn free(&mut self, offset: u64) -> bool {
+        let (mut order, _) = match self.allocated.remove(&offset) {
+            Some(block) => block,
+            None => return false,
+        };
+        let mut offset = offset;
+        while order < self.max_order {
+            let buddy = offset ^ (self.min_block << order);
+            if !self.free[order as usize].remove(&buddy) {
+                break;
+            }
+            offset = offset.min(buddy);
+            order += 1;
+        }
+        self.free[order as usize].insert(offset);
+        true
+    }
+
+    /// Allocated blocks as `(offset, requested size)`
+    pub fn allocations(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.allocated
+            .iter()
+            .map(|(&offset, &(_, size))| (offset, size))
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            reserved: self.size(),
+            ..HeapStats::default()
+        };
+        for &(order, size) in self.allocated.values() {
+            stats.allocations += 1;
+            stats.requested += size;
+            stats.used += self.min_block << order;
+        }
+        for (order, blocks) in self.free.iter().enumerate() {
+            stats.free_blocks += blocks.len();
+            if !blocks.is_empty() {
+                stats.largest_free = self.min_block << order;
+            }
+        }
+        stats
+    }
+}
+
+/// Usage of one heap or all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    pub heaps: usize,
+    /// Bytes of the heaps
+    pub reserved: u64,
+    /// Bytes of the blocks handed out, including rounding
+    pub used: u64,
+    /// Bytes the resources asked for
+    pub requested: u64,
+    pub allocations: usize,
+    pub free_blocks: usize,
+    pub largest_free: u64,
+}
+
+impl HeapStats {
+    /// Share of the free bytes outside the largest free block, zero when
+    /// all free memory is contiguous
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.reserved - self.used;
+        if free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free as f32 / free as f32
+        }
+    }
+
+    fn add(&mut self, other: &HeapStats) {
+        self.heaps += other.heaps;
+        self.reserved += other.reserved;
+        self.used += other.used;
+        self.requested += other.requested;
+        self.allocations += other.allocations;
+        self.free_blocks += other.free_blocks;
+        self.largest_free = self.largest_free.max(other.largest_free);
+    }
+}
+
+/// Placed resource location, `CreatePlacedResource` arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Allocation {
+    pub heap: usize,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Heap created through the callback of `HeapAllocator::allocate`
+#[derive(Debug)]
+pub struct Heap<H> {
+    pub heap: H,
+    pub desc: HeapDesc,
+    blocks: BuddyAllocator,
+    /// Holds a single resource bigger than the shared heaps
+    dedicated: bool,
+}
+
+/// Suggested moves that would let a sparsely used heap be released
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefragHint {
+    pub heap: usize,
+    /// Allocations of the heap and where they would fit instead
+    pub moves: Vec<(Allocation, Allocation)>,
+}
+
+/// Heaps per heap type and resource kind, split with buddy allocators
+#[derive(Debug)]
+pub struct HeapAllocator<H> {
+    heap_size: u64,
+    /// Released heaps leave `None` so indices stay valid
+    heaps: Vec<Option<Heap<H>>>,
+}
+
+impl<H> HeapAllocator<H> {
+    /// `heap_size` must be a power of two of at least 64KB
+    pub fn new(heap_size: u64) -> Self {
+        assert!(heap_size.is_power_of_two() && heap_size >= DEFAULT_PLACEMENT_ALIGNMENT);
+        HeapAllocator {
+            heap_size,
+            heaps: Vec::new(),
+        }
+    }
+
+    pub fn heap(&self, index: usize) -> Option<&Heap<H>> {
+        self.heaps.get(index).and_then(Option::as_ref)
+    }
+
+    /// Place a resource, creating a heap when none of the type has room
+    ///
+    /// Resources bigger than the shared heaps get a heap of their own.
+    pub fn allocate<E>(
+        &mut self,
+        heap_type: HeapType,
+        kind: ResourceKind,
+        info: &AllocationInfo,
+        create_heap: impl FnOnce(&HeapDesc) -> Result<H, E>,
+    ) -> Result<Allocation, E> {
+        let alignment = info.alignment.max(DEFAULT_PLACEMENT_ALIGNMENT);
+        let fits = |heap: &Heap<H>| {
+            !heap.dedicated && heap.desc.heap_type == heap_type && heap.desc.kind == kind
+        };
+        for (index, heap) in self.heaps.iter_mut().enumerate() {
+            let heap = match heap {
+                Some(heap) if fits(heap) => heap,
+                _ => continue,
+            };
+            if let Some(offset) = heap.blocks.allocate(info.size, alignment) {
+                return Ok(Allocation {
+                    heap: index,
+                    offset,
+                    size: info.size,
+                });
+            }
+        }
+
+        let dedicated = info.size.max(alignment) > self.heap_size;
+        let desc = HeapDesc {
+            size: if dedicated {
+                align(info.size, alignment)
+            } else {
+                self.heap_size
+            },
+            heap_type,
+            alignment: alignment.max(MSAA_PLACEMENT_ALIGNMENT),
+            kind,
+        };
+        let mut blocks =
+            BuddyAllocator::new(desc.size.next_power_of_two(), DEFAULT_PLACEMENT_ALIGNMENT);
+        let offset = blocks
+            .allocate(info.size, alignment)
+            .expect("New heap fits the resource");
+        let heap = Heap {
+            heap: create_heap(&desc)?,
+            desc,
+            blocks,
+            dedicated,
+        };
+        let index = match self.heaps.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                self.heaps.push(None);
+                self.heaps.len() - 1
+            }
+        };
+        self.heaps[index] = Some(heap);
+        Ok(Allocation {
+            heap: index,
+            offset,
+            size: info.size,
+        })
+    }
+
+    pub fn free(&mut self, allocation: Allocation) -> Result<(), MemoryError> {
+        let freed = match self.heaps.get_mut(allocation.heap) {
+            Some(Some(heap)) => heap.blocks.free(allocation.offset),
+            _ => false,
+        };
+        if freed {
+            Ok(())
+        } else {
+            Err(MemoryError::NotAllocated {
+                heap: allocation.heap,
+                offset: allocation.offset,
+            })
+        }
+    }
+
+    /// Remove the heaps without allocations, returning them for release
+    pub fn release_empty(&mut self) -> Vec<H> {
+        let mut released = Vec::new();
+        for slot in &mut self.heaps {
+            if slot
+                .as_ref()
+                .is_some_and(|heap| heap.blocks.allocated.is_empty())
+            {
+                released.extend(slot.take().map(|heap| heap.heap));
+            }
+        }
+        released
+    }
+
+    pub fn heap_stats(&self, index: usize) -> Option<HeapStats> {
+        self.heap(index).map(|heap| {
+            let blocks = heap.blocks.stats();
+            // Dedicated heaps are smaller than their power of two block
+            HeapStats {
+                heaps: 1,
+                reserved: heap.desc.size,
+                used: blocks.used.min(heap.desc.size),
+                ..blocks
+            }
+        })
+    }
+
+    /// Totals over all heaps
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats::default();
+        for index in 0..self.heaps.len() {
+            if let Some(heap) = self.heap_stats(index) {
+                stats.add(&heap);
+            }
+        }
+        stats
+    }
+
+    /// Heaps used less than `max_usage` whose allocations fit in the other
+    /// heaps of the same type, emptiest first
+    pub fn defrag_hints(&self, max_usage: f32) -> Vec<DefragHint> {
+        let mut candidates = (0..self.heaps.len())
+            .filter_map(|i| Some((i, self.heap(i)?)))
+            .filter(|(_, heap)| !heap.dedicated && !heap.blocks.allocated.is_empty())
+            .map(|(i, heap)| (i, heap.blocks.stats().used))
+            .filter(|&(_, used)| (used as f32) < max_usage * self.heap_size as f32)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&(i, used)| (used, i));
+
+        // Trial allocations go to copies of the target heaps
+        let mut trial = self
+            .heaps
+            .iter()
+            .map(|heap| heap.as_ref().map(|heap| heap.blocks.clone()))
+            .collect::<Vec<_>>();
+        let mut released = Vec::new();
+        let mut hints = Vec::new();
+        for (source, _) in candidates {
+            let heap = self.heap(source).expect("Candidate heap exists");
+            released.push(source);
+            let targets = (0..self.heaps.len())
+                .filter(|i| !released.contains(i))
+                .filter(|&i| {
+                    self.heap(i).is_some_and(|other| {
+                        !other.dedicated
+                            && other.desc.heap_type == heap.desc.heap_type
+                            && other.desc.kind == heap.desc.kind
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut attempt = trial.clone();
+            let mut moves = Vec::new();
+            for (offset, size) in heap.blocks.allocations() {
+                let moved = targets.iter().find_map(|&target| {
+                    let blocks = attempt[target].as_mut()?;
+                    let to = blocks.allocate(size, DEFAULT_PLACEMENT_ALIGNMENT)?;
+                    Some(Allocation {
+                        heap: target,
+                        offset: to,
+                        size,
+                    })
+                });
+                match moved {
+                    Some(to) => moves.push((
+                        Allocation {
+                            heap: source,
+                            offset,
+                            size,
+                        },
+                        to,
+                    )),
+                    None => break,
+                }
+            }
+            if moves.len() == heap.blocks.allocated.len() {
+                trial = attempt;
+                hints.push(DefragHint {
+                    heap: source,
+                    moves,
+                });
+            } else {
+                released.pop();
+            }
+        }
+        hints
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+
+    fn info(size: u64, alignment: u64) -> AllocationInfo {
+        AllocationInfo { size, alignment }
+    }
+
+    /// Heaps are numbered in creation order
+    fn allocate(
+        allocator: &mut HeapAllocator<usize>,
+        heap_type: HeapType,
+        info: AllocationInfo,
+    ) -> Allocation {
+        let next = allocator.heaps.len();
+        allocator
+            .allocate(heap_type, ResourceKind::Buffer, &info, |_| {
+                Ok::<_, ()>(next)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn buddy_splits_and_merges() {
+        let mut buddy = BuddyAllocator::new(MB, 64 * KB);
+        assert_eq!(buddy.allocate(64 * KB, 0), Some(0));
+        assert_eq!(buddy.allocate(100 * KB, 0), Some(128 * KB));
+        assert_eq!(buddy.allocate(1, 0), Some(64 * KB));
+        assert_eq!(buddy.allocate(MB, 0), None);
+
+        let stats = buddy.stats();
+        assert_eq!(stats.used, 256 * KB);
+        assert_eq!(stats.requested, 164 * KB + 1);
+        assert_eq!(stats.largest_free, 512 * KB);
+        assert_eq!(stats.free_blocks, 2);
+
+        assert!(buddy.free(64 * KB));
+        assert!(!buddy.free(64 * KB));
+        assert!(buddy.free(0));
+        assert!(buddy.free(128 * KB));
+        assert_eq!(buddy.stats().free_blocks, 1);
+        assert_eq!(buddy.allocate(MB, 0), Some(0));
+    }
+
+    #[test]
+    fn honors_placement_alignment() {
+        let mut allocator = HeapAllocator::new(16 * MB);
+        let small = allocate(&mut allocator, HeapType::Default, info(4 * KB, 4 * KB));
+        let buffer = allocate(&mut allocator, HeapType::Default, info(200 * KB, 64 * KB));
+        let msaa = allocate(&mut allocator, HeapType::Default, info(MB, 4 * MB));
+        assert_eq!(small.offset, 0);
+        assert_eq!(buffer.offset % DEFAULT_PLACEMENT_ALIGNMENT, 0);
+        assert_ne!(buffer.offset, 0);
+        assert_eq!(msaa.offset % MSAA_PLACEMENT_ALIGNMENT, 0);
+        assert_eq!(msaa.offset, 4 * MB);
+        assert_eq!(
+            allocator.heap(0).unwrap().desc.alignment,
+            MSAA_PLACEMENT_ALIGNMENT
+        );
+    }
+
+    #[test]
+    fn creates_heaps_per_type_and_when_full() {
+        let mut allocator = HeapAllocator::new(MB);
+        let upload = allocate(&mut allocator, HeapType::Upload, info(512 * KB, 0));
+        let default = allocate(&mut allocator, HeapType::Default, info(512 * KB, 0));
+        let second = allocate(&mut allocator, HeapType::Upload, info(512 * KB, 0));
+        let third = allocate(&mut allocator, HeapType::Upload, info(512 * KB, 0));
+        assert_eq!((upload.heap, default.heap, second.heap), (0, 1, 0));
+        assert_eq!(third.heap, 2);
+        assert_eq!(allocator.heap(2).unwrap().desc.heap_type, HeapType::Upload);
+
+        // Bigger than the shared heaps
+        let big = allocate(&mut allocator, HeapType::Upload, info(3 * MB, 0));
+        let desc = allocator.heap(big.heap).unwrap().desc;
+        assert_eq!((big.heap, big.offset, desc.size), (3, 0, 3 * MB));
+        let stats = allocator.heap_stats(3).unwrap();
+        assert_eq!((stats.used, stats.fragmentation()), (3 * MB, 0.0));
+        let next = allocate(&mut allocator, HeapType::Upload, info(64 * KB, 0));
+        assert_eq!(next.heap, 2);
+
+        // Heap creation errors are passed through
+        let failed = allocator.allocate(
+            HeapType::Readback,
+            ResourceKind::Buffer,
+            &info(KB, 0),
+            |_| Err("out of memory"),
+        );
+        assert_eq!(failed, Err("out of memory"));
+        assert!(allocator.heap(4).is_none());
+    }
+
+    #[test]
+    fn frees_and_releases_empty_heaps() {
+        let mut allocator = HeapAllocator::new(MB);
+        let a = allocate(&mut allocator, HeapType::Upload, info(MB, 0));
+        let b = allocate(&mut allocator, HeapType::Upload, info(100 * KB, 0));
+        let stats = allocator.stats();
+        assert_eq!((stats.heaps, stats.reserved), (2, 2 * MB));
+        assert_eq!(
+            (stats.used, stats.requested),
+            (MB + 128 * KB, MB + 100 * KB)
+        );
+        assert_eq!(stats.allocations, 2);
+
+        allocator.free(a).unwrap();
+        assert_eq!(
+            allocator.free(a),
+            Err(MemoryError::NotAllocated { heap: 0, offset: 0 })
+        );
+        assert_eq!(allocator.release_empty(), vec![0]);
+        assert_eq!(allocator.stats().heaps, 1);
+
+        // Released slot is reused
+        let c = allocate(&mut allocator, HeapType::Default, info(KB, 0));
+        assert_eq!(c.heap, 0);
+        allocator.free(b).unwrap();
+    }
+
+    #[test]
+    fn hints_moves_that_release_heaps() {
+        let mut allocator = HeapAllocator::new(MB);
+        let blocks = (0..32)
+            .map(|_| allocate(&mut allocator, HeapType::Upload, info(64 * KB, 0)))
+            .collect::<Vec<_>>();
+        assert_eq!(allocator.stats().heaps, 2);
+
+        // Every other block freed leaves both heaps half used and fragmented
+        for block in blocks.iter().step_by(2) {
+            allocator.free(*block).unwrap();
+        }
+        let stats = allocator.heap_stats(0).unwrap();
+        assert_eq!(stats.used, 512 * KB);
+        assert!((stats.fragmentation() - 0.875).abs() < 1e-6);
+        assert!(allocator.defrag_hints(0.5).is_empty());
+
+        let hints = allocator.defrag_hints(0.6);
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].heap, 0);
+        assert_eq!(hints[0].moves.len(), 8);
+        assert!(hints[0]
+            .moves
+            .iter()
+            .all(|(from, to)| from.heap == 0 && to.heap == 1 && to.offset % (128 * KB) == 0));
+    }
+}
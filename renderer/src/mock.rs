@@ -0,0 +1,431 @@
+//! In-memory backend recording every call, for testing the frame flow
+use crate::backend::{Backend, CommandQueueDesc, SwapChainDesc, VertexBufferView};
+use crate::descriptor::{CpuDescriptorHandle, DescriptorHeap, DescriptorHeapDesc};
+use crate::image::Image;
+use crate::pacing::SimulatedGpu;
+use crate::pipeline::PrimitiveTopology;
+use crate::resource::{Barrier, ResourceId};
+use crate::scene::{Rect, Viewport};
+
+/// Recorded `Backend` call
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    CreateCommandQueue(CommandQueueDesc),
+    CreateSwapChain(SwapChainDesc),
+    CreateDescriptorHeap(DescriptorHeapDesc),
+    CreateRenderTargetView {
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    },
+    CreateCommandAllocators(usize),
+    CreateCommandList,
+    CreateVertexBuffer {
+        data: Vec<u8>,
+        stride: u32,
+    },
+    CreateUploadBuffer(u64),
+    ResetCommandList(usize),
+    SetGraphicsRootSignature,
+    SetViewports(Vec<Viewport>),
+    SetScissorRects(Vec<Rect>),
+    ResourceBarrier(Vec<Barrier>),
+    SetRenderTargets(Vec<CpuDescriptorHandle>),
+    ClearRenderTargetView {
+        rtv: CpuDescriptorHandle,
+        color: [f32; 4],
+    },
+    SetPrimitiveTopology(PrimitiveTopology),
+    SetVertexBuffers {
+        start_slot: u32,
+        views: Vec<VertexBufferView>,
+    },
+    DrawInstanced {
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    },
+    CloseCommandList,
+    ExecuteCommandList,
+    Signal(u64),
+    WaitForValue(u64),
+    Present {
+        sync_interval: u32,
+        flags: u32,
+    },
+    ReadBackBuffer(usize),
+}
+
+/// Misuse the D3D12 runtime would also reject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockError {
+    NoCommandQueue,
+    NoSwapChain,
+    NoCommandList,
+    NoCommandAllocator,
+    /// Reset while the GPU may still execute commands from the allocator
+    AllocatorInUse(usize),
+    /// Reset while recording, or executed before closing
+    ListOpen,
+    /// Closed twice
+    ListClosed,
+}
+
+/// Records calls instead of talking to a GPU
+///
+/// Descriptor handles and GPU addresses are made up but unique.
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    pub calls: Vec<Call>,
+    /// Value returned by `current_back_buffer_index`, advanced by `present`
+    pub back_buffer: usize,
+    /// Completed value of the fence
+    pub completed: u64,
+    /// Whether signaled values complete at once, otherwise only waiting
+    /// completes them
+    pub complete_immediately: bool,
+    /// Queue timing, replaces `completed` and `complete_immediately`
+    pub gpu: Option<SimulatedGpu>,
+    queue: bool,
+    /// Fence value guarding each allocator
+    allocators: Vec<u64>,
+    allocator: usize,
+    /// Allocators executed since the last signal
+    unsignaled: Vec<usize>,
+    buffer_count: usize,
+    size: (u32, u32),
+    list: Option<bool>,
+    next_address: u64,
+    /// Upload buffers as `(address, data)`
+    uploads: Vec<(u64, Vec<u8>)>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        MockBackend {
+            calls: Vec::new(),
+            back_buffer: 0,
+            completed: 0,
+            complete_immediately: true,
+            gpu: None,
+            queue: false,
+            allocators: Vec::new(),
+            allocator: 0,
+            unsignaled: Vec::new(),
+            buffer_count: 0,
+            size: (0, 0),
+            list: None,
+            next_address: 0x1000,
+            uploads: Vec::new(),
+        }
+    }
+}
+
+impl MockBackend {
+    /// Whether the command list is open for recording
+    pub fn is_recording(&self) -> bool {
+        self.list == Some(true)
+    }
+
+    fn alloc_address(&mut self, size: u64) -> u64 {
+        let address = self.next_address;
+        self.next_address += (size + 0xffff) & !0xffff;
+        address
+    }
+}
+
+/// Slice of the buffer containing `address`, shared with the software backend
+pub(crate) fn upload_memory(buffers: &mut [(u64, Vec<u8>)], address: u64) -> Option<&mut [u8]> {
+    buffers
+        .iter_mut()
+        .find(|(start, data)| address >= *start && address < *start + data.len() as u64)
+        .map(|(start, data)| &mut data[(address - *start) as usize..])
+}
+
+impl Backend for MockBackend {
+    type Error = MockError;
+
+    fn create_command_queue(&mut self, desc: &CommandQueueDesc) -> Result<(), Self::Error> {
+        self.calls.push(Call::CreateCommandQueue(*desc));
+        self.queue = true;
+        Ok(())
+    }
+
+    fn create_swap_chain(&mut self, desc: &SwapChainDesc) -> Result<(), Self::Error> {
+        self.calls.push(Call::CreateSwapChain(*desc));
+        if !self.queue {
+            return Err(MockError::NoCommandQueue);
+        }
+        self.buffer_count = desc.buffer_count as _;
+        self.size = (desc.width, desc.height);
+        Ok(())
+    }
+
+    fn create_descriptor_heap(
+        &mut self,
+        desc: &DescriptorHeapDesc,
+    ) -> Result<DescriptorHeap, Self::Error> {
+        self.calls.push(Call::CreateDescriptorHeap(*desc));
+        let increment = 32;
+        let start = self.alloc_address(increment * desc.num_descriptors as u64);
+        Ok(DescriptorHeap {
+            desc: *desc,
+            start: CpuDescriptorHandle(start as _),
+            increment: increment as _,
+        })
+    }
+
+    fn create_render_target_view(
+        &mut self,
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    ) -> Result<(), Self::Error> {
+        self.calls
+            .push(Call::CreateRenderTargetView { resource, handle });
+        Ok(())
+    }
+
+    fn create_command_allocators(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.calls.push(Call::CreateCommandAllocators(count));
+        self.allocators = vec![0; count];
+        Ok(())
+    }
+
+    fn create_command_list(&mut self) -> Result<(), Self::Error> {
+        self.calls.push(Call::CreateCommandList);
+        if self.allocators.is_empty() {
+            return Err(MockError::NoCommandAllocator);
+        }
+        self.list = Some(false);
+        Ok(())
+    }
+
+    fn create_vertex_buffer(
+        &mut self,
+        data: &[u8],
+        stride: u32,
+    ) -> Result<VertexBufferView, Self::Error> {
+        self.calls.push(Call::CreateVertexBuffer {
+            data: data.to_vec(),
+            stride,
+        });
+        Ok(VertexBufferView {
+            buffer_location: self.alloc_address(data.len() as _),
+            size_in_bytes: data.len() as _,
+            stride_in_bytes: stride,
+        })
+    }
+
+    fn create_upload_buffer(&mut self, size: u64) -> Result<u64, Self::Error> {
+        self.calls.push(Call::CreateUploadBuffer(size));
+        let address = self.alloc_address(size);
+        self.uploads.push((address, vec![0; size as usize]));
+        Ok(address)
+    }
+
+    fn upload_memory(&mut self, address: u64) -> Option<&mut [u8]> {
+        upload_memory(&mut self.uploads, address)
+    }
+
+    fn reset_command_list(&mut self, allocator: usize) -> Result<(), Self::Error> {
+        self.calls.push(Call::ResetCommandList(allocator));
+        match self.list {
+            None => return Err(MockError::NoCommandList),
+            Some(true) => return Err(MockError::ListOpen),
+            Some(false) => {}
+        }
+        let guard = *self
+            .allocators
+            .get(allocator)
+            .ok_or(MockError::NoCommandAllocator)?;
+        if self.unsignaled.contains(&allocator) || guard > self.completed_value() {
+            return Err(MockError::AllocatorInUse(allocator));
+        }
+        self.allocator = allocator;
+        self.list = Some(true);
+        Ok(())
+    }
+
+    fn set_graphics_root_signature(&mut self) {
+        self.calls.push(Call::SetGraphicsRootSignature);
+    }
+
+    fn set_viewports(&mut self, viewports: &[Viewport]) {
+        self.calls.push(Call::SetViewports(viewports.to_vec()));
+    }
+
+    fn set_scissor_rects(&mut self, rects: &[Rect]) {
+        self.calls.push(Call::SetScissorRects(rects.to_vec()));
+    }
+
+    fn resource_barrier(&mut self, barriers: &[Barrier]) {
+        self.calls.push(Call::ResourceBarrier(barriers.to_vec()));
+    }
+
+    fn set_render_targets(&mut self, rtvs: &[CpuDescriptorHandle]) {
+        self.calls.push(Call::SetRenderTargets(rtvs.to_vec()));
+    }
+
+    fn clear_render_target_view(&mut self, rtv: CpuDescriptorHandle, color: [f32; 4]) {
+        self.calls.push(Call::ClearRenderTargetView { rtv, color });
+    }
+
+    fn set_primitive_topology(&mut self, topology: PrimitiveTopology) {
+        self.calls.push(Call::SetPrimitiveTopology(topology));
+    }
+
+    fn set_vertex_buffers(&mut self, start_slot: u32, views: &[VertexBufferView]) {
+        self.calls.push(Call::SetVertexBuffers {
+            start_slot,
+            views: views.to_vec(),
+        });
+    }
+
+    fn draw_instanced(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) {
+        self.calls.push(Call::DrawInstanced {
+            vertex_count,
+            instance_count,
+            start_vertex,
+            start_instance,
+        });
+    }
+
+    fn close_command_list(&mut self) -> Result<(), Self::Error> {
+        self.calls.push(Call::CloseCommandList);
+        match self.list {
+            None => Err(MockError::NoCommandList),
+            Some(false) => Err(MockError::ListClosed),
+            Some(true) => {
+                self.list = Some(false);
+                Ok(())
+            }
+        }
+    }
+
+    fn execute_command_list(&mut self) -> Result<(), Self::Error> {
+        self.calls.push(Call::ExecuteCommandList);
+        match self.list {
+            None => Err(MockError::NoCommandList),
+            Some(true) => Err(MockError::ListOpen),
+            Some(false) => {
+                self.unsignaled.push(self.allocator);
+                if let Some(gpu) = self.gpu.as_mut() {
+                    gpu.execute();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn signal(&mut self, value: u64) -> Result<(), Self::Error> {
+        self.calls.push(Call::Signal(value));
+        for allocator in self.unsignaled.drain(..) {
+            self.allocators[allocator] = value;
+        }
+        match self.gpu.as_mut() {
+            Some(gpu) => gpu.signal(value),
+            None if self.complete_immediately => self.completed = self.completed.max(value),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn completed_value(&self) -> u64 {
+        match &self.gpu {
+            Some(gpu) => gpu.completed_value(),
+            None => self.completed,
+        }
+    }
+
+    fn wait_for_value(&mut self, value: u64) -> Result<(), Self::Error> {
+        self.calls.push(Call::WaitForValue(value));
+        match self.gpu.as_mut() {
+            Some(gpu) => gpu.wait_for_value(value),
+            None => self.completed = self.completed.max(value),
+        }
+        Ok(())
+    }
+
+    fn current_back_buffer_index(&self) -> usize {
+        self.back_buffer
+    }
+
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<(), Self::Error> {
+        self.calls.push(Call::Present {
+            sync_interval,
+            flags,
+        });
+        if self.buffer_count == 0 {
+            return Err(MockError::NoSwapChain);
+        }
+        self.back_buffer = (self.back_buffer + 1) % self.buffer_count;
+        Ok(())
+    }
+
+    /// Back buffers are always blank
+    fn read_back_buffer(&mut self, index: usize) -> Result<Image, Self::Error> {
+        self.calls.push(Call::ReadBackBuffer(index));
+        if index >= self.buffer_count {
+            return Err(MockError::NoSwapChain);
+        }
+        Ok(Image::new(self.size.0, self.size.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_list_misuse() {
+        let mut mock = MockBackend::default();
+        assert_eq!(mock.reset_command_list(0), Err(MockError::NoCommandList));
+        assert_eq!(
+            mock.create_command_list(),
+            Err(MockError::NoCommandAllocator)
+        );
+        mock.create_command_allocators(2).unwrap();
+        mock.create_command_list().unwrap();
+        assert_eq!(mock.close_command_list(), Err(MockError::ListClosed));
+        assert_eq!(
+            mock.reset_command_list(2),
+            Err(MockError::NoCommandAllocator)
+        );
+        mock.reset_command_list(0).unwrap();
+        assert!(mock.is_recording());
+        assert_eq!(mock.reset_command_list(0), Err(MockError::ListOpen));
+        assert_eq!(mock.execute_command_list(), Err(MockError::ListOpen));
+        mock.close_command_list().unwrap();
+        mock.execute_command_list().unwrap();
+    }
+
+    #[test]
+    fn rejects_reset_of_busy_allocator() {
+        let mut mock = MockBackend {
+            gpu: Some(SimulatedGpu::new(2)),
+            ..Default::default()
+        };
+        mock.create_command_allocators(1).unwrap();
+        mock.create_command_list().unwrap();
+        mock.reset_command_list(0).unwrap();
+        mock.close_command_list().unwrap();
+        mock.execute_command_list().unwrap();
+        assert_eq!(
+            mock.reset_command_list(0),
+            Err(MockError::AllocatorInUse(0))
+        );
+        mock.signal(1).unwrap();
+        assert_eq!(
+            mock.reset_command_list(0),
+            Err(MockError::AllocatorInUse(0))
+        );
+        mock.wait_for_value(1).unwrap();
+        mock.reset_command_list(0).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! Frames in flight, and a simulated GPU to test the pacing on
+//!
+//! Each back buffer has its own command allocator and remembers the fence
+//! value signaled after its commands. The CPU waits only when it comes back
+//! to a frame whose value has not completed yet.
+use std::collections::VecDeque;
+
+/// Fence values of the frames in flight
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    /// Value signaled after the last use of each frame
+    values: Vec<u64>,
+    next: u64,
+}
+
+impl FramePacer {
+    /// The fence starts at zero, so the first signaled value is one
+    pub fn new(frames: usize) -> Self {
+        FramePacer {
+            values: vec![0; frames],
+            next: 1,
+        }
+    }
+
+    /// Value to wait for before reusing the frame's allocator and back buffer
+    pub fn wait_value(&self, frame: usize, completed: u64) -> Option<u64> {
+        let value = self.values[frame];
+        if completed < value {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Value to signal after submitting the frame
+    pub fn end_frame(&mut self, frame: usize) -> u64 {
+        let value = self.next_value();
+        self.values[frame] = value;
+        value
+    }
+
+    /// Value to signal outside of a frame, e.g. to wait for the GPU to idle
+    pub fn next_value(&mut self) -> u64 {
+        let value = self.next;
+        self.next += 1;
+        value
+    }
+
+    /// Value signaled after the frame, zero before its first use
+    pub fn frame_value(&self, frame: usize) -> u64 {
+        self.values[frame]
+    }
+}
+
+/// Queue of a GPU that takes `work` ticks per submission
+///
+/// Time only moves with `advance` and `wait_for_value`, so tests decide how
+/// long the CPU takes and see exactly when it stalls.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedGpu {
+    /// Ticks each executed command list takes
+    pub work: u64,
+    now: u64,
+    busy_until: u64,
+    /// Signals as `(tick, value)` in queue order
+    signals: VecDeque<(u64, u64)>,
+    completed: u64,
+    stalled: u64,
+}
+
+impl SimulatedGpu {
+    pub fn new(work: u64) -> Self {
+        SimulatedGpu {
+            work,
+            ..Default::default()
+        }
+    }
+
+    /// Current tick
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Ticks the CPU spent in `wait_for_value`
+    pub fn stalled(&self) -> u64 {
+        self.stalled
+    }
+
+    /// Queue a command list after the previous ones
+    pub fn execute(&mut self) {
+        self.busy_until = self.busy_until.max(self.now) + self.work;
+    }
+
+    /// Queue a fence signal after the submitted work
+    pub fn signal(&mut self, value: u64) {
+        let tick = self.busy_until.max(self.now);
+        self.signals.push_back((tick, value));
+        self.update();
+    }
+
+    pub fn completed_value(&self) -> u64 {
+        self.completed
+    }
+
+    /// Let CPU time pass
+    pub fn advance(&mut self, ticks: u64) {
+        self.now += ticks;
+        self.update();
+    }
+
+    /// Block until the fence reaches the value, never returns for values that
+    /// are not signaled so panics instead
+    pub fn wait_for_value(&mut self, value: u64) {
+        if self.completed >= value {
+            return;
+        }
+        let tick = self
+            .signals
+            .iter()
+            .find(|&&(_, v)| v >= value)
+            .map(|&(tick, _)| tick)
+            .expect("waiting for a value that is never signaled");
+        self.stalled += tick - self.now;
+        self.now = tick;
+        self.update();
+    }
+
+    fn update(&mut self) {
+        while let Some(&(tick, value)) = self.signals.front() {
+            if tick > self.now {
+                break;
+            }
+            self.completed = self.completed.max(value);
+            self.signals.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_only_for_reused_frames() {
+        let mut pacer = FramePacer::new(2);
+        assert_eq!(pacer.wait_value(0, 0), None);
+        assert_eq!(pacer.end_frame(0), 1);
+        assert_eq!(pacer.wait_value(1, 0), None);
+        assert_eq!(pacer.end_frame(1), 2);
+        assert_eq!(pacer.wait_value(0, 0), Some(1));
+        assert_eq!(pacer.wait_value(0, 1), None);
+        assert_eq!(pacer.next_value(), 3);
+        assert_eq!(pacer.frame_value(1), 2);
+    }
+
+    #[test]
+    fn gpu_completes_in_order() {
+        let mut gpu = SimulatedGpu::new(3);
+        gpu.execute();
+        gpu.signal(1);
+        gpu.execute();
+        gpu.signal(2);
+        assert_eq!(gpu.completed_value(), 0);
+        gpu.advance(3);
+        assert_eq!(gpu.completed_value(), 1);
+        gpu.wait_for_value(2);
+        assert_eq!((gpu.now(), gpu.stalled(), gpu.completed_value()), (6, 3, 2));
+
+        // Idle GPU signals right away
+        gpu.advance(10);
+        gpu.signal(3);
+        assert_eq!(gpu.completed_value(), 3);
+    }
+}
@@ -0,0 +1,814 @@
+//! Fixed function pipeline state
+//!
+//! Discriminants are the D3D12 enum values, so bindings can cast with `as`.
+//! Defaults are those of the d3dx12 helpers, [`GraphicsPipelineDesc`] puts the
+//! parts together with the shaders and formats.
+pub mod definition;
+pub mod reload;
+pub mod stream;
+pub mod toml;
+
+use crate::format::Format;
+use crate::vertex::InputElement;
+use std::hash::{Hash, Hasher};
+
+/// `D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT`
+pub const SIMULTANEOUS_RENDER_TARGET_COUNT: usize = 8;
+
+/// `D3D12_FILL_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum FillMode {
+    Wireframe = 2,
+    Solid = 3,
+}
+
+impl FillMode {
+    pub fn from_u32(value: u32) -> Option<FillMode> {
+        use FillMode::*;
+        [Wireframe, Solid]
+            .get((value as usize).wrapping_sub(2))
+            .copied()
+    }
+}
+
+/// `D3D12_CULL_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum CullMode {
+    None = 1,
+    Front = 2,
+    Back = 3,
+}
+
+impl CullMode {
+    pub fn from_u32(value: u32) -> Option<CullMode> {
+        use CullMode::*;
+        [None, Front, Back]
+            .get((value as usize).wrapping_sub(1))
+            .copied()
+    }
+}
+
+/// `D3D12_CONSERVATIVE_RASTERIZATION_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ConservativeRasterizationMode {
+    Off = 0,
+    On = 1,
+}
+
+impl ConservativeRasterizationMode {
+    pub fn from_u32(value: u32) -> Option<ConservativeRasterizationMode> {
+        use ConservativeRasterizationMode::*;
+        [Off, On].get(value as usize).copied()
+    }
+}
+
+/// `D3D12_BLEND`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Blend {
+    Zero = 1,
+    One = 2,
+    SrcColor = 3,
+    InvSrcColor = 4,
+    SrcAlpha = 5,
+    InvSrcAlpha = 6,
+    DestAlpha = 7,
+    InvDestAlpha = 8,
+    DestColor = 9,
+    InvDestColor = 10,
+    SrcAlphaSat = 11,
+    BlendFactor = 14,
+    InvBlendFactor = 15,
+    Src1Color = 16,
+    InvSrc1Color = 17,
+    Src1Alpha = 18,
+    InvSrc1Alpha = 19,
+}
+
+impl Blend {
+    pub fn from_u32(value: u32) -> Option<Blend> {
+        use Blend::*;
+        [
+            Zero,
+            One,
+            SrcColor,
+            InvSrcColor,
+            SrcAlpha,
+            InvSrcAlpha,
+            DestAlpha,
+            InvDestAlpha,
+            DestColor,
+            InvDestColor,
+            SrcAlphaSat,
+            BlendFactor,
+            InvBlendFactor,
+            Src1Color,
+            InvSrc1Color,
+            Src1Alpha,
+            InvSrc1Alpha,
+        ]
+        .iter()
+        .copied()
+        .find(|blend| *blend as u32 == value)
+    }
+}
+
+/// `D3D12_BLEND_OP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum BlendOp {
+    Add = 1,
+    Subtract = 2,
+    RevSubtract = 3,
+    Min = 4,
+    Max = 5,
+}
+
+impl BlendOp {
+    pub fn from_u32(value: u32) -> Option<BlendOp> {
+        use BlendOp::*;
+        [Add, Subtract, RevSubtract, Min, Max]
+            .get((value as usize).wrapping_sub(1))
+            .copied()
+    }
+}
+
+/// `D3D12_LOGIC_OP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum LogicOp {
+    Clear = 0,
+    Set = 1,
+    Copy = 2,
+    CopyInverted = 3,
+    Noop = 4,
+    Invert = 5,
+    And = 6,
+    Nand = 7,
+    Or = 8,
+    Nor = 9,
+    Xor = 10,
+    Equiv = 11,
+    AndReverse = 12,
+    AndInverted = 13,
+    OrReverse = 14,
+    OrInverted = 15,
+}
+
+impl LogicOp {
+    pub fn from_u32(value: u32) -> Option<LogicOp> {
+        use LogicOp::*;
+        [
+            Clear,
+            Set,
+            Copy,
+            CopyInverted,
+            Noop,
+            Invert,
+            And,
+            Nand,
+            Or,
+            Nor,
+            Xor,
+            Equiv,
+            AndReverse,
+            AndInverted,
+            OrReverse,
+            OrInverted,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
+/// `D3D12_COMPARISON_FUNC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ComparisonFunc {
+    Never = 1,
+    Less = 2,
+    Equal = 3,
+    LessEqual = 4,
+    Greater = 5,
+    NotEqual = 6,
+    GreaterEqual = 7,
+    Always = 8,
+}
+
+impl ComparisonFunc {
+    pub fn from_u32(value: u32) -> Option<ComparisonFunc> {
+        use ComparisonFunc::*;
+        [
+            Never,
+            Less,
+            Equal,
+            LessEqual,
+            Greater,
+            NotEqual,
+            GreaterEqual,
+            Always,
+        ]
+        .get((value as usize).wrapping_sub(1))
+        .copied()
+    }
+}
+
+/// `D3D12_COLOR_WRITE_ENABLE_ALL`
+pub const COLOR_WRITE_ENABLE_ALL: u8 = 0xf;
+
+/// `D3D12_PRIMITIVE_TOPOLOGY_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum PrimitiveTopologyType {
+    Undefined = 0,
+    Point = 1,
+    Line = 2,
+    Triangle = 3,
+    Patch = 4,
+}
+
+impl PrimitiveTopologyType {
+    pub fn from_u32(value: u32) -> Option<PrimitiveTopologyType> {
+        use PrimitiveTopologyType::*;
+        [Undefined, Point, Line, Triangle, Patch]
+            .get(value as usize)
+            .copied()
+    }
+}
+
+/// `D3D_PRIMITIVE_TOPOLOGY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum PrimitiveTopology {
+    Undefined = 0,
+    PointList = 1,
+    LineList = 2,
+    LineStrip = 3,
+    TriangleList = 4,
+    TriangleStrip = 5,
+}
+
+/// `D3D12_RASTERIZER_DESC`, floats compare by their bits so it can be hashed
+#[derive(Debug, Clone, Copy)]
+pub struct RasterizerDesc {
+    pub fill_mode: FillMode,
+    pub cull_mode: CullMode,
+    pub front_counter_clockwise: bool,
+    pub depth_bias: i32,
+    pub depth_bias_clamp: f32,
+    pub slope_scaled_depth_bias: f32,
+    pub depth_clip_enable: bool,
+    pub multisample_enable: bool,
+    pub antialiased_line_enable: bool,
+    pub forced_sample_count: u32,
+    pub conservative_raster: ConservativeRasterizationMode,
+}
+
+impl Default for RasterizerDesc {
+    /// `CD3DX12_RASTERIZER_DESC(CD3DX12_DEFAULT)`
+    fn default() -> Self {
+        RasterizerDesc {
+            fill_mode: FillMode::Solid,
+            cull_mode: CullMode::Back,
+            front_counter_clockwise: false,
+            depth_bias: 0,
+            depth_bias_clamp: 0.0,
+            slope_scaled_depth_bias: 0.0,
+            depth_clip_enable: true,
+            multisample_enable: false,
+            antialiased_line_enable: false,
+            forced_sample_count: 0,
+            conservative_raster: ConservativeRasterizationMode::Off,
+        }
+    }
+}
+
+impl RasterizerDesc {
+    /// The fields with floats as bits
+    fn key(&self) -> impl Hash + Eq {
+        (
+            (self.fill_mode, self.cull_mode, self.front_counter_clockwise),
+            (self.depth_bias, self.depth_bias_clamp.to_bits()),
+            self.slope_scaled_depth_bias.to_bits(),
+            (self.depth_clip_enable, self.multisample_enable),
+            (self.antialiased_line_enable, self.forced_sample_count),
+            self.conservative_raster,
+        )
+    }
+}
+
+impl PartialEq for RasterizerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for RasterizerDesc {}
+
+impl Hash for RasterizerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// `D3D12_RENDER_TARGET_BLEND_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderTargetBlendDesc {
+    pub blend_enable: bool,
+    pub logic_op_enable: bool,
+    pub src_blend: Blend,
+    pub dest_blend: Blend,
+    pub blend_op: BlendOp,
+    pub src_blend_alpha: Blend,
+    pub dest_blend_alpha: Blend,
+    pub blend_op_alpha: BlendOp,
+    pub logic_op: LogicOp,
+    pub render_target_write_mask: u8,
+}
+
+impl RenderTargetBlendDesc {
+    /// `src * src.a + dest * (1 - src.a)`
+    pub fn alpha_blend() -> Self {
+        RenderTargetBlendDesc {
+            blend_enable: true,
+            src_blend: Blend::SrcAlpha,
+            dest_blend: Blend::InvSrcAlpha,
+            dest_blend_alpha: Blend::InvSrcAlpha,
+            ..Default::default()
+        }
+    }
+
+    /// `src + dest * (1 - src.a)`, for colors multiplied by their alpha
+    pub fn premultiplied() -> Self {
+        RenderTargetBlendDesc {
+            blend_enable: true,
+            dest_blend: Blend::InvSrcAlpha,
+            dest_blend_alpha: Blend::InvSrcAlpha,
+            ..Default::default()
+        }
+    }
+
+    /// `src + dest`
+    pub fn additive() -> Self {
+        RenderTargetBlendDesc {
+            blend_enable: true,
+            dest_blend: Blend::One,
+            dest_blend_alpha: Blend::One,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RenderTargetBlendDesc {
+    fn default() -> Self {
+        RenderTargetBlendDesc {
+            blend_enable: false,
+            logic_op_enable: false,
+            src_blend: Blend::One,
+            dest_blend: Blend::Zero,
+            blend_op: BlendOp::Add,
+            src_blend_alpha: Blend::One,
+            dest_blend_alpha: Blend::Zero,
+            blend_op_alpha: BlendOp::Add,
+            logic_op: LogicOp::Noop,
+            render_target_write_mask: COLOR_WRITE_ENABLE_ALL,
+        }
+    }
+}
+
+/// `D3D12_BLEND_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlendDesc {
+    pub alpha_to_coverage_enable: bool,
+    pub independent_blend_enable: bool,
+    pub render_target: [RenderTargetBlendDesc; SIMULTANEOUS_RENDER_TARGET_COUNT],
+}
+
+impl Default for BlendDesc {
+    /// `CD3DX12_BLEND_DESC(D3D12_DEFAULT)`
+    fn default() -> Self {
+        BlendDesc {
+            alpha_to_coverage_enable: false,
+            independent_blend_enable: false,
+            render_target: [RenderTargetBlendDesc::default(); SIMULTANEOUS_RENDER_TARGET_COUNT],
+        }
+    }
+}
+
+impl BlendDesc {
+    /// The same blending for every render target
+    pub fn all(render_target: RenderTargetBlendDesc) -> Self {
+        BlendDesc {
+            render_target: [render_target; SIMULTANEOUS_RENDER_TARGET_COUNT],
+            ..Default::default()
+        }
+    }
+}
+
+/// `D3D12_DEPTH_WRITE_MASK`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum DepthWriteMask {
+    Zero = 0,
+    All = 1,
+}
+
+impl DepthWriteMask {
+    pub fn from_u32(value: u32) -> Option<DepthWriteMask> {
+        use DepthWriteMask::*;
+        [Zero, All].get(value as usize).copied()
+    }
+}
+
+/// `D3D12_STENCIL_OP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum StencilOp {
+    Keep = 1,
+    Zero = 2,
+    Replace = 3,
+    IncrSat = 4,
+    DecrSat = 5,
+    Invert = 6,
+    Incr = 7,
+    Decr = 8,
+}
+
+impl StencilOp {
+    pub fn from_u32(value: u32) -> Option<StencilOp> {
+        use StencilOp::*;
+        [Keep, Zero, Replace, IncrSat, DecrSat, Invert, Incr, Decr]
+            .get((value as usize).wrapping_sub(1))
+            .copied()
+    }
+}
+
+/// `D3D12_DEPTH_STENCILOP_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthStencilOpDesc {
+    pub stencil_fail_op: StencilOp,
+    pub stencil_depth_fail_op: StencilOp,
+    pub stencil_pass_op: StencilOp,
+    pub stencil_func: ComparisonFunc,
+}
+
+impl Default for DepthStencilOpDesc {
+    fn default() -> Self {
+        DepthStencilOpDesc {
+            stencil_fail_op: StencilOp::Keep,
+            stencil_depth_fail_op: StencilOp::Keep,
+            stencil_pass_op: StencilOp::Keep,
+            stencil_func: ComparisonFunc::Always,
+        }
+    }
+}
+
+/// `D3D12_DEFAULT_STENCIL_READ_MASK`
+pub const DEFAULT_STENCIL_READ_MASK: u8 = 0xff;
+/// `D3D12_DEFAULT_STENCIL_WRITE_MASK`
+pub const DEFAULT_STENCIL_WRITE_MASK: u8 = 0xff;
+
+/// `D3D12_DEPTH_STENCIL_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthStencilDesc {
+    pub depth_enable: bool,
+    pub depth_write_mask: DepthWriteMask,
+    pub depth_func: ComparisonFunc,
+    pub stencil_enable: bool,
+    pub stencil_read_mask: u8,
+    pub stencil_write_mask: u8,
+    pub front_face: DepthStencilOpDesc,
+    pub back_face: DepthStencilOpDesc,
+}
+
+impl Default for DepthStencilDesc {
+    /// `CD3DX12_DEPTH_STENCIL_DESC(D3D12_DEFAULT)`, depth tested and written
+    fn default() -> Self {
+        DepthStencilDesc {
+            depth_enable: true,
+            depth_write_mask: DepthWriteMask::All,
+            depth_func: ComparisonFunc::Less,
+            stencil_enable: false,
+            stencil_read_mask: DEFAULT_STENCIL_READ_MASK,
+            stencil_write_mask: DEFAULT_STENCIL_WRITE_MASK,
+            front_face: DepthStencilOpDesc::default(),
+            back_face: DepthStencilOpDesc::default(),
+        }
+    }
+}
+
+impl DepthStencilDesc {
+    /// No depth or stencil test
+    pub fn disabled() -> Self {
+        DepthStencilDesc {
+            depth_enable: false,
+            ..Default::default()
+        }
+    }
+
+    /// Depth tested but not written, e.g. for transparent geometry
+    pub fn read_only() -> Self {
+        DepthStencilDesc {
+            depth_write_mask: DepthWriteMask::Zero,
+            ..Default::default()
+        }
+    }
+}
+
+/// `DXGI_SAMPLE_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SampleDesc {
+    pub count: u32,
+    pub quality: u32,
+}
+
+impl Default for SampleDesc {
+    /// One sample per pixel
+    fn default() -> Self {
+        SampleDesc {
+            count: 1,
+            quality: 0,
+        }
+    }
+}
+
+/// `D3D12_GRAPHICS_PIPELINE_STATE_DESC` of a vertex and pixel shader
+///
+/// The root signature is left to the backend. Equal descriptions hash the
+/// same, so created pipelines can be cached by them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GraphicsPipelineDesc<'a> {
+    pub vertex_shader: &'a [u8],
+    pub pixel_shader: &'a [u8],
+    pub input_layout: &'a [InputElement],
+    pub blend: BlendDesc,
+    pub sample_mask: u32,
+    pub rasterizer: RasterizerDesc,
+    pub depth_stencil: DepthStencilDesc,
+    pub primitive_topology_type: PrimitiveTopologyType,
+    /// Up to `SIMULTANEOUS_RENDER_TARGET_COUNT` formats
+    pub render_target_formats: Vec<Format>,
+    /// `Unknown` without a depth buffer
+    pub depth_stencil_format: Format,
+    pub sample_desc: SampleDesc,
+}
+
+impl Default for GraphicsPipelineDesc<'_> {
+    /// The d3dx12 default of each part, triangles to no render targets
+    fn default() -> Self {
+        GraphicsPipelineDesc {
+            vertex_shader: &[],
+            pixel_shader: &[],
+            input_layout: &[],
+            blend: BlendDesc::default(),
+            sample_mask: u32::MAX,
+            rasterizer: RasterizerDesc::default(),
+            depth_stencil: DepthStencilDesc::default(),
+            primitive_topology_type: PrimitiveTopologyType::Triangle,
+            render_target_formats: Vec::new(),
+            depth_stencil_format: Format::Unknown,
+            sample_desc: SampleDesc::default(),
+        }
+    }
+}
+
+impl<'a> GraphicsPipelineDesc<'a> {
+    /// Solid geometry, depth tested and written, no blending
+    pub fn opaque() -> Self {
+        Self::default()
+    }
+
+    /// Premultiplied colors blended over what's drawn, depth tested but not
+    /// written
+    pub fn transparent() -> Self {
+        GraphicsPipelineDesc {
+            blend: BlendDesc::all(RenderTargetBlendDesc::premultiplied()),
+            depth_stencil: DepthStencilDesc::read_only(),
+            ..Self::default()
+        }
+    }
+
+    /// Premultiplied 2D drawing on top, without depth or culling
+    pub fn overlay() -> Self {
+        GraphicsPipelineDesc {
+            blend: BlendDesc::all(RenderTargetBlendDesc::premultiplied()),
+            rasterizer: RasterizerDesc {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            },
+            depth_stencil: DepthStencilDesc::disabled(),
+            ..Self::default()
+        }
+    }
+
+    pub fn shaders(mut self, vertex_shader: &'a [u8], pixel_shader: &'a [u8]) -> Self {
+        self.vertex_shader = vertex_shader;
+        self.pixel_shader = pixel_shader;
+        self
+    }
+
+    pub fn input_layout(mut self, input_layout: &'a [InputElement]) -> Self {
+        self.input_layout = input_layout;
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendDesc) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn rasterizer(mut self, rasterizer: RasterizerDesc) -> Self {
+        self.rasterizer = rasterizer;
+        self
+    }
+
+    pub fn fill_mode(mut self, fill_mode: FillMode) -> Self {
+        self.rasterizer.fill_mode = fill_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.rasterizer.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: DepthStencilDesc) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    pub fn primitive_topology_type(mut self, topology: PrimitiveTopologyType) -> Self {
+        self.primitive_topology_type = topology;
+        self
+    }
+
+    /// Panics with more than `SIMULTANEOUS_RENDER_TARGET_COUNT` formats
+    pub fn render_targets(mut self, formats: &[Format]) -> Self {
+        assert!(
+            formats.len() <= SIMULTANEOUS_RENDER_TARGET_COUNT,
+            "{} render targets, at most {} can be bound",
+            formats.len(),
+            SIMULTANEOUS_RENDER_TARGET_COUNT
+        );
+        self.render_target_formats = formats.to_vec();
+        self
+    }
+
+    pub fn depth_stencil_format(mut self, format: Format) -> Self {
+        self.depth_stencil_format = format;
+        self
+    }
+
+    pub fn sample_desc(mut self, count: u32, quality: u32) -> Self {
+        self.sample_desc = SampleDesc { count, quality };
+        self
+    }
+
+    /// `RTVFormats`, `Unknown` past the bound render targets
+    pub fn rtv_formats(&self) -> [Format; SIMULTANEOUS_RENDER_TARGET_COUNT] {
+        let mut formats = [Format::Unknown; SIMULTANEOUS_RENDER_TARGET_COUNT];
+        formats[..self.render_target_formats.len()].copy_from_slice(&self.render_target_formats);
+        formats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    // Values of d3d12.h, the d3dx12 constructors spelled out with them
+
+    #[test]
+    fn matches_d3dx12_defaults() {
+        // CD3DX12_RASTERIZER_DESC(CD3DX12_DEFAULT)
+        let rasterizer = RasterizerDesc::default();
+        assert_eq!(
+            (
+                rasterizer.fill_mode as u32,
+                rasterizer.cull_mode as u32,
+                rasterizer.front_counter_clockwise,
+                rasterizer.depth_bias,
+                rasterizer.depth_bias_clamp,
+                rasterizer.slope_scaled_depth_bias,
+            ),
+            (3, 3, false, 0, 0.0, 0.0)
+        );
+        assert_eq!(
+            (
+                rasterizer.depth_clip_enable,
+                rasterizer.multisample_enable,
+                rasterizer.antialiased_line_enable,
+                rasterizer.forced_sample_count,
+                rasterizer.conservative_raster as u32,
+            ),
+            (true, false, false, 0, 0)
+        );
+
+        // CD3DX12_BLEND_DESC(D3D12_DEFAULT)
+        let blend = BlendDesc::default();
+        assert!(!blend.alpha_to_coverage_enable && !blend.independent_blend_enable);
+        for rt in &blend.render_target {
+            assert_eq!(
+                (
+                    rt.blend_enable,
+                    rt.logic_op_enable,
+                    [
+                        rt.src_blend as u32,
+                        rt.dest_blend as u32,
+                        rt.blend_op as u32
+                    ],
+                    [
+                        rt.src_blend_alpha as u32,
+                        rt.dest_blend_alpha as u32,
+                        rt.blend_op_alpha as u32,
+                    ],
+                    rt.logic_op as u32,
+                    rt.render_target_write_mask,
+                ),
+                (false, false, [2, 1, 1], [2, 1, 1], 4, 0xf)
+            );
+        }
+
+        // CD3DX12_DEPTH_STENCIL_DESC(D3D12_DEFAULT)
+        let depth = DepthStencilDesc::default();
+        assert_eq!(
+            (
+                depth.depth_enable,
+                depth.depth_write_mask as u32,
+                depth.depth_func as u32,
+                depth.stencil_enable,
+                depth.stencil_read_mask,
+                depth.stencil_write_mask,
+            ),
+            (true, 1, 2, false, 0xff, 0xff)
+        );
+        for face in &[depth.front_face, depth.back_face] {
+            assert_eq!(
+                [
+                    face.stencil_fail_op as u32,
+                    face.stencil_depth_fail_op as u32,
+                    face.stencil_pass_op as u32,
+                    face.stencil_func as u32,
+                ],
+                [1, 1, 1, 8]
+            );
+        }
+
+        // CD3DX12_PIPELINE_STATE_STREAM's sample mask and sample desc
+        let pipeline = GraphicsPipelineDesc::default();
+        assert_eq!(pipeline.sample_mask, 0xffff_ffff);
+        assert_eq!(
+            (pipeline.sample_desc.count, pipeline.sample_desc.quality),
+            (1, 0)
+        );
+        assert_eq!(pipeline.rtv_formats(), [Format::Unknown; 8]);
+    }
+
+    #[test]
+    fn presets_hash_by_value() {
+        let hash = |desc: &GraphicsPipelineDesc| {
+            let mut hasher = DefaultHasher::new();
+            desc.hash(&mut hasher);
+            hasher.finish()
+        };
+        let shader = [1, 2, 3];
+        let a = GraphicsPipelineDesc::opaque()
+            .shaders(&shader, &shader)
+            .render_targets(&[Format::R8G8B8A8Unorm]);
+        let b = GraphicsPipelineDesc::opaque()
+            .render_targets(&[Format::R8G8B8A8Unorm])
+            .shaders(&shader[..], &[1, 2, 3]);
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+
+        let wireframe = a.clone().fill_mode(FillMode::Wireframe);
+        assert_ne!(wireframe, a);
+        assert_ne!(hash(&wireframe), hash(&a));
+        let biased = a.clone().rasterizer(RasterizerDesc {
+            slope_scaled_depth_bias: 1.0,
+            ..Default::default()
+        });
+        assert_ne!(hash(&biased), hash(&a));
+
+        let overlay = GraphicsPipelineDesc::overlay();
+        assert!(!overlay.depth_stencil.depth_enable);
+        assert_eq!(overlay.rasterizer.cull_mode, CullMode::None);
+        assert_eq!(
+            overlay.blend.render_target[7],
+            RenderTargetBlendDesc::premultiplied()
+        );
+        let transparent = GraphicsPipelineDesc::transparent();
+        assert_eq!(
+            transparent.depth_stencil.depth_write_mask,
+            DepthWriteMask::Zero
+        );
+        assert_eq!(
+            a.rtv_formats()[..2],
+            [Format::R8G8B8A8Unorm, Format::Unknown]
+        );
+    }
+}
@@ -0,0 +1,887 @@
+//! Pipelines defined in TOML instead of code
+//!
+//! Each top-level table is a pipeline named by its key. What a pipeline leaves
+//! out comes from its preset, [`GraphicsPipelineDesc::opaque`] by default:
+//!
+//! ```toml
+//! [triangle]
+//! shader = "simple.hlsl"        # relative to the definitions file
+//! vertex = "VSMain"
+//! pixel = "PSMain"
+//! defines = { VERTEX_COLOR = true, QUALITY = 2 }
+//! preset = "opaque"             # opaque, transparent or overlay
+//! topology = "triangle"         # point, line, triangle or patch
+//! render_targets = ["B8G8R8A8_UNORM"]
+//! depth_stencil_format = "D32_FLOAT"
+//! input = [
+//!     { semantic = "POSITION", format = "R32G32B32_FLOAT" },
+//!     { semantic = "COLOR", format = "R32G32B32A32_FLOAT" },
+//! ]
+//!
+//! [triangle.rasterizer]
+//! cull = "none"
+//!
+//! [triangle.blend]
+//! preset = "premultiplied"
+//!
+//! [triangle.depth_stencil]
+//! depth_write = false
+//! ```
+//!
+//! Unknown keys and names are errors that list what's accepted. Input element
+//! offsets default to right after the previous element of the slot.
+use super::toml::{self, Item, ParseError, Table, Value};
+use super::{
+    Blend, BlendDesc, BlendOp, ComparisonFunc, CullMode, DepthWriteMask, FillMode,
+    GraphicsPipelineDesc, PrimitiveTopologyType, RenderTargetBlendDesc, SampleDesc,
+    SIMULTANEOUS_RENDER_TARGET_COUNT,
+};
+use crate::format::Format;
+use crate::shader::permutation::Defines;
+use crate::vertex::{InputClassification, InputElement};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Constructor of a preset's state
+type Preset<T> = fn() -> T;
+
+const PRESETS: &[(&str, Preset<GraphicsPipelineDesc<'static>>)] = &[
+    ("opaque", GraphicsPipelineDesc::opaque),
+    ("transparent", GraphicsPipelineDesc::transparent),
+    ("overlay", GraphicsPipelineDesc::overlay),
+];
+
+const BLEND_PRESETS: &[(&str, Preset<RenderTargetBlendDesc>)] = &[
+    ("opaque", RenderTargetBlendDesc::default),
+    ("alpha", RenderTargetBlendDesc::alpha_blend),
+    ("premultiplied", RenderTargetBlendDesc::premultiplied),
+    ("additive", RenderTargetBlendDesc::additive),
+];
+
+const TOPOLOGIES: &[(&str, PrimitiveTopologyType)] = &[
+    ("point", PrimitiveTopologyType::Point),
+    ("line", PrimitiveTopologyType::Line),
+    ("triangle", PrimitiveTopologyType::Triangle),
+    ("patch", PrimitiveTopologyType::Patch),
+];
+
+const FILL_MODES: &[(&str, FillMode)] = &[
+    ("solid", FillMode::Solid),
+    ("wireframe", FillMode::Wireframe),
+];
+
+const CULL_MODES: &[(&str, CullMode)] = &[
+    ("none", CullMode::None),
+    ("front", CullMode::Front),
+    ("back", CullMode::Back),
+];
+
+const BLENDS: &[(&str, Blend)] = &[
+    ("zero", Blend::Zero),
+    ("one", Blend::One),
+    ("src_color", Blend::SrcColor),
+    ("inv_src_color", Blend::InvSrcColor),
+    ("src_alpha", Blend::SrcAlpha),
+    ("inv_src_alpha", Blend::InvSrcAlpha),
+    ("dest_alpha", Blend::DestAlpha),
+    ("inv_dest_alpha", Blend::InvDestAlpha),
+    ("dest_color", Blend::DestColor),
+    ("inv_dest_color", Blend::InvDestColor),
+    ("src_alpha_sat", Blend::SrcAlphaSat),
+    ("blend_factor", Blend::BlendFactor),
+    ("inv_blend_factor", Blend::InvBlendFactor),
+    ("src1_color", Blend::Src1Color),
+    ("inv_src1_color", Blend::InvSrc1Color),
+    ("src1_alpha", Blend::Src1Alpha),
+    ("inv_src1_alpha", Blend::InvSrc1Alpha),
+];
+
+const BLEND_OPS: &[(&str, BlendOp)] = &[
+    ("add", BlendOp::Add),
+    ("subtract", BlendOp::Subtract),
+    ("rev_subtract", BlendOp::RevSubtract),
+    ("min", BlendOp::Min),
+    ("max", BlendOp::Max),
+];
+
+const COMPARISON_FUNCS: &[(&str, ComparisonFunc)] = &[
+    ("never", ComparisonFunc::Never),
+    ("less", ComparisonFunc::Less),
+    ("equal", ComparisonFunc::Equal),
+    ("less_equal", ComparisonFunc::LessEqual),
+    ("greater", ComparisonFunc::Greater),
+    ("not_equal", ComparisonFunc::NotEqual),
+    ("greater_equal", ComparisonFunc::GreaterEqual),
+    ("always", ComparisonFunc::Always),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefinitionError {
+    /// Not TOML this reads
+    Syntax(ParseError),
+    /// Valid TOML that isn't a pipeline definition, `path` is the dotted key
+    Schema {
+        line: u32,
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefinitionError::Syntax(error) => write!(f, "{}", error),
+            DefinitionError::Schema {
+                line,
+                path,
+                message,
+            } => write!(f, "line {}: {}: {}", line, path, message),
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+impl From<ParseError> for DefinitionError {
+    fn from(error: ParseError) -> Self {
+        DefinitionError::Syntax(error)
+    }
+}
+
+/// Pipelines of a definitions file, in the order written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Definitions {
+    pub pipelines: Vec<PipelineDefinition>,
+}
+
+impl Definitions {
+    pub fn parse(text: &str) -> Result<Self, DefinitionError> {
+        let root = toml::parse(text)?;
+        let pipelines = root
+            .entries
+            .iter()
+            .map(|(name, item)| match &item.value {
+                Value::Table(table) => PipelineDefinition::read(name, table, item.line),
+                other => Err(DefinitionError::Schema {
+                    line: item.line,
+                    path: name.clone(),
+                    message: format!("expected a pipeline table, found {}", other.kind()),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Definitions { pipelines })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PipelineDefinition> {
+        self.pipelines.iter().find(|pipeline| pipeline.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineDefinition {
+    pub name: String,
+    /// HLSL file, relative to the definitions file
+    pub shader: String,
+    pub vertex_entry_point: String,
+    pub pixel_entry_point: String,
+    /// Selects the variants of both entry points
+    pub defines: Defines,
+    pub input_layout: Vec<InputElement>,
+    /// Everything but the shaders and the input layout
+    pub state: GraphicsPipelineDesc<'static>,
+}
+
+impl PipelineDefinition {
+    /// Description with the compiled shaders
+    pub fn desc<'a>(
+        &'a self,
+        vertex_shader: &'a [u8],
+        pixel_shader: &'a [u8],
+    ) -> GraphicsPipelineDesc<'a> {
+        GraphicsPipelineDesc {
+            vertex_shader,
+            pixel_shader,
+            input_layout: &self.input_layout,
+            ..self.state.clone()
+        }
+    }
+
+    fn read(name: &str, table: &Table, line: u32) -> Result<Self, DefinitionError> {
+        let mut fields = Fields::new(table, name.to_string(), line);
+        let shader = fields.required("shader", string)?;
+        let vertex_entry_point = fields.required("vertex", string)?;
+        let pixel_entry_point = fields.required("pixel", string)?;
+        let defines = fields.get("defines", defines)?.unwrap_or_default();
+        let preset = fields.get("preset", choice(PRESETS))?;
+        let mut state = preset.map_or_else(GraphicsPipelineDesc::opaque, |preset| preset());
+
+        if let Some(topology) = fields.get("topology", choice(TOPOLOGIES))? {
+            state.primitive_topology_type = topology;
+        }
+        if let Some(formats) = fields.get("render_targets", render_targets)? {
+            state.render_target_formats = formats;
+        }
+        if let Some(format) = fields.get("depth_stencil_format", depth_format)? {
+            state.depth_stencil_format = format;
+        }
+        if let Some(count) = fields.get("sample_count", sample_count)? {
+            state.sample_desc = SampleDesc {
+                count,
+                ..state.sample_desc
+            };
+        }
+        if let Some(quality) = fields.get("sample_quality", unsigned)? {
+            state.sample_desc.quality = quality;
+        }
+        if let Some(mask) = fields.get("sample_mask", unsigned)? {
+            state.sample_mask = mask;
+        }
+        let input_layout = match fields.item("input") {
+            Some(item) => fields.input_layout(item)?,
+            None => Vec::new(),
+        };
+        if let Some(mut rasterizer) = fields.table("rasterizer")? {
+            read_rasterizer(&mut rasterizer, &mut state)?;
+        }
+        if let Some(mut blend) = fields.table("blend")? {
+            read_blend(&mut blend, &mut state.blend)?;
+        }
+        if let Some(mut depth_stencil) = fields.table("depth_stencil")? {
+            read_depth_stencil(&mut depth_stencil, &mut state)?;
+        }
+        fields.finish()?;
+
+        let tested = state.depth_stencil.depth_enable || state.depth_stencil.stencil_enable;
+        if tested && state.depth_stencil_format == Format::Unknown {
+            return Err(DefinitionError::Schema {
+                line,
+                path: name.to_string(),
+                message: "depth testing needs a depth_stencil_format, or \
+                          `depth = false` in depth_stencil"
+                    .to_string(),
+            });
+        }
+        Ok(PipelineDefinition {
+            name: name.to_string(),
+            shader,
+            vertex_entry_point,
+            pixel_entry_point,
+            defines,
+            input_layout,
+            state,
+        })
+    }
+}
+
+fn read_rasterizer(
+    fields: &mut Fields,
+    state: &mut GraphicsPipelineDesc,
+) -> Result<(), DefinitionError> {
+    let r = &mut state.rasterizer;
+    if let Some(fill_mode) = fields.get("fill", choice(FILL_MODES))? {
+        r.fill_mode = fill_mode;
+    }
+    if let Some(cull_mode) = fields.get("cull", choice(CULL_MODES))? {
+        r.cull_mode = cull_mode;
+    }
+    if let Some(ccw) = fields.get("front_counter_clockwise", boolean)? {
+        r.front_counter_clockwise = ccw;
+    }
+    if let Some(bias) = fields.get("depth_bias", integer)? {
+        r.depth_bias = bias;
+    }
+    if let Some(clamp) = fields.get("depth_bias_clamp", float)? {
+        r.depth_bias_clamp = clamp;
+    }
+    if let Some(bias) = fields.get("slope_scaled_depth_bias", float)? {
+        r.slope_scaled_depth_bias = bias;
+    }
+    if let Some(clip) = fields.get("depth_clip", boolean)? {
+        r.depth_clip_enable = clip;
+    }
+    if let Some(multisample) = fields.get("multisample", boolean)? {
+        r.multisample_enable = multisample;
+    }
+    if let Some(antialiased) = fields.get("antialiased_lines", boolean)? {
+        r.antialiased_line_enable = antialiased;
+    }
+    if let Some(count) = fields.get("forced_sample_count", unsigned)? {
+        r.forced_sample_count = count;
+    }
+    if let Some(conservative) = fields.get("conservative", boolean)? {
+        r.conservative_raster = if conservative {
+            super::ConservativeRasterizationMode::On
+        } else {
+            super::ConservativeRasterizationMode::Off
+        };
+    }
+    fields.finish()
+}
+
+/// Same blending for every render target, a preset and overrides of it
+fn read_blend(fields: &mut Fields, blend: &mut BlendDesc) -> Result<(), DefinitionError> {
+    let mut rt = match fields.get("preset", choice(BLEND_PRESETS))? {
+        Some(preset) => preset(),
+        None => blend.render_target[0],
+    };
+    // Giving any factor or operation turns blending on
+    let factors = [
+        ("src", &mut rt.src_blend),
+        ("dest", &mut rt.dest_blend),
+        ("src_alpha", &mut rt.src_blend_alpha),
+        ("dest_alpha", &mut rt.dest_blend_alpha),
+    ];
+    let mut given = false;
+    for (key, factor) in factors {
+        if let Some(blend) = fields.get(key, choice(BLENDS))? {
+            *factor = blend;
+            given = true;
+        }
+    }
+    for (key, op) in [
+        ("op", &mut rt.blend_op),
+        ("op_alpha", &mut rt.blend_op_alpha),
+    ] {
+        if let Some(blend_op) = fields.get(key, choice(BLEND_OPS))? {
+            *op = blend_op;
+            given = true;
+        }
+    }
+    rt.blend_enable |= given;
+    if let Some(mask) = fields.get("write_mask", write_mask)? {
+        rt.render_target_write_mask = mask;
+    }
+    if let Some(enable) = fields.get("enable", boolean)? {
+        rt.blend_enable = enable;
+    }
+    let alpha_to_coverage = fields.get("alpha_to_coverage", boolean)?;
+    fields.finish()?;
+
+    *blend = BlendDesc::all(rt);
+    if let Some(alpha_to_coverage) = alpha_to_coverage {
+        blend.alpha_to_coverage_enable = alpha_to_coverage;
+    }
+    Ok(())
+}
+
+fn read_depth_stencil(
+    fields: &mut Fields,
+    state: &mut GraphicsPipelineDesc,
+) -> Result<(), DefinitionError> {
+    let d = &mut state.depth_stencil;
+    if let Some(depth) = fields.get("depth", boolean)? {
+        d.depth_enable = depth;
+    }
+    if let Some(write) = fields.get("depth_write", boolean)? {
+        d.depth_write_mask = if write {
+            DepthWriteMask::All
+        } else {
+            DepthWriteMask::Zero
+        };
+    }
+    if let Some(func) = fields.get("depth_func", choice(COMPARISON_FUNCS))? {
+        d.depth_func = func;
+    }
+    if let Some(stencil) = fields.get("stencil", boolean)? {
+        d.stencil_enable = stencil;
+    }
+    if let Some(mask) = fields.get("stencil_read_mask", byte)? {
+        d.stencil_read_mask = mask;
+    }
+    if let Some(mask) = fields.get("stencil_write_mask", byte)? {
+        d.stencil_write_mask = mask;
+    }
+    fields.finish()
+}
+
+/// Keys of a table as they're read, the rest are unknown
+struct Fields<'t> {
+    table: &'t Table,
+    path: String,
+    line: u32,
+    known: Vec<&'static str>,
+}
+
+impl<'t> Fields<'t> {
+    fn new(table: &'t Table, path: String, line: u32) -> Self {
+        Fields {
+            table,
+            path,
+            line,
+            known: Vec::new(),
+        }
+    }
+
+    fn error(&self, line: u32, key: &str, message: String) -> DefinitionError {
+        DefinitionError::Schema {
+            line,
+            path: format!("{}.{}", self.path, key),
+            message,
+        }
+    }
+
+    fn item(&mut self, key: &'static str) -> Option<&'t Item> {
+        self.known.push(key);
+        self.table.get(key)
+    }
+
+    fn get<T>(
+        &mut self,
+        key: &'static str,
+        convert: impl Fn(&Value) -> Result<T, String>,
+    ) -> Result<Option<T>, DefinitionError> {
+        match self.item(key) {
+            Some(item) => convert(&item.value)
+                .map(Some)
+                .map_err(|message| self.error(item.line, key, message)),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T>(
+        &mut self,
+        key: &'static str,
+        convert: impl Fn(&Value) -> Result<T, String>,
+    ) -> Result<T, DefinitionError> {
+        self.get(key, convert)?
+            .ok_or_else(|| self.error(self.line, key, "missing".to_string()))
+    }
+
+    fn table(&mut self, key: &'static str) -> Result<Option<Fields<'t>>, DefinitionError> {
+        match self.item(key) {
+            Some(Item {
+                value: Value::Table(table),
+                line,
+            }) => Ok(Some(Fields::new(
+                table,
+                format!("{}.{}", self.path, key),
+                *line,
+            ))),
+            Some(item) => Err(self.error(item.line, key, expected("a table", &item.value))),
+            None => Ok(None),
+        }
+    }
+
+    fn input_layout(&self, item: &Item) -> Result<Vec<InputElement>, DefinitionError> {
+        let items = match &item.value {
+            Value::Array(items) => items,
+            other => return Err(self.error(item.line, "input", expected("an array", other))),
+        };
+        // End of the previous element per slot
+        let mut ends: Vec<(u32, u32)> = Vec::new();
+        let mut elements = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let path = format!("{}.input[{}]", self.path, i);
+            let table = match &item.value {
+                Value::Table(table) => table,
+                other => {
+                    return Err(DefinitionError::Schema {
+                        line: item.line,
+                        path,
+                        message: expected("an input element table", other),
+                    })
+                }
+            };
+            let mut fields = Fields::new(table, path, item.line);
+            let semantic = fields.required("semantic", semantic)?;
+            let semantic_index = fields.get("index", unsigned)?.unwrap_or(0);
+            let format = fields.required("format", vertex_format)?;
+            let input_slot = fields.get("slot", unsigned)?.unwrap_or(0);
+            let end = ends.iter_mut().find(|(slot, _)| *slot == input_slot);
+            let offset = match fields.get("offset", unsigned)? {
+                Some(offset) => offset,
+                None => end.as_ref().map_or(0, |(_, end)| *end),
+            };
+            let step_rate = fields.get("instance_step_rate", unsigned)?;
+            fields.finish()?;
+
+            let next = offset + format.size_in_bytes();
+            match end {
+                Some((_, end)) => *end = next,
+                None => ends.push((input_slot, next)),
+            }
+            elements.push(InputElement {
+                semantic_name: intern(&semantic),
+                semantic_index,
+                format,
+                input_slot,
+                aligned_byte_offset: offset,
+                input_slot_class: match step_rate {
+                    Some(_) => InputClassification::PerInstanceData,
+                    None => InputClassification::PerVertexData,
+                },
+                instance_data_step_rate: step_rate.unwrap_or(0),
+            });
+        }
+        Ok(elements)
+    }
+
+    fn finish(&self) -> Result<(), DefinitionError> {
+        for (key, item) in &self.table.entries {
+            if !self.known.contains(&key.as_str()) {
+                let message = format!("unknown key, expected one of {}", self.known.join(", "));
+                return Err(self.error(item.line, key, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn expected(kind: &str, value: &Value) -> String {
+    format!("expected {}, found {}", kind, value.kind())
+}
+
+fn string(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(string) => Ok(string.clone()),
+        other => Err(expected("a string", other)),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(boolean) => Ok(*boolean),
+        other => Err(expected("true or false", other)),
+    }
+}
+
+fn integer(value: &Value) -> Result<i32, String> {
+    match value {
+        Value::Integer(integer) => {
+            i32::try_from(*integer).map_err(|_| format!("{} is out of range", integer))
+        }
+        other => Err(expected("an integer", other)),
+    }
+}
+
+fn unsigned(value: &Value) -> Result<u32, String> {
+    match value {
+        Value::Integer(integer) => {
+            u32::try_from(*integer).map_err(|_| format!("{} is out of range", integer))
+        }
+        other => Err(expected("an unsigned integer", other)),
+    }
+}
+
+fn sample_count(value: &Value) -> Result<u32, String> {
+    match unsigned(value)? {
+        0 => Err("at least one sample is needed".to_string()),
+        count => Ok(count),
+    }
+}
+
+fn byte(value: &Value) -> Result<u8, String> {
+    let integer = unsigned(value)?;
+    u8::try_from(integer).map_err(|_| format!("{} doesn't fit in 8 bits", integer))
+}
+
+fn write_mask(value: &Value) -> Result<u8, String> {
+    let mask = byte(value)?;
+    if mask > 0xf {
+        return Err(format!("{:#x} has bits past RGBA, 0xf is all", mask));
+    }
+    Ok(mask)
+}
+
+fn float(value: &Value) -> Result<f32, String> {
+    match value {
+        Value::Float(float) => Ok(*float as f32),
+        Value::Integer(integer) => Ok(*integer as f32),
+        other => Err(expected("a number", other)),
+    }
+}
+
+fn choice<T: Copy>(names: &'static [(&'static str, T)]) -> impl Fn(&Value) -> Result<T, String> {
+    move |value| {
+        let name = string(value)?;
+        names
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, choice)| *choice)
+            .ok_or_else(|| {
+                let known = names.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                format!("unknown `{}`, expected one of {}", name, known.join(", "))
+            })
+    }
+}
+
+/// True sets the define to `1` and false leaves it out, like
+/// [`Axis::toggle`](crate::shader::permutation::Axis::toggle)
+fn defines(value: &Value) -> Result<Defines, String> {
+    let table = match value {
+        Value::Table(table) => table,
+        other => return Err(expected("a table of defines", other)),
+    };
+    let mut defines = Defines::new();
+    for (name, item) in &table.entries {
+        defines = match &item.value {
+            Value::String(value) => defines.set(name, value),
+            Value::Integer(value) => defines.set(name, &value.to_string()),
+            Value::Boolean(true) => defines.set(name, "1"),
+            Value::Boolean(false) => defines,
+            other => return Err(format!("{}: {}", name, expected("a string", other))),
+        };
+    }
+    Ok(defines)
+}
+
+fn format(value: &Value) -> Result<Format, String> {
+    let name = string(value)?;
+    Format::from_name(&name).ok_or_else(|| format!("unknown format `{}`", name))
+}
+
+fn vertex_format(value: &Value) -> Result<Format, String> {
+    let format = format(value)?;
+    if format == Format::Unknown || format.is_depth() {
+        return Err(format!("{} is not a vertex format", format.name()));
+    }
+    Ok(format)
+}
+
+fn depth_format(value: &Value) -> Result<Format, String> {
+    let format = format(value)?;
+    if format != Format::Unknown && !format.is_depth() {
+        return Err(format!("{} is not a depth stencil format", format.name()));
+    }
+    Ok(format)
+}
+
+fn render_targets(value: &Value) -> Result<Vec<Format>, String> {
+    let items = match value {
+        Value::Array(items) => items,
+        other => return Err(expected("an array of formats", other)),
+    };
+    if items.len() > SIMULTANEOUS_RENDER_TARGET_COUNT {
+        return Err(format!(
+            "{} render targets, at most {} can be bound",
+            items.len(),
+            SIMULTANEOUS_RENDER_TARGET_COUNT
+        ));
+    }
+    items
+        .iter()
+        .map(|item| {
+            let format = format(&item.value)?;
+            if format == Format::Unknown || format.is_depth() {
+                return Err(format!("{} is not a render target format", format.name()));
+            }
+            Ok(format)
+        })
+        .collect()
+}
+
+fn semantic(value: &Value) -> Result<String, String> {
+    let name = string(value)?;
+    if name.is_empty() {
+        return Err("empty semantic name".to_string());
+    }
+    if name.ends_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!(
+            "{} ends with a digit, the index goes in `index`",
+            name
+        ));
+    }
+    Ok(name)
+}
+
+/// Semantic names are `'static` in [`InputElement`], each distinct name is
+/// leaked once however often definitions are reloaded
+fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut names = NAMES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match names.iter().find(|known| **known == name) {
+        Some(known) => known,
+        None => {
+            let leaked: &'static str = Box::leak(name.to_string().into_boxed_str());
+            names.push(leaked);
+            leaked
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::DepthStencilDesc;
+    use crate::scene::BACK_BUFFER_FORMAT;
+    use crate::vertex::{Vertex, VertexLayout};
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.pipelines.toml");
+
+    #[test]
+    fn reads_the_hello_triangle_pipeline() {
+        let definitions = Definitions::parse(SIMPLE).unwrap();
+        let triangle = definitions.get("triangle").unwrap();
+        assert_eq!(
+            (
+                triangle.shader.as_str(),
+                triangle.vertex_entry_point.as_str(),
+                triangle.pixel_entry_point.as_str()
+            ),
+            ("simple.hlsl", "VSMain", "PSMain")
+        );
+        // Straight alpha, as the software backend draws
+        assert_eq!(triangle.defines, Defines::new().set("VERTEX_COLOR", "1"));
+        // What `Window::new` spelled out before
+        let shader = [0x44, 0x58, 0x42, 0x43];
+        assert_eq!(
+            triangle.desc(&shader, &shader),
+            GraphicsPipelineDesc::opaque()
+                .shaders(&shader, &shader)
+                .input_layout(Vertex::LAYOUT)
+                .depth_stencil(DepthStencilDesc::disabled())
+                .render_targets(&[BACK_BUFFER_FORMAT])
+        );
+    }
+
+    #[test]
+    fn overrides_presets() {
+        let text = r#"
+[sprites]
+shader = "sprites.hlsl"
+vertex = "VSMain"
+pixel = "PSMain"
+defines = { TINT = false, QUALITY = 2 }
+preset = "transparent"
+topology = "triangle"
+render_targets = ["R8G8B8A8_UNORM", "R16G16B16A16_FLOAT"]
+depth_stencil_format = "D24_UNORM_S8_UINT"
+sample_count = 4
+
+[[sprites.input]]
+semantic = "POSITION"
+format = "R32G32_FLOAT"
+
+[[sprites.input]]
+semantic = "TEXCOORD"
+index = 1
+format = "R32G32_FLOAT"
+
+[[sprites.input]]
+semantic = "COLOR"
+format = "R8G8B8A8_UNORM"
+slot = 1
+instance_step_rate = 1
+
+[sprites.rasterizer]
+fill = "wireframe"
+cull = "none"
+slope_scaled_depth_bias = 1.5
+
+[sprites.blend]
+dest = "one"
+write_mask = 7
+
+[sprites.depth_stencil]
+depth_func = "less_equal"
+stencil = true
+"#;
+        let definitions = Definitions::parse(text).unwrap();
+        let sprites = &definitions.pipelines[0];
+        assert_eq!(sprites.defines, Defines::new().set("QUALITY", "2"));
+
+        let state = &sprites.state;
+        assert_eq!(state.render_target_formats.len(), 2);
+        assert_eq!(state.depth_stencil_format, Format::D24UnormS8Uint);
+        assert_eq!(
+            state.sample_desc,
+            SampleDesc {
+                count: 4,
+                quality: 0
+            }
+        );
+        assert_eq!(state.rasterizer.fill_mode, FillMode::Wireframe);
+        assert_eq!(state.rasterizer.cull_mode, CullMode::None);
+        assert_eq!(state.rasterizer.slope_scaled_depth_bias, 1.5);
+        // Premultiplied of the preset, overridden
+        let rt = state.blend.render_target[3];
+        assert!(rt.blend_enable);
+        assert_eq!((rt.src_blend, rt.dest_blend), (Blend::One, Blend::One));
+        assert_eq!(rt.dest_blend_alpha, Blend::InvSrcAlpha);
+        assert_eq!(rt.render_target_write_mask, 7);
+        assert_eq!(state.depth_stencil.depth_write_mask, DepthWriteMask::Zero);
+        assert_eq!(state.depth_stencil.depth_func, ComparisonFunc::LessEqual);
+        assert!(state.depth_stencil.stencil_enable);
+
+        let layout = &sprites.input_layout;
+        assert_eq!(
+            layout
+                .iter()
+                .map(|e| (
+                    e.semantic_name,
+                    e.semantic_index,
+                    e.input_slot,
+                    e.aligned_byte_offset
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("POSITION", 0, 0, 0),
+                ("TEXCOORD", 1, 0, 8),
+                ("COLOR", 0, 1, 0)
+            ]
+        );
+        assert_eq!(
+            layout[2].input_slot_class,
+            InputClassification::PerInstanceData
+        );
+        assert_eq!(layout[2].instance_data_step_rate, 1);
+
+        // Same text, same description
+        assert_eq!(Definitions::parse(text).unwrap(), definitions);
+    }
+
+    #[test]
+    fn schema_errors_explain_themselves() {
+        let error = |body: &str| {
+            let text = format!(
+                "[quad]\nshader = \"quad.hlsl\"\nvertex = \"VS\"\npixel = \"PS\"\n{}",
+                body
+            );
+            Definitions::parse(&text).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("depth_stencil.depth = false\n[quad.rasterizer]\ncul = \"none\""),
+            "line 7: quad.rasterizer.cul: unknown key, expected one of fill, cull, \
+             front_counter_clockwise, depth_bias, depth_bias_clamp, slope_scaled_depth_bias, \
+             depth_clip, multisample, antialiased_lines, forced_sample_count, conservative"
+        );
+        assert_eq!(
+            error("preset = \"glass\""),
+            "line 5: quad.preset: unknown `glass`, expected one of opaque, transparent, overlay"
+        );
+        assert_eq!(
+            error("render_targets = \"R8G8B8A8_UNORM\""),
+            "line 5: quad.render_targets: expected an array of formats, found a string"
+        );
+        assert_eq!(
+            error("render_targets = [\"D32_FLOAT\"]"),
+            "line 5: quad.render_targets: D32_FLOAT is not a render target format"
+        );
+        assert_eq!(
+            error("input = [{ semantic = \"TEXCOORD0\", format = \"R32G32_FLOAT\" }]"),
+            "line 5: quad.input[0].semantic: TEXCOORD0 ends with a digit, the index goes in `index`"
+        );
+        assert_eq!(
+            error(""),
+            "line 1: quad: depth testing needs a depth_stencil_format, or `depth = false` in \
+             depth_stencil"
+        );
+        assert_eq!(
+            Definitions::parse("[quad]\nvertex = \"VS\"")
+                .unwrap_err()
+                .to_string(),
+            "line 1: quad.shader: missing"
+        );
+        assert_eq!(
+            Definitions::parse("quad = 1").unwrap_err().to_string(),
+            "line 1: quad: expected a pipeline table, found an integer"
+        );
+        assert!(matches!(
+            Definitions::parse("[quad"),
+            Err(DefinitionError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Hot reload of pipeline definitions
+//!
+//! Modification times are polled, e.g. once a frame, so there's no watcher
+//! thread or platform API. The definitions file and the shaders it names are
+//! watched, files the shaders include aren't.
+use super::definition::{DefinitionError, Definitions};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug)]
+pub enum ReloadError {
+    Io(PathBuf, io::Error),
+    Definition(PathBuf, DefinitionError),
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReloadError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ReloadError::Definition(path, error) => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {}
+
+/// Definitions file and the shader files it names
+pub struct Reloader {
+    path: PathBuf,
+    definitions: Definitions,
+    /// Watched files and their modification time when last polled, `None`
+    /// while missing
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+    /// Shaders that changed in a poll whose definitions failed to read
+    pending: Vec<PathBuf>,
+}
+
+impl Reloader {
+    /// Reads the definitions from `path`
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, ReloadError> {
+        let path = path.into();
+        let definitions = read(&path)?;
+        Ok(Reloader::new(path, definitions))
+    }
+
+    /// Watches `path` from now on, `definitions` being what it holds, e.g.
+    /// embedded in the binary
+    pub fn new(path: impl Into<PathBuf>, definitions: Definitions) -> Self {
+        let mut reloader = Reloader {
+            path: path.into(),
+            definitions,
+            stamps: Vec::new(),
+            pending: Vec::new(),
+        };
+        reloader.stamps = reloader
+            .watched()
+            .into_iter()
+            .map(|path| {
+                let stamp = modified(&path);
+                (path, stamp)
+            })
+            .collect();
+        reloader
+    }
+
+    pub fn definitions(&self) -> &Definitions {
+        &self.definitions
+    }
+
+    /// Path of a definition's shader file
+    pub fn shader_path(&self, shader: &str) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) => dir.join(shader),
+            None => PathBuf::from(shader),
+        }
+    }
+
+    /// Names of the pipelines to rebuild since the last poll, in definition
+    /// order
+    ///
+    /// A pipeline is rebuilt when its definition or shader file changed. On
+    /// an error the previous definitions stay, the next edit is tried again
+    /// and shader changes are reported by the next poll.
+    pub fn poll(&mut self) -> Result<Vec<String>, ReloadError> {
+        let mut changed = std::mem::take(&mut self.pending);
+        for (path, stamp) in &mut self.stamps {
+            let now = modified(path);
+            if now != *stamp {
+                *stamp = now;
+                if !changed.contains(path) {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        if changed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let definitions = if changed.contains(&self.path) {
+            match read(&self.path) {
+                Ok(definitions) => definitions,
+                Err(e) => {
+                    changed.retain(|path| *path != self.path);
+                    self.pending = changed;
+                    return Err(e);
+                }
+            }
+        } else {
+            self.definitions.clone()
+        };
+        let rebuild = definitions
+            .pipelines
+            .iter()
+            .filter(|pipeline| {
+                self.definitions.get(&pipeline.name) != Some(pipeline)
+                    || changed.contains(&self.shader_path(&pipeline.shader))
+            })
+            .map(|pipeline| pipeline.name.clone())
+            .collect();
+        self.definitions = definitions;
+
+        // Shaders named for the first time are watched from now on
+        let stamps = std::mem::take(&mut self.stamps);
+        self.stamps = self
+            .watched()
+            .into_iter()
+            .map(
+                |path| match stamps.iter().find(|(known, _)| *known == path) {
+                    Some(stamp) => stamp.clone(),
+                    None => {
+                        let stamp = modified(&path);
+                        (path, stamp)
+                    }
+                },
+            )
+            .collect();
+        Ok(rebuild)
+    }
+
+    fn watched(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.path.clone()];
+        for pipeline in &self.definitions.pipelines {
+            let path = self.shader_path(&pipeline.shader);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+}
+
+fn read(path: &Path) -> Result<Definitions, ReloadError> {
+    let text = fs::read_to_string(path).map_err(|e| ReloadError::Io(path.to_path_buf(), e))?;
+    Definitions::parse(&text).map_err(|e| ReloadError::Definition(path.to_path_buf(), e))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    const QUAD: &str = "[quad]\nshader = \"quad.hlsl\"\nvertex = \"VS\"\npixel = \"PS\"\n\
+                        depth_stencil.depth = false\n";
+    const SPRITES: &str = "[sprites]\nshader = \"sprites.hlsl\"\nvertex = \"VS\"\n\
+                           pixel = \"PS\"\ndepth_stencil.depth = false\n";
+
+    /// Written with a modification time of its own, file systems may round
+    /// quick successive writes to the same time
+    fn write(path: &Path, text: &str, seconds: u64) {
+        fs::write(path, text).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_pipelines_to_rebuild() {
+        let dir = std::env::temp_dir().join(format!("pipeline-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pipelines.toml");
+        write(&path, QUAD, 1);
+        write(&dir.join("quad.hlsl"), "// quad", 1);
+
+        let mut reloader = Reloader::open(&path).unwrap();
+        assert_eq!(reloader.poll().unwrap(), Vec::<String>::new());
+
+        // Shader edits rebuild the pipelines using the shader
+        write(&dir.join("quad.hlsl"), "// quad, edited", 2);
+        assert_eq!(reloader.poll().unwrap(), ["quad"]);
+        assert_eq!(reloader.poll().unwrap(), Vec::<String>::new());
+
+        // New and changed definitions, unchanged ones stay
+        write(&path, &format!("{}{}", QUAD, SPRITES), 3);
+        assert_eq!(reloader.poll().unwrap(), ["sprites"]);
+        write(&dir.join("sprites.hlsl"), "// sprites", 4);
+        assert_eq!(reloader.poll().unwrap(), ["sprites"]);
+        let edited = QUAD.replace("\"PS\"", "\"PSMain\"");
+        write(&path, &format!("{}{}", edited, SPRITES), 5);
+        assert_eq!(reloader.poll().unwrap(), ["quad"]);
+
+        // A broken edit keeps the previous definitions
+        write(&path, "[quad]\nshader = 1\n", 6);
+        match reloader.poll() {
+            Err(ReloadError::Definition(at, error)) => {
+                assert_eq!(at, path);
+                assert_eq!(
+                    error.to_string(),
+                    "line 2: quad.shader: expected a string, found an integer"
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(reloader.definitions().pipelines.len(), 2);
+        assert_eq!(reloader.poll().unwrap(), Vec::<String>::new());
+        assert_eq!(
+            reloader
+                .definitions()
+                .get("quad")
+                .unwrap()
+                .pixel_entry_point,
+            "PSMain"
+        );
+
+        // Shader edits of a broken poll wait for the next one
+        write(&dir.join("quad.hlsl"), "// quad, edited again", 7);
+        write(&path, "[quad]\nshader = 1\n", 7);
+        assert!(reloader.poll().is_err());
+        write(&path, &format!("{}{}", edited, SPRITES), 8);
+        assert_eq!(reloader.poll().unwrap(), ["quad"]);
+        assert_eq!(reloader.poll().unwrap(), Vec::<String>::new());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,825 @@
+//! Pipeline state streams
+//!
+//! `D3D12_PIPELINE_STATE_STREAM_DESC` points at subobjects laid out back to
+//! back, as `CD3DX12_PIPELINE_STATE_STREAM_SUBOBJECT` does: a
+//! `D3D12_PIPELINE_STATE_SUBOBJECT_TYPE` word, the desc at its own alignment,
+//! then padding to the next pointer. [`PipelineStateStream`] writes them,
+//! [`parse`] reads them back, so streams can be built and compared without
+//! Windows. Pointers are written as the addresses they hold, in the target's
+//! pointer size.
+//!
+//! Descs on 64-bit targets, offsets count from the subobject's start:
+//!
+//! ```text
+//! type         desc                                offset  subobject size
+//! 0            root signature pointer              8       16
+//! 1-6, 24-25   shader bytecode pointer and size    8       24
+//! 8            blend, 2 + 8 * 10 words             4       336
+//! 10           rasterizer, 11 words                4       48
+//! 11           depth stencil, 13 words             4       56
+//! 12           input layout pointer and count      8       24
+//! 15           8 render target formats and count   4       40
+//! 17           sample count and quality            4       16
+//! 19           cached blob pointer and size        8       24
+//! 21           depth stencil 1, 14 words           4       64
+//! ```
+//!
+//! Sample mask, strip cut value, topology, depth format, node mask and flags
+//! are a word each, 8 bytes with the type.
+use super::{
+    Blend, BlendDesc, BlendOp, ComparisonFunc, ConservativeRasterizationMode, CullMode,
+    DepthStencilDesc, DepthStencilOpDesc, DepthWriteMask, FillMode, GraphicsPipelineDesc, LogicOp,
+    PrimitiveTopologyType, RasterizerDesc, RenderTargetBlendDesc, SampleDesc, StencilOp,
+    SIMULTANEOUS_RENDER_TARGET_COUNT,
+};
+use crate::format::Format;
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+
+/// Subobjects and pointers in the stream are aligned to this
+pub const POINTER_SIZE: usize = mem::size_of::<usize>();
+
+/// `D3D12_PIPELINE_STATE_SUBOBJECT_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum SubobjectType {
+    RootSignature = 0,
+    VertexShader = 1,
+    PixelShader = 2,
+    DomainShader = 3,
+    HullShader = 4,
+    GeometryShader = 5,
+    ComputeShader = 6,
+    StreamOutput = 7,
+    Blend = 8,
+    SampleMask = 9,
+    Rasterizer = 10,
+    DepthStencil = 11,
+    InputLayout = 12,
+    IbStripCutValue = 13,
+    PrimitiveTopology = 14,
+    RenderTargetFormats = 15,
+    DepthStencilFormat = 16,
+    SampleDesc = 17,
+    NodeMask = 18,
+    CachedPso = 19,
+    Flags = 20,
+    DepthStencil1 = 21,
+    ViewInstancing = 22,
+    AmplificationShader = 24,
+    MeshShader = 25,
+}
+
+impl SubobjectType {
+    pub fn from_u32(value: u32) -> Option<SubobjectType> {
+        use SubobjectType::*;
+        [
+            RootSignature,
+            VertexShader,
+            PixelShader,
+            DomainShader,
+            HullShader,
+            GeometryShader,
+            ComputeShader,
+            StreamOutput,
+            Blend,
+            SampleMask,
+            Rasterizer,
+            DepthStencil,
+            InputLayout,
+            IbStripCutValue,
+            PrimitiveTopology,
+            RenderTargetFormats,
+            DepthStencilFormat,
+            SampleDesc,
+            NodeMask,
+            CachedPso,
+            Flags,
+            DepthStencil1,
+            ViewInstancing,
+            AmplificationShader,
+            MeshShader,
+        ]
+        .iter()
+        .copied()
+        .find(|ty| *ty as u32 == value)
+    }
+
+    /// Alignment and size of the desc, `None` for the types pointing at
+    /// arrays of their own, stream output and view instancing
+    fn layout(self) -> Option<(usize, usize)> {
+        use SubobjectType::*;
+        Some(match self {
+            RootSignature => (POINTER_SIZE, POINTER_SIZE),
+            VertexShader | PixelShader | DomainShader | HullShader | GeometryShader
+            | ComputeShader | AmplificationShader | MeshShader | CachedPso | InputLayout => {
+                (POINTER_SIZE, 2 * POINTER_SIZE)
+            }
+            Blend => (4, 8 + 40 * SIMULTANEOUS_RENDER_TARGET_COUNT),
+            Rasterizer => (4, 44),
+            DepthStencil => (4, 52),
+            DepthStencil1 => (4, 56),
+            RenderTargetFormats => (4, 4 + 4 * SIMULTANEOUS_RENDER_TARGET_COUNT),
+            SampleDesc => (4, 8),
+            SampleMask | IbStripCutValue | PrimitiveTopology | DepthStencilFormat | NodeMask
+            | Flags => (4, 4),
+            StreamOutput | ViewInstancing => return None,
+        })
+    }
+}
+
+/// `D3D12_SHADER_BYTECODE` or `D3D12_CACHED_PIPELINE_STATE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Blob {
+    pub address: usize,
+    pub size: usize,
+}
+
+impl Blob {
+    pub fn of(bytes: &[u8]) -> Self {
+        Blob {
+            address: bytes.as_ptr() as usize,
+            size: bytes.len(),
+        }
+    }
+}
+
+/// `D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFF`
+pub const IB_STRIP_CUT_VALUE_0XFFFF: u32 = 1;
+/// `D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFFFFFF`
+pub const IB_STRIP_CUT_VALUE_0XFFFFFFFF: u32 = 2;
+
+/// Subobject with its desc, pointers as addresses
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subobject {
+    /// `ID3D12RootSignature*`
+    RootSignature(usize),
+    VertexShader(Blob),
+    PixelShader(Blob),
+    DomainShader(Blob),
+    HullShader(Blob),
+    GeometryShader(Blob),
+    ComputeShader(Blob),
+    AmplificationShader(Blob),
+    MeshShader(Blob),
+    /// Boxed, it's the largest by far
+    Blend(Box<BlendDesc>),
+    SampleMask(u32),
+    Rasterizer(RasterizerDesc),
+    DepthStencil(DepthStencilDesc),
+    /// `D3D12_DEPTH_STENCIL_DESC1`, with `DepthBoundsTestEnable`
+    DepthStencil1(DepthStencilDesc, bool),
+    /// `D3D12_INPUT_LAYOUT_DESC`, the address and count of the
+    /// `D3D12_INPUT_ELEMENT_DESC`s
+    InputLayout(usize, u32),
+    /// `D3D12_INDEX_BUFFER_STRIP_CUT_VALUE`, 0 when disabled
+    IbStripCutValue(u32),
+    PrimitiveTopology(PrimitiveTopologyType),
+    /// `D3D12_RT_FORMAT_ARRAY`, up to `SIMULTANEOUS_RENDER_TARGET_COUNT`
+    RenderTargetFormats(Vec<Format>),
+    DepthStencilFormat(Format),
+    SampleDesc(SampleDesc),
+    NodeMask(u32),
+    CachedPso(Blob),
+    /// `D3D12_PIPELINE_STATE_FLAGS`
+    Flags(u32),
+}
+
+impl Subobject {
+    pub fn subobject_type(&self) -> SubobjectType {
+        match self {
+            Subobject::RootSignature(_) => SubobjectType::RootSignature,
+            Subobject::VertexShader(_) => SubobjectType::VertexShader,
+            Subobject::PixelShader(_) => SubobjectType::PixelShader,
+            Subobject::DomainShader(_) => SubobjectType::DomainShader,
+            Subobject::HullShader(_) => SubobjectType::HullShader,
+            Subobject::GeometryShader(_) => SubobjectType::GeometryShader,
+            Subobject::ComputeShader(_) => SubobjectType::ComputeShader,
+            Subobject::AmplificationShader(_) => SubobjectType::AmplificationShader,
+            Subobject::MeshShader(_) => SubobjectType::MeshShader,
+            Subobject::Blend(_) => SubobjectType::Blend,
+            Subobject::SampleMask(_) => SubobjectType::SampleMask,
+            Subobject::Rasterizer(_) => SubobjectType::Rasterizer,
+            Subobject::DepthStencil(_) => SubobjectType::DepthStencil,
+            Subobject::DepthStencil1(..) => SubobjectType::DepthStencil1,
+            Subobject::InputLayout(..) => SubobjectType::InputLayout,
+            Subobject::IbStripCutValue(_) => SubobjectType::IbStripCutValue,
+            Subobject::PrimitiveTopology(_) => SubobjectType::PrimitiveTopology,
+            Subobject::RenderTargetFormats(_) => SubobjectType::RenderTargetFormats,
+            Subobject::DepthStencilFormat(_) => SubobjectType::DepthStencilFormat,
+            Subobject::SampleDesc(_) => SubobjectType::SampleDesc,
+            Subobject::NodeMask(_) => SubobjectType::NodeMask,
+            Subobject::CachedPso(_) => SubobjectType::CachedPso,
+            Subobject::Flags(_) => SubobjectType::Flags,
+        }
+    }
+
+    /// Appends the subobject, padded to the next pointer
+    fn write(&self, out: &mut Vec<u8>) {
+        let ty = self.subobject_type();
+        let (alignment, size) = ty.layout().expect("subobjects have a layout");
+        let start = out.len();
+        put(out, &[ty as u32]);
+        let desc = start + 4usize.next_multiple_of(alignment);
+        out.resize(desc, 0);
+        match self {
+            Subobject::RootSignature(address) => put_address(out, *address),
+            Subobject::VertexShader(blob)
+            | Subobject::PixelShader(blob)
+            | Subobject::DomainShader(blob)
+            | Subobject::HullShader(blob)
+            | Subobject::GeometryShader(blob)
+            | Subobject::ComputeShader(blob)
+            | Subobject::AmplificationShader(blob)
+            | Subobject::MeshShader(blob)
+            | Subobject::CachedPso(blob) => {
+                put_address(out, blob.address);
+                put_address(out, blob.size);
+            }
+            Subobject::Blend(blend) => {
+                put(
+                    out,
+                    &[
+                        blend.alpha_to_coverage_enable as u32,
+                        blend.independent_blend_enable as u32,
+                    ],
+                );
+                for rt in &blend.render_target {
+                    put(
+                        out,
+                        &[
+                            rt.blend_enable as u32,
+                            rt.logic_op_enable as u32,
+                            rt.src_blend as u32,
+                            rt.dest_blend as u32,
+                            rt.blend_op as u32,
+                            rt.src_blend_alpha as u32,
+                            rt.dest_blend_alpha as u32,
+                            rt.blend_op_alpha as u32,
+                            rt.logic_op as u32,
+                            rt.render_target_write_mask as u32,
+                        ],
+                    );
+                }
+            }
+            Subobject::Rasterizer(rasterizer) => put(
+                out,
+                &[
+                    rasterizer.fill_mode as u32,
+                    rasterizer.cull_mode as u32,
+                    rasterizer.front_counter_clockwise as u32,
+                    rasterizer.depth_bias as u32,
+                    rasterizer.depth_bias_clamp.to_bits(),
+                    rasterizer.slope_scaled_depth_bias.to_bits(),
+                    rasterizer.depth_clip_enable as u32,
+                    rasterizer.multisample_enable as u32,
+                    rasterizer.antialiased_line_enable as u32,
+                    rasterizer.forced_sample_count,
+                    rasterizer.conservative_raster as u32,
+                ],
+            ),
+            Subobject::DepthStencil(depth_stencil) => put_depth_stencil(out, depth_stencil),
+            Subobject::DepthStencil1(depth_stencil, depth_bounds_test_enable) => {
+                put_depth_stencil(out, depth_stencil);
+                put(out, &[*depth_bounds_test_enable as u32]);
+            }
+            Subobject::InputLayout(address, count) => {
+                put_address(out, *address);
+                put(out, &[*count]);
+            }
+            Subobject::RenderTargetFormats(formats) => {
+                assert!(
+                    formats.len() <= SIMULTANEOUS_RENDER_TARGET_COUNT,
+                    "{} render targets, at most {} can be bound",
+                    formats.len(),
+                    SIMULTANEOUS_RENDER_TARGET_COUNT
+                );
+                for i in 0..SIMULTANEOUS_RENDER_TARGET_COUNT {
+                    put(
+                        out,
+                        &[formats.get(i).copied().unwrap_or(Format::Unknown) as u32],
+                    );
+                }
+                put(out, &[formats.len() as u32]);
+            }
+            Subobject::SampleDesc(sample_desc) => {
+                put(out, &[sample_desc.count, sample_desc.quality])
+            }
+            Subobject::PrimitiveTopology(topology) => put(out, &[*topology as u32]),
+            Subobject::DepthStencilFormat(format) => put(out, &[*format as u32]),
+            Subobject::SampleMask(value)
+            | Subobject::IbStripCutValue(value)
+            | Subobject::NodeMask(value)
+            | Subobject::Flags(value) => put(out, &[*value]),
+        }
+        debug_assert!(out.len() <= desc + size);
+        out.resize((desc + size).next_multiple_of(POINTER_SIZE), 0);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// Subobject past the end of the stream
+    Truncated,
+    /// Value that isn't one of its enum, e.g. `("blend", 12)`
+    Invalid(&'static str, u32),
+    Unsupported(SubobjectType),
+    /// D3D12 rejects streams with a subobject type twice
+    Duplicate(SubobjectType),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Truncated => write!(f, "truncated pipeline state stream"),
+            StreamError::Invalid(what, value) => write!(f, "invalid {} {}", what, value),
+            StreamError::Unsupported(ty) => write!(f, "unsupported {:?} subobject", ty),
+            StreamError::Duplicate(ty) => write!(f, "more than one {:?} subobject", ty),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Subobjects serialized for `D3D12_PIPELINE_STATE_STREAM_DESC`
+#[derive(Debug, Clone, Default)]
+pub struct PipelineStateStream {
+    /// Pointer sized words so the descs are aligned in place
+    words: Vec<usize>,
+    types: Vec<SubobjectType>,
+}
+
+impl PipelineStateStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Panics when the stream has a subobject of the type already
+    pub fn subobject(mut self, subobject: Subobject) -> Self {
+        let ty = subobject.subobject_type();
+        assert!(
+            !self.types.contains(&ty),
+            "more than one {:?} subobject",
+            ty
+        );
+        let mut bytes = Vec::new();
+        subobject.write(&mut bytes);
+        self.words.extend(
+            bytes
+                .chunks_exact(POINTER_SIZE)
+                .map(|word| usize::from_ne_bytes(word.try_into().unwrap())),
+        );
+        self.types.push(ty);
+        self
+    }
+
+    /// Stream of what `D3D12_GRAPHICS_PIPELINE_STATE_DESC` holds, in its
+    /// field order
+    ///
+    /// `root_signature` and `input_elements` are the addresses of the
+    /// `ID3D12RootSignature` and of the `D3D12_INPUT_ELEMENT_DESC`s of
+    /// `desc.input_layout`, the backend creates those. Empty shaders are left
+    /// out.
+    pub fn graphics(
+        desc: &GraphicsPipelineDesc,
+        root_signature: usize,
+        input_elements: usize,
+    ) -> Self {
+        let mut stream = Self::new().subobject(Subobject::RootSignature(root_signature));
+        if !desc.vertex_shader.is_empty() {
+            stream = stream.subobject(Subobject::VertexShader(Blob::of(desc.vertex_shader)));
+        }
+        if !desc.pixel_shader.is_empty() {
+            stream = stream.subobject(Subobject::PixelShader(Blob::of(desc.pixel_shader)));
+        }
+        stream
+            .subobject(Subobject::Blend(Box::new(desc.blend)))
+            .subobject(Subobject::SampleMask(desc.sample_mask))
+            .subobject(Subobject::Rasterizer(desc.rasterizer))
+            .subobject(Subobject::DepthStencil(desc.depth_stencil))
+            .subobject(Subobject::InputLayout(
+                input_elements,
+                desc.input_layout.len() as u32,
+            ))
+            .subobject(Subobject::PrimitiveTopology(desc.primitive_topology_type))
+            .subobject(Subobject::RenderTargetFormats(
+                desc.render_target_formats.clone(),
+            ))
+            .subobject(Subobject::DepthStencilFormat(desc.depth_stencil_format))
+            .subobject(Subobject::SampleDesc(desc.sample_desc))
+    }
+
+    /// `pPipelineStateSubobjectStream`, aligned to a pointer
+    pub fn as_bytes(&self) -> &[u8] {
+        // Plain words, viewed as the bytes they were made of
+        unsafe {
+            std::slice::from_raw_parts(
+                self.words.as_ptr() as *const u8,
+                self.words.len() * POINTER_SIZE,
+            )
+        }
+    }
+
+    /// `SizeInBytes`
+    pub fn size_in_bytes(&self) -> usize {
+        self.words.len() * POINTER_SIZE
+    }
+
+    pub fn subobjects(&self) -> Vec<Subobject> {
+        parse(self.as_bytes()).expect("Streams parse as they were written")
+    }
+}
+
+/// Subobjects of a stream, in order
+pub fn parse(bytes: &[u8]) -> Result<Vec<Subobject>, StreamError> {
+    let reader = Reader { bytes };
+    let mut subobjects: Vec<Subobject> = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let value = reader.word(offset)?;
+        let ty =
+            SubobjectType::from_u32(value).ok_or(StreamError::Invalid("subobject type", value))?;
+        let (alignment, size) = ty.layout().ok_or(StreamError::Unsupported(ty))?;
+        if subobjects.iter().any(|known| known.subobject_type() == ty) {
+            return Err(StreamError::Duplicate(ty));
+        }
+        let at = offset + 4usize.next_multiple_of(alignment);
+        let end = (at + size).next_multiple_of(POINTER_SIZE);
+        if end > bytes.len() {
+            return Err(StreamError::Truncated);
+        }
+        let blob = || -> Result<Blob, StreamError> {
+            Ok(Blob {
+                address: reader.address(at)?,
+                size: reader.address(at + POINTER_SIZE)?,
+            })
+        };
+        subobjects.push(match ty {
+            SubobjectType::RootSignature => Subobject::RootSignature(reader.address(at)?),
+            SubobjectType::VertexShader => Subobject::VertexShader(blob()?),
+            SubobjectType::PixelShader => Subobject::PixelShader(blob()?),
+            SubobjectType::DomainShader => Subobject::DomainShader(blob()?),
+            SubobjectType::HullShader => Subobject::HullShader(blob()?),
+            SubobjectType::GeometryShader => Subobject::GeometryShader(blob()?),
+            SubobjectType::ComputeShader => Subobject::ComputeShader(blob()?),
+            SubobjectType::AmplificationShader => Subobject::AmplificationShader(blob()?),
+            SubobjectType::MeshShader => Subobject::MeshShader(blob()?),
+            SubobjectType::CachedPso => Subobject::CachedPso(blob()?),
+            SubobjectType::Blend => Subobject::Blend(Box::new(reader.blend(at)?)),
+            SubobjectType::Rasterizer => Subobject::Rasterizer(reader.rasterizer(at)?),
+            SubobjectType::DepthStencil => Subobject::DepthStencil(reader.depth_stencil(at)?),
+            SubobjectType::DepthStencil1 => {
+                Subobject::DepthStencil1(reader.depth_stencil(at)?, reader.boolean(at + 52)?)
+            }
+            SubobjectType::InputLayout => {
+                Subobject::InputLayout(reader.address(at)?, reader.word(at + POINTER_SIZE)?)
+            }
+            SubobjectType::RenderTargetFormats => {
+                let count = reader.word(at + 4 * SIMULTANEOUS_RENDER_TARGET_COUNT)?;
+                if count as usize > SIMULTANEOUS_RENDER_TARGET_COUNT {
+                    return Err(StreamError::Invalid("render target count", count));
+                }
+                let formats = (0..count as usize)
+                    .map(|i| reader.value(at + 4 * i, "format", Format::from_u32))
+                    .collect::<Result<_, _>>()?;
+                Subobject::RenderTargetFormats(formats)
+            }
+            SubobjectType::SampleDesc => Subobject::SampleDesc(SampleDesc {
+                count: reader.word(at)?,
+                quality: reader.word(at + 4)?,
+            }),
+            SubobjectType::PrimitiveTopology => Subobject::PrimitiveTopology(reader.value(
+                at,
+                "primitive topology type",
+                PrimitiveTopologyType::from_u32,
+            )?),
+            SubobjectType::DepthStencilFormat => {
+                Subobject::DepthStencilFormat(reader.value(at, "format", Format::from_u32)?)
+            }
+            SubobjectType::SampleMask => Subobject::SampleMask(reader.word(at)?),
+            SubobjectType::IbStripCutValue => Subobject::IbStripCutValue(reader.word(at)?),
+            SubobjectType::NodeMask => Subobject::NodeMask(reader.word(at)?),
+            SubobjectType::Flags => Subobject::Flags(reader.word(at)?),
+            SubobjectType::StreamOutput | SubobjectType::ViewInstancing => unreachable!(),
+        });
+        offset = end;
+    }
+    Ok(subobjects)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn word(&self, pos: usize) -> Result<u32, StreamError> {
+        self.bytes
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or(StreamError::Truncated)
+    }
+
+    /// `BOOL`, any non-zero value is true
+    fn boolean(&self, pos: usize) -> Result<bool, StreamError> {
+        Ok(self.word(pos)? != 0)
+    }
+
+    fn address(&self, pos: usize) -> Result<usize, StreamError> {
+        self.bytes
+            .get(pos..pos + POINTER_SIZE)
+            .map(|b| usize::from_le_bytes(b.try_into().unwrap()))
+            .ok_or(StreamError::Truncated)
+    }
+
+    fn value<T>(
+        &self,
+        pos: usize,
+        what: &'static str,
+        from_u32: fn(u32) -> Option<T>,
+    ) -> Result<T, StreamError> {
+        let value = self.word(pos)?;
+        from_u32(value).ok_or(StreamError::Invalid(what, value))
+    }
+
+    fn blend(&self, at: usize) -> Result<BlendDesc, StreamError> {
+        let mut blend = BlendDesc {
+            alpha_to_coverage_enable: self.boolean(at)?,
+            independent_blend_enable: self.boolean(at + 4)?,
+            ..Default::default()
+        };
+        for (i, rt) in blend.render_target.iter_mut().enumerate() {
+            let at = at + 8 + 40 * i;
+            let write_mask = self.word(at + 36)?;
+            *rt = RenderTargetBlendDesc {
+                blend_enable: self.boolean(at)?,
+                logic_op_enable: self.boolean(at + 4)?,
+                src_blend: self.value(at + 8, "blend", Blend::from_u32)?,
+                dest_blend: self.value(at + 12, "blend", Blend::from_u32)?,
+                blend_op: self.value(at + 16, "blend op", BlendOp::from_u32)?,
+                src_blend_alpha: self.value(at + 20, "blend", Blend::from_u32)?,
+                dest_blend_alpha: self.value(at + 24, "blend", Blend::from_u32)?,
+                blend_op_alpha: self.value(at + 28, "blend op", BlendOp::from_u32)?,
+                logic_op: self.value(at + 32, "logic op", LogicOp::from_u32)?,
+                render_target_write_mask: write_mask
+                    .try_into()
+                    .map_err(|_| StreamError::Invalid("write mask", write_mask))?,
+            };
+        }
+        Ok(blend)
+    }
+
+    fn rasterizer(&self, at: usize) -> Result<RasterizerDesc, StreamError> {
+        Ok(RasterizerDesc {
+            fill_mode: self.value(at, "fill mode", FillMode::from_u32)?,
+            cull_mode: self.value(at + 4, "cull mode", CullMode::from_u32)?,
+            front_counter_clockwise: self.boolean(at + 8)?,
+            depth_bias: self.word(at + 12)? as i32,
+            depth_bias_clamp: f32::from_bits(self.word(at + 16)?),
+            slope_scaled_depth_bias: f32::from_bits(self.word(at + 20)?),
+            depth_clip_enable: self.boolean(at + 24)?,
+            multisample_enable: self.boolean(at + 28)?,
+            antialiased_line_enable: self.boolean(at + 32)?,
+            forced_sample_count: self.word(at + 36)?,
+            conservative_raster: self.value(
+                at + 40,
+                "conservative rasterization mode",
+                ConservativeRasterizationMode::from_u32,
+            )?,
+        })
+    }
+
+    fn depth_stencil(&self, at: usize) -> Result<DepthStencilDesc, StreamError> {
+        let face = |at: usize| -> Result<DepthStencilOpDesc, StreamError> {
+            Ok(DepthStencilOpDesc {
+                stencil_fail_op: self.value(at, "stencil op", StencilOp::from_u32)?,
+                stencil_depth_fail_op: self.value(at + 4, "stencil op", StencilOp::from_u32)?,
+                stencil_pass_op: self.value(at + 8, "stencil op", StencilOp::from_u32)?,
+                stencil_func: self.value(
+                    at + 12,
+                    "comparison function",
+                    ComparisonFunc::from_u32,
+                )?,
+            })
+        };
+        Ok(DepthStencilDesc {
+            depth_enable: self.boolean(at)?,
+            depth_write_mask: self.value(at + 4, "depth write mask", DepthWriteMask::from_u32)?,
+            depth_func: self.value(at + 8, "comparison function", ComparisonFunc::from_u32)?,
+            stencil_enable: self.boolean(at + 12)?,
+            stencil_read_mask: self.bytes[at + 16],
+            stencil_write_mask: self.bytes[at + 17],
+            front_face: face(at + 20)?,
+            back_face: face(at + 36)?,
+        })
+    }
+}
+
+fn put(out: &mut Vec<u8>, words: &[u32]) {
+    for word in words {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn put_address(out: &mut Vec<u8>, address: usize) {
+    out.extend_from_slice(&address.to_le_bytes());
+}
+
+/// `D3D12_DEPTH_STENCIL_DESC`, the two masks are bytes
+fn put_depth_stencil(out: &mut Vec<u8>, desc: &DepthStencilDesc) {
+    put(
+        out,
+        &[
+            desc.depth_enable as u32,
+            desc.depth_write_mask as u32,
+            desc.depth_func as u32,
+            desc.stencil_enable as u32,
+        ],
+    );
+    out.extend_from_slice(&[desc.stencil_read_mask, desc.stencil_write_mask, 0, 0]);
+    for face in &[desc.front_face, desc.back_face] {
+        put(
+            out,
+            &[
+                face.stencil_fail_op as u32,
+                face.stencil_depth_fail_op as u32,
+                face.stencil_pass_op as u32,
+                face.stencil_func as u32,
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::{Vertex, VertexLayout};
+
+    fn words(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    fn patch(data: &mut [u8], pos: usize, word: u32) {
+        data[pos..pos + 4].copy_from_slice(&word.to_le_bytes());
+    }
+
+    // Offsets of the x64 d3dx12 CD3DX12_PIPELINE_STATE_STREAM_* subobjects
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn writes_subobject_layout() {
+        let (vertex_shader, pixel_shader) = ([1; 3], [2; 5]);
+        let desc = GraphicsPipelineDesc::opaque()
+            .shaders(&vertex_shader, &pixel_shader)
+            .input_layout(Vertex::LAYOUT)
+            .render_targets(&[Format::B8G8R8A8Unorm])
+            .depth_stencil_format(Format::D32Float);
+        let stream = PipelineStateStream::graphics(&desc, 0x1000, 0x2000);
+        let bytes = stream.as_bytes();
+        assert_eq!(bytes.as_ptr() as usize % POINTER_SIZE, 0);
+        assert_eq!(stream.size_in_bytes(), 608);
+
+        let w = words(bytes);
+        let types = [
+            (0, SubobjectType::RootSignature),
+            (16, SubobjectType::VertexShader),
+            (40, SubobjectType::PixelShader),
+            (64, SubobjectType::Blend),
+            (400, SubobjectType::SampleMask),
+            (408, SubobjectType::Rasterizer),
+            (456, SubobjectType::DepthStencil),
+            (512, SubobjectType::InputLayout),
+            (536, SubobjectType::PrimitiveTopology),
+            (544, SubobjectType::RenderTargetFormats),
+            (584, SubobjectType::DepthStencilFormat),
+            (592, SubobjectType::SampleDesc),
+        ];
+        for (offset, ty) in &types {
+            assert_eq!(w[offset / 4], *ty as u32, "{:?}", ty);
+        }
+        // Pointers start after a word of padding
+        assert_eq!(w[1..4], [0, 0x1000, 0]);
+        let address = u64::from_le_bytes(bytes[24..32].try_into().unwrap());
+        assert_eq!(address, vertex_shader.as_ptr() as u64);
+        assert_eq!(w[8..10], [3, 0]);
+        assert_eq!(w[14..16], [5, 0]);
+        // Other descs follow the type, the first render target's blend after
+        // the two enables
+        assert_eq!(w[17..19], [0, 0]);
+        assert_eq!(w[19..29], [0, 0, 2, 1, 1, 2, 1, 1, 4, 0xf]);
+        assert_eq!(w[101], 0xffff_ffff);
+        assert_eq!(w[103..114], [3, 3, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(w[115..128], [1, 1, 2, 0, 0xffff, 1, 1, 1, 8, 1, 1, 1, 8]);
+        assert_eq!(w[130..133], [0x2000, 0, 2]);
+        assert_eq!(w[135], 3);
+        assert_eq!(w[137..146], [87, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(w[147], 40);
+        assert_eq!(w[149..152], [1, 0, 0]);
+
+        // Empty shaders are left out
+        let stream = PipelineStateStream::graphics(&GraphicsPipelineDesc::default(), 0, 0);
+        assert_eq!(stream.size_in_bytes(), 608 - 2 * 24);
+    }
+
+    #[test]
+    fn round_trips_subobjects() {
+        let shader = [0x44, 0x58, 0x42, 0x43];
+        let depth_stencil = DepthStencilDesc {
+            stencil_enable: true,
+            stencil_read_mask: 0x0f,
+            stencil_write_mask: 0xf0,
+            back_face: DepthStencilOpDesc {
+                stencil_pass_op: StencilOp::IncrSat,
+                stencil_func: ComparisonFunc::Equal,
+                ..Default::default()
+            },
+            ..DepthStencilDesc::read_only()
+        };
+        let mut blend = BlendDesc::all(RenderTargetBlendDesc::alpha_blend());
+        blend.independent_blend_enable = true;
+        blend.render_target[3] = RenderTargetBlendDesc {
+            logic_op_enable: true,
+            logic_op: LogicOp::Xor,
+            render_target_write_mask: 0x3,
+            ..Default::default()
+        };
+        let subobjects = vec![
+            Subobject::RootSignature(usize::MAX),
+            Subobject::MeshShader(Blob::of(&shader)),
+            Subobject::AmplificationShader(Blob::of(&shader[1..])),
+            Subobject::PixelShader(Blob::of(&shader)),
+            Subobject::Blend(Box::new(blend)),
+            Subobject::SampleMask(0x5),
+            Subobject::Rasterizer(RasterizerDesc {
+                fill_mode: FillMode::Wireframe,
+                depth_bias: -2,
+                slope_scaled_depth_bias: 1.5,
+                conservative_raster: ConservativeRasterizationMode::On,
+                ..Default::default()
+            }),
+            Subobject::DepthStencil1(depth_stencil, true),
+            Subobject::InputLayout(0, 0),
+            Subobject::IbStripCutValue(IB_STRIP_CUT_VALUE_0XFFFF),
+            Subobject::PrimitiveTopology(PrimitiveTopologyType::Line),
+            Subobject::RenderTargetFormats(vec![Format::R16G16B16A16Float; 8]),
+            Subobject::DepthStencilFormat(Format::D24UnormS8Uint),
+            Subobject::SampleDesc(SampleDesc {
+                count: 4,
+                quality: 1,
+            }),
+            Subobject::NodeMask(1),
+            Subobject::CachedPso(Blob::of(&[])),
+            Subobject::Flags(1),
+        ];
+        let stream = subobjects
+            .iter()
+            .cloned()
+            .fold(PipelineStateStream::new(), PipelineStateStream::subobject);
+        assert_eq!(parse(stream.as_bytes()), Ok(subobjects));
+
+        let desc = GraphicsPipelineDesc::overlay()
+            .shaders(&shader, &shader)
+            .render_targets(&[Format::R8G8B8A8Unorm, Format::R32Float]);
+        let subobjects = PipelineStateStream::graphics(&desc, 0x10, 0x20).subobjects();
+        assert_eq!(subobjects.len(), 12);
+        assert_eq!(subobjects[3], Subobject::Blend(Box::new(desc.blend)));
+        assert_eq!(subobjects[5], Subobject::Rasterizer(desc.rasterizer));
+        assert_eq!(
+            subobjects[9],
+            Subobject::RenderTargetFormats(desc.render_target_formats)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_streams() {
+        let bytes = PipelineStateStream::new()
+            .subobject(Subobject::SampleMask(u32::MAX))
+            .subobject(Subobject::Rasterizer(RasterizerDesc::default()))
+            .as_bytes()
+            .to_vec();
+        let raster = 8;
+        assert_eq!(
+            parse(&bytes[..bytes.len() - 4]),
+            Err(StreamError::Truncated)
+        );
+        assert_eq!(
+            parse(&[&bytes[..], &bytes[..]].concat())
+                .unwrap_err()
+                .to_string(),
+            "more than one SampleMask subobject"
+        );
+
+        let mut bad = bytes.clone();
+        patch(&mut bad, raster + 8, 4);
+        assert_eq!(parse(&bad).unwrap_err().to_string(), "invalid cull mode 4");
+        patch(&mut bad, raster, 23);
+        assert_eq!(parse(&bad), Err(StreamError::Invalid("subobject type", 23)));
+        patch(&mut bad, raster, SubobjectType::StreamOutput as u32);
+        assert_eq!(
+            parse(&bad),
+            Err(StreamError::Unsupported(SubobjectType::StreamOutput))
+        );
+    }
+}
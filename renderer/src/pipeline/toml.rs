@@ -0,0 +1,633 @@
+//! The subset of TOML pipeline definitions are written in
+//!
+//! Tables, arrays of tables, dotted keys, inline tables, arrays, strings,
+//! integers, floats and booleans. Multi-line strings and dates aren't read.
+//! Values keep their line so the schema can point at them.
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Item>),
+    Table(Table),
+}
+
+impl Value {
+    /// Name of the type for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+/// Value and the line it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub value: Value,
+    pub line: u32,
+}
+
+/// Keys in the order written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub entries: Vec<(String, Item)>,
+}
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Item> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, item)| item)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Item> {
+        self.entries
+            .iter_mut()
+            .find(|(name, _)| name == key)
+            .map(|(_, item)| item)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(text: &str) -> Result<Table, ParseError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+        line: 1,
+        line_start: 0,
+    };
+    let mut root = Table::default();
+    // Headers written so far, a table can't be opened twice
+    let mut headers: Vec<Vec<String>> = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        let c = match parser.peek() {
+            Some(c) => c,
+            None => return Ok(root),
+        };
+        let line = parser.line;
+        if c == '[' {
+            parser.at += 1;
+            let array = parser.eat('[');
+            parser.skip_spaces();
+            let path = parser.key()?;
+            parser.skip_spaces();
+            let closing = if array { "]]" } else { "]" };
+            if !closing.chars().all(|c| parser.eat(c)) {
+                return Err(parser.error(format!("expected `{}` after the table name", closing)));
+            }
+            parser.end_of_line()?;
+
+            let (parent, last) = path.split_at(path.len() - 1);
+            let table = table_at(&mut root, parent, line).map_err(|m| parser.error_at(line, m))?;
+            if array {
+                match table.get_mut(&last[0]) {
+                    None => table.entries.push((
+                        last[0].clone(),
+                        Item {
+                            value: Value::Array(Vec::new()),
+                            line,
+                        },
+                    )),
+                    Some(Item {
+                        value: Value::Array(_),
+                        ..
+                    }) => {}
+                    Some(_) => {
+                        return Err(
+                            parser.error_at(line, format!("{} is not an array", dotted(&path)))
+                        )
+                    }
+                }
+                if let Some(Item {
+                    value: Value::Array(items),
+                    ..
+                }) = table.get_mut(&last[0])
+                {
+                    items.push(Item {
+                        value: Value::Table(Table::default()),
+                        line,
+                    });
+                }
+            } else {
+                if headers.contains(&path) {
+                    return Err(
+                        parser.error_at(line, format!("table {} defined twice", dotted(&path)))
+                    );
+                }
+                headers.push(path.clone());
+                table_at(&mut root, &path, line).map_err(|m| parser.error_at(line, m))?;
+            }
+            current = path;
+        } else {
+            let (key, item) = parser.key_value()?;
+            parser.end_of_line()?;
+            let table =
+                table_at(&mut root, &current, line).map_err(|m| parser.error_at(line, m))?;
+            insert(table, &key, item).map_err(|m| parser.error_at(line, m))?;
+        }
+    }
+}
+
+fn dotted(path: &[String]) -> String {
+    path.join(".")
+}
+
+/// Table of a dotted path, missing tables are created and arrays of tables
+/// lead to their last table
+fn table_at<'t>(table: &'t mut Table, path: &[String], line: u32) -> Result<&'t mut Table, String> {
+    let mut table = table;
+    for (i, key) in path.iter().enumerate() {
+        if table.get(key).is_none() {
+            table.entries.push((
+                key.clone(),
+                Item {
+                    value: Value::Table(Table::default()),
+                    line,
+                },
+            ));
+        }
+        let item = table.get_mut(key).expect("inserted above");
+        table = match &mut item.value {
+            Value::Table(table) => table,
+            Value::Array(items) => match items.last_mut() {
+                Some(Item {
+                    value: Value::Table(table),
+                    ..
+                }) => table,
+                _ => return Err(format!("{} is not a table", dotted(&path[..=i]))),
+            },
+            _ => return Err(format!("{} is not a table", dotted(&path[..=i]))),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Table, key: &[String], item: Item) -> Result<(), String> {
+    let (parent, last) = key.split_at(key.len() - 1);
+    let table = table_at(table, parent, item.line)?;
+    if table.get(&last[0]).is_some() {
+        return Err(format!("{} defined twice", dotted(key)));
+    }
+    table.entries.push((last[0].clone(), item));
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+    line: u32,
+    line_start: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: (self.at - self.line_start) as u32 + 1,
+            message,
+        }
+    }
+
+    /// Error for a whole line, pointing at its start
+    fn error_at(&self, line: u32, message: String) -> ParseError {
+        ParseError {
+            line,
+            column: 1,
+            message,
+        }
+    }
+
+    fn newline(&mut self) {
+        self.at += 1;
+        self.line += 1;
+        self.line_start = self.at;
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.at += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.at += 1;
+            }
+        }
+    }
+
+    /// Whitespace, comments and newlines, e.g. between array values
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => self.newline(),
+                Some('\r') => self.at += 1,
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_spaces();
+        self.skip_comment();
+        self.eat('\r');
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.newline();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected the end of the line, found `{}`", c))),
+        }
+    }
+
+    /// Dotted key, e.g. `rasterizer.cull` or `"quoted key"`
+    fn key(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.at;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.at += 1;
+                    }
+                    if start == self.at {
+                        return Err(match self.peek() {
+                            Some(c) if c != '\n' => {
+                                self.error(format!("expected a key, found `{}`", c))
+                            }
+                            _ => self.error("expected a key".to_string()),
+                        });
+                    }
+                    self.chars[start..self.at].iter().collect()
+                }
+            };
+            path.push(part);
+            self.skip_spaces();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key_value(&mut self) -> Result<(Vec<String>, Item), ParseError> {
+        let key = self.key()?;
+        self.skip_spaces();
+        if !self.eat('=') {
+            return Err(self.error(format!("expected `=` after {}", dotted(&key))));
+        }
+        self.skip_spaces();
+        let item = self.value()?;
+        Ok((key, item))
+    }
+
+    fn value(&mut self) -> Result<Item, ParseError> {
+        let line = self.line;
+        let value = match self.peek() {
+            Some('"') => Value::String(self.basic_string()?),
+            Some('\'') => Value::String(self.literal_string()?),
+            Some('[') => self.array()?,
+            Some('{') => self.inline_table()?,
+            _ => self.scalar()?,
+        };
+        Ok(Item { value, line })
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        if self.chars[self.at..].starts_with(&['"', '"', '"']) {
+            return Err(self.error("multi-line strings aren't supported".to_string()));
+        }
+        self.at += 1;
+        let mut string = String::new();
+        loop {
+            let c = match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some(c) => c,
+            };
+            self.at += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.peek();
+                    self.at += 1;
+                    let c = match escape {
+                        Some('b') => '\u{8}',
+                        Some('t') => '\t',
+                        Some('n') => '\n',
+                        Some('f') => '\u{c}',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(u @ 'u') | Some(u @ 'U') => {
+                            let len = if u == 'u' { 4 } else { 8 };
+                            let digits: String =
+                                self.chars.iter().skip(self.at).take(len).collect();
+                            self.at += len;
+                            u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == len)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    self.error(format!("invalid escape \\{}{}", u, digits))
+                                })?
+                        }
+                        Some(c) => return Err(self.error(format!("invalid escape \\{}", c))),
+                        None => return Err(self.error("unterminated string".to_string())),
+                    };
+                    string.push(c);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        self.at += 1;
+        let start = self.at;
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some('\'') => {
+                    let string = self.chars[start..self.at].iter().collect();
+                    self.at += 1;
+                    return Ok(string);
+                }
+                Some(_) => self.at += 1,
+            }
+        }
+    }
+
+    /// Values may span lines, a trailing comma is allowed
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.at += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected `,` or `]` in the array".to_string()));
+            }
+        }
+    }
+
+    /// On one line, without a trailing comma
+    fn inline_table(&mut self) -> Result<Value, ParseError> {
+        self.at += 1;
+        let mut table = Table::default();
+        self.skip_spaces();
+        if self.eat('}') {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            self.skip_spaces();
+            let (key, item) = self.key_value()?;
+            let line = item.line;
+            insert(&mut table, &key, item).map_err(|m| self.error_at(line, m))?;
+            self.skip_spaces();
+            if self.eat('}') {
+                return Ok(Value::Table(table));
+            }
+            if !self.eat(',') {
+                return Err(self.error("expected `,` or `}` in the inline table".to_string()));
+            }
+        }
+    }
+
+    /// Boolean, integer or float
+    fn scalar(&mut self) -> Result<Value, ParseError> {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || "+-._".contains(c))
+        {
+            self.at += 1;
+        }
+        let word: String = self.chars[start..self.at].iter().collect();
+        let unexpected = |parser: &Self| {
+            let message = match word.as_str() {
+                "" => match parser.peek() {
+                    Some(c) if c != '\n' && c != '\r' => format!("expected a value, found `{}`", c),
+                    _ => "expected a value".to_string(),
+                },
+                _ if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    format!("expected a value, found `{}`, strings need quotes", word)
+                }
+                _ => format!("invalid number `{}`", word),
+            };
+            ParseError {
+                column: (start - parser.line_start) as u32 + 1,
+                ..parser.error(message)
+            }
+        };
+        match word.as_str() {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            "inf" | "+inf" => return Ok(Value::Float(f64::INFINITY)),
+            "-inf" => return Ok(Value::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Float(f64::NAN)),
+            _ => {}
+        }
+        // Underscores only between digits
+        let valid = word.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+            && !word.contains("__")
+            && !word.ends_with('_');
+        let digits = word.replace('_', "");
+        if !valid {
+            return Err(unexpected(self));
+        }
+        if let Some(hex) = digits.strip_prefix("0x") {
+            return i64::from_str_radix(hex, 16)
+                .map(Value::Integer)
+                .map_err(|_| unexpected(self));
+        }
+        if digits.contains(['.', 'e', 'E']) {
+            return digits
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| unexpected(self));
+        }
+        digits
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| unexpected(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get<'t>(table: &'t Table, path: &str) -> &'t Value {
+        let mut value = None;
+        let mut table = table;
+        for key in path.split('.') {
+            let item = table.get(key).unwrap_or_else(|| panic!("no {}", path));
+            if let Value::Table(inner) = &item.value {
+                table = inner;
+            }
+            value = Some(&item.value);
+        }
+        value.unwrap()
+    }
+
+    #[test]
+    fn parses_tables_and_values() {
+        let text = r#"
+# Comment
+title = "tri\tangle \u00e9" # after a value
+'literal' = 'C:\shaders'
+
+[triangle]
+count = 1_000
+bias = -0.5
+scale = 2e3
+enabled = true
+mask = 0xff
+formats = [
+    "R8G8B8A8_UNORM", # first
+    "D32_FLOAT",
+]
+defines = { VERTEX_COLOR = "1", QUALITY = 2 }
+rasterizer.cull = "none"
+
+[triangle.blend]
+preset = "additive"
+
+[[triangle.input]]
+semantic = "POSITION"
+
+[[triangle.input]]
+semantic = "COLOR"
+"#;
+        let table = parse(text).unwrap();
+        assert_eq!(get(&table, "title"), &Value::String("tri\tangle é".into()));
+        assert_eq!(get(&table, "literal"), &Value::String("C:\\shaders".into()));
+        assert_eq!(get(&table, "triangle.count"), &Value::Integer(1000));
+        assert_eq!(get(&table, "triangle.bias"), &Value::Float(-0.5));
+        assert_eq!(get(&table, "triangle.scale"), &Value::Float(2000.0));
+        assert_eq!(get(&table, "triangle.enabled"), &Value::Boolean(true));
+        assert_eq!(get(&table, "triangle.mask"), &Value::Integer(255));
+        assert_eq!(get(&table, "triangle.defines.QUALITY"), &Value::Integer(2));
+        assert_eq!(
+            get(&table, "triangle.rasterizer.cull"),
+            &Value::String("none".into())
+        );
+        assert_eq!(
+            get(&table, "triangle.blend.preset"),
+            &Value::String("additive".into())
+        );
+
+        match get(&table, "triangle.formats") {
+            Value::Array(items) => {
+                assert_eq!(items.len(), 2);
+                assert_eq!((items[0].line, items[1].line), (13, 14));
+            }
+            other => panic!("{:?}", other),
+        }
+        match get(&table, "triangle.input") {
+            Value::Array(items) => {
+                let semantics = items
+                    .iter()
+                    .map(|item| match &item.value {
+                        Value::Table(table) => table.get("semantic").unwrap().value.clone(),
+                        other => panic!("{:?}", other),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    semantics,
+                    [
+                        Value::String("POSITION".into()),
+                        Value::String("COLOR".into())
+                    ]
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = |text: &str| parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("[a]\ncull = none\n"),
+            "line 2, column 8: expected a value, found `none`, strings need quotes"
+        );
+        assert_eq!(error("a = 1\na = 2"), "line 2, column 1: a defined twice");
+        assert_eq!(
+            error("[a]\n[b]\n[a]"),
+            "line 3, column 1: table a defined twice"
+        );
+        assert_eq!(
+            error("a = \"open\nb = 1"),
+            "line 1, column 10: unterminated string"
+        );
+        assert_eq!(
+            error("a = [1, 2 3]"),
+            "line 1, column 11: expected `,` or `]` in the array"
+        );
+        assert_eq!(
+            error("a = 1 2"),
+            "line 1, column 7: expected the end of the line, found `2`"
+        );
+        assert_eq!(error("a = 1\n[a.b]"), "line 2, column 1: a is not a table");
+        assert_eq!(error("a = 1__0"), "line 1, column 5: invalid number `1__0`");
+    }
+}
@@ -0,0 +1,374 @@
+//! Triangle rasterization following the D3D rules
+//!
+//! Vertices are snapped to 8 bits of subpixel precision, pixels are sampled
+//! at their centers and the top-left rule decides ownership of shared edges.
+//! Triangles are not clipped, ones with a vertex behind the eye are dropped.
+use crate::image::Image;
+use crate::pipeline::{Blend, BlendOp, CullMode, RasterizerDesc, RenderTargetBlendDesc};
+use crate::scene::{Rect, Viewport};
+
+/// Output of the vertex shader, `SV_POSITION` and `COLOR`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipVertex {
+    pub position: [f32; 4],
+    pub color: [f32; 4],
+}
+
+/// Fixed function state used while drawing
+#[derive(Debug, Clone, Copy)]
+pub struct RasterState<'a> {
+    pub viewport: Viewport,
+    pub scissor: Rect,
+    pub rasterizer: &'a RasterizerDesc,
+    pub blend: &'a RenderTargetBlendDesc,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    x: f64,
+    y: f64,
+    z: f64,
+    inv_w: f64,
+    color: [f64; 4],
+}
+
+const SUBPIXEL: f64 = 256.0;
+
+fn to_screen(v: &ClipVertex, viewport: &Viewport) -> Option<ScreenVertex> {
+    let [x, y, z, w] = v.position;
+    if w <= 0.0 {
+        return None;
+    }
+    let inv_w = 1.0 / w as f64;
+    let (nx, ny, nz) = (x as f64 * inv_w, y as f64 * inv_w, z as f64 * inv_w);
+    let sx = viewport.top_left_x as f64 + (nx + 1.0) * 0.5 * viewport.width as f64;
+    let sy = viewport.top_left_y as f64 + (1.0 - ny) * 0.5 * viewport.height as f64;
+    let sz = viewport.min_depth as f64 + nz * (viewport.max_depth - viewport.min_depth) as f64;
+    let c = v.color;
+    Some(ScreenVertex {
+        x: (sx * SUBPIXEL).round() / SUBPIXEL,
+        y: (sy * SUBPIXEL).round() / SUBPIXEL,
+        z: sz,
+        inv_w,
+        color: [
+            c[0] as f64 * inv_w,
+            c[1] as f64 * inv_w,
+            c[2] as f64 * inv_w,
+            c[3] as f64 * inv_w,
+        ],
+    })
+}
+
+fn edge(a: &ScreenVertex, b: &ScreenVertex, px: f64, py: f64) -> f64 {
+    (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x)
+}
+
+/// Top or left edge of a clockwise triangle, `y` grows downwards
+fn is_top_left(a: &ScreenVertex, b: &ScreenVertex) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+fn covers(w: f64, top_left: bool) -> bool {
+    w > 0.0 || (w == 0.0 && top_left)
+}
+
+/// Draw a triangle to the target, returns the number of pixels written
+pub fn draw_triangle(target: &mut Image, state: &RasterState, triangle: &[ClipVertex; 3]) -> u32 {
+    let mut v = [
+        match to_screen(&triangle[0], &state.viewport) {
+            Some(v) => v,
+            None => return 0,
+        },
+        match to_screen(&triangle[1], &state.viewport) {
+            Some(v) => v,
+            None => return 0,
+        },
+        match to_screen(&triangle[2], &state.viewport) {
+            Some(v) => v,
+            None => return 0,
+        },
+    ];
+
+    // Clockwise on screen has positive area
+    let area = edge(&v[0], &v[1], v[2].x, v[2].y);
+    if area == 0.0 {
+        return 0;
+    }
+    let front = (area > 0.0) != state.rasterizer.front_counter_clockwise;
+    match state.rasterizer.cull_mode {
+        CullMode::Back if !front => return 0,
+        CullMode::Front if front => return 0,
+        _ => {}
+    }
+    if area < 0.0 {
+        v.swap(1, 2);
+    }
+    let area = area.abs();
+
+    // Bounds of the triangle, limited by the viewport, scissor and target
+    let vp = &state.viewport;
+    let sc = &state.scissor;
+    let min_x = v.iter().map(|v| v.x).fold(f64::MAX, f64::min).floor();
+    let max_x = v.iter().map(|v| v.x).fold(f64::MIN, f64::max).ceil();
+    let min_y = v.iter().map(|v| v.y).fold(f64::MAX, f64::min).floor();
+    let max_y = v.iter().map(|v| v.y).fold(f64::MIN, f64::max).ceil();
+    let x0 = min_x
+        .max(vp.top_left_x.floor() as f64)
+        .max(sc.left as f64)
+        .max(0.0) as i64;
+    let y0 = min_y
+        .max(vp.top_left_y.floor() as f64)
+        .max(sc.top as f64)
+        .max(0.0) as i64;
+    let x1 = max_x
+        .min((vp.top_left_x + vp.width).ceil() as f64)
+        .min(sc.right as f64)
+        .min(target.width as f64) as i64;
+    let y1 = max_y
+        .min((vp.top_left_y + vp.height).ceil() as f64)
+        .min(sc.bottom as f64)
+        .min(target.height as f64) as i64;
+
+    let top_left = [
+        is_top_left(&v[1], &v[2]),
+        is_top_left(&v[2], &v[0]),
+        is_top_left(&v[0], &v[1]),
+    ];
+    let (z_min, z_max) = (
+        vp.min_depth.min(vp.max_depth) as f64,
+        vp.min_depth.max(vp.max_depth) as f64,
+    );
+
+    let mut written = 0;
+    for y in y0..y1 {
+        let py = y as f64 + 0.5;
+        for x in x0..x1 {
+            let px = x as f64 + 0.5;
+            let w = [
+                edge(&v[1], &v[2], px, py),
+                edge(&v[2], &v[0], px, py),
+                edge(&v[0], &v[1], px, py),
+            ];
+            if !(0..3).all(|i| covers(w[i], top_left[i])) {
+                continue;
+            }
+            let b = [w[0] / area, w[1] / area, w[2] / area];
+            let z = b[0] * v[0].z + b[1] * v[1].z + b[2] * v[2].z;
+            if state.rasterizer.depth_clip_enable && (z < z_min || z > z_max) {
+                continue;
+            }
+
+            // Perspective correct interpolation
+            let inv_w = b[0] * v[0].inv_w + b[1] * v[1].inv_w + b[2] * v[2].inv_w;
+            let mut color = [0.0f32; 4];
+            for (c, out) in color.iter_mut().enumerate() {
+                let sum = b[0] * v[0].color[c] + b[1] * v[1].color[c] + b[2] * v[2].color[c];
+                *out = (sum / inv_w) as f32;
+            }
+
+            let (x, y) = (x as u32, y as u32);
+            let dest = target.color(x, y);
+            target.set_color(x, y, output_merger(color, dest, state.blend));
+            written += 1;
+        }
+    }
+    written
+}
+
+/// Blend the pixel shader output with the render target and apply the write mask
+pub fn output_merger(src: [f32; 4], dest: [f32; 4], desc: &RenderTargetBlendDesc) -> [f32; 4] {
+    let mut out = if desc.blend_enable {
+        let mut out = [0.0; 4];
+        for c in 0..3 {
+            out[c] = blend_op(
+                desc.blend_op,
+                src[c] * factor(desc.src_blend, src, dest, c),
+                dest[c] * factor(desc.dest_blend, src, dest, c),
+            );
+        }
+        out[3] = blend_op(
+            desc.blend_op_alpha,
+            src[3] * factor(desc.src_blend_alpha, src, dest, 3),
+            dest[3] * factor(desc.dest_blend_alpha, src, dest, 3),
+        );
+        out
+    } else {
+        src
+    };
+    for (c, value) in out.iter_mut().enumerate() {
+        // Mask bits are red, green, blue, alpha
+        if desc.render_target_write_mask & (1 << c) == 0 {
+            *value = dest[c];
+        }
+    }
+    out
+}
+
+fn factor(blend: Blend, src: [f32; 4], dest: [f32; 4], c: usize) -> f32 {
+    match blend {
+        Blend::Zero => 0.0,
+        Blend::One => 1.0,
+        Blend::SrcColor => src[c],
+        Blend::InvSrcColor => 1.0 - src[c],
+        Blend::SrcAlpha => src[3],
+        Blend::InvSrcAlpha => 1.0 - src[3],
+        Blend::DestAlpha => dest[3],
+        Blend::InvDestAlpha => 1.0 - dest[3],
+        Blend::DestColor => dest[c],
+        Blend::InvDestColor => 1.0 - dest[c],
+        Blend::SrcAlphaSat => {
+            if c == 3 {
+                1.0
+            } else {
+                src[3].min(1.0 - dest[3])
+            }
+        }
+        // Blend factor, dual source blending and the like are not supported
+        _ => 1.0,
+    }
+}
+
+fn blend_op(op: BlendOp, src: f32, dest: f32) -> f32 {
+    match op {
+        BlendOp::Add => src + dest,
+        BlendOp::Subtract => src - dest,
+        BlendOp::RevSubtract => dest - src,
+        BlendOp::Min => src.min(dest),
+        BlendOp::Max => src.max(dest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32) -> ClipVertex {
+        ClipVertex {
+            position: [x, y, 0.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    fn state<'a>(
+        size: u32,
+        rasterizer: &'a RasterizerDesc,
+        blend: &'a RenderTargetBlendDesc,
+    ) -> RasterState<'a> {
+        RasterState {
+            viewport: Viewport {
+                top_left_x: 0.0,
+                top_left_y: 0.0,
+                width: size as f32,
+                height: size as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            },
+            scissor: Rect {
+                left: 0,
+                top: 0,
+                right: size as i32,
+                bottom: size as i32,
+            },
+            rasterizer,
+            blend,
+        }
+    }
+
+    #[test]
+    fn shared_edge_is_drawn_once() {
+        let rasterizer = RasterizerDesc {
+            cull_mode: CullMode::None,
+            ..Default::default()
+        };
+        // Additive blending shows pixels written twice
+        let blend = RenderTargetBlendDesc {
+            blend_enable: true,
+            src_blend: Blend::SrcAlpha,
+            dest_blend: Blend::One,
+            ..Default::default()
+        };
+        let mut target = Image::new(8, 8);
+        let state = state(8, &rasterizer, &blend);
+        let mut quad = [
+            ClipVertex {
+                color: [0.5, 0.5, 0.5, 0.5],
+                ..vertex(-1.0, 1.0)
+            },
+            ClipVertex {
+                color: [0.5, 0.5, 0.5, 0.5],
+                ..vertex(1.0, 1.0)
+            },
+            ClipVertex {
+                color: [0.5, 0.5, 0.5, 0.5],
+                ..vertex(1.0, -1.0)
+            },
+        ];
+        let first = draw_triangle(&mut target, &state, &quad);
+        quad[1] = quad[2];
+        quad[2] = ClipVertex {
+            color: [0.5, 0.5, 0.5, 0.5],
+            ..vertex(-1.0, -1.0)
+        };
+        let second = draw_triangle(&mut target, &state, &quad);
+
+        assert_eq!(first + second, 64);
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(target.pixel(x, y), [64, 64, 64, 128]);
+            }
+        }
+    }
+
+    #[test]
+    fn culls_back_faces() {
+        let rasterizer = RasterizerDesc::default();
+        let blend = RenderTargetBlendDesc::default();
+        let mut target = Image::new(4, 4);
+        let state = state(4, &rasterizer, &blend);
+        let clockwise = [vertex(0.0, 1.0), vertex(1.0, -1.0), vertex(-1.0, -1.0)];
+        let counter_clockwise = [vertex(0.0, 1.0), vertex(-1.0, -1.0), vertex(1.0, -1.0)];
+        assert_eq!(draw_triangle(&mut target, &state, &counter_clockwise), 0);
+        assert!(draw_triangle(&mut target, &state, &clockwise) > 0);
+    }
+
+    #[test]
+    fn respects_scissor() {
+        let rasterizer = RasterizerDesc::default();
+        let blend = RenderTargetBlendDesc::default();
+        let mut target = Image::new(4, 4);
+        let mut state = state(4, &rasterizer, &blend);
+        state.scissor.right = 1;
+        let full = [vertex(-1.0, 3.0), vertex(3.0, -1.0), vertex(-1.0, -1.0)];
+        assert_eq!(draw_triangle(&mut target, &state, &full), 4);
+        assert_eq!(target.pixel(0, 3), [255, 255, 255, 255]);
+        assert_eq!(target.pixel(1, 3), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn interpolates_colors() {
+        let rasterizer = RasterizerDesc::default();
+        let blend = RenderTargetBlendDesc::default();
+        let mut target = Image::new(2, 1);
+        let state = state(2, &rasterizer, &blend);
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let blue = [0.0, 0.0, 1.0, 1.0];
+        let triangle = [
+            ClipVertex {
+                position: [-1.0, 3.0, 0.0, 1.0],
+                color: red,
+            },
+            ClipVertex {
+                position: [3.0, -1.0, 0.0, 1.0],
+                color: blue,
+            },
+            ClipVertex {
+                position: [-1.0, -1.0, 0.0, 1.0],
+                color: red,
+            },
+        ];
+        draw_triangle(&mut target, &state, &triangle);
+        // Pixel centers are an eighth and three eighths of the way to blue
+        assert_eq!(target.pixel(0, 0), [32, 0, 223, 255]);
+        assert_eq!(target.pixel(1, 0), [96, 0, 159, 255]);
+    }
+}
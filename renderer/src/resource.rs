@@ -0,0 +1,89 @@
+/// Identifies a resource owned by the backend, e.g. a back buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(pub u32);
+
+impl ResourceId {
+    /// Back buffers of the swap chain are the first resources
+    pub const fn back_buffer(index: usize) -> Self {
+        ResourceId(index as u32)
+    }
+}
+
+/// `D3D12_RESOURCE_STATES`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceStates(pub u32);
+
+impl ResourceStates {
+    pub const COMMON: Self = ResourceStates(0);
+    pub const PRESENT: Self = ResourceStates(0);
+    pub const VERTEX_AND_CONSTANT_BUFFER: Self = ResourceStates(0x1);
+    pub const INDEX_BUFFER: Self = ResourceStates(0x2);
+    pub const RENDER_TARGET: Self = ResourceStates(0x4);
+    pub const UNORDERED_ACCESS: Self = ResourceStates(0x8);
+    pub const DEPTH_WRITE: Self = ResourceStates(0x10);
+    pub const DEPTH_READ: Self = ResourceStates(0x20);
+    pub const NON_PIXEL_SHADER_RESOURCE: Self = ResourceStates(0x40);
+    pub const PIXEL_SHADER_RESOURCE: Self = ResourceStates(0x80);
+    pub const STREAM_OUT: Self = ResourceStates(0x100);
+    pub const INDIRECT_ARGUMENT: Self = ResourceStates(0x200);
+    pub const COPY_DEST: Self = ResourceStates(0x400);
+    pub const COPY_SOURCE: Self = ResourceStates(0x800);
+    pub const RESOLVE_DEST: Self = ResourceStates(0x1000);
+    pub const RESOLVE_SOURCE: Self = ResourceStates(0x2000);
+    pub const GENERIC_READ: Self = ResourceStates(0xac3);
+
+    /// States the GPU writes in, these can't be combined with others
+    const WRITE: u32 = 0x4 | 0x8 | 0x10 | 0x100 | 0x400 | 0x1000;
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Combination of read states, `COMMON` is not one
+    pub const fn is_read_only(self) -> bool {
+        self.0 != 0 && self.0 & Self::WRITE == 0
+    }
+
+    /// Write states are exclusive, read states combine freely
+    pub const fn is_valid(self) -> bool {
+        self.0 & Self::WRITE == 0 || self.0.count_ones() == 1
+    }
+}
+
+impl std::ops::BitOr for ResourceStates {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        ResourceStates(self.0 | other.0)
+    }
+}
+
+/// `D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES`
+pub const ALL_SUBRESOURCES: u32 = 0xffff_ffff;
+
+/// `D3D12_RESOURCE_BARRIER`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Barrier {
+    Transition {
+        resource: ResourceId,
+        subresource: u32,
+        before: ResourceStates,
+        after: ResourceStates,
+    },
+}
+
+impl Barrier {
+    /// Transition of all subresources
+    pub const fn transition(
+        resource: ResourceId,
+        before: ResourceStates,
+        after: ResourceStates,
+    ) -> Self {
+        Barrier::Transition {
+            resource,
+            subresource: ALL_SUBRESOURCES,
+            before,
+            after,
+        }
+    }
+}
@@ -0,0 +1,1032 @@
+//! Root signatures and their serialized form
+//!
+//! [`RootSignature`] holds what `D3D12_VERSIONED_ROOT_SIGNATURE_DESC` does,
+//! without the pointers. `to_bytes` writes the blob
+//! `D3D12SerializeVersionedRootSignature` returns, a `DXBC` container with an
+//! `RTS0` chunk, so signatures can be built and compared without Windows.
+//! `from_bytes` reads it back, also out of shaders compiled with a
+//! `[RootSignature]` attribute.
+//!
+//! The `RTS0` chunk is little endian words, offsets count from its start:
+//!
+//! ```text
+//! version, parameter count, parameter offset, sampler count, sampler offset, flags
+//! parameters  type, visibility, payload offset
+//! payloads    table: range count, range offset, then the ranges
+//!             constants: register, space, count
+//!             descriptor: register, space, flags (1.1)
+//! samplers    D3D12_STATIC_SAMPLER_DESC, 13 words each
+//! ```
+//!
+//! Ranges are type, count, base register, space, flags (1.1) and offset.
+pub mod hlsl;
+
+use crate::pipeline::ComparisonFunc;
+use crate::shader::container::{Chunk, Container, ContainerError};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// `D3D_ROOT_SIGNATURE_VERSION`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum RootSignatureVersion {
+    V1_0 = 1,
+    V1_1 = 2,
+}
+
+/// `D3D12_SHADER_VISIBILITY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ShaderVisibility {
+    All = 0,
+    Vertex = 1,
+    Hull = 2,
+    Domain = 3,
+    Geometry = 4,
+    Pixel = 5,
+    Amplification = 6,
+    Mesh = 7,
+}
+
+impl ShaderVisibility {
+    pub fn from_u32(value: u32) -> Option<ShaderVisibility> {
+        use ShaderVisibility::*;
+        [
+            All,
+            Vertex,
+            Hull,
+            Domain,
+            Geometry,
+            Pixel,
+            Amplification,
+            Mesh,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
+macro_rules! flags {
+    ($($name:ident,)*) => {
+        $(impl $name {
+            pub const fn contains(self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+        }
+
+        impl std::ops::BitOr for $name {
+            type Output = Self;
+
+            fn bitor(self, other: Self) -> Self {
+                $name(self.0 | other.0)
+            }
+        })*
+    };
+}
+
+/// `D3D12_ROOT_SIGNATURE_FLAGS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RootSignatureFlags(pub u32);
+
+impl RootSignatureFlags {
+    pub const NONE: Self = RootSignatureFlags(0);
+    pub const ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT: Self = RootSignatureFlags(0x1);
+    pub const DENY_VERTEX_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x2);
+    pub const DENY_HULL_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x4);
+    pub const DENY_DOMAIN_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x8);
+    pub const DENY_GEOMETRY_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x10);
+    pub const DENY_PIXEL_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x20);
+    pub const ALLOW_STREAM_OUTPUT: Self = RootSignatureFlags(0x40);
+    pub const LOCAL_ROOT_SIGNATURE: Self = RootSignatureFlags(0x80);
+    pub const DENY_AMPLIFICATION_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x100);
+    pub const DENY_MESH_SHADER_ROOT_ACCESS: Self = RootSignatureFlags(0x200);
+    pub const CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED: Self = RootSignatureFlags(0x400);
+    pub const SAMPLER_HEAP_DIRECTLY_INDEXED: Self = RootSignatureFlags(0x800);
+}
+
+/// `D3D12_DESCRIPTOR_RANGE_FLAGS`, version 1.1 only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorRangeFlags(pub u32);
+
+impl DescriptorRangeFlags {
+    pub const NONE: Self = DescriptorRangeFlags(0);
+    pub const DESCRIPTORS_VOLATILE: Self = DescriptorRangeFlags(0x1);
+    pub const DATA_VOLATILE: Self = DescriptorRangeFlags(0x2);
+    pub const DATA_STATIC_WHILE_SET_AT_EXECUTE: Self = DescriptorRangeFlags(0x4);
+    pub const DATA_STATIC: Self = DescriptorRangeFlags(0x8);
+    pub const DESCRIPTORS_STATIC_KEEPING_BUFFER_BOUNDS_CHECKS: Self = DescriptorRangeFlags(0x10000);
+}
+
+/// `D3D12_ROOT_DESCRIPTOR_FLAGS`, version 1.1 only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RootDescriptorFlags(pub u32);
+
+impl RootDescriptorFlags {
+    pub const NONE: Self = RootDescriptorFlags(0);
+    pub const DATA_VOLATILE: Self = RootDescriptorFlags(0x2);
+    pub const DATA_STATIC_WHILE_SET_AT_EXECUTE: Self = RootDescriptorFlags(0x4);
+    pub const DATA_STATIC: Self = RootDescriptorFlags(0x8);
+}
+
+flags! {
+    RootSignatureFlags,
+    DescriptorRangeFlags,
+    RootDescriptorFlags,
+}
+
+/// `D3D12_DESCRIPTOR_RANGE_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum DescriptorRangeType {
+    Srv = 0,
+    Uav = 1,
+    Cbv = 2,
+    Sampler = 3,
+}
+
+impl DescriptorRangeType {
+    pub fn from_u32(value: u32) -> Option<DescriptorRangeType> {
+        use DescriptorRangeType::*;
+        [Srv, Uav, Cbv, Sampler].get(value as usize).copied()
+    }
+}
+
+/// `D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND`, the range follows the previous one
+pub const DESCRIPTOR_RANGE_OFFSET_APPEND: u32 = 0xffff_ffff;
+
+/// `D3D12_DESCRIPTOR_RANGE1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DescriptorRange {
+    pub range_type: DescriptorRangeType,
+    /// `u32::MAX` for an unbounded range
+    pub count: u32,
+    pub base_register: u32,
+    pub space: u32,
+    pub flags: DescriptorRangeFlags,
+    /// Descriptors from the start of the table
+    pub offset: u32,
+}
+
+impl DescriptorRange {
+    /// Range appended to the table, in space 0
+    pub fn new(range_type: DescriptorRangeType, count: u32, base_register: u32) -> Self {
+        DescriptorRange {
+            range_type,
+            count,
+            base_register,
+            space: 0,
+            flags: DescriptorRangeFlags::NONE,
+            offset: DESCRIPTOR_RANGE_OFFSET_APPEND,
+        }
+    }
+
+    pub fn srv(count: u32, base_register: u32) -> Self {
+        Self::new(DescriptorRangeType::Srv, count, base_register)
+    }
+
+    pub fn uav(count: u32, base_register: u32) -> Self {
+        Self::new(DescriptorRangeType::Uav, count, base_register)
+    }
+
+    pub fn cbv(count: u32, base_register: u32) -> Self {
+        Self::new(DescriptorRangeType::Cbv, count, base_register)
+    }
+
+    pub fn sampler(count: u32, base_register: u32) -> Self {
+        Self::new(DescriptorRangeType::Sampler, count, base_register)
+    }
+
+    pub fn space(mut self, space: u32) -> Self {
+        self.space = space;
+        self
+    }
+
+    pub fn flags(mut self, flags: DescriptorRangeFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+/// `D3D12_ROOT_DESCRIPTOR1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RootDescriptor {
+    pub register: u32,
+    pub space: u32,
+    pub flags: RootDescriptorFlags,
+}
+
+/// `D3D12_ROOT_PARAMETER_TYPE` with its payload
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParameterKind {
+    DescriptorTable(Vec<DescriptorRange>),
+    /// 32-bit values in a constant buffer
+    Constants {
+        register: u32,
+        space: u32,
+        count: u32,
+    },
+    Cbv(RootDescriptor),
+    Srv(RootDescriptor),
+    Uav(RootDescriptor),
+}
+
+impl ParameterKind {
+    fn type_value(&self) -> u32 {
+        match self {
+            ParameterKind::DescriptorTable(_) => 0,
+            ParameterKind::Constants { .. } => 1,
+            ParameterKind::Cbv(_) => 2,
+            ParameterKind::Srv(_) => 3,
+            ParameterKind::Uav(_) => 4,
+        }
+    }
+}
+
+/// `D3D12_ROOT_PARAMETER1`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RootParameter {
+    pub kind: ParameterKind,
+    pub visibility: ShaderVisibility,
+}
+
+impl RootParameter {
+    /// Visible to all stages
+    pub fn new(kind: ParameterKind) -> Self {
+        RootParameter {
+            kind,
+            visibility: ShaderVisibility::All,
+        }
+    }
+
+    pub fn table(ranges: Vec<DescriptorRange>) -> Self {
+        Self::new(ParameterKind::DescriptorTable(ranges))
+    }
+
+    pub fn constants(count: u32, register: u32, space: u32) -> Self {
+        Self::new(ParameterKind::Constants {
+            register,
+            space,
+            count,
+        })
+    }
+
+    pub fn cbv(register: u32, space: u32) -> Self {
+        Self::new(ParameterKind::Cbv(RootDescriptor::new(register, space)))
+    }
+
+    pub fn srv(register: u32, space: u32) -> Self {
+        Self::new(ParameterKind::Srv(RootDescriptor::new(register, space)))
+    }
+
+    pub fn uav(register: u32, space: u32) -> Self {
+        Self::new(ParameterKind::Uav(RootDescriptor::new(register, space)))
+    }
+
+    pub fn visibility(mut self, visibility: ShaderVisibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+}
+
+impl RootDescriptor {
+    pub fn new(register: u32, space: u32) -> Self {
+        RootDescriptor {
+            register,
+            space,
+            flags: RootDescriptorFlags::NONE,
+        }
+    }
+
+    pub fn flags(mut self, flags: RootDescriptorFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+/// `D3D12_FILTER`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Filter(pub u32);
+
+impl Filter {
+    pub const MIN_MAG_MIP_POINT: Self = Filter(0);
+    pub const MIN_MAG_POINT_MIP_LINEAR: Self = Filter(0x1);
+    pub const MIN_POINT_MAG_LINEAR_MIP_POINT: Self = Filter(0x4);
+    pub const MIN_POINT_MAG_MIP_LINEAR: Self = Filter(0x5);
+    pub const MIN_LINEAR_MAG_MIP_POINT: Self = Filter(0x10);
+    pub const MIN_LINEAR_MAG_POINT_MIP_LINEAR: Self = Filter(0x11);
+    pub const MIN_MAG_LINEAR_MIP_POINT: Self = Filter(0x14);
+    pub const MIN_MAG_MIP_LINEAR: Self = Filter(0x15);
+    pub const ANISOTROPIC: Self = Filter(0x55);
+
+    /// `FILTER_COMPARISON_` variant, for `SampleCmp`
+    pub const fn comparison(self) -> Self {
+        Filter(self.0 | 0x80)
+    }
+
+    /// `FILTER_MINIMUM_` variant
+    pub const fn minimum(self) -> Self {
+        Filter(self.0 | 0x100)
+    }
+
+    /// `FILTER_MAXIMUM_` variant
+    pub const fn maximum(self) -> Self {
+        Filter(self.0 | 0x180)
+    }
+}
+
+/// `D3D12_TEXTURE_ADDRESS_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum TextureAddressMode {
+    Wrap = 1,
+    Mirror = 2,
+    Clamp = 3,
+    Border = 4,
+    MirrorOnce = 5,
+}
+
+impl TextureAddressMode {
+    pub fn from_u32(value: u32) -> Option<TextureAddressMode> {
+        use TextureAddressMode::*;
+        [Wrap, Mirror, Clamp, Border, MirrorOnce]
+            .get((value as usize).wrapping_sub(1))
+            .copied()
+    }
+}
+
+/// `D3D12_STATIC_BORDER_COLOR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum StaticBorderColor {
+    TransparentBlack = 0,
+    OpaqueBlack = 1,
+    OpaqueWhite = 2,
+}
+
+impl StaticBorderColor {
+    pub fn from_u32(value: u32) -> Option<StaticBorderColor> {
+        use StaticBorderColor::*;
+        [TransparentBlack, OpaqueBlack, OpaqueWhite]
+            .get(value as usize)
+            .copied()
+    }
+}
+
+/// `D3D12_FLOAT32_MAX`
+pub const FLOAT32_MAX: f32 = 3.402_823_5e38;
+
+/// `D3D12_STATIC_SAMPLER_DESC`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticSampler {
+    pub filter: Filter,
+    pub address_u: TextureAddressMode,
+    pub address_v: TextureAddressMode,
+    pub address_w: TextureAddressMode,
+    pub mip_lod_bias: f32,
+    pub max_anisotropy: u32,
+    pub comparison_func: ComparisonFunc,
+    pub border_color: StaticBorderColor,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub register: u32,
+    pub space: u32,
+    pub visibility: ShaderVisibility,
+}
+
+impl StaticSampler {
+    /// `CD3DX12_STATIC_SAMPLER_DESC(register)`
+    pub fn new(register: u32) -> Self {
+        StaticSampler {
+            filter: Filter::ANISOTROPIC,
+            address_u: TextureAddressMode::Wrap,
+            address_v: TextureAddressMode::Wrap,
+            address_w: TextureAddressMode::Wrap,
+            mip_lod_bias: 0.0,
+            max_anisotropy: 16,
+            comparison_func: ComparisonFunc::LessEqual,
+            border_color: StaticBorderColor::OpaqueWhite,
+            min_lod: 0.0,
+            max_lod: FLOAT32_MAX,
+            register,
+            space: 0,
+            visibility: ShaderVisibility::All,
+        }
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Same mode for `u`, `v` and `w`
+    pub fn address(mut self, mode: TextureAddressMode) -> Self {
+        self.address_u = mode;
+        self.address_v = mode;
+        self.address_w = mode;
+        self
+    }
+
+    pub fn space(mut self, space: u32) -> Self {
+        self.space = space;
+        self
+    }
+
+    pub fn visibility(mut self, visibility: ShaderVisibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootSignatureError {
+    Container(ContainerError),
+    /// Offsets past the end of the `RTS0` chunk
+    Truncated,
+    Version(u32),
+    /// Value that isn't one of its enum, e.g. `("shader visibility", 9)`
+    Invalid(&'static str, u32),
+    /// Range or descriptor flags in a version 1.0 signature
+    FlagsNeedV1_1(usize),
+    /// Sampler ranges share a table with CBV, SRV or UAV ranges
+    MixedTable(usize),
+    EmptyTable(usize),
+    /// Parameters cost more than the 64 DWORDs of a root signature
+    TooLarge(u32),
+    /// Range whose last register is past `u32::MAX`
+    RegisterOverflow(usize),
+    /// Appended range following an unbounded one
+    AppendAfterUnbounded(usize),
+}
+
+impl fmt::Display for RootSignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootSignatureError::Container(error) => write!(f, "{}", error),
+            RootSignatureError::Truncated => write!(f, "truncated root signature"),
+            RootSignatureError::Version(version) => {
+                write!(f, "unsupported root signature version {}", version)
+            }
+            RootSignatureError::Invalid(what, value) => write!(f, "invalid {} {}", what, value),
+            RootSignatureError::FlagsNeedV1_1(parameter) => write!(
+                f,
+                "parameter {} has flags, they need root signature version 1.1",
+                parameter
+            ),
+            RootSignatureError::MixedTable(parameter) => write!(
+                f,
+                "parameter {} mixes sampler ranges with CBV, SRV and UAV ranges",
+                parameter
+            ),
+            RootSignatureError::EmptyTable(parameter) => {
+                write!(f, "parameter {} is a table without ranges", parameter)
+            }
+            RootSignatureError::TooLarge(size) => write!(
+                f,
+                "root signature takes {} DWORDs, the limit is {}",
+                size, MAX_DWORDS
+            ),
+            RootSignatureError::RegisterOverflow(parameter) => write!(
+                f,
+                "parameter {} has a range past the last register",
+                parameter
+            ),
+            RootSignatureError::AppendAfterUnbounded(parameter) => write!(
+                f,
+                "parameter {} appends a range after an unbounded one",
+                parameter
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RootSignatureError {}
+
+impl From<ContainerError> for RootSignatureError {
+    fn from(error: ContainerError) -> Self {
+        RootSignatureError::Container(error)
+    }
+}
+
+/// `D3D12_VERSIONED_ROOT_SIGNATURE_DESC`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootSignature {
+    pub version: RootSignatureVersion,
+    pub flags: RootSignatureFlags,
+    pub parameters: Vec<RootParameter>,
+    pub static_samplers: Vec<StaticSampler>,
+}
+
+const HEADER_SIZE: u32 = 24;
+/// Tables take 1 DWORD, root descriptors 2 and constants one per value
+const MAX_DWORDS: u32 = 64;
+
+impl RootSignature {
+    pub fn new(version: RootSignatureVersion) -> Self {
+        RootSignature {
+            version,
+            flags: RootSignatureFlags::NONE,
+            parameters: Vec::new(),
+            static_samplers: Vec::new(),
+        }
+    }
+
+    pub fn flags(mut self, flags: RootSignatureFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn parameter(mut self, parameter: RootParameter) -> Self {
+        self.parameters.push(parameter);
+        self
+    }
+
+    pub fn static_sampler(mut self, sampler: StaticSampler) -> Self {
+        self.static_samplers.push(sampler);
+        self
+    }
+
+    /// Errors `D3D12SerializeVersionedRootSignature` would give for the
+    /// layout, register overlaps are left to the runtime
+    pub fn validate(&self) -> Result<(), RootSignatureError> {
+        let v1_0 = self.version == RootSignatureVersion::V1_0;
+        let mut size = 0u32;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            size = size.saturating_add(match &parameter.kind {
+                ParameterKind::DescriptorTable(_) => 1,
+                ParameterKind::Constants { count, .. } => *count,
+                _ => 2,
+            });
+            match &parameter.kind {
+                ParameterKind::DescriptorTable(ranges) => {
+                    let samplers = ranges
+                        .iter()
+                        .filter(|range| range.range_type == DescriptorRangeType::Sampler)
+                        .count();
+                    if ranges.is_empty() {
+                        return Err(RootSignatureError::EmptyTable(i));
+                    }
+                    if samplers != 0 && samplers != ranges.len() {
+                        return Err(RootSignatureError::MixedTable(i));
+                    }
+                    if v1_0 && ranges.iter().any(|r| r.flags != DescriptorRangeFlags::NONE) {
+                        return Err(RootSignatureError::FlagsNeedV1_1(i));
+                    }
+                    let wraps = |r: &DescriptorRange| {
+                        r.count != u32::MAX
+                            && r.base_register
+                                .checked_add(r.count.saturating_sub(1))
+                                .is_none()
+                    };
+                    if ranges.iter().any(wraps) {
+                        return Err(RootSignatureError::RegisterOverflow(i));
+                    }
+                    if ranges.windows(2).any(|pair| {
+                        pair[0].count == u32::MAX
+                            && pair[1].offset == DESCRIPTOR_RANGE_OFFSET_APPEND
+                    }) {
+                        return Err(RootSignatureError::AppendAfterUnbounded(i));
+                    }
+                }
+                ParameterKind::Constants { .. } => {}
+                ParameterKind::Cbv(descriptor)
+                | ParameterKind::Srv(descriptor)
+                | ParameterKind::Uav(descriptor) => {
+                    if v1_0 && descriptor.flags != RootDescriptorFlags::NONE {
+                        return Err(RootSignatureError::FlagsNeedV1_1(i));
+                    }
+                }
+            }
+        }
+        if size > MAX_DWORDS {
+            return Err(RootSignatureError::TooLarge(size));
+        }
+        Ok(())
+    }
+
+    /// Serialized blob, ready for `CreateRootSignature`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RootSignatureError> {
+        let chunk = self.to_chunk()?;
+        let container = Container {
+            chunks: vec![Chunk {
+                fourcc: *b"RTS0",
+                data: &chunk,
+            }],
+        };
+        Ok(container.to_bytes())
+    }
+
+    /// The `RTS0` chunk alone
+    pub fn to_chunk(&self) -> Result<Vec<u8>, RootSignatureError> {
+        self.validate()?;
+        let v1_1 = self.version == RootSignatureVersion::V1_1;
+        let mut out = Vec::new();
+        put(
+            &mut out,
+            &[
+                self.version as u32,
+                self.parameters.len() as u32,
+                HEADER_SIZE,
+                self.static_samplers.len() as u32,
+                0,
+                self.flags.0,
+            ],
+        );
+        let parameters = out.len();
+        out.resize(parameters + 12 * self.parameters.len(), 0);
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            let entry = [
+                parameter.kind.type_value(),
+                parameter.visibility as u32,
+                out.len() as u32,
+            ];
+            for (j, word) in entry.iter().enumerate() {
+                patch(&mut out, parameters + 12 * i + 4 * j, *word);
+            }
+            match &parameter.kind {
+                ParameterKind::DescriptorTable(ranges) => {
+                    let offset = out.len() as u32 + 8;
+                    put(&mut out, &[ranges.len() as u32, offset]);
+                    for range in ranges {
+                        put(
+                            &mut out,
+                            &[
+                                range.range_type as u32,
+                                range.count,
+                                range.base_register,
+                                range.space,
+                            ],
+                        );
+                        if v1_1 {
+                            put(&mut out, &[range.flags.0]);
+                        }
+                        put(&mut out, &[range.offset]);
+                    }
+                }
+                ParameterKind::Constants {
+                    register,
+                    space,
+                    count,
+                } => put(&mut out, &[*register, *space, *count]),
+                ParameterKind::Cbv(descriptor)
+                | ParameterKind::Srv(descriptor)
+                | ParameterKind::Uav(descriptor) => {
+                    put(&mut out, &[descriptor.register, descriptor.space]);
+                    if v1_1 {
+                        put(&mut out, &[descriptor.flags.0]);
+                    }
+                }
+            }
+        }
+        let samplers = out.len() as u32;
+        patch(&mut out, 16, samplers);
+        for sampler in &self.static_samplers {
+            put(
+                &mut out,
+                &[
+                    sampler.filter.0,
+                    sampler.address_u as u32,
+                    sampler.address_v as u32,
+                    sampler.address_w as u32,
+                    sampler.mip_lod_bias.to_bits(),
+                    sampler.max_anisotropy,
+                    sampler.comparison_func as u32,
+                    sampler.border_color as u32,
+                    sampler.min_lod.to_bits(),
+                    sampler.max_lod.to_bits(),
+                    sampler.register,
+                    sampler.space,
+                    sampler.visibility as u32,
+                ],
+            );
+        }
+        Ok(out)
+    }
+
+    /// Serialized root signature, or a shader with one embedded
+    pub fn from_bytes(blob: &[u8]) -> Result<RootSignature, RootSignatureError> {
+        let container = Container::parse(blob)?;
+        let chunk = container
+            .chunk(b"RTS0")
+            .ok_or(ContainerError::MissingChunk(*b"RTS0"))?;
+        Self::from_chunk(chunk)
+    }
+
+    pub fn from_chunk(data: &[u8]) -> Result<RootSignature, RootSignatureError> {
+        // Offsets come from the blob, u64 math keeps them from overflowing
+        let word = |pos: u64| -> Result<u32, RootSignatureError> {
+            let pos = usize::try_from(pos).map_err(|_| RootSignatureError::Truncated)?;
+            data.get(pos..pos.checked_add(4).ok_or(RootSignatureError::Truncated)?)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or(RootSignatureError::Truncated)
+        };
+        let valid = |what: &'static str, value: u32| RootSignatureError::Invalid(what, value);
+        let visibility = |value: u32| {
+            ShaderVisibility::from_u32(value).ok_or_else(|| valid("shader visibility", value))
+        };
+        let address = |value: u32| {
+            TextureAddressMode::from_u32(value).ok_or_else(|| valid("address mode", value))
+        };
+
+        let version = match word(0)? {
+            1 => RootSignatureVersion::V1_0,
+            2 => RootSignatureVersion::V1_1,
+            version => return Err(RootSignatureError::Version(version)),
+        };
+        let v1_1 = version == RootSignatureVersion::V1_1;
+        let mut signature = RootSignature::new(version).flags(RootSignatureFlags(word(20)?));
+
+        let parameters = u64::from(word(8)?);
+        for i in 0..u64::from(word(4)?) {
+            let entry = parameters + 12 * i;
+            let at = u64::from(word(entry + 8)?);
+            let kind = match word(entry)? {
+                0 => {
+                    let ranges = u64::from(word(at + 4)?);
+                    let size = if v1_1 { 24 } else { 20 };
+                    let ranges = (0..u64::from(word(at)?))
+                        .map(|j| {
+                            let at = ranges + size * j;
+                            let range_type = word(at)?;
+                            let flags = if v1_1 { word(at + 16)? } else { 0 };
+                            Ok(DescriptorRange {
+                                range_type: DescriptorRangeType::from_u32(range_type)
+                                    .ok_or_else(|| valid("descriptor range type", range_type))?,
+                                count: word(at + 4)?,
+                                base_register: word(at + 8)?,
+                                space: word(at + 12)?,
+                                flags: DescriptorRangeFlags(flags),
+                                offset: word(at + size - 4)?,
+                            })
+                        })
+                        .collect::<Result<_, RootSignatureError>>()?;
+                    ParameterKind::DescriptorTable(ranges)
+                }
+                1 => ParameterKind::Constants {
+                    register: word(at)?,
+                    space: word(at + 4)?,
+                    count: word(at + 8)?,
+                },
+                kind @ 2..=4 => {
+                    let descriptor = RootDescriptor {
+                        register: word(at)?,
+                        space: word(at + 4)?,
+                        flags: RootDescriptorFlags(if v1_1 { word(at + 8)? } else { 0 }),
+                    };
+                    match kind {
+                        2 => ParameterKind::Cbv(descriptor),
+                        3 => ParameterKind::Srv(descriptor),
+                        _ => ParameterKind::Uav(descriptor),
+                    }
+                }
+                kind => return Err(valid("root parameter type", kind)),
+            };
+            signature = signature.parameter(RootParameter {
+                kind,
+                visibility: visibility(word(entry + 4)?)?,
+            });
+        }
+
+        let samplers = u64::from(word(16)?);
+        for i in 0..u64::from(word(12)?) {
+            let at = samplers + 52 * i;
+            let comparison = word(at + 24)?;
+            let border = word(at + 28)?;
+            signature = signature.static_sampler(StaticSampler {
+                filter: Filter(word(at)?),
+                address_u: address(word(at + 4)?)?,
+                address_v: address(word(at + 8)?)?,
+                address_w: address(word(at + 12)?)?,
+                mip_lod_bias: f32::from_bits(word(at + 16)?),
+                max_anisotropy: word(at + 20)?,
+                comparison_func: ComparisonFunc::from_u32(comparison)
+                    .ok_or_else(|| valid("comparison function", comparison))?,
+                border_color: StaticBorderColor::from_u32(border)
+                    .ok_or_else(|| valid("border color", border))?,
+                min_lod: f32::from_bits(word(at + 32)?),
+                max_lod: f32::from_bits(word(at + 36)?),
+                register: word(at + 40)?,
+                space: word(at + 44)?,
+                visibility: visibility(word(at + 48)?)?,
+            });
+        }
+        Ok(signature)
+    }
+}
+
+fn put(out: &mut Vec<u8>, words: &[u32]) {
+    for word in words {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn patch(out: &mut [u8], pos: usize, word: u32) {
+    out[pos..pos + 4].copy_from_slice(&word.to_le_bytes());
+}
+
+/// Root signature of the hello triangle, no parameters and the input layout
+/// allowed
+pub fn hello_triangle() -> RootSignature {
+    RootSignature::new(RootSignatureVersion::V1_0)
+        .flags(RootSignatureFlags::ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    fn textured() -> RootSignature {
+        RootSignature::new(RootSignatureVersion::V1_1)
+            .flags(RootSignatureFlags::ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT)
+            .parameter(RootParameter::constants(4, 0, 0).visibility(ShaderVisibility::Pixel))
+            .parameter(RootParameter::table(vec![
+                DescriptorRange::srv(1, 0).flags(DescriptorRangeFlags::DATA_STATIC)
+            ]))
+            .static_sampler(StaticSampler::new(0).filter(Filter::MIN_MAG_MIP_LINEAR))
+    }
+
+    #[test]
+    fn writes_rts0_layout() {
+        let blob = hello_triangle().to_bytes().unwrap();
+        let container = Container::parse(&blob).unwrap();
+        assert_eq!(container.chunks.len(), 1);
+        assert_eq!(
+            words(container.chunk(b"RTS0").unwrap()),
+            [1, 0, 24, 0, 24, 1]
+        );
+
+        let chunk = words(&textured().to_chunk().unwrap());
+        assert_eq!(chunk.len(), 144 / 4);
+        assert_eq!(chunk[..6], [2, 2, 24, 1, 92, 1]);
+        // Parameter entries, then their payloads in order
+        assert_eq!(chunk[6..12], [1, 5, 48, 0, 0, 60]);
+        assert_eq!(chunk[12..15], [0, 0, 4]);
+        assert_eq!(chunk[15..17], [1, 68]);
+        assert_eq!(
+            chunk[17..23],
+            [0, 1, 0, 0, 8, DESCRIPTOR_RANGE_OFFSET_APPEND]
+        );
+        assert_eq!(chunk[23..27], [0x15, 1, 1, 1]);
+        assert_eq!(chunk[29..31], [4, 2]);
+        assert_eq!(f32::from_bits(chunk[32]), FLOAT32_MAX);
+    }
+
+    #[test]
+    fn round_trips_both_versions() {
+        let full = textured()
+            .parameter(RootParameter::new(ParameterKind::Cbv(
+                RootDescriptor::new(1, 0).flags(RootDescriptorFlags::DATA_VOLATILE),
+            )))
+            .parameter(RootParameter::srv(3, 2).visibility(ShaderVisibility::Vertex))
+            .parameter(RootParameter::uav(0, 0))
+            .parameter(
+                RootParameter::table(vec![
+                    DescriptorRange::cbv(2, 4).space(1),
+                    DescriptorRange::uav(u32::MAX, 0).offset(8),
+                ])
+                .visibility(ShaderVisibility::Mesh),
+            )
+            .parameter(RootParameter::table(vec![DescriptorRange::sampler(4, 0)]))
+            .static_sampler(
+                StaticSampler::new(1)
+                    .filter(Filter::MIN_MAG_MIP_POINT.comparison())
+                    .address(TextureAddressMode::Border)
+                    .space(3),
+            );
+        let blob = full.to_bytes().unwrap();
+        assert_eq!(RootSignature::from_bytes(&blob), Ok(full.clone()));
+
+        let mut v1_0 = full;
+        v1_0.version = RootSignatureVersion::V1_0;
+        assert_eq!(v1_0.to_bytes(), Err(RootSignatureError::FlagsNeedV1_1(1)));
+        for parameter in &mut v1_0.parameters {
+            match &mut parameter.kind {
+                ParameterKind::DescriptorTable(ranges) => {
+                    for range in ranges {
+                        range.flags = DescriptorRangeFlags::NONE;
+                    }
+                }
+                ParameterKind::Cbv(descriptor) => descriptor.flags = RootDescriptorFlags::NONE,
+                _ => {}
+            }
+        }
+        let chunk = v1_0.to_chunk().unwrap();
+        assert_eq!(RootSignature::from_chunk(&chunk), Ok(v1_0));
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let mixed = RootSignature::new(RootSignatureVersion::V1_1)
+            .parameter(RootParameter::cbv(0, 0))
+            .parameter(RootParameter::table(vec![
+                DescriptorRange::srv(1, 0),
+                DescriptorRange::sampler(1, 0),
+            ]));
+        assert_eq!(
+            mixed.validate().unwrap_err().to_string(),
+            "parameter 1 mixes sampler ranges with CBV, SRV and UAV ranges"
+        );
+        let empty = RootSignature::new(RootSignatureVersion::V1_0)
+            .parameter(RootParameter::table(Vec::new()));
+        assert_eq!(empty.validate(), Err(RootSignatureError::EmptyTable(0)));
+
+        let mut chunk = textured().to_chunk().unwrap();
+        assert_eq!(
+            RootSignature::from_chunk(&chunk[..100]),
+            Err(RootSignatureError::Truncated)
+        );
+        patch(&mut chunk, 28, 9);
+        assert_eq!(
+            RootSignature::from_chunk(&chunk).unwrap_err().to_string(),
+            "invalid shader visibility 9"
+        );
+        patch(&mut chunk, 0, 3);
+        assert_eq!(
+            RootSignature::from_chunk(&chunk),
+            Err(RootSignatureError::Version(3))
+        );
+        assert_eq!(
+            RootSignature::from_bytes(b"DXBC"),
+            Err(RootSignatureError::Container(ContainerError::NotDxbc))
+        );
+    }
+
+    #[test]
+    fn rejects_offsets_past_u32() {
+        let chunk = |header: &[u32]| {
+            let mut out = Vec::new();
+            put(&mut out, header);
+            out
+        };
+        // Parameter offset
+        let parameters = chunk(&[1, 1, 0xffff_fff8, 0, 24, 0]);
+        assert_eq!(
+            RootSignature::from_chunk(&parameters),
+            Err(RootSignatureError::Truncated)
+        );
+        // Sampler offset
+        let samplers = chunk(&[1, 0, 24, 1, 0xffff_ffff, 0]);
+        assert_eq!(
+            RootSignature::from_chunk(&samplers),
+            Err(RootSignatureError::Truncated)
+        );
+        // Table whose ranges start near the end of the address space
+        let table = chunk(&[1, 1, 24, 0, 0, 0, 0, 0, 36, 2, 0xffff_fff0]);
+        assert_eq!(
+            RootSignature::from_chunk(&table),
+            Err(RootSignatureError::Truncated)
+        );
+    }
+
+    fn parse(text: &str) -> RootSignature {
+        hlsl::parse(text, RootSignatureVersion::V1_1).unwrap()
+    }
+
+    #[test]
+    fn rejects_more_than_64_dwords() {
+        let large = parse("RootConstants(num32BitConstants=65, b0)");
+        assert_eq!(large.validate(), Err(RootSignatureError::TooLarge(65)));
+        assert_eq!(
+            large.to_bytes().unwrap_err().to_string(),
+            "root signature takes 65 DWORDs, the limit is 64"
+        );
+        let full =
+            parse("RootConstants(num32BitConstants=61, b0), CBV(b1), DescriptorTable(SRV(t0))");
+        assert_eq!(full.validate(), Ok(()));
+        let over = full.parameter(RootParameter::table(vec![DescriptorRange::srv(1, 1)]));
+        assert_eq!(over.validate(), Err(RootSignatureError::TooLarge(65)));
+    }
+
+    #[test]
+    fn rejects_wrapping_register_range() {
+        let wraps = parse("DescriptorTable(SRV(t4294967295, numDescriptors=2))");
+        assert_eq!(
+            wraps.validate(),
+            Err(RootSignatureError::RegisterOverflow(0))
+        );
+        let last = parse("DescriptorTable(SRV(t4294967294, numDescriptors=2))");
+        assert_eq!(last.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_append_after_unbounded_range() {
+        let append = parse("CBV(b0), DescriptorTable(SRV(t0, numDescriptors=unbounded), SRV(t1))");
+        assert_eq!(
+            append.validate().unwrap_err().to_string(),
+            "parameter 1 appends a range after an unbounded one"
+        );
+        let offset =
+            parse("DescriptorTable(SRV(t0, numDescriptors=unbounded), SRV(t1, offset=100))");
+        assert_eq!(offset.validate(), Ok(()));
+    }
+}
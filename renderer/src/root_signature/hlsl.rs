@@ -0,0 +1,967 @@
+//! Root signatures written in HLSL
+//!
+//! Parses the string of a `[RootSignature("...")]` attribute, e.g.
+//! `"RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT), CBV(b0)"`, into the same
+//! [`RootSignature`] the builder makes. Keywords are case-insensitive as with
+//! DXC. The string is read from the attribute of an entry point, or from a
+//! define like the ones of `.rs.hlsl` files compiled with
+//! `-T rootsig_1_1 -E name`. Flags left out stay `NONE`, which version 1.1
+//! reads as its defaults.
+use super::*;
+use crate::shader::hlsl::Function;
+use crate::shader::preprocess::{Preprocessed, Preprocessor};
+
+/// Error in a root signature string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based character of the string
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceError {
+    /// No object-like define of the name
+    Undefined(String),
+    /// Define or attribute at the location isn't string literals
+    NotString(String),
+    /// Error in the string of the define or attribute at the location
+    Parse(String, ParseError),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceError::Undefined(name) => write!(f, "{} is not defined", name),
+            SourceError::NotString(at) => write!(f, "{}: root signature is not a string", at),
+            SourceError::Parse(at, error) => write!(f, "{}: {}", at, error),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+pub fn parse(text: &str, version: RootSignatureVersion) -> Result<RootSignature, ParseError> {
+    let tokens = lex(text)?;
+    Parser {
+        tokens: &tokens,
+        pos: 0,
+        end: text.chars().count() as u32 + 1,
+        version,
+    }
+    .root_signature()
+}
+
+/// Root signature a define holds, what DXC compiles for `-E name`
+pub fn from_define(
+    preprocessor: &Preprocessor,
+    name: &str,
+    version: RootSignatureVersion,
+) -> Result<RootSignature, SourceError> {
+    let value = preprocessor
+        .value(name)
+        .ok_or_else(|| SourceError::Undefined(name.to_string()))?;
+    let text = string_literals(&value).ok_or_else(|| SourceError::NotString(name.to_string()))?;
+    parse(&text, version).map_err(|e| SourceError::Parse(name.to_string(), e))
+}
+
+/// Root signature of the `[RootSignature]` attribute of an entry point,
+/// `preprocessed` is the source the function was reflected from
+pub fn from_attribute(
+    function: &Function,
+    preprocessed: &Preprocessed,
+    version: RootSignatureVersion,
+) -> Result<Option<RootSignature>, SourceError> {
+    let argument = function.attributes.iter().find_map(|attribute| {
+        let (name, rest) = attribute.split_once('(')?;
+        if !name.trim().eq_ignore_ascii_case("RootSignature") {
+            return None;
+        }
+        Some(rest.strip_suffix(')').unwrap_or(rest))
+    });
+    let argument = match argument {
+        Some(argument) => argument,
+        None => return Ok(None),
+    };
+    let at = match preprocessed.origin(function.line) {
+        Some((file, line)) => format!("{}:{}", file, line),
+        None => function.name.clone(),
+    };
+    let text = string_literals(argument).ok_or_else(|| SourceError::NotString(at.clone()))?;
+    parse(&text, version)
+        .map(Some)
+        .map_err(|e| SourceError::Parse(at, e))
+}
+
+/// Contents of adjacent string literals, `"a" "b"` gives `ab`
+fn string_literals(text: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = text.trim().chars();
+    loop {
+        match chars.next() {
+            None => break,
+            Some('"') => {}
+            Some(c) if c.is_whitespace() => continue,
+            Some(_) => return None,
+        }
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => out.push(chars.next()?),
+                c => out.push(c),
+            }
+        }
+    }
+    if out.is_empty() && !text.contains('"') {
+        return None;
+    }
+    Some(out)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Word(String),
+    Number(String),
+    Punct(char),
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Word(text) | Kind::Number(text) => write!(f, "{}", text),
+            Kind::Punct(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+struct Token {
+    kind: Kind,
+    column: u32,
+}
+
+fn lex(text: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let start = i;
+        let kind = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Kind::Word(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit()
+            || c == '.'
+            || ((c == '-' || c == '+') && next.is_some_and(|n| n.is_ascii_digit() || n == '.'))
+        {
+            i += 1;
+            // `1.5e-3f`, the sign only after an exponent
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '.'
+                    || ((chars[i] == '-' || chars[i] == '+') && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            Kind::Number(chars[start..i].iter().collect())
+        } else if "(),=|".contains(c) {
+            i += 1;
+            Kind::Punct(c)
+        } else {
+            return Err(ParseError {
+                column: start as u32 + 1,
+                message: format!("unexpected `{}`", c),
+            });
+        };
+        tokens.push(Token {
+            kind,
+            column: start as u32 + 1,
+        });
+    }
+    Ok(tokens)
+}
+
+const ROOT_FLAGS: &[(&str, u32)] = &[
+    (
+        "ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT",
+        RootSignatureFlags::ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT.0,
+    ),
+    (
+        "DENY_VERTEX_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_VERTEX_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "DENY_HULL_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_HULL_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "DENY_DOMAIN_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_DOMAIN_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "DENY_GEOMETRY_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_GEOMETRY_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "DENY_PIXEL_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_PIXEL_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "ALLOW_STREAM_OUTPUT",
+        RootSignatureFlags::ALLOW_STREAM_OUTPUT.0,
+    ),
+    (
+        "LOCAL_ROOT_SIGNATURE",
+        RootSignatureFlags::LOCAL_ROOT_SIGNATURE.0,
+    ),
+    (
+        "DENY_AMPLIFICATION_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_AMPLIFICATION_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "DENY_MESH_SHADER_ROOT_ACCESS",
+        RootSignatureFlags::DENY_MESH_SHADER_ROOT_ACCESS.0,
+    ),
+    (
+        "CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED",
+        RootSignatureFlags::CBV_SRV_UAV_HEAP_DIRECTLY_INDEXED.0,
+    ),
+    (
+        "SAMPLER_HEAP_DIRECTLY_INDEXED",
+        RootSignatureFlags::SAMPLER_HEAP_DIRECTLY_INDEXED.0,
+    ),
+];
+
+const RANGE_FLAGS: &[(&str, u32)] = &[
+    (
+        "DESCRIPTORS_VOLATILE",
+        DescriptorRangeFlags::DESCRIPTORS_VOLATILE.0,
+    ),
+    ("DATA_VOLATILE", DescriptorRangeFlags::DATA_VOLATILE.0),
+    (
+        "DATA_STATIC_WHILE_SET_AT_EXECUTE",
+        DescriptorRangeFlags::DATA_STATIC_WHILE_SET_AT_EXECUTE.0,
+    ),
+    ("DATA_STATIC", DescriptorRangeFlags::DATA_STATIC.0),
+    (
+        "DESCRIPTORS_STATIC_KEEPING_BUFFER_BOUNDS_CHECKS",
+        DescriptorRangeFlags::DESCRIPTORS_STATIC_KEEPING_BUFFER_BOUNDS_CHECKS.0,
+    ),
+];
+
+const DESCRIPTOR_FLAGS: &[(&str, u32)] = &[
+    ("DATA_VOLATILE", RootDescriptorFlags::DATA_VOLATILE.0),
+    (
+        "DATA_STATIC_WHILE_SET_AT_EXECUTE",
+        RootDescriptorFlags::DATA_STATIC_WHILE_SET_AT_EXECUTE.0,
+    ),
+    ("DATA_STATIC", RootDescriptorFlags::DATA_STATIC.0),
+];
+
+const VISIBILITIES: &[(&str, ShaderVisibility)] = &[
+    ("SHADER_VISIBILITY_ALL", ShaderVisibility::All),
+    ("SHADER_VISIBILITY_VERTEX", ShaderVisibility::Vertex),
+    ("SHADER_VISIBILITY_HULL", ShaderVisibility::Hull),
+    ("SHADER_VISIBILITY_DOMAIN", ShaderVisibility::Domain),
+    ("SHADER_VISIBILITY_GEOMETRY", ShaderVisibility::Geometry),
+    ("SHADER_VISIBILITY_PIXEL", ShaderVisibility::Pixel),
+    (
+        "SHADER_VISIBILITY_AMPLIFICATION",
+        ShaderVisibility::Amplification,
+    ),
+    ("SHADER_VISIBILITY_MESH", ShaderVisibility::Mesh),
+];
+
+/// Filters without the reduction, the `COMPARISON_`, `MINIMUM_` and
+/// `MAXIMUM_` forms are derived
+const FILTERS: &[(&str, Filter)] = &[
+    ("MIN_MAG_MIP_POINT", Filter::MIN_MAG_MIP_POINT),
+    ("MIN_MAG_POINT_MIP_LINEAR", Filter::MIN_MAG_POINT_MIP_LINEAR),
+    (
+        "MIN_POINT_MAG_LINEAR_MIP_POINT",
+        Filter::MIN_POINT_MAG_LINEAR_MIP_POINT,
+    ),
+    ("MIN_POINT_MAG_MIP_LINEAR", Filter::MIN_POINT_MAG_MIP_LINEAR),
+    ("MIN_LINEAR_MAG_MIP_POINT", Filter::MIN_LINEAR_MAG_MIP_POINT),
+    (
+        "MIN_LINEAR_MAG_POINT_MIP_LINEAR",
+        Filter::MIN_LINEAR_MAG_POINT_MIP_LINEAR,
+    ),
+    ("MIN_MAG_LINEAR_MIP_POINT", Filter::MIN_MAG_LINEAR_MIP_POINT),
+    ("MIN_MAG_MIP_LINEAR", Filter::MIN_MAG_MIP_LINEAR),
+    ("ANISOTROPIC", Filter::ANISOTROPIC),
+];
+
+const ADDRESS_MODES: &[(&str, TextureAddressMode)] = &[
+    ("TEXTURE_ADDRESS_WRAP", TextureAddressMode::Wrap),
+    ("TEXTURE_ADDRESS_MIRROR", TextureAddressMode::Mirror),
+    ("TEXTURE_ADDRESS_CLAMP", TextureAddressMode::Clamp),
+    ("TEXTURE_ADDRESS_BORDER", TextureAddressMode::Border),
+    (
+        "TEXTURE_ADDRESS_MIRROR_ONCE",
+        TextureAddressMode::MirrorOnce,
+    ),
+];
+
+const COMPARISON_FUNCS: &[(&str, ComparisonFunc)] = &[
+    ("COMPARISON_NEVER", ComparisonFunc::Never),
+    ("COMPARISON_LESS", ComparisonFunc::Less),
+    ("COMPARISON_EQUAL", ComparisonFunc::Equal),
+    ("COMPARISON_LESS_EQUAL", ComparisonFunc::LessEqual),
+    ("COMPARISON_GREATER", ComparisonFunc::Greater),
+    ("COMPARISON_NOT_EQUAL", ComparisonFunc::NotEqual),
+    ("COMPARISON_GREATER_EQUAL", ComparisonFunc::GreaterEqual),
+    ("COMPARISON_ALWAYS", ComparisonFunc::Always),
+];
+
+const BORDER_COLORS: &[(&str, StaticBorderColor)] = &[
+    (
+        "STATIC_BORDER_COLOR_TRANSPARENT_BLACK",
+        StaticBorderColor::TransparentBlack,
+    ),
+    (
+        "STATIC_BORDER_COLOR_OPAQUE_BLACK",
+        StaticBorderColor::OpaqueBlack,
+    ),
+    (
+        "STATIC_BORDER_COLOR_OPAQUE_WHITE",
+        StaticBorderColor::OpaqueWhite,
+    ),
+];
+
+/// Argument of an element, values are parsed by the element
+enum Argument {
+    /// e.g. `b0`
+    Register(char, u32),
+    /// `name =`, lower case
+    Key(String),
+    /// `SRV(...)` of a descriptor table, after the `(`
+    Clause(String),
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// Column past the end of the string
+    end: u32,
+    version: RootSignatureVersion,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a Kind> {
+        self.tokens.get(self.pos + offset).map(|token| &token.kind)
+    }
+
+    fn column(&self) -> u32 {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |token| token.column)
+    }
+
+    fn error<T>(&self, column: u32, message: String) -> Result<T, ParseError> {
+        Err(ParseError { column, message })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        let message = match self.peek(0) {
+            Some(kind) => format!("expected {}, found `{}`", expected, kind),
+            None => format!("expected {}", expected),
+        };
+        self.error(self.column(), message)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        if self.peek(0) == Some(&Kind::Punct(punct)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        self.unexpected(&format!("`{}`", punct))
+    }
+
+    fn word(&mut self, expected: &str) -> Result<&'a str, ParseError> {
+        match self.peek(0) {
+            Some(Kind::Word(word)) => {
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => self.unexpected(expected),
+        }
+    }
+
+    fn root_signature(mut self) -> Result<RootSignature, ParseError> {
+        let mut signature = RootSignature::new(self.version);
+        let mut flags = false;
+        while self.peek(0).is_some() {
+            let column = self.column();
+            let element = self.word("a root signature element")?;
+            self.expect('(')?;
+            match element.to_ascii_lowercase().as_str() {
+                "rootflags" => {
+                    if flags {
+                        return self.error(column, "RootFlags given twice".into());
+                    }
+                    flags = true;
+                    signature.flags = RootSignatureFlags(self.flags(ROOT_FLAGS, "root flag")?);
+                    self.expect(')')?;
+                }
+                "rootconstants" => {
+                    let parameter = self.constants(column)?;
+                    signature.parameters.push(parameter);
+                }
+                "cbv" | "srv" | "uav" => {
+                    let parameter = self.descriptor(element, column)?;
+                    signature.parameters.push(parameter);
+                }
+                "descriptortable" => {
+                    let parameter = self.table(column)?;
+                    signature.parameters.push(parameter);
+                }
+                "staticsampler" => {
+                    let sampler = self.static_sampler(column)?;
+                    signature.static_samplers.push(sampler);
+                }
+                _ => {
+                    return self.error(
+                        column,
+                        format!("unknown root signature element `{}`", element),
+                    )
+                }
+            }
+            if self.peek(0).is_some() {
+                self.expect(',')?;
+            }
+        }
+        Ok(signature)
+    }
+
+    /// Next argument, `seen` keys can't repeat
+    fn argument(&mut self, seen: &mut Vec<String>) -> Result<(Argument, u32), ParseError> {
+        let column = self.column();
+        let word = self.word("an argument")?;
+        if self.eat('(') {
+            return Ok((Argument::Clause(word.to_string()), column));
+        }
+        if self.eat('=') {
+            let key = word.to_ascii_lowercase();
+            if seen.contains(&key) {
+                return self.error(column, format!("`{}` given twice", word));
+            }
+            seen.push(key.clone());
+            return Ok((Argument::Key(key), column));
+        }
+        let mut chars = word.chars();
+        let class = chars.next().map(|c| c.to_ascii_lowercase());
+        match (class, chars.as_str().parse::<u32>()) {
+            (Some(class @ ('b' | 't' | 'u' | 's')), Ok(index)) => {
+                Ok((Argument::Register(class, index), column))
+            }
+            _ => self.error(column, format!("expected a register, found `{}`", word)),
+        }
+    }
+
+    /// Arguments up to the `)`, handed to `each` with their column
+    fn arguments(
+        &mut self,
+        mut each: impl FnMut(&mut Self, Argument, u32) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        let mut seen = Vec::new();
+        loop {
+            let (argument, column) = self.argument(&mut seen)?;
+            each(self, argument, column)?;
+            if self.eat(')') {
+                return Ok(());
+            }
+            if !self.eat(',') {
+                return self.unexpected("`,` or `)`");
+            }
+        }
+    }
+
+    /// The register of an element, given once and of its class
+    fn register(
+        &self,
+        register: &mut Option<u32>,
+        class: char,
+        argument: (char, u32),
+        column: u32,
+    ) -> Result<(), ParseError> {
+        if argument.0 != class {
+            return self.error(
+                column,
+                format!(
+                    "expected a `{}` register, found `{}{}`",
+                    class, argument.0, argument.1
+                ),
+            );
+        }
+        if register.is_some() {
+            return self.error(column, "register given twice".into());
+        }
+        *register = Some(argument.1);
+        Ok(())
+    }
+
+    fn unknown<T>(&self, element: &str, argument: Argument, column: u32) -> Result<T, ParseError> {
+        let message = match argument {
+            Argument::Register(class, index) => {
+                format!("{} takes no `{}{}` register", element, class, index)
+            }
+            Argument::Key(key) | Argument::Clause(key) => {
+                format!("{} takes no `{}`", element, key)
+            }
+        };
+        self.error(column, message)
+    }
+
+    fn needs_v1_1(&self, column: u32) -> Result<(), ParseError> {
+        if self.version == RootSignatureVersion::V1_0 {
+            return self.error(column, "flags need root signature version 1.1".into());
+        }
+        Ok(())
+    }
+
+    fn constants(&mut self, column: u32) -> Result<RootParameter, ParseError> {
+        let (mut register, mut count) = (None, None);
+        let mut parameter = RootParameter::constants(0, 0, 0);
+        let mut space = 0;
+        self.arguments(|parser, argument, column| {
+            match argument {
+                Argument::Register(class, index) => {
+                    parser.register(&mut register, 'b', (class, index), column)?
+                }
+                Argument::Key(key) if key == "num32bitconstants" => count = Some(parser.number()?),
+                Argument::Key(key) if key == "space" => space = parser.number()?,
+                Argument::Key(key) if key == "visibility" => {
+                    parser.visibility(&mut parameter.visibility)?
+                }
+                argument => return parser.unknown("RootConstants", argument, column),
+            }
+            Ok(())
+        })?;
+        parameter.kind = ParameterKind::Constants {
+            register: register.map_or_else(|| self.missing(column, "a `b` register"), Ok)?,
+            space,
+            count: count.map_or_else(|| self.missing(column, "`num32BitConstants`"), Ok)?,
+        };
+        Ok(parameter)
+    }
+
+    fn missing(&self, column: u32, what: &str) -> Result<u32, ParseError> {
+        self.error(column, format!("missing {}", what))
+    }
+
+    fn descriptor(&mut self, element: &str, column: u32) -> Result<RootParameter, ParseError> {
+        let (class, mut parameter) = match element.to_ascii_lowercase().as_str() {
+            "cbv" => ('b', RootParameter::cbv(0, 0)),
+            "srv" => ('t', RootParameter::srv(0, 0)),
+            _ => ('u', RootParameter::uav(0, 0)),
+        };
+        let (mut register, mut space) = (None, 0);
+        let mut flags = RootDescriptorFlags::NONE;
+        self.arguments(|parser, argument, column| {
+            match argument {
+                Argument::Register(c, index) => {
+                    parser.register(&mut register, class, (c, index), column)?
+                }
+                Argument::Key(key) if key == "space" => space = parser.number()?,
+                Argument::Key(key) if key == "visibility" => {
+                    parser.visibility(&mut parameter.visibility)?
+                }
+                Argument::Key(key) if key == "flags" => {
+                    parser.needs_v1_1(column)?;
+                    flags = RootDescriptorFlags(
+                        parser.flags(DESCRIPTOR_FLAGS, "root descriptor flag")?,
+                    );
+                }
+                argument => return parser.unknown(element, argument, column),
+            }
+            Ok(())
+        })?;
+        let register = register.map_or_else(
+            || self.missing(column, &format!("a `{}` register", class)),
+            Ok,
+        )?;
+        if let ParameterKind::Cbv(descriptor)
+        | ParameterKind::Srv(descriptor)
+        | ParameterKind::Uav(descriptor) = &mut parameter.kind
+        {
+            *descriptor = RootDescriptor {
+                register,
+                space,
+                flags,
+            };
+        }
+        Ok(parameter)
+    }
+
+    fn table(&mut self, column: u32) -> Result<RootParameter, ParseError> {
+        let mut parameter = RootParameter::table(Vec::new());
+        let mut ranges = Vec::new();
+        self.arguments(|parser, argument, column| {
+            match argument {
+                Argument::Clause(clause) => ranges.push(parser.range(&clause, column)?),
+                Argument::Key(key) if key == "visibility" => {
+                    parser.visibility(&mut parameter.visibility)?
+                }
+                argument => return parser.unknown("DescriptorTable", argument, column),
+            }
+            Ok(())
+        })?;
+        let samplers = ranges
+            .iter()
+            .filter(|range| range.range_type == DescriptorRangeType::Sampler)
+            .count();
+        if samplers != 0 && samplers != ranges.len() {
+            return self.error(
+                column,
+                "samplers can't share a table with CBV, SRV and UAV ranges".into(),
+            );
+        }
+        if ranges.is_empty() {
+            return self.error(column, "table without ranges".into());
+        }
+        parameter.kind = ParameterKind::DescriptorTable(ranges);
+        Ok(parameter)
+    }
+
+    /// Range of a table, the `(` is eaten
+    fn range(&mut self, clause: &str, column: u32) -> Result<DescriptorRange, ParseError> {
+        let (range_type, class) = match clause.to_ascii_lowercase().as_str() {
+            "cbv" => (DescriptorRangeType::Cbv, 'b'),
+            "srv" => (DescriptorRangeType::Srv, 't'),
+            "uav" => (DescriptorRangeType::Uav, 'u'),
+            "sampler" => (DescriptorRangeType::Sampler, 's'),
+            _ => return self.error(column, format!("unknown descriptor range `{}`", clause)),
+        };
+        let mut register = None;
+        let mut range = DescriptorRange::new(range_type, 1, 0);
+        self.arguments(|parser, argument, column| {
+            match argument {
+                Argument::Register(c, index) => {
+                    parser.register(&mut register, class, (c, index), column)?
+                }
+                Argument::Key(key) if key == "numdescriptors" => {
+                    range.count = match parser.peek(0) {
+                        Some(Kind::Word(word)) if word.eq_ignore_ascii_case("unbounded") => {
+                            parser.pos += 1;
+                            u32::MAX
+                        }
+                        _ => parser.number()?,
+                    }
+                }
+                Argument::Key(key) if key == "space" => range.space = parser.number()?,
+                Argument::Key(key) if key == "offset" => {
+                    range.offset = match parser.peek(0) {
+                        Some(Kind::Word(word))
+                            if word.eq_ignore_ascii_case("DESCRIPTOR_RANGE_OFFSET_APPEND") =>
+                        {
+                            parser.pos += 1;
+                            DESCRIPTOR_RANGE_OFFSET_APPEND
+                        }
+                        _ => parser.number()?,
+                    }
+                }
+                Argument::Key(key) if key == "flags" => {
+                    parser.needs_v1_1(column)?;
+                    range.flags =
+                        DescriptorRangeFlags(parser.flags(RANGE_FLAGS, "descriptor range flag")?);
+                }
+                argument => return parser.unknown(clause, argument, column),
+            }
+            Ok(())
+        })?;
+        range.base_register = register.map_or_else(
+            || self.missing(column, &format!("a `{}` register", class)),
+            Ok,
+        )?;
+        Ok(range)
+    }
+
+    fn static_sampler(&mut self, column: u32) -> Result<StaticSampler, ParseError> {
+        let mut register = None;
+        let mut sampler = StaticSampler::new(0);
+        self.arguments(|parser, argument, column| {
+            let key = match argument {
+                Argument::Register(c, index) => {
+                    return parser.register(&mut register, 's', (c, index), column)
+                }
+                Argument::Key(key) => key,
+                argument => return parser.unknown("StaticSampler", argument, column),
+            };
+            match key.as_str() {
+                "filter" => sampler.filter = parser.filter()?,
+                "addressu" => sampler.address_u = parser.named(ADDRESS_MODES, "address mode")?,
+                "addressv" => sampler.address_v = parser.named(ADDRESS_MODES, "address mode")?,
+                "addressw" => sampler.address_w = parser.named(ADDRESS_MODES, "address mode")?,
+                "miplodbias" => sampler.mip_lod_bias = parser.float()?,
+                "maxanisotropy" => sampler.max_anisotropy = parser.number()?,
+                "comparisonfunc" => {
+                    sampler.comparison_func =
+                        parser.named(COMPARISON_FUNCS, "comparison function")?
+                }
+                "bordercolor" => {
+                    sampler.border_color = parser.named(BORDER_COLORS, "border color")?
+                }
+                "minlod" => sampler.min_lod = parser.float()?,
+                "maxlod" => sampler.max_lod = parser.float()?,
+                "space" => sampler.space = parser.number()?,
+                "visibility" => parser.visibility(&mut sampler.visibility)?,
+                _ => return parser.unknown("StaticSampler", Argument::Key(key), column),
+            }
+            Ok(())
+        })?;
+        sampler.register = register.map_or_else(|| self.missing(column, "an `s` register"), Ok)?;
+        Ok(sampler)
+    }
+
+    fn number(&mut self) -> Result<u32, ParseError> {
+        if let Some(Kind::Number(text)) = self.peek(0) {
+            let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => text.parse().ok(),
+            };
+            if let Some(value) = value {
+                self.pos += 1;
+                return Ok(value);
+            }
+        }
+        self.unexpected("a number")
+    }
+
+    fn float(&mut self) -> Result<f32, ParseError> {
+        if let Some(Kind::Number(text)) = self.peek(0) {
+            let text = text.trim_end_matches(['f', 'F']);
+            if let Ok(value) = text.parse() {
+                self.pos += 1;
+                return Ok(value);
+            }
+        }
+        self.unexpected("a number")
+    }
+
+    fn named<T: Copy>(&mut self, names: &[(&str, T)], what: &str) -> Result<T, ParseError> {
+        let column = self.column();
+        let word = self.word(&format!("a {}", what))?;
+        match names
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(word))
+        {
+            Some((_, value)) => Ok(*value),
+            None => self.error(column, format!("unknown {} `{}`", what, word)),
+        }
+    }
+
+    fn visibility(&mut self, visibility: &mut ShaderVisibility) -> Result<(), ParseError> {
+        *visibility = self.named(VISIBILITIES, "shader visibility")?;
+        Ok(())
+    }
+
+    fn filter(&mut self) -> Result<Filter, ParseError> {
+        let column = self.column();
+        let word = self.word("a filter")?;
+        let upper = word.to_ascii_uppercase();
+        let name = upper.strip_prefix("FILTER_").unwrap_or("");
+        let (name, reduce): (&str, fn(Filter) -> Filter) =
+            if let Some(name) = name.strip_prefix("COMPARISON_") {
+                (name, Filter::comparison)
+            } else if let Some(name) = name.strip_prefix("MINIMUM_") {
+                (name, Filter::minimum)
+            } else if let Some(name) = name.strip_prefix("MAXIMUM_") {
+                (name, Filter::maximum)
+            } else {
+                (name, |filter| filter)
+            };
+        match FILTERS.iter().find(|(filter, _)| *filter == name) {
+            Some((_, filter)) => Ok(reduce(*filter)),
+            None => self.error(column, format!("unknown filter `{}`", word)),
+        }
+    }
+
+    /// `0` or names joined by `|`
+    fn flags(&mut self, names: &[(&str, u32)], what: &str) -> Result<u32, ParseError> {
+        let mut bits = 0;
+        loop {
+            bits |= match self.peek(0) {
+                Some(Kind::Number(_)) => self.number()?,
+                _ => self.named(names, what)?,
+            };
+            if !self.eat('|') {
+                return Ok(bits);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::hlsl::Reflection;
+    use crate::shader::preprocess::Embedded;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    #[test]
+    fn parses_like_the_builder() {
+        let text = "RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT), \
+            RootConstants(num32BitConstants=4, b0, visibility=SHADER_VISIBILITY_PIXEL), \
+            DescriptorTable(SRV(t0, flags = DATA_STATIC)), \
+            StaticSampler(s0, filter = FILTER_MIN_MAG_MIP_LINEAR), \
+            cbv(b1, flags=DATA_VOLATILE), SRV(t3, space=2, visibility=SHADER_VISIBILITY_VERTEX), \
+            UAV(u0), \
+            DescriptorTable(CBV(b4, numDescriptors=2, space=1), \
+                UAV(u0, numDescriptors=unbounded, offset=8), visibility=SHADER_VISIBILITY_MESH), \
+            DescriptorTable(Sampler(s0, numDescriptors = 4)), \
+            StaticSampler(s1, filter=FILTER_COMPARISON_MIN_MAG_MIP_POINT, \
+                addressU=TEXTURE_ADDRESS_BORDER, addressV=TEXTURE_ADDRESS_BORDER, \
+                addressW=TEXTURE_ADDRESS_BORDER, space=3, maxLOD=3.402823466e+38f)";
+        let full = RootSignature::new(RootSignatureVersion::V1_1)
+            .flags(RootSignatureFlags::ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT)
+            .parameter(RootParameter::constants(4, 0, 0).visibility(ShaderVisibility::Pixel))
+            .parameter(RootParameter::table(vec![
+                DescriptorRange::srv(1, 0).flags(DescriptorRangeFlags::DATA_STATIC)
+            ]))
+            .static_sampler(StaticSampler::new(0).filter(Filter::MIN_MAG_MIP_LINEAR))
+            .parameter(RootParameter::new(ParameterKind::Cbv(
+                RootDescriptor::new(1, 0).flags(RootDescriptorFlags::DATA_VOLATILE),
+            )))
+            .parameter(RootParameter::srv(3, 2).visibility(ShaderVisibility::Vertex))
+            .parameter(RootParameter::uav(0, 0))
+            .parameter(
+                RootParameter::table(vec![
+                    DescriptorRange::cbv(2, 4).space(1),
+                    DescriptorRange::uav(u32::MAX, 0).offset(8),
+                ])
+                .visibility(ShaderVisibility::Mesh),
+            )
+            .parameter(RootParameter::table(vec![DescriptorRange::sampler(4, 0)]))
+            .static_sampler(
+                StaticSampler::new(1)
+                    .filter(Filter::MIN_MAG_MIP_POINT.comparison())
+                    .address(TextureAddressMode::Border)
+                    .space(3),
+            );
+        assert_eq!(parse(text, RootSignatureVersion::V1_1), Ok(full));
+        assert_eq!(
+            parse("", RootSignatureVersion::V1_0),
+            Ok(RootSignature::new(RootSignatureVersion::V1_0))
+        );
+        assert_eq!(
+            parse("RootFlags(0)", RootSignatureVersion::V1_0)
+                .unwrap()
+                .flags,
+            RootSignatureFlags::NONE
+        );
+    }
+
+    #[test]
+    fn reports_columns() {
+        let errors = [
+            ("CBV(b0", "column 7: expected `,` or `)`"),
+            ("CBV(t0)", "column 5: expected a `b` register, found `t0`"),
+            (
+                "RootFlags(0), RootFlags(0)",
+                "column 15: RootFlags given twice",
+            ),
+            (
+                "SRV(t0, space=1, space=2)",
+                "column 18: `space` given twice",
+            ),
+            (
+                "DescriptorTable(SRV(t0), Sampler(s0))",
+                "column 1: samplers can't share a table with CBV, SRV and UAV ranges",
+            ),
+            ("RootConstants(b0)", "column 1: missing `num32BitConstants`"),
+            (
+                "StaticSampler(s0, filter=FILTER_LINEAR)",
+                "column 26: unknown filter `FILTER_LINEAR`",
+            ),
+            ("CBV(b0) CBV(b1)", "column 9: expected `,`, found `CBV`"),
+            (
+                "UAV(u0, flags=DATA_STATIC)",
+                "column 9: flags need root signature version 1.1",
+            ),
+            (
+                "Table(t0)",
+                "column 1: unknown root signature element `Table`",
+            ),
+            (
+                "CBV(b0, visibility=PIXEL)",
+                "column 20: unknown shader visibility `PIXEL`",
+            ),
+            ("CBV(b0; space=1)", "column 7: unexpected `;`"),
+        ];
+        for (text, message) in &errors {
+            let error = parse(text, RootSignatureVersion::V1_0).unwrap_err();
+            assert_eq!(error.to_string(), *message, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reads_defines_and_attributes() {
+        let resolver = Embedded::default();
+        let mut preprocessor = Preprocessor::new(&resolver);
+        preprocessor.run("simple.hlsl", SIMPLE).unwrap();
+        assert_eq!(
+            from_define(&preprocessor, "HelloTriangleRS", RootSignatureVersion::V1_0),
+            Ok(hello_triangle())
+        );
+        assert_eq!(
+            from_define(&preprocessor, "MissingRS", RootSignatureVersion::V1_0),
+            Err(SourceError::Undefined("MissingRS".into()))
+        );
+
+        // A `.rs.hlsl` file, strings split over lines
+        let source = "#define Compute \"CBV(b0), \" \\\n    \"UAV(u0, flags=DATA_VOLATILE\"\n";
+        let mut preprocessor = Preprocessor::new(&resolver);
+        preprocessor.run("compute.rs.hlsl", source).unwrap();
+        assert_eq!(
+            from_define(&preprocessor, "Compute", RootSignatureVersion::V1_1)
+                .unwrap_err()
+                .to_string(),
+            "Compute: column 37: expected `,` or `)`"
+        );
+
+        let source = "#define RS \"RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT)\"\n\
+            [RootSignature(RS)]\n\
+            float4 main() : SV_Target { return 0; }\n\
+            [RootSignature(\"CBV(b0, space=x)\")]\n\
+            [numthreads(1, 1, 1)]\n\
+            void broken() {}\n";
+        let preprocessed = Preprocessor::new(&resolver)
+            .run("main.hlsl", source)
+            .unwrap();
+        let reflection = Reflection::parse(&preprocessed.source).unwrap();
+        let attribute = |name| {
+            from_attribute(
+                reflection.function(name).unwrap(),
+                &preprocessed,
+                RootSignatureVersion::V1_0,
+            )
+        };
+        assert_eq!(attribute("main"), Ok(Some(hello_triangle())));
+        assert_eq!(
+            attribute("broken").unwrap_err().to_string(),
+            "main.hlsl:6: column 15: expected a number, found `x`"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::format::Format;
+use crate::vertex::Vertex;
+
+/// Format of the swap chain back buffers
+pub const BACK_BUFFER_FORMAT: Format = Format::B8G8R8A8Unorm;
+
+/// `D3D12_VIEWPORT`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub top_left_x: f32,
+    pub top_left_y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+/// `D3D12_RECT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+/// What is drawn each frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: [f32; 4],
+    pub vertices: Vec<Vertex>,
+}
+
+impl Scene {
+    /// Triangle of the HelloTriangle sample over a pink background
+    pub fn hello_triangle(width: u32, height: u32) -> Self {
+        // Blue end of the triangle is semi transparent
+        let ar = 1.0;
+        let scale = 1.0;
+        Scene {
+            width,
+            height,
+            clear_color: [1.0, 0.2, 0.4, 0.5],
+            vertices: vec![
+                Vertex::new([0.0, scale * ar, 0.0], [1.0, 0.0, 0.0, 1.0]),
+                Vertex::new([scale, -scale * ar, 0.0], [0.0, 1.0, 0.0, 1.0]),
+                Vertex::new([-scale, -scale * ar, 0.0], [0.0, 0.0, 1.0, 0.5]),
+            ],
+        }
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            top_left_x: 0.0,
+            top_left_y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+
+    pub fn scissor(&self) -> Rect {
+        Rect {
+            left: 0,
+            top: 0,
+            right: self.width as i32,
+            bottom: self.height as i32,
+        }
+    }
+
+    /// Size of the vertex data in bytes
+    pub fn vertices_size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.vertices.as_slice())
+    }
+}
@@ -0,0 +1,11 @@
+//! Shader tooling that works without the D3D compiler
+pub mod cache;
+pub mod compiler;
+pub mod container;
+pub mod diagnostic;
+pub mod hlsl;
+pub mod layout;
+pub mod manifest;
+pub mod permutation;
+pub mod preprocess;
+pub mod signature;
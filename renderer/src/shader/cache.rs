@@ -0,0 +1,466 @@
+//! Content-addressed on-disk cache of compiled shaders
+//!
+//! Entries are named by a hash of everything the compiler sees and of the
+//! compiler itself, so a changed source, define or compiler is a new file
+//! rather than an invalidation. Each file
+//! carries a format version, its own key and a checksum, anything that
+//! doesn't check out is a miss and gets recompiled.
+use super::permutation::Defines;
+use crate::encode::crc32;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"DXSC";
+
+/// Bumped when the entry layout changes, older entries are recompiled
+pub const CACHE_VERSION: u32 = 1;
+
+/// Magic, version, key, checksum, blob and diagnostics lengths
+const HEADER_SIZE: usize = 4 + 4 + 16 + 4 + 4 + 4;
+
+/// Inputs that decide the compiled output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'a> {
+    /// Preprocessed source, includes already resolved
+    pub source: &'a str,
+    pub entry_point: &'a str,
+    /// Target profile, e.g. `vs_5_0`
+    pub profile: &'a str,
+    /// `D3DCOMPILE_*` flags
+    pub flags: u32,
+    pub defines: &'a Defines,
+    /// Version or executable of the compiler, see `Compiler::identity`
+    pub compiler: &'a str,
+}
+
+impl Request<'_> {
+    pub fn key(&self) -> Key {
+        let mut hash = Fnv::new();
+        hash.field(self.source.as_bytes());
+        hash.field(self.entry_point.as_bytes());
+        hash.field(self.profile.as_bytes());
+        hash.field(&self.flags.to_le_bytes());
+        for (name, value) in self.defines.iter() {
+            hash.field(name.as_bytes());
+            hash.field(value.as_bytes());
+        }
+        hash.field(self.compiler.as_bytes());
+        Key(hash.0)
+    }
+}
+
+/// 128-bit FNV-1a hash of a request, stable across runs and toolchains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key(pub u128);
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+struct Fnv(u128);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u128;
+            self.0 = self
+                .0
+                .wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+    }
+
+    /// Length prefixed so `"ab", "c"` and `"a", "bc"` differ
+    fn field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+/// Compiler output worth keeping
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Compiled {
+    pub blob: Vec<u8>,
+    /// Warnings of a successful compile, shown again on a hit
+    pub diagnostics: String,
+}
+
+/// Why an entry couldn't be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Miss {
+    Absent,
+    /// Written by another version of the cache
+    Version(u32),
+    /// Truncated, wrong checksum or another key's contents
+    Corrupt,
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for Miss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Miss::Absent => write!(f, "not cached"),
+            Miss::Version(version) => {
+                write!(f, "cache version {}, expected {}", version, CACHE_VERSION)
+            }
+            Miss::Corrupt => write!(f, "corrupt cache entry"),
+            Miss::Io(kind) => write!(f, "unreadable cache entry: {:?}", kind),
+        }
+    }
+}
+
+/// Directory of compiled shaders, one file per key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderCache {
+    pub dir: PathBuf,
+}
+
+impl ShaderCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ShaderCache { dir: dir.into() }
+    }
+
+    pub fn path(&self, key: Key) -> PathBuf {
+        self.dir.join(format!("{}.dxsc", key))
+    }
+
+    pub fn read(&self, key: Key) -> Result<Compiled, Miss> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => decode(key, &bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Miss::Absent),
+            Err(e) => Err(Miss::Io(e.kind())),
+        }
+    }
+
+    /// Written to a temporary file first so readers never see half an entry
+    pub fn write(&self, key: Key, compiled: &Compiled) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        let temp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, encode(key, compiled))?;
+        fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+
+    /// Cached output of the request, compiled and stored on a miss
+    ///
+    /// Failed compiles aren't stored. Neither is anything when the directory
+    /// isn't writable, that only costs the compile on the next run.
+    pub fn get_or_compile<E>(
+        &self,
+        request: &Request,
+        compile: impl FnOnce(&Request) -> Result<Compiled, E>,
+    ) -> Result<Compiled, E> {
+        let key = request.key();
+        if let Ok(compiled) = self.read(key) {
+            return Ok(compiled);
+        }
+        let compiled = compile(request)?;
+        let _ = self.write(key, &compiled);
+        Ok(compiled)
+    }
+}
+
+fn encode(key: Key, compiled: &Compiled) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(8 + compiled.blob.len() + compiled.diagnostics.len());
+    payload.extend_from_slice(&(compiled.blob.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&(compiled.diagnostics.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&compiled.blob);
+    payload.extend_from_slice(compiled.diagnostics.as_bytes());
+
+    let mut out = Vec::with_capacity(28 + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    out.extend_from_slice(&key.0.to_le_bytes());
+    out.extend_from_slice(&crc32(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    out
+}
+
+fn decode(key: Key, bytes: &[u8]) -> Result<Compiled, Miss> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+        return Err(Miss::Corrupt);
+    }
+    let version = le32(bytes, 4);
+    if version != CACHE_VERSION {
+        return Err(Miss::Version(version));
+    }
+    let mut stored = [0; 16];
+    stored.copy_from_slice(&bytes[8..24]);
+    let payload = &bytes[28..];
+    if u128::from_le_bytes(stored) != key.0 || crc32(payload) != le32(bytes, 24) {
+        return Err(Miss::Corrupt);
+    }
+    let blob_len = le32(bytes, 28) as usize;
+    let diagnostics_len = le32(bytes, 32) as usize;
+    if HEADER_SIZE + blob_len + diagnostics_len != bytes.len() {
+        return Err(Miss::Corrupt);
+    }
+    let (blob, diagnostics) = bytes[HEADER_SIZE..].split_at(blob_len);
+    Ok(Compiled {
+        blob: blob.to_vec(),
+        diagnostics: String::from_utf8(diagnostics.to_vec()).map_err(|_| Miss::Corrupt)?,
+    })
+}
+
+fn le32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+/// Cache directory when nothing else is configured
+pub fn default_dir() -> PathBuf {
+    std::env::temp_dir().join("dx12-scratch").join("shaders")
+}
+
+/// Removes entries that no longer decode and temporary files left by
+/// interrupted writes, returns how many. Other files are left alone
+pub fn prune(dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let key = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| u128::from_str_radix(stem, 16).ok());
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let stale = match key {
+            Some(key) if extension == "dxsc" => {
+                !fs::read(&path).is_ok_and(|bytes| decode(Key(key), &bytes).is_ok())
+            }
+            // `ShaderCache::write` names them after the process
+            Some(_) => extension
+                .strip_prefix("tmp")
+                .is_some_and(|pid| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit())),
+            None => false,
+        };
+        if stale {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::permutation;
+    use crate::shader::preprocess::Embedded;
+    use std::cell::Cell;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    fn cache(name: &str) -> ShaderCache {
+        let dir =
+            std::env::temp_dir().join(format!("shader-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ShaderCache::new(dir)
+    }
+
+    /// Stands in for the compiler and counts the calls
+    fn compile<'a>(calls: &'a Cell<u32>) -> impl Fn(&Request) -> Result<Compiled, String> + 'a {
+        move |request| {
+            calls.set(calls.get() + 1);
+            if request.source.contains("error") {
+                return Err(format!("{}: syntax error", request.entry_point));
+            }
+            Ok(Compiled {
+                blob: format!("{} {}", request.profile, request.source).into_bytes(),
+                diagnostics: format!(
+                    "{}: warning X3206: implicit truncation",
+                    request.entry_point
+                ),
+            })
+        }
+    }
+
+    #[test]
+    fn keys_cover_every_input() {
+        let defines = Defines::new();
+        let base = Request {
+            source: "float4 main() : SV_Target { return 0; }",
+            entry_point: "main",
+            profile: "ps_5_0",
+            flags: 0,
+            defines: &defines,
+            compiler: "stub",
+        };
+        // Entry file names must not change between toolchains
+        assert_eq!(base.key().to_string(), "627102df6008d882d9073d9422cbc827");
+
+        let other = Defines::new().set("FAST", "1");
+        let keys = [
+            base.key(),
+            Request {
+                source: "float4 main() : SV_Target { return 1; }",
+                ..base
+            }
+            .key(),
+            Request {
+                entry_point: "PSMain",
+                ..base
+            }
+            .key(),
+            Request {
+                profile: "ps_5_1",
+                ..base
+            }
+            .key(),
+            Request { flags: 1, ..base }.key(),
+            Request {
+                defines: &other,
+                ..base
+            }
+            .key(),
+            Request {
+                compiler: "dxc 1.8",
+                ..base
+            }
+            .key(),
+        ];
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(base.key(), Request { ..base }.key());
+    }
+
+    #[test]
+    fn compiles_once_and_keeps_diagnostics() {
+        let cache = cache("hit");
+        let calls = Cell::new(0);
+        let defines = Defines::new().set("VERTEX_COLOR", "1");
+        let request = Request {
+            source: "float4 VSMain() : SV_Position { return 0; }",
+            entry_point: "VSMain",
+            profile: "vs_5_0",
+            flags: 0,
+            defines: &defines,
+            compiler: "stub",
+        };
+        assert_eq!(cache.read(request.key()), Err(Miss::Absent));
+        let first = cache.get_or_compile(&request, compile(&calls)).unwrap();
+        let second = cache.get_or_compile(&request, compile(&calls)).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(first, second);
+        assert_eq!(
+            second.diagnostics,
+            "VSMain: warning X3206: implicit truncation"
+        );
+
+        // Failures are compiled again every time
+        let broken = Request {
+            source: "error",
+            ..request
+        };
+        assert!(cache.get_or_compile(&broken, compile(&calls)).is_err());
+        assert!(cache.get_or_compile(&broken, compile(&calls)).is_err());
+        assert_eq!(calls.get(), 3);
+        assert_eq!(cache.read(broken.key()), Err(Miss::Absent));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn recompiles_bad_entries() {
+        let cache = cache("corrupt");
+        let calls = Cell::new(0);
+        let defines = Defines::new();
+        let request = Request {
+            source: "float4 main() : SV_Target { return 0; }",
+            entry_point: "main",
+            profile: "ps_5_0",
+            flags: 0,
+            defines: &defines,
+            compiler: "stub",
+        };
+        let key = request.key();
+        let compiled = cache.get_or_compile(&request, compile(&calls)).unwrap();
+        let path = cache.path(key);
+        let good = fs::read(&path).unwrap();
+
+        let mut flipped = good.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        assert_eq!(cache.read(key), Err(Miss::Corrupt));
+
+        fs::write(&path, &good[..good.len() - 1]).unwrap();
+        assert_eq!(cache.read(key), Err(Miss::Corrupt));
+
+        let mut old = good.clone();
+        old[4..8].copy_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, &old).unwrap();
+        assert_eq!(cache.read(key), Err(Miss::Version(0)));
+
+        // Renamed from another key
+        fs::copy(&path, cache.path(Key(key.0 + 1))).unwrap();
+        fs::write(&path, &good).unwrap();
+        assert_eq!(cache.read(key), Ok(compiled.clone()));
+        assert_eq!(cache.read(Key(key.0 + 1)), Err(Miss::Version(0)));
+        fs::write(cache.path(Key(key.0 + 1)), &good).unwrap();
+        assert_eq!(cache.read(Key(key.0 + 1)), Err(Miss::Corrupt));
+
+        fs::write(&path, &flipped).unwrap();
+        assert_eq!(
+            cache.get_or_compile(&request, compile(&calls)),
+            Ok(compiled)
+        );
+        assert_eq!(calls.get(), 2);
+        assert_eq!(fs::read(&path).unwrap(), good);
+
+        // Only entries and temporary files are the cache's to remove
+        fs::write(cache.path(key).with_extension("tmp123"), "partial").unwrap();
+        fs::write(cache.dir.join("notes.txt"), "stray").unwrap();
+        fs::write(cache.dir.join("notes.dxsc"), "stray").unwrap();
+        assert_eq!(prune(&cache.dir).unwrap(), 2);
+        assert_eq!(prune(&cache.dir).unwrap(), 0);
+        assert!(cache.dir.join("notes.txt").exists());
+        assert!(cache.dir.join("notes.dxsc").exists());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn caches_permutations() {
+        let cache = cache("permutations");
+        let calls = Cell::new(0);
+        let build = || {
+            permutation::hello_triangle("5_0")
+                .compile(
+                    "simple.hlsl",
+                    SIMPLE,
+                    &Embedded::default(),
+                    |variant, preprocessed| {
+                        let request = Request {
+                            source: &preprocessed.source,
+                            entry_point: &variant.entry_point,
+                            profile: &variant.profile,
+                            flags: 0,
+                            defines: &variant.defines,
+                            compiler: "stub",
+                        };
+                        cache
+                            .get_or_compile(&request, compile(&calls))
+                            .map(|compiled| compiled.blob)
+                    },
+                )
+                .unwrap()
+        };
+        let first = build();
+        assert_eq!(calls.get(), 6);
+        // Next launch reads everything from disk
+        let second = build();
+        assert_eq!(calls.get(), 6);
+        assert_eq!(first, second);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,616 @@
+//! Shader compilers behind one trait
+//!
+//! The runtime `D3DCompile` backend lives in the window shells, debug builds
+//! use it unless `DXC` is set. The DXC command line, which release builds
+//! run from `build.rs`, and the stub work anywhere. [`Build`] runs
+//! permutations through a compiler and the cache, at startup or ahead of time
+//! from a [`Manifest`](super::manifest::Manifest).
+use super::cache::{Compiled, Request, ShaderCache};
+use super::diagnostic::{self, Diagnostic, Severity};
+use super::manifest::{self, Manifest};
+use super::permutation::{PermutationError, Permutations, ShaderLibrary, Variant};
+use super::preprocess::{IncludeResolver, Preprocessed, SearchPath};
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `D3DCOMPILE_DEBUG`
+pub const DEBUG: u32 = 1 << 0;
+/// `D3DCOMPILE_SKIP_OPTIMIZATION`
+pub const SKIP_OPTIMIZATION: u32 = 1 << 2;
+/// `D3DCOMPILE_OPTIMIZATION_LEVEL3`
+pub const OPTIMIZATION_LEVEL3: u32 = 1 << 15;
+/// `D3DCOMPILE_WARNINGS_ARE_ERRORS`
+pub const WARNINGS_ARE_ERRORS: u32 = 1 << 18;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// The compiler rejected the source, at least one diagnostic
+    Failed(Vec<Diagnostic>),
+    /// The compiler couldn't be run, e.g. no `dxc` on the path
+    Unavailable(String),
+}
+
+impl CompileError {
+    /// Failure with the diagnostics parsed from compiler output
+    pub fn failed(output: &str) -> Self {
+        CompileError::Failed(diagnostic::parse_failure(output))
+    }
+
+    /// Maps the diagnostics back to the files of the preprocessed source
+    pub fn locate(mut self, preprocessed: &Preprocessed) -> Self {
+        if let CompileError::Failed(diagnostics) = &mut self {
+            for diagnostic in diagnostics {
+                diagnostic.locate(preprocessed);
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Failed(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "\n" };
+                    write!(f, "{}{}", separator, diagnostic)?;
+                }
+                Ok(())
+            }
+            CompileError::Unavailable(reason) => write!(f, "compiler unavailable: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+pub trait Compiler {
+    /// Identifies the compiler's output, cache entries are kept apart by it
+    fn name(&self) -> &str;
+
+    /// Part of every cache key, changes when the same request may compile
+    /// differently, e.g. after an upgrade
+    fn identity(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// Compiles preprocessed source, the defines are already applied
+    fn compile(&self, request: &Request) -> Result<Compiled, CompileError>;
+}
+
+/// Stands in for a compiler in tests, the blob names the request
+#[derive(Debug, Default)]
+pub struct Stub {
+    /// Entry point that fails to compile and the output it gives
+    pub fail: Option<(String, String)>,
+    /// Entry point that compiles with warnings and the output it gives
+    pub warn: Option<(String, String)>,
+    calls: Cell<usize>,
+}
+
+impl Stub {
+    pub fn new() -> Self {
+        Stub::default()
+    }
+
+    pub fn failing(entry_point: &str, output: &str) -> Self {
+        Stub {
+            fail: Some((entry_point.to_string(), output.to_string())),
+            ..Stub::default()
+        }
+    }
+
+    pub fn warning(entry_point: &str, output: &str) -> Self {
+        Stub {
+            warn: Some((entry_point.to_string(), output.to_string())),
+            ..Stub::default()
+        }
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+}
+
+impl Compiler for Stub {
+    fn name(&self) -> &str {
+        "stub"
+    }
+
+    fn compile(&self, request: &Request) -> Result<Compiled, CompileError> {
+        self.calls.set(self.calls.get() + 1);
+        match &self.fail {
+            Some((entry_point, output)) if entry_point == request.entry_point => {
+                Err(CompileError::failed(output))
+            }
+            _ => Ok(Compiled {
+                blob: format!(
+                    "{} {} {}",
+                    request.profile,
+                    request.entry_point,
+                    request.key()
+                )
+                .into_bytes(),
+                diagnostics: match &self.warn {
+                    Some((entry_point, output)) if entry_point == request.entry_point => {
+                        output.clone()
+                    }
+                    _ => String::new(),
+                },
+            }),
+        }
+    }
+}
+
+/// The DirectX Shader Compiler command line, shader model 6 and up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dxc {
+    pub executable: PathBuf,
+    /// Where sources and outputs are written for the process
+    pub scratch: PathBuf,
+}
+
+impl Dxc {
+    pub fn new(executable: impl Into<PathBuf>) -> Self {
+        Dxc {
+            executable: executable.into(),
+            scratch: std::env::temp_dir().join("dx12-scratch").join("dxc"),
+        }
+    }
+
+    /// `dxc` from the path unless `DXC` names another
+    pub fn from_env() -> Self {
+        Dxc::new(std::env::var_os("DXC").unwrap_or_else(|| "dxc".into()))
+    }
+
+    /// Command line arguments besides the executable
+    pub fn arguments(&self, request: &Request, input: &Path, output: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "-nologo".into(),
+            "-T".into(),
+            request.profile.into(),
+            "-E".into(),
+            request.entry_point.into(),
+            "-Fo".into(),
+            output.into(),
+        ];
+        let flags = [
+            (DEBUG, "-Zi"),
+            (SKIP_OPTIMIZATION, "-Od"),
+            (OPTIMIZATION_LEVEL3, "-O3"),
+            (WARNINGS_ARE_ERRORS, "-WX"),
+        ];
+        for (flag, arg) in &flags {
+            if request.flags & flag != 0 {
+                args.push(arg.into());
+            }
+        }
+        args.push(input.into());
+        args
+    }
+}
+
+impl Compiler for Dxc {
+    fn name(&self) -> &str {
+        "dxc"
+    }
+
+    /// Path, size and modification time of the executable and the
+    /// `dxcompiler` library beside it
+    fn identity(&self) -> String {
+        let executable = locate(&self.executable).unwrap_or_else(|| self.executable.clone());
+        let library = executable.with_file_name(format!(
+            "{}dxcompiler{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ));
+        format!("dxc {} {}", stamp(&executable), stamp(&library))
+    }
+
+    fn compile(&self, request: &Request) -> Result<Compiled, CompileError> {
+        let unavailable = |e: io::Error| CompileError::Unavailable(e.to_string());
+        fs::create_dir_all(&self.scratch).map_err(unavailable)?;
+        let stem = format!("{}-{}", request.key(), std::process::id());
+        let input = self.scratch.join(format!("{}.hlsl", stem));
+        let output = self.scratch.join(format!("{}.bin", stem));
+        fs::write(&input, request.source).map_err(unavailable)?;
+        let result = Command::new(&self.executable)
+            .args(self.arguments(request, &input, &output))
+            .output();
+        let _ = fs::remove_file(&input);
+        let result = result.map_err(|e| {
+            CompileError::Unavailable(format!("{}: {}", self.executable.display(), e))
+        })?;
+        let diagnostics = String::from_utf8_lossy(&result.stderr).into_owned();
+        if !result.status.success() {
+            let _ = fs::remove_file(&output);
+            return Err(CompileError::failed(&diagnostics));
+        }
+        let blob = fs::read(&output).map_err(|e| {
+            let message = format!("{} wrote no output: {}", self.executable.display(), e);
+            CompileError::Failed(vec![Diagnostic::new(Severity::Error, &message)])
+        })?;
+        let _ = fs::remove_file(&output);
+        Ok(Compiled { blob, diagnostics })
+    }
+}
+
+/// The executable the way `Command` finds it, bare names on the path
+fn locate(executable: &Path) -> Option<PathBuf> {
+    if executable.components().count() > 1 {
+        return Some(executable.to_path_buf());
+    }
+    let names = [
+        executable.to_path_buf(),
+        executable.with_extension(std::env::consts::EXE_EXTENSION),
+    ];
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+fn stamp(path: &Path) -> String {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            format!(
+                "{}:{}:{}",
+                path.display(),
+                metadata.len(),
+                modified.as_nanos()
+            )
+        }
+        Err(_) => format!("{}:missing", path.display()),
+    }
+}
+
+/// Diagnostic of a successful compile and the variant it came from
+pub type Warning = (Variant, Diagnostic);
+
+/// Library and the warnings of compiling it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compilation {
+    pub library: ShaderLibrary,
+    /// Diagnostics of successful compiles
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    Io(PathBuf, io::Error),
+    Compile(String, PermutationError<CompileError>),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            BuildError::Compile(file, e) => write!(f, "{}: {}", file, e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Compiler, flags and cache used for every variant
+pub struct Build<'a> {
+    pub compiler: &'a dyn Compiler,
+    pub flags: u32,
+    pub cache: Option<ShaderCache>,
+}
+
+impl<'a> Build<'a> {
+    pub fn new(compiler: &'a dyn Compiler) -> Self {
+        Build {
+            compiler,
+            flags: 0,
+            cache: None,
+        }
+    }
+
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Cache under `dir`, in a subdirectory per compiler
+    pub fn cache(mut self, dir: impl AsRef<Path>) -> Self {
+        self.cache = Some(ShaderCache::new(dir.as_ref().join(self.compiler.name())));
+        self
+    }
+
+    pub fn compile(
+        &self,
+        permutations: &Permutations,
+        file: &str,
+        source: &str,
+        resolver: &dyn IncludeResolver,
+    ) -> Result<Compilation, PermutationError<CompileError>> {
+        let mut warnings = Vec::new();
+        let identity = self.compiler.identity();
+        let library = permutations.compile(file, source, resolver, |variant, preprocessed| {
+            let request = Request {
+                source: &preprocessed.source,
+                entry_point: &variant.entry_point,
+                profile: &variant.profile,
+                flags: self.flags,
+                defines: &variant.defines,
+                compiler: &identity,
+            };
+            let compiled = match &self.cache {
+                Some(cache) => cache.get_or_compile(&request, |r| self.compiler.compile(r)),
+                None => self.compiler.compile(&request),
+            }
+            .map_err(|e| e.locate(preprocessed))?;
+            for mut diagnostic in diagnostic::parse(&compiled.diagnostics) {
+                diagnostic.locate(preprocessed);
+                warnings.push((variant.clone(), diagnostic));
+            }
+            Ok(compiled.blob)
+        })?;
+        Ok(Compilation { library, warnings })
+    }
+
+    /// Compiles each file of the manifest from `source_dir` and writes its
+    /// library to `out_dir`, returns the written paths and the warnings
+    pub fn manifest(
+        &self,
+        manifest: &Manifest,
+        source_dir: &Path,
+        out_dir: &Path,
+    ) -> Result<(Vec<PathBuf>, Vec<Warning>), BuildError> {
+        let resolver = SearchPath::new(vec![source_dir]);
+        let mut written = Vec::new();
+        let mut warnings = Vec::new();
+        fs::create_dir_all(out_dir).map_err(|e| BuildError::Io(out_dir.into(), e))?;
+        for (file, permutations) in &manifest.files {
+            let path = source_dir.join(file);
+            let source = fs::read_to_string(&path).map_err(|e| BuildError::Io(path.clone(), e))?;
+            let compilation = self
+                .compile(permutations, &path.to_string_lossy(), &source, &resolver)
+                .map_err(|e| BuildError::Compile(file.clone(), e))?;
+            let out = out_dir.join(manifest::library_name(file));
+            fs::write(&out, compilation.library.to_bytes())
+                .map_err(|e| BuildError::Io(out.clone(), e))?;
+            written.push(out);
+            warnings.extend(compilation.warnings);
+        }
+        Ok((written, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::permutation::{self, Defines};
+    use crate::shader::preprocess::Embedded;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    #[test]
+    fn builds_through_the_cache() {
+        let dir = std::env::temp_dir().join(format!("compiler-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let stub = Stub::new();
+        let build = Build::new(&stub).cache(&dir);
+        let first = build
+            .compile(
+                &permutation::hello_triangle("5_0"),
+                "simple.hlsl",
+                SIMPLE,
+                &Embedded::default(),
+            )
+            .unwrap();
+        assert_eq!(stub.calls(), 6);
+        let second = build
+            .compile(
+                &permutation::hello_triangle("5_0"),
+                "simple.hlsl",
+                SIMPLE,
+                &Embedded::default(),
+            )
+            .unwrap();
+        assert_eq!(stub.calls(), 6);
+        assert_eq!(first.library.to_bytes(), second.library.to_bytes());
+        assert!(dir.join("stub").is_dir());
+
+        // Flags are part of the key
+        Build::new(&stub)
+            .cache(&dir)
+            .flags(DEBUG)
+            .compile(
+                &permutation::hello_triangle("5_0"),
+                "simple.hlsl",
+                SIMPLE,
+                &Embedded::default(),
+            )
+            .unwrap();
+        assert_eq!(stub.calls(), 12);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let failing = Stub::failing(
+            "PSMain",
+            "simple.hlsl(35,20): error X3004: undeclared identifier 'input'",
+        );
+        let error = Build::new(&failing)
+            .compile(
+                &permutation::hello_triangle("5_0"),
+                "simple.hlsl",
+                SIMPLE,
+                &Embedded::default(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PSMain (ps_5_0): simple.hlsl:35:20: error X3004: undeclared identifier 'input'
+   |
+35 |     float4 color = input.color;
+   |                    ^"
+        );
+
+        let warning = Stub::warning("VSMain", "simple.hlsl(23,5): warning X3206: truncation");
+        let compilation = Build::new(&warning)
+            .compile(
+                &permutation::hello_triangle("5_0"),
+                "simple.hlsl",
+                SIMPLE,
+                &Embedded::default(),
+            )
+            .unwrap();
+        let lines: Vec<_> = compilation
+            .warnings
+            .iter()
+            .map(|(variant, diagnostic)| {
+                (
+                    variant.to_string(),
+                    diagnostic.line,
+                    diagnostic.snippet.clone(),
+                )
+            })
+            .collect();
+        let snippet = Some("    result.position = position;".to_string());
+        assert_eq!(
+            lines,
+            [
+                ("VSMain (vs_5_0)".to_string(), 23, snippet.clone()),
+                ("VSMain (vs_5_0) VERTEX_COLOR=1".to_string(), 23, snippet),
+            ]
+        );
+    }
+
+    #[test]
+    fn builds_manifest_ahead_of_time() {
+        let dir = std::env::temp_dir().join(format!("compiler-manifest-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/common")).unwrap();
+        fs::write(dir.join("src/common/color.hlsl"), "float4 tint;\n").unwrap();
+        fs::write(
+            dir.join("src/tint.hlsl"),
+            "#include \"common/color.hlsl\"\nfloat4 main() : SV_Target { return tint; }\n",
+        )
+        .unwrap();
+        let manifest = Manifest::parse("tint.hlsl main ps_6_0 FOG").unwrap();
+        let stub = Stub::new();
+        let (written, warnings) = Build::new(&stub)
+            .manifest(&manifest, &dir.join("src"), &dir.join("out"))
+            .unwrap();
+        assert_eq!(written, [dir.join("out/tint.dxlib")]);
+        assert!(warnings.is_empty());
+        let library = ShaderLibrary::from_bytes(&fs::read(&written[0]).unwrap()).unwrap();
+        assert_eq!(library.variant_count(), 2);
+        assert!(library
+            .get("main", "ps_6_0", &Defines::new().set("FOG", "1"))
+            .is_some());
+
+        let missing = Manifest::parse("missing.hlsl main ps_6_0").unwrap();
+        match Build::new(&stub).manifest(&missing, &dir.join("src"), &dir.join("out")) {
+            Err(BuildError::Io(path, _)) => assert_eq!(path, dir.join("src/missing.hlsl")),
+            other => panic!("unexpected {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn runs_dxc() {
+        let dxc = Dxc::new("dxc");
+        let defines = Defines::new();
+        let request = Request {
+            source: "float4 main() : SV_Target { return 0; }",
+            entry_point: "main",
+            profile: "ps_6_0",
+            flags: DEBUG | WARNINGS_ARE_ERRORS,
+            defines: &defines,
+            compiler: "dxc",
+        };
+        let args = dxc.arguments(&request, Path::new("in.hlsl"), Path::new("out.bin"));
+        assert_eq!(
+            args,
+            ["-nologo", "-T", "ps_6_0", "-E", "main", "-Fo", "out.bin", "-Zi", "-WX", "in.hlsl"]
+        );
+
+        let missing = Dxc::new("/nonexistent/dxc");
+        match missing.compile(&request) {
+            Err(CompileError::Unavailable(reason)) => {
+                assert!(reason.starts_with("/nonexistent/dxc"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    /// A script with the DXC command line in place of the real thing
+    #[cfg(unix)]
+    #[test]
+    fn reads_dxc_output() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("compiler-dxc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("dxc");
+        fs::write(
+            &script,
+            "#!/bin/sh
+while [ $# -gt 1 ]; do
+    case \"$1\" in -E) entry=\"$2\";; -Fo) out=\"$2\";; esac
+    shift
+done
+if grep -q error \"$1\"; then echo \"$1:1:1: error: broken\" >&2; exit 1; fi
+echo \"warning: $entry is slow\" >&2
+printf DXBC > \"$out\"
+",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let dxc = Dxc {
+            executable: script.clone(),
+            scratch: dir.join("scratch"),
+        };
+        // Another build of the executable is another cache key
+        let identity = dxc.identity();
+        assert!(identity.starts_with(&format!("dxc {}:", script.display())));
+        let library = format!(
+            "{}dxcompiler{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        );
+        fs::write(dir.join(library), "").unwrap();
+        assert_ne!(dxc.identity(), identity);
+        let defines = Defines::new();
+        let request = Request {
+            source: "float4 main() : SV_Target { return 0; }",
+            entry_point: "main",
+            profile: "ps_6_0",
+            flags: 0,
+            defines: &defines,
+            compiler: "dxc",
+        };
+        let compiled = dxc.compile(&request).unwrap();
+        assert_eq!(compiled.blob, b"DXBC");
+        assert_eq!(compiled.diagnostics, "warning: main is slow\n");
+
+        let broken = Request {
+            source: "error",
+            ..request
+        };
+        match dxc.compile(&broken) {
+            Err(CompileError::Failed(diagnostics)) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].message, "broken");
+                assert_eq!((diagnostics[0].line, diagnostics[0].column), (1, 1));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // Scratch files are removed
+        assert_eq!(fs::read_dir(dir.join("scratch")).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
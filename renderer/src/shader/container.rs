@@ -0,0 +1,961 @@
+//! Chunks of compiled shader blobs
+//!
+//! `D3DCompile` and DXC both return a `DXBC` container: a header with the
+//! offsets of four character code chunks such as `ISGN` for the input
+//! signature. `D3DCompile` adds `RDEF` reflection, `SHEX` bytecode and `STAT`
+//! statistics, DXC a `DXIL` program. Parsing doesn't verify the checksum,
+//! written containers carry the one the runtime checks.
+use super::hlsl::{Register, RegisterClass};
+use super::signature::{ComponentType, SignatureElement};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerError {
+    /// Doesn't start with `DXBC`
+    NotDxbc,
+    Truncated,
+    MissingChunk([u8; 4]),
+    /// Chunk whose contents don't parse
+    Malformed([u8; 4]),
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerError::NotDxbc => write!(f, "not a DXBC container"),
+            ContainerError::Truncated => write!(f, "truncated shader container"),
+            ContainerError::MissingChunk(fourcc) => {
+                write!(f, "no {} chunk", String::from_utf8_lossy(fourcc))
+            }
+            ContainerError::Malformed(fourcc) => {
+                write!(f, "malformed {} chunk", String::from_utf8_lossy(fourcc))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Pixel = 0,
+    Vertex = 1,
+    Geometry = 2,
+    Hull = 3,
+    Domain = 4,
+    Compute = 5,
+}
+
+impl Stage {
+    /// Profile prefix, e.g. `vs`
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Stage::Pixel => "ps",
+            Stage::Vertex => "vs",
+            Stage::Geometry => "gs",
+            Stage::Hull => "hs",
+            Stage::Domain => "ds",
+            Stage::Compute => "cs",
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<Stage> {
+        [
+            Stage::Pixel,
+            Stage::Vertex,
+            Stage::Geometry,
+            Stage::Hull,
+            Stage::Domain,
+            Stage::Compute,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
+/// Target of a compiled program, displayed as a profile like `vs_5_0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderModel {
+    pub stage: Stage,
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ShaderModel {
+    /// Version token leading `SHEX` and `DXIL` programs
+    fn from_version(token: u32) -> Option<ShaderModel> {
+        Some(ShaderModel {
+            stage: Stage::from_u32(token >> 16)?,
+            major: (token >> 4) & 0xf,
+            minor: token & 0xf,
+        })
+    }
+}
+
+impl fmt::Display for ShaderModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}_{}_{}", self.stage.prefix(), self.major, self.minor)
+    }
+}
+
+/// `D3D_SHADER_INPUT_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputType {
+    ConstantBuffer = 0,
+    TextureBuffer = 1,
+    Texture = 2,
+    Sampler = 3,
+    RwTyped = 4,
+    Structured = 5,
+    RwStructured = 6,
+    ByteAddress = 7,
+    RwByteAddress = 8,
+    AppendStructured = 9,
+    ConsumeStructured = 10,
+    RwStructuredWithCounter = 11,
+}
+
+impl InputType {
+    pub fn register_class(self) -> RegisterClass {
+        match self {
+            InputType::ConstantBuffer => RegisterClass::ConstantBuffer,
+            InputType::Sampler => RegisterClass::Sampler,
+            InputType::TextureBuffer
+            | InputType::Texture
+            | InputType::Structured
+            | InputType::ByteAddress => RegisterClass::ShaderResource,
+            InputType::RwTyped
+            | InputType::RwStructured
+            | InputType::RwByteAddress
+            | InputType::AppendStructured
+            | InputType::ConsumeStructured
+            | InputType::RwStructuredWithCounter => RegisterClass::UnorderedAccess,
+        }
+    }
+
+    fn from_u32(value: u32) -> Option<InputType> {
+        use InputType::*;
+        [
+            ConstantBuffer,
+            TextureBuffer,
+            Texture,
+            Sampler,
+            RwTyped,
+            Structured,
+            RwStructured,
+            ByteAddress,
+            RwByteAddress,
+            AppendStructured,
+            ConsumeStructured,
+            RwStructuredWithCounter,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
+/// Bound resource of `D3D12_SHADER_INPUT_BIND_DESC`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub name: String,
+    pub input_type: InputType,
+    pub register: Register,
+    /// 0 for unbounded arrays
+    pub count: u32,
+}
+
+/// Member of a constant buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    /// Byte offset in the buffer
+    pub offset: u32,
+    pub size: u32,
+    /// Read by the program, unused variables still take space
+    pub used: bool,
+    /// e.g. `float4`, empty before shader model 5
+    pub type_name: String,
+    pub rows: u32,
+    pub columns: u32,
+    /// Array length, 0 for non-arrays
+    pub elements: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantBuffer {
+    pub name: String,
+    pub size: u32,
+    pub variables: Vec<Variable>,
+}
+
+/// Contents of the `RDEF` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceDefinitions {
+    pub model: ShaderModel,
+    /// Compiler version string
+    pub creator: String,
+    pub constant_buffers: Vec<ConstantBuffer>,
+    pub bindings: Vec<Binding>,
+}
+
+/// Opcode and length of a `SHEX` instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: u32,
+    /// Length in tokens including the opcode token
+    pub len: u32,
+}
+
+/// `D3D10_SB_OPCODE_CUSTOMDATA`, the length follows the opcode token
+const CUSTOM_DATA: u32 = 0x35;
+
+impl Instruction {
+    /// `dcl_*` and custom data, everything else executes
+    pub fn is_declaration(&self) -> bool {
+        matches!(self.opcode, CUSTOM_DATA | 0x58..=0x6a | 0x8f..=0xa2 | 0xce)
+    }
+}
+
+/// SM4/5 bytecode of a `SHEX` or `SHDR` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub model: ShaderModel,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Program {
+    /// Executable instructions, `ret` included
+    pub fn instruction_count(&self) -> usize {
+        self.instructions
+            .iter()
+            .filter(|instruction| !instruction.is_declaration())
+            .count()
+    }
+}
+
+/// SM6 program of a `DXIL` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dxil<'a> {
+    pub model: ShaderModel,
+    /// DXIL version, e.g. `(1, 0)`
+    pub version: (u32, u32),
+    /// LLVM bitcode of the module
+    pub bitcode: &'a [u8],
+}
+
+/// Counts of the `STAT` chunk, as in `D3D12_SHADER_DESC`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stats {
+    pub instruction_count: u32,
+    pub temp_register_count: u32,
+    pub def_count: u32,
+    pub dcl_count: u32,
+    pub float_instruction_count: u32,
+    pub int_instruction_count: u32,
+    pub uint_instruction_count: u32,
+    pub static_flow_control_count: u32,
+    pub dynamic_flow_control_count: u32,
+    pub array_instruction_count: u32,
+    /// Sample, load, compare, bias and gradient instructions
+    pub texture_instruction_count: u32,
+    pub mov_instruction_count: u32,
+    pub conversion_instruction_count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    pub fourcc: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Parsed view of a shader blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container<'a> {
+    pub chunks: Vec<Chunk<'a>>,
+}
+
+impl<'a> Container<'a> {
+    pub fn parse(blob: &'a [u8]) -> Result<Container<'a>, ContainerError> {
+        if blob.len() < 32 || &blob[..4] != b"DXBC" {
+            return Err(ContainerError::NotDxbc);
+        }
+        // Magic, 16 byte checksum, version, total size, chunk count
+        let size = le32(blob, 24)? as usize;
+        if size > blob.len() {
+            return Err(ContainerError::Truncated);
+        }
+        let blob = &blob[..size];
+        let count = le32(blob, 28)? as usize;
+        let chunks = (0..count)
+            .map(|i| {
+                let offset = le32(blob, 32 + 4 * i)? as usize;
+                let len = le32(blob, offset + 4)? as usize;
+                let data = blob
+                    .get(offset + 8..offset + 8 + len)
+                    .ok_or(ContainerError::Truncated)?;
+                Ok(Chunk {
+                    fourcc: fourcc(blob, offset)?,
+                    data,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Container { chunks })
+    }
+
+    /// Container with the chunks in order and its checksum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = 32 + 4 * self.chunks.len();
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for chunk in &self.chunks {
+            offsets.push((header + body.len()) as u32);
+            body.extend_from_slice(&chunk.fourcc);
+            body.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk.data);
+        }
+        let mut blob = b"DXBC".to_vec();
+        blob.extend_from_slice(&[0; 16]);
+        blob.extend_from_slice(&1u32.to_le_bytes());
+        blob.extend_from_slice(&((header + body.len()) as u32).to_le_bytes());
+        blob.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for offset in offsets {
+            blob.extend_from_slice(&offset.to_le_bytes());
+        }
+        blob.extend_from_slice(&body);
+        let checksum = checksum(&blob);
+        for (i, word) in checksum.iter().enumerate() {
+            blob[4 + 4 * i..8 + 4 * i].copy_from_slice(&word.to_le_bytes());
+        }
+        blob
+    }
+
+    pub fn chunk(&self, fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+        self.chunks
+            .iter()
+            .find(|chunk| &chunk.fourcc == fourcc)
+            .map(|chunk| chunk.data)
+    }
+
+    /// `ISGN`, or `ISG1` written by DXC
+    pub fn input_signature(&self) -> Result<Vec<SignatureElement>, ContainerError> {
+        self.signature(b"ISGN", b"ISG1")
+    }
+
+    /// `OSGN`, or `OSG1` written by DXC
+    pub fn output_signature(&self) -> Result<Vec<SignatureElement>, ContainerError> {
+        self.signature(b"OSGN", b"OSG1")
+    }
+
+    fn signature(
+        &self,
+        fourcc: &[u8; 4],
+        fourcc1: &[u8; 4],
+    ) -> Result<Vec<SignatureElement>, ContainerError> {
+        match (self.chunk(fourcc), self.chunk(fourcc1)) {
+            (Some(data), _) => signature(data, false).ok_or(ContainerError::Malformed(*fourcc)),
+            (None, Some(data)) => signature(data, true).ok_or(ContainerError::Malformed(*fourcc1)),
+            (None, None) => Err(ContainerError::MissingChunk(*fourcc)),
+        }
+    }
+
+    /// Target of the `SHEX`/`SHDR` or `DXIL` program
+    pub fn shader_model(&self) -> Result<ShaderModel, ContainerError> {
+        match self.dxil() {
+            Ok(dxil) => Ok(dxil.model),
+            Err(ContainerError::MissingChunk(_)) => self.program().map(|program| program.model),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Constant buffers and bindings of the `RDEF` chunk
+    pub fn resources(&self) -> Result<ResourceDefinitions, ContainerError> {
+        let data = self
+            .chunk(b"RDEF")
+            .ok_or(ContainerError::MissingChunk(*b"RDEF"))?;
+        resources(data).ok_or(ContainerError::Malformed(*b"RDEF"))
+    }
+
+    /// Instructions of the `SHEX` chunk, or `SHDR` of shader model 4
+    pub fn program(&self) -> Result<Program, ContainerError> {
+        let (fourcc, data) = match (self.chunk(b"SHEX"), self.chunk(b"SHDR")) {
+            (Some(data), _) => (*b"SHEX", data),
+            (None, Some(data)) => (*b"SHDR", data),
+            (None, None) => return Err(ContainerError::MissingChunk(*b"SHEX")),
+        };
+        program(data).ok_or(ContainerError::Malformed(fourcc))
+    }
+
+    pub fn dxil(&self) -> Result<Dxil<'a>, ContainerError> {
+        let data = self
+            .chunk(b"DXIL")
+            .ok_or(ContainerError::MissingChunk(*b"DXIL"))?;
+        dxil(data).ok_or(ContainerError::Malformed(*b"DXIL"))
+    }
+
+    /// `STAT` of `D3DCompile`, DXC writes reflection bitcode under the same
+    /// code which is reported as malformed
+    pub fn stats(&self) -> Result<Stats, ContainerError> {
+        let data = self
+            .chunk(b"STAT")
+            .ok_or(ContainerError::MissingChunk(*b"STAT"))?;
+        stats(data).ok_or(ContainerError::Malformed(*b"STAT"))
+    }
+}
+
+fn resources(data: &[u8]) -> Option<ResourceDefinitions> {
+    let u32_at = |pos| le32(data, pos).ok();
+    let cbuffer_count = u32_at(0)? as usize;
+    let cbuffer_offset = u32_at(4)? as usize;
+    let binding_count = u32_at(8)? as usize;
+    let binding_offset = u32_at(12)? as usize;
+    let target = u32_at(16)?;
+    let stage = match target >> 16 {
+        0xffff => Stage::Pixel,
+        0xfffe => Stage::Vertex,
+        0x4753 => Stage::Geometry,
+        0x4853 => Stage::Hull,
+        0x4453 => Stage::Domain,
+        0x4353 => Stage::Compute,
+        _ => return None,
+    };
+    let model = ShaderModel {
+        stage,
+        major: (target >> 8) & 0xff,
+        minor: target & 0xff,
+    };
+    let creator = cstr(data, u32_at(24)? as usize)?;
+
+    // Shader model 5 adds the `RD11` header with the record sizes
+    let rd11 = model.major >= 5;
+    if rd11 && data.get(28..32)? != b"RD11" {
+        return None;
+    }
+    let binding_size = if rd11 { u32_at(40)? as usize } else { 32 };
+    let variable_size = if rd11 { u32_at(44)? as usize } else { 24 };
+
+    let bindings = (0..binding_count)
+        .map(|i| {
+            let at = binding_offset + i * binding_size;
+            // Register spaces came with shader model 5.1
+            let space = if binding_size >= 40 {
+                u32_at(at + 32)?
+            } else {
+                0
+            };
+            let input_type = InputType::from_u32(u32_at(at + 4)?)?;
+            Some(Binding {
+                name: cstr(data, u32_at(at)? as usize)?,
+                input_type,
+                register: Register {
+                    class: input_type.register_class(),
+                    index: u32_at(at + 20)?,
+                    space,
+                },
+                count: u32_at(at + 24)?,
+            })
+        })
+        .collect::<Option<_>>()?;
+
+    let constant_buffers = (0..cbuffer_count)
+        .map(|i| {
+            let at = cbuffer_offset + i * 24;
+            let variable_count = u32_at(at + 4)? as usize;
+            let variable_offset = u32_at(at + 8)? as usize;
+            let variables = (0..variable_count)
+                .map(|v| {
+                    let at = variable_offset + v * variable_size;
+                    let ty = u32_at(at + 16)? as usize;
+                    let u16_at = |pos| {
+                        data.get(pos..pos + 2)
+                            .map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+                    };
+                    let type_name = if rd11 {
+                        cstr(data, u32_at(ty + 32)? as usize)?
+                    } else {
+                        String::new()
+                    };
+                    Some(Variable {
+                        name: cstr(data, u32_at(at)? as usize)?,
+                        offset: u32_at(at + 4)?,
+                        size: u32_at(at + 8)?,
+                        // D3D_SVF_USED
+                        used: u32_at(at + 12)? & 2 != 0,
+                        type_name,
+                        rows: u16_at(ty + 4)?,
+                        columns: u16_at(ty + 6)?,
+                        elements: u16_at(ty + 8)?,
+                    })
+                })
+                .collect::<Option<_>>()?;
+            Some(ConstantBuffer {
+                name: cstr(data, u32_at(at)? as usize)?,
+                size: u32_at(at + 12)?,
+                variables,
+            })
+        })
+        .collect::<Option<_>>()?;
+
+    Some(ResourceDefinitions {
+        model,
+        creator,
+        constant_buffers,
+        bindings,
+    })
+}
+
+fn program(data: &[u8]) -> Option<Program> {
+    let model = ShaderModel::from_version(le32(data, 0).ok()?)?;
+    let len = le32(data, 4).ok()? as usize;
+    if len * 4 > data.len() {
+        return None;
+    }
+    let mut instructions = Vec::new();
+    let mut pos = 2;
+    while pos < len {
+        let token = le32(data, pos * 4).ok()?;
+        let opcode = token & 0x7ff;
+        let len = if opcode == CUSTOM_DATA {
+            le32(data, pos * 4 + 4).ok()?
+        } else {
+            (token >> 24) & 0x7f
+        };
+        if len == 0 {
+            return None;
+        }
+        instructions.push(Instruction { opcode, len });
+        pos += len as usize;
+    }
+    if pos != len {
+        return None;
+    }
+    Some(Program {
+        model,
+        instructions,
+    })
+}
+
+fn dxil(data: &[u8]) -> Option<Dxil<'_>> {
+    let model = ShaderModel::from_version(le32(data, 0).ok()?)?;
+    // Program size in tokens, then the `DXIL` header
+    if data.get(8..12)? != b"DXIL" {
+        return None;
+    }
+    let version = le32(data, 12).ok()?;
+    let offset = le32(data, 16).ok()? as usize;
+    let size = le32(data, 20).ok()? as usize;
+    let bitcode = data.get(8 + offset..8 + offset + size)?;
+    Some(Dxil {
+        model,
+        version: (version >> 8, version & 0xff),
+        bitcode,
+    })
+}
+
+fn stats(data: &[u8]) -> Option<Stats> {
+    // 29 tokens for shader model 4, 37 for 5
+    if data.len() != 29 * 4 && data.len() != 37 * 4 {
+        return None;
+    }
+    let at = |i: usize| le32(data, i * 4).unwrap_or(0);
+    Some(Stats {
+        instruction_count: at(0),
+        temp_register_count: at(1),
+        def_count: at(2),
+        dcl_count: at(3),
+        float_instruction_count: at(4),
+        int_instruction_count: at(5),
+        uint_instruction_count: at(6),
+        static_flow_control_count: at(7),
+        dynamic_flow_control_count: at(8),
+        array_instruction_count: at(11),
+        texture_instruction_count: (14..19).map(at).sum(),
+        mov_instruction_count: at(19),
+        conversion_instruction_count: at(21),
+    })
+}
+
+fn cstr(data: &[u8], pos: usize) -> Option<String> {
+    let bytes = data.get(pos..)?;
+    let bytes = &bytes[..bytes.iter().position(|&b| b == 0)?];
+    std::str::from_utf8(bytes).ok().map(String::from)
+}
+
+/// Elements of a signature chunk, `extended` ones lead with a stream index
+/// and end with the minimum precision
+fn signature(data: &[u8], extended: bool) -> Option<Vec<SignatureElement>> {
+    let count = le32(data, 0).ok()? as usize;
+    let start = le32(data, 4).ok()? as usize;
+    let (stride, skip) = if extended { (32, 4) } else { (24, 0) };
+    (0..count)
+        .map(|i| {
+            let at = start + i * stride + skip;
+            let name = cstr(data, le32(data, at).ok()? as usize)?;
+            let component_type = match le32(data, at + 12).ok()? {
+                1 => ComponentType::Uint32,
+                2 => ComponentType::Sint32,
+                3 => ComponentType::Float32,
+                _ => ComponentType::Unknown,
+            };
+            Some(SignatureElement {
+                semantic_name: name.to_ascii_uppercase(),
+                semantic_index: le32(data, at + 4).ok()?,
+                register: le32(data, at + 16).ok()?,
+                component_type,
+                mask: *data.get(at + 20)?,
+            })
+        })
+        .collect()
+}
+
+fn fourcc(data: &[u8], pos: usize) -> Result<[u8; 4], ContainerError> {
+    data.get(pos..pos + 4)
+        .map(|b| [b[0], b[1], b[2], b[3]])
+        .ok_or(ContainerError::Truncated)
+}
+
+fn le32(data: &[u8], pos: usize) -> Result<u32, ContainerError> {
+    fourcc(data, pos).map(u32::from_le_bytes)
+}
+
+/// Checksum of a container, MD5 of everything after it with the message
+/// length moved to the front of the last block
+pub fn checksum(blob: &[u8]) -> [u32; 4] {
+    let data = blob.get(20..).unwrap_or(&[]);
+    let bits = (data.len() as u32).wrapping_mul(8);
+    let mut state = MD5_INIT;
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+        md5_block(&mut state, block);
+    }
+    let rest = blocks.remainder();
+    let mut last = [0u8; 64];
+    if rest.len() >= 56 {
+        let mut padded = [0u8; 64];
+        padded[..rest.len()].copy_from_slice(rest);
+        padded[rest.len()] = 0x80;
+        md5_block(&mut state, &padded);
+    } else {
+        last[4..4 + rest.len()].copy_from_slice(rest);
+        last[4 + rest.len()] = 0x80;
+    }
+    last[..4].copy_from_slice(&bits.to_le_bytes());
+    last[60..].copy_from_slice(&((bits >> 2) | 1).to_le_bytes());
+    md5_block(&mut state, &last);
+    state
+}
+
+const MD5_INIT: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+/// One 64 byte block of RFC 1321
+fn md5_block(state: &mut [u32; 4], block: &[u8]) {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let words: Vec<u32> = block
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let [mut a, mut b, mut c, mut d] = *state;
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let k = ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32;
+        let rotated = a
+            .wrapping_add(f)
+            .wrapping_add(k)
+            .wrapping_add(words[g])
+            .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    for (word, value) in state.iter_mut().zip(&[a, b, c, d]) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+/// Writers of test blobs, the layouts `D3DCompile` produces
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::{Chunk, Container};
+    use crate::shader::signature::SignatureElement;
+
+    pub fn container(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let chunks = chunks
+            .iter()
+            .map(|(fourcc, data)| Chunk {
+                fourcc: **fourcc,
+                data,
+            })
+            .collect();
+        Container { chunks }.to_bytes()
+    }
+
+    /// `ISGN`/`OSGN` chunk, names as `D3DCompile` writes them
+    pub fn signature(elements: &[(&str, &SignatureElement)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+        data.extend_from_slice(&8u32.to_le_bytes());
+        let mut names = Vec::new();
+        let names_start = 8 + 24 * elements.len();
+        for (name, element) in elements {
+            let name_offset = (names_start + names.len()) as u32;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            let system_value = if element.is_system_value() { 1 } else { 0 };
+            for value in [
+                name_offset,
+                element.semantic_index,
+                system_value,
+                element.component_type as u32,
+                element.register,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&[element.mask, element.mask, 0, 0]);
+        }
+        data.extend_from_slice(&names);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::hlsl::Reflection;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+    const SIMPLE_VS: &[u8] = include_bytes!("../../tests/shaders/simple_vs.dxbc");
+    const SIMPLE_PS: &[u8] = include_bytes!("../../tests/shaders/simple_ps.dxbc");
+    const SIMPLE_VS_DXIL: &[u8] = include_bytes!("../../tests/shaders/simple_vs.dxil");
+    const TEXTURED: &str = include_str!("../../tests/shaders/textured.hlsl");
+    const TEXTURED_PS: &[u8] = include_bytes!("../../tests/shaders/textured_ps.dxbc");
+
+    fn float4(name: &str, register: u32) -> SignatureElement {
+        SignatureElement {
+            semantic_name: name.into(),
+            semantic_index: 0,
+            register,
+            component_type: ComponentType::Float32,
+            mask: 0b1111,
+        }
+    }
+
+    #[test]
+    fn reads_signatures() {
+        let position = float4("POSITION", 0);
+        let color = float4("COLOR", 1);
+        let sv_position = float4("SV_POSITION", 0);
+        let blob = fixture::container(&[
+            (b"RDEF", vec![0; 28]),
+            (
+                b"ISGN",
+                fixture::signature(&[("POSITION", &position), ("COLOR", &color)]),
+            ),
+            (
+                b"OSGN",
+                fixture::signature(&[("SV_Position", &sv_position), ("Color", &color)]),
+            ),
+        ]);
+        let container = Container::parse(&blob).unwrap();
+        assert_eq!(container.chunks.len(), 3);
+        assert_eq!(container.chunk(b"RDEF"), Some(&[0; 28][..]));
+        assert_eq!(
+            container.input_signature().unwrap(),
+            [position, color.clone()]
+        );
+        // Names are compared in upper case
+        assert_eq!(container.output_signature().unwrap(), [sv_position, color]);
+    }
+
+    #[test]
+    fn reads_hello_triangle_fixtures() {
+        let reflection = Reflection::parse(SIMPLE).unwrap();
+        for (blob, entry_point, profile) in [
+            (SIMPLE_VS, "VSMain", "vs_5_0"),
+            (SIMPLE_PS, "PSMain", "ps_5_0"),
+        ] {
+            let container = Container::parse(blob).unwrap();
+            assert_eq!(container.shader_model().unwrap().to_string(), profile);
+            // Source reflection agrees with the compiler
+            assert_eq!(
+                container.input_signature().unwrap(),
+                reflection.input_signature(entry_point).unwrap()
+            );
+            assert_eq!(
+                container.output_signature().unwrap(),
+                reflection.output_signature(entry_point).unwrap()
+            );
+
+            let resources = container.resources().unwrap();
+            assert_eq!(resources.model, container.shader_model().unwrap());
+            assert!(resources.constant_buffers.is_empty() && resources.bindings.is_empty());
+
+            let program = container.program().unwrap();
+            let stats = container.stats().unwrap();
+            assert_eq!(
+                program.instruction_count(),
+                stats.instruction_count as usize
+            );
+        }
+
+        let program = Container::parse(SIMPLE_VS).unwrap().program().unwrap();
+        let opcodes: Vec<_> = program.instructions.iter().map(|i| i.opcode).collect();
+        // dcl_globalFlags, dcl_input x2, dcl_output_siv, dcl_output, mov x2, ret
+        assert_eq!(opcodes, [0x6a, 0x5f, 0x5f, 0x67, 0x65, 0x36, 0x36, 0x3e]);
+    }
+
+    #[test]
+    fn reads_constant_buffers_and_bindings() {
+        let container = Container::parse(TEXTURED_PS).unwrap();
+        let resources = container.resources().unwrap();
+        let material = &resources.constant_buffers[0];
+        assert_eq!((material.name.as_str(), material.size), ("Material", 32));
+        assert_eq!(
+            material.variables[0],
+            Variable {
+                name: "tint".into(),
+                offset: 0,
+                size: 16,
+                used: true,
+                type_name: "float4".into(),
+                rows: 1,
+                columns: 4,
+                elements: 0,
+            }
+        );
+        assert_eq!(material.variables[1].name, "exposure");
+        assert_eq!(material.variables[1].offset, 16);
+
+        let bindings: Vec<_> = resources
+            .bindings
+            .iter()
+            .map(|b| (b.name.as_str(), b.input_type, b.register.to_string()))
+            .collect();
+        assert_eq!(
+            bindings,
+            [
+                ("linearSampler", InputType::Sampler, "s0, space0".into()),
+                ("albedo", InputType::Texture, "t0, space0".into()),
+                ("Material", InputType::ConstantBuffer, "b0, space0".into()),
+            ]
+        );
+        // Same registers as declared in the source
+        let reflection = Reflection::parse(TEXTURED).unwrap();
+        for binding in reflection.bindings() {
+            let compiled = resources.bindings.iter().find(|b| b.name == binding.name);
+            assert_eq!(compiled.map(|b| b.register), binding.register);
+        }
+
+        let stats = container.stats().unwrap();
+        assert_eq!(stats.instruction_count, 4);
+        assert_eq!(stats.temp_register_count, 1);
+        assert_eq!(stats.texture_instruction_count, 1);
+        assert_eq!(container.program().unwrap().instruction_count(), 4);
+    }
+
+    #[test]
+    fn reads_dxil_programs() {
+        let container = Container::parse(SIMPLE_VS_DXIL).unwrap();
+        let dxil = container.dxil().unwrap();
+        assert_eq!(dxil.model.to_string(), "vs_6_0");
+        assert_eq!(dxil.version, (1, 0));
+        assert_eq!(&dxil.bitcode[..4], b"BC\xc0\xde");
+        assert_eq!(container.shader_model(), Ok(dxil.model));
+
+        let reflection = Reflection::parse(SIMPLE).unwrap();
+        assert_eq!(
+            container.input_signature().unwrap(),
+            reflection.input_signature("VSMain").unwrap()
+        );
+        assert_eq!(
+            container.output_signature().unwrap(),
+            reflection.output_signature("VSMain").unwrap()
+        );
+        assert_eq!(
+            container.program(),
+            Err(ContainerError::MissingChunk(*b"SHEX"))
+        );
+        assert_eq!(
+            container.stats(),
+            Err(ContainerError::MissingChunk(*b"STAT"))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_blobs() {
+        assert_eq!(Container::parse(b"DXIL"), Err(ContainerError::NotDxbc));
+
+        let blob = fixture::container(&[(b"ISGN", vec![1, 0, 0, 0, 8, 0, 0, 0])]);
+        assert_eq!(
+            Container::parse(&blob[..blob.len() - 1]),
+            Err(ContainerError::Truncated)
+        );
+        let container = Container::parse(&blob).unwrap();
+        assert_eq!(
+            container.input_signature(),
+            Err(ContainerError::Malformed(*b"ISGN"))
+        );
+        assert_eq!(
+            container.output_signature(),
+            Err(ContainerError::MissingChunk(*b"OSGN"))
+        );
+
+        // Program length past the end of the chunk
+        let shex = [0x10050u32, 4, 0x0100003e];
+        let shex: Vec<u8> = shex.iter().flat_map(|t| t.to_le_bytes()).collect();
+        let blob = fixture::container(&[(b"SHEX", shex)]);
+        assert_eq!(
+            Container::parse(&blob).unwrap().program(),
+            Err(ContainerError::Malformed(*b"SHEX"))
+        );
+    }
+
+    #[test]
+    #[ignore = "the fixtures are hand assembled with zero checksums, see tests/shaders/README.md"]
+    fn checksums_match_fxc() {
+        for blob in [SIMPLE_VS, SIMPLE_PS, TEXTURED_PS] {
+            let stored = [4, 8, 12, 16].map(|offset| le32(blob, offset).unwrap());
+            assert_eq!(checksum(blob), stored);
+        }
+    }
+
+    #[test]
+    fn writes_checksums() {
+        // RFC 1321 test suite, "abc" padded by hand
+        let mut block = [0u8; 64];
+        block[..4].copy_from_slice(b"abc\x80");
+        block[56] = 24;
+        let mut state = MD5_INIT;
+        md5_block(&mut state, &block);
+        let digest: Vec<u8> = state.iter().flat_map(|w| w.to_le_bytes()).collect();
+        assert_eq!(
+            digest,
+            [
+                0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0, 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1,
+                0x7f, 0x72
+            ]
+        );
+
+        // Both ways of padding the last block
+        for len in &[0, 8, 36, 44] {
+            let blob = fixture::container(&[(b"RTS0", vec![7; *len])]);
+            let stored = le32(&blob, 4).unwrap();
+            assert_eq!(stored, checksum(&blob)[0]);
+            let mut changed = blob.clone();
+            *changed.last_mut().unwrap() ^= 1;
+            assert_ne!(checksum(&changed), checksum(&blob));
+            assert_eq!(
+                Container::parse(&blob)
+                    .unwrap()
+                    .chunk(b"RTS0")
+                    .unwrap()
+                    .len(),
+                *len
+            );
+        }
+    }
+}
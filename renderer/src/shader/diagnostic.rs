@@ -0,0 +1,346 @@
+//! Compiler output parsed into diagnostics
+//!
+//! Reads both the FXC form, `file(line,column): error X3000: message`, and
+//! the DXC form, `file:line:column: error: message`. Positions the compiler
+//! reports in the preprocessed source are mapped back to the included file
+//! they came from and the line is kept for a snippet.
+use super::preprocess::Preprocessed;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Empty when the compiler gave no position
+    pub file: String,
+    /// 1-based, 0 when unknown
+    pub line: u32,
+    /// 1-based, 0 when unknown
+    pub column: u32,
+    pub severity: Severity,
+    /// e.g. `X3000`, FXC only
+    pub code: Option<String>,
+    pub message: String,
+    /// The reported line as the compiler saw it
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    /// Diagnostic without a position
+    pub fn new(severity: Severity, message: &str) -> Self {
+        Diagnostic {
+            file: String::new(),
+            line: 0,
+            column: 0,
+            severity,
+            code: None,
+            message: message.to_string(),
+            snippet: None,
+        }
+    }
+
+    /// Maps the position to the file it came from and fills in the snippet
+    ///
+    /// Compilers honour the `#line` directives of the preprocessed source,
+    /// positions in any other file name are lines of the source itself.
+    pub fn locate(&mut self, preprocessed: &Preprocessed) {
+        if self.line == 0 {
+            return;
+        }
+        let known = preprocessed
+            .files()
+            .iter()
+            .find(|file| same_file(&self.file, file));
+        match known {
+            Some(file) => self.file = file.clone(),
+            None => match preprocessed.origin(self.line) {
+                Some((file, line)) => {
+                    self.file = file.to_string();
+                    self.line = line;
+                }
+                None => return,
+            },
+        }
+        self.snippet = preprocessed.line(&self.file, self.line).map(str::to_string);
+    }
+}
+
+/// Same file when one path ends with the other, compilers may print the
+/// `#line` names absolute
+fn same_file(reported: &str, file: &str) -> bool {
+    let reported = reported.replace('\\', "/");
+    let file = file.replace('\\', "/");
+    reported == file
+        || reported
+            .strip_suffix(file.as_str())
+            .is_some_and(|dir| dir.ends_with('/'))
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+            if self.line > 0 {
+                write!(f, "{}:", self.line)?;
+            }
+            if self.column > 0 {
+                write!(f, "{}:", self.column)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.severity.name())?;
+        if let Some(code) = &self.code {
+            write!(f, " {}", code)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(snippet) = &self.snippet {
+            let number = self.line.to_string();
+            let gutter = " ".repeat(number.len());
+            write!(f, "\n{} |\n{} | {}", gutter, number, snippet)?;
+            if self.column > 0 {
+                // Tabs kept so the caret lines up
+                let indent: String = snippet
+                    .chars()
+                    .take(self.column as usize - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                write!(f, "\n{} | {}^", gutter, indent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Diagnostics of compiler output, lines that aren't one are skipped
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    output.lines().filter_map(parse_line).collect()
+}
+
+/// Diagnostics of failed compiler output, never empty so the failure always
+/// has a reason
+pub fn parse_failure(output: &str) -> Vec<Diagnostic> {
+    let diagnostics = parse(output);
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+    let message = output.trim();
+    let message = if message.is_empty() {
+        "compiler gave no output"
+    } else {
+        message
+    };
+    vec![Diagnostic::new(Severity::Error, message)]
+}
+
+fn parse_line(line: &str) -> Option<Diagnostic> {
+    let line = line.trim_end();
+    let (location, severity, rest) = [Severity::Error, Severity::Warning, Severity::Note]
+        .iter()
+        .filter_map(|&severity| {
+            let (location, rest) = split_severity(line, severity.name())?;
+            Some((location, severity, rest))
+        })
+        .min_by_key(|(location, _, _)| location.len())?;
+
+    // `X3000: message` or `: message`
+    let (code, message) = match rest.strip_prefix(':') {
+        Some(message) => (None, message),
+        None => {
+            let (code, message) = rest.trim_start().split_once(':')?;
+            if !is_code(code) {
+                return None;
+            }
+            (Some(code.to_string()), message)
+        }
+    };
+    let (file, line, column) = parse_location(location)?;
+    Some(Diagnostic {
+        file,
+        line,
+        column,
+        severity,
+        code,
+        message: message.trim().to_string(),
+        snippet: None,
+    })
+}
+
+/// Location before `: error`, or nothing when the line starts with it
+fn split_severity<'a>(line: &'a str, severity: &str) -> Option<(&'a str, &'a str)> {
+    if let Some(rest) = line.strip_prefix(severity) {
+        if rest.starts_with(':') || rest.starts_with(" X") {
+            return Some(("", rest));
+        }
+    }
+    let pattern = format!(": {}", severity);
+    let mut start = 0;
+    while let Some(found) = line[start..].find(&pattern) {
+        let at = start + found;
+        let rest = &line[at + pattern.len()..];
+        if rest.starts_with(':') || rest.starts_with(" X") {
+            return Some((&line[..at], rest));
+        }
+        start = at + pattern.len();
+    }
+    None
+}
+
+fn is_code(code: &str) -> bool {
+    let mut chars = code.chars();
+    chars.next().is_some_and(|c| c.is_ascii_uppercase())
+        && chars.as_str().len() > 1
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+/// `file(line,column)`, `file(line,column-end)`, `file:line:column` or
+/// `file:line`
+fn parse_location(location: &str) -> Option<(String, u32, u32)> {
+    if location.is_empty() {
+        return Some((String::new(), 0, 0));
+    }
+    if let Some(position) = location.strip_suffix(')') {
+        let (file, position) = position.rsplit_once('(')?;
+        let (line, column) = position.split_once(',').unwrap_or((position, ""));
+        let column = column.split('-').next().unwrap_or("");
+        return Some((
+            file.to_string(),
+            line.parse().ok()?,
+            column.parse().unwrap_or(0),
+        ));
+    }
+    // Drive letters have a colon too, only numbers count as positions
+    let number = |text: &str| text.parse::<u32>().ok();
+    match location.rsplit_once(':') {
+        Some((rest, last)) if number(last).is_some() => match rest.rsplit_once(':') {
+            Some((file, line)) if number(line).is_some() => {
+                Some((file.to_string(), number(line)?, number(last)?))
+            }
+            _ => Some((rest.to_string(), number(last)?, 0)),
+        },
+        _ => Some((location.to_string(), 0, 0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::preprocess::{Embedded, Preprocessor};
+
+    #[test]
+    fn parses_fxc_and_dxc_output() {
+        let output = "\
+C:\\shaders\\simple.hlsl(30,5-12): error X3004: undeclared identifier 'fo'
+simple.hlsl(12,9): warning X3206: implicit truncation of vector type
+error X3501: 'main': entrypoint not found
+
+/tmp/dxc/5d0c.hlsl:7:14: error: use of undeclared identifier 'fo'
+    return fo;
+           ^
+C:\\shaders\\simple.hlsl:3: note: declared here
+Compilation failed
+";
+        let diagnostics = parse(output);
+        assert_eq!(diagnostics.len(), 5);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic {
+                file: "C:\\shaders\\simple.hlsl".into(),
+                line: 30,
+                column: 5,
+                severity: Severity::Error,
+                code: Some("X3004".into()),
+                message: "undeclared identifier 'fo'".into(),
+                snippet: None,
+            }
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "simple.hlsl:12:9: warning X3206: implicit truncation of vector type"
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "error X3501: 'main': entrypoint not found"
+        );
+        assert_eq!(
+            (
+                diagnostics[3].line,
+                diagnostics[3].column,
+                &diagnostics[3].code
+            ),
+            (7, 14, &None)
+        );
+        assert_eq!(
+            diagnostics[4].to_string(),
+            "C:\\shaders\\simple.hlsl:3: note: declared here"
+        );
+
+        assert_eq!(
+            parse_failure("Compilation failed\n"),
+            [Diagnostic::new(Severity::Error, "Compilation failed")]
+        );
+        assert_eq!(parse_failure("")[0].message, "compiler gave no output");
+    }
+
+    #[test]
+    fn maps_through_includes() {
+        let files = [(
+            "common.hlsli",
+            "#define TINT float3(1, 0.5, 0)\nfloat3 tint(float3 c)\n{\n\treturn c * TINT\n}\n",
+        )];
+        let resolver = Embedded { files: &files };
+        let preprocessed = Preprocessor::new(&resolver)
+            .run(
+                "main.hlsl",
+                "#include \"common.hlsli\"\nfloat4 main() : SV_Target { return 0; }\n",
+            )
+            .unwrap();
+
+        // `#line` names as FXC prints them
+        let output = "D:\\src\\common.hlsli(5,1): error X3000: syntax error: unexpected token '}'";
+        let mut diagnostic = parse(output).remove(0);
+        diagnostic.locate(&preprocessed);
+        assert_eq!(diagnostic.file, "common.hlsli");
+        assert_eq!(
+            diagnostic.to_string(),
+            "common.hlsli:5:1: error X3000: syntax error: unexpected token '}'\n  |\n5 | }\n  | ^"
+        );
+
+        // Lines of the preprocessed file itself
+        let line = preprocessed
+            .source
+            .lines()
+            .position(|l| l.contains("return c"))
+            .unwrap() as u32
+            + 1;
+        let output = format!("/tmp/dxc/5d0c.hlsl:{}:18: error: expected ';'", line);
+        let mut diagnostic = parse(&output).remove(0);
+        diagnostic.locate(&preprocessed);
+        assert_eq!(
+            (diagnostic.file.as_str(), diagnostic.line),
+            ("common.hlsli", 4)
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "common.hlsli:4:18: error: expected ';'\n  |\n4 | \treturn c * float3(1, 0.5, 0)\n  | \t                ^"
+        );
+
+        let mut unplaced = Diagnostic::new(Severity::Error, "out of memory");
+        unplaced.locate(&preprocessed);
+        assert_eq!(unplaced.to_string(), "error: out of memory");
+    }
+}
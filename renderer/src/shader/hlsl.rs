@@ -0,0 +1,1216 @@
+//! Reflection of HLSL source without the D3D compiler
+//!
+//! Reads the declarations tooling cares about: structs, functions with their
+//! parameter semantics, cbuffers and resource bindings. Function bodies and
+//! initializers are skipped and preprocessor lines are ignored, so sources
+//! relying on macros should go through [`preprocess`](super::preprocess)
+//! first.
+use super::signature::{ComponentType, SignatureElement};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HlslError {
+    /// Source that isn't a declaration this parser understands
+    Syntax {
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    NoFunction(String),
+    /// Parameter or struct field without a semantic in a signature
+    MissingSemantic {
+        name: String,
+        line: u32,
+    },
+    /// Type that can't be passed between stages
+    UnsupportedType {
+        ty: String,
+        line: u32,
+    },
+}
+
+impl fmt::Display for HlslError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HlslError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+            HlslError::NoFunction(name) => write!(f, "no function named `{}`", name),
+            HlslError::MissingSemantic { name, line } => {
+                write!(f, "{}: `{}` has no semantic", line, name)
+            }
+            HlslError::UnsupportedType { ty, line } => {
+                write!(f, "{}: `{}` can't be used in a signature", line, ty)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HlslError {}
+
+pub type Result<T> = std::result::Result<T, HlslError>;
+
+/// Semantic such as `TEXCOORD1`, kept in upper case as semantics are
+/// case-insensitive
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Semantic {
+    pub name: String,
+    pub index: u32,
+}
+
+impl Semantic {
+    /// Trailing digits are the index, `SV_Target1` is `SV_TARGET` index 1
+    pub fn parse(semantic: &str) -> Semantic {
+        let name = semantic.trim_end_matches(|c: char| c.is_ascii_digit());
+        Semantic {
+            name: name.to_ascii_uppercase(),
+            index: semantic[name.len()..].parse().unwrap_or(0),
+        }
+    }
+
+    pub fn is_system_value(&self) -> bool {
+        self.name.starts_with("SV_")
+    }
+}
+
+impl fmt::Display for Semantic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.index == 0 {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}{}", self.name, self.index)
+        }
+    }
+}
+
+/// Register prefix of a binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterClass {
+    /// `b`
+    ConstantBuffer,
+    /// `t`
+    ShaderResource,
+    /// `u`
+    UnorderedAccess,
+    /// `s`
+    Sampler,
+}
+
+impl RegisterClass {
+    pub fn prefix(self) -> char {
+        match self {
+            RegisterClass::ConstantBuffer => 'b',
+            RegisterClass::ShaderResource => 't',
+            RegisterClass::UnorderedAccess => 'u',
+            RegisterClass::Sampler => 's',
+        }
+    }
+
+    fn from_prefix(prefix: char) -> Option<RegisterClass> {
+        match prefix.to_ascii_lowercase() {
+            'b' => Some(RegisterClass::ConstantBuffer),
+            't' => Some(RegisterClass::ShaderResource),
+            'u' => Some(RegisterClass::UnorderedAccess),
+            's' => Some(RegisterClass::Sampler),
+            _ => None,
+        }
+    }
+
+    /// Class a resource type binds to, `None` for plain data
+    pub fn of_type(ty: &str) -> Option<RegisterClass> {
+        let base = ty.split('<').next().unwrap_or(ty);
+        if base == "SamplerState" || base == "SamplerComparisonState" {
+            Some(RegisterClass::Sampler)
+        } else if base == "ConstantBuffer" {
+            Some(RegisterClass::ConstantBuffer)
+        } else if base.starts_with("RW")
+            || base.starts_with("RasterizerOrdered")
+            || base.starts_with("Append")
+            || base.starts_with("Consume")
+        {
+            Some(RegisterClass::UnorderedAccess)
+        } else if base.starts_with("Texture")
+            || base.ends_with("Buffer")
+            || base == "RaytracingAccelerationStructure"
+        {
+            Some(RegisterClass::ShaderResource)
+        } else {
+            None
+        }
+    }
+}
+
+/// `register(t3, space1)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Register {
+    pub class: RegisterClass,
+    pub index: u32,
+    pub space: u32,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}, space{}",
+            self.class.prefix(),
+            self.index,
+            self.space
+        )
+    }
+}
+
+/// Scalar, vector or matrix type such as `float4` or `uint3x2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NumericType {
+    pub component_type: ComponentType,
+    pub rows: u32,
+    pub columns: u32,
+}
+
+impl NumericType {
+    /// Vectors have a single row, `None` for structs and resources
+    pub fn parse(ty: &str) -> Option<NumericType> {
+        // `vector<float, 4>` and `matrix<float, 4, 4>`
+        if let Some(arguments) = ty
+            .strip_prefix("vector<")
+            .or_else(|| ty.strip_prefix("matrix<"))
+        {
+            let mut arguments = arguments.trim_end_matches('>').split(',');
+            let component_type = scalar(arguments.next()?.trim())?;
+            let dims = arguments
+                .map(|n| n.trim().parse().ok())
+                .collect::<Option<Vec<u32>>>()?;
+            let (rows, columns) = match dims[..] {
+                [columns] if ty.starts_with("vector") => (1, columns),
+                [rows, columns] if ty.starts_with("matrix") => (rows, columns),
+                _ => return None,
+            };
+            return Some(NumericType {
+                component_type,
+                rows,
+                columns,
+            });
+        }
+
+        let base = ty.trim_end_matches(|c: char| c.is_ascii_digit() || c == 'x');
+        let component_type = scalar(base)?;
+        let dims = &ty[base.len()..];
+        let (rows, columns) = match dims.split_once('x') {
+            Some((rows, columns)) => (rows.parse().ok()?, columns.parse().ok()?),
+            None if dims.is_empty() => (1, 1),
+            None => (1, dims.parse().ok()?),
+        };
+        if !(1..=4).contains(&rows) || !(1..=4).contains(&columns) {
+            return None;
+        }
+        Some(NumericType {
+            component_type,
+            rows,
+            columns,
+        })
+    }
+}
+
+fn scalar(name: &str) -> Option<ComponentType> {
+    match name {
+        "float" | "half" | "double" | "min16float" | "min10float" | "float16_t" | "float32_t" => {
+            Some(ComponentType::Float32)
+        }
+        "int" | "min16int" | "min12int" | "int16_t" | "int32_t" => Some(ComponentType::Sint32),
+        "uint" | "dword" | "bool" | "min16uint" | "uint16_t" | "uint32_t" => {
+            Some(ComponentType::Uint32)
+        }
+        _ => None,
+    }
+}
+
+/// Variable, struct field or parameter declaration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    /// Type as written, template arguments included, e.g. `Texture2D<float4>`
+    pub ty: String,
+    pub name: String,
+    /// Element count of arrays, `Some(0)` for unbounded `[]`
+    pub array_size: Option<u32>,
+    pub semantic: Option<Semantic>,
+    pub register: Option<Register>,
+    /// Storage and interpolation modifiers, e.g. `static` or `nointerpolation`
+    pub modifiers: Vec<String>,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<Variable>,
+    pub line: u32,
+}
+
+/// `cbuffer` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstantBuffer {
+    pub name: String,
+    pub register: Option<Register>,
+    pub members: Vec<Variable>,
+    pub line: u32,
+}
+
+/// Global texture, buffer, sampler or `ConstantBuffer<T>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub name: String,
+    pub ty: String,
+    pub class: RegisterClass,
+    /// `None` leaves the slot to the compiler
+    pub register: Option<Register>,
+    /// Bound descriptors, 0 for unbounded arrays
+    pub count: u32,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    In,
+    Out,
+    InOut,
+    Uniform,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub direction: Direction,
+    pub variable: Variable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub return_type: String,
+    pub return_semantic: Option<Semantic>,
+    pub parameters: Vec<Parameter>,
+    /// Attributes as written without brackets, e.g. `numthreads(8,8,1)`
+    pub attributes: Vec<String>,
+    pub line: u32,
+}
+
+/// Declarations of a HLSL source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reflection {
+    pub structs: Vec<Struct>,
+    pub functions: Vec<Function>,
+    pub cbuffers: Vec<ConstantBuffer>,
+    pub resources: Vec<Resource>,
+    /// Non-static globals, the compiler packs these into `$Globals`
+    pub globals: Vec<Variable>,
+}
+
+impl Reflection {
+    pub fn parse(source: &str) -> Result<Reflection> {
+        let tokens = lex(source)?;
+        Parser {
+            tokens: &tokens,
+            pos: 0,
+            reflection: Reflection::default(),
+        }
+        .parse()
+    }
+
+    pub fn structure(&self, name: &str) -> Option<&Struct> {
+        self.structs.iter().find(|s| s.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// cbuffers and resources with the register class they bind to
+    pub fn bindings(&self) -> Vec<Resource> {
+        let cbuffers = self.cbuffers.iter().map(|cbuffer| Resource {
+            name: cbuffer.name.clone(),
+            ty: "cbuffer".into(),
+            class: RegisterClass::ConstantBuffer,
+            register: cbuffer.register,
+            count: 1,
+            line: cbuffer.line,
+        });
+        cbuffers.chain(self.resources.iter().cloned()).collect()
+    }
+
+    /// Inputs of an entry point, struct parameters expanded to their fields
+    ///
+    /// Registers are assigned in declaration order, one per vector and one
+    /// per matrix row, like the compiler does for vertex inputs.
+    pub fn input_signature(&self, entry_point: &str) -> Result<Vec<SignatureElement>> {
+        let function = self
+            .function(entry_point)
+            .ok_or_else(|| HlslError::NoFunction(entry_point.into()))?;
+        let mut elements = Vec::new();
+        for parameter in &function.parameters {
+            if let Direction::In | Direction::InOut = parameter.direction {
+                self.flatten(&parameter.variable, &mut elements)?;
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Return value and `out` parameters of an entry point
+    pub fn output_signature(&self, entry_point: &str) -> Result<Vec<SignatureElement>> {
+        let function = self
+            .function(entry_point)
+            .ok_or_else(|| HlslError::NoFunction(entry_point.into()))?;
+        let mut elements = Vec::new();
+        if function.return_type != "void" {
+            let result = Variable {
+                ty: function.return_type.clone(),
+                name: function.name.clone(),
+                array_size: None,
+                semantic: function.return_semantic.clone(),
+                register: None,
+                modifiers: Vec::new(),
+                line: function.line,
+            };
+            self.flatten(&result, &mut elements)?;
+        }
+        for parameter in &function.parameters {
+            if let Direction::Out | Direction::InOut = parameter.direction {
+                self.flatten(&parameter.variable, &mut elements)?;
+            }
+        }
+        Ok(elements)
+    }
+
+    fn flatten(&self, variable: &Variable, elements: &mut Vec<SignatureElement>) -> Result<()> {
+        let semantic = match (&variable.semantic, self.structure(&variable.ty)) {
+            (Some(semantic), _) => semantic,
+            (None, Some(fields)) => {
+                for field in &fields.fields {
+                    self.flatten(field, elements)?;
+                }
+                return Ok(());
+            }
+            (None, None) => {
+                return Err(HlslError::MissingSemantic {
+                    name: variable.name.clone(),
+                    line: variable.line,
+                })
+            }
+        };
+        let ty = NumericType::parse(&variable.ty).ok_or_else(|| HlslError::UnsupportedType {
+            ty: variable.ty.clone(),
+            line: variable.line,
+        })?;
+        let count = ty.rows * variable.array_size.unwrap_or(1).max(1);
+        for i in 0..count {
+            elements.push(SignatureElement {
+                semantic_name: semantic.name.clone(),
+                semantic_index: semantic.index + i,
+                register: elements.len() as u32,
+                component_type: ty.component_type,
+                mask: (1 << ty.columns) - 1,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Punct(char),
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Ident(text) | Kind::Number(text) => write!(f, "{}", text),
+            Kind::Str(text) => write!(f, "{:?}", text),
+            Kind::Punct(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    line: u32,
+    column: u32,
+}
+
+fn lex(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let mut line_start = true;
+
+    // Moves past `n` characters keeping track of the position
+    let advance = |i: &mut usize, line: &mut u32, column: &mut u32, n: usize| {
+        for _ in 0..n {
+            if chars.get(*i) == Some(&'\n') {
+                *line += 1;
+                *column = 1;
+            } else {
+                *column += 1;
+            }
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line_start = true;
+            advance(&mut i, &mut line, &mut column, 1);
+        } else if c.is_whitespace() {
+            advance(&mut i, &mut line, &mut column, 1);
+        } else if c == '#' && line_start {
+            // Directive up to the end of the line, `\` continues it
+            while i < chars.len() && chars[i] != '\n' {
+                let n = if chars[i] == '\\' && chars.get(i + 1) == Some(&'\n') {
+                    2
+                } else {
+                    1
+                };
+                advance(&mut i, &mut line, &mut column, n);
+            }
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+        } else if c == '/' && next == Some('*') {
+            let (start_line, start_column) = (line, column);
+            advance(&mut i, &mut line, &mut column, 2);
+            loop {
+                if i >= chars.len() {
+                    return Err(HlslError::Syntax {
+                        line: start_line,
+                        column: start_column,
+                        message: "unterminated comment".into(),
+                    });
+                }
+                if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                    advance(&mut i, &mut line, &mut column, 2);
+                    break;
+                }
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+        } else {
+            line_start = false;
+            let start = i;
+            let (token_line, token_column) = (line, column);
+            let kind = if c.is_ascii_alphabetic() || c == '_' {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    advance(&mut i, &mut line, &mut column, 1);
+                }
+                Kind::Ident(chars[start..i].iter().collect())
+            } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    advance(&mut i, &mut line, &mut column, 1);
+                }
+                Kind::Number(chars[start..i].iter().collect())
+            } else if c == '"' {
+                advance(&mut i, &mut line, &mut column, 1);
+                while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                    let n = if chars[i] == '\\' { 2 } else { 1 };
+                    advance(&mut i, &mut line, &mut column, n);
+                }
+                if chars.get(i) != Some(&'"') {
+                    return Err(HlslError::Syntax {
+                        line: token_line,
+                        column: token_column,
+                        message: "unterminated string".into(),
+                    });
+                }
+                advance(&mut i, &mut line, &mut column, 1);
+                Kind::Str(chars[start + 1..i - 1].iter().collect())
+            } else {
+                advance(&mut i, &mut line, &mut column, 1);
+                Kind::Punct(c)
+            };
+            tokens.push(Token {
+                kind,
+                line: token_line,
+                column: token_column,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Keywords that may precede the type of a declaration
+const MODIFIERS: &[&str] = &[
+    "in",
+    "out",
+    "inout",
+    "uniform",
+    "static",
+    "const",
+    "extern",
+    "shared",
+    "groupshared",
+    "volatile",
+    "precise",
+    "inline",
+    "export",
+    "row_major",
+    "column_major",
+    "nointerpolation",
+    "linear",
+    "centroid",
+    "noperspective",
+    "sample",
+    "snorm",
+    "unorm",
+    "globallycoherent",
+    "point",
+    "line",
+    "triangle",
+    "lineadj",
+    "triangleadj",
+];
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    reflection: Reflection,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(mut self) -> Result<Reflection> {
+        let mut attributes = Vec::new();
+        while let Some(token) = self.peek() {
+            match &token.kind {
+                Kind::Punct(';') => self.pos += 1,
+                Kind::Punct('[') => attributes.push(self.attribute()?),
+                Kind::Ident(word) if word == "struct" => {
+                    let declaration = self.structure()?;
+                    self.reflection.structs.push(declaration);
+                }
+                Kind::Ident(word) if word == "cbuffer" || word == "tbuffer" => {
+                    let cbuffer = self.cbuffer()?;
+                    self.reflection.cbuffers.push(cbuffer);
+                }
+                Kind::Ident(word) if word == "typedef" => self.skip_past(';')?,
+                _ => self.declaration(std::mem::take(&mut attributes))?,
+            }
+        }
+        Ok(self.reflection)
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self, offset: usize) -> Option<&'a Kind> {
+        self.tokens.get(self.pos + offset).map(|token| &token.kind)
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        let (line, column) = match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(token) => (token.line, token.column),
+            None => (1, 1),
+        };
+        Err(HlslError::Syntax {
+            line,
+            column,
+            message,
+        })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T> {
+        match self.peek() {
+            Some(token) => self.error(format!("expected {}, found `{}`", expected, token.kind)),
+            None => self.error(format!("expected {}, found end of file", expected)),
+        }
+    }
+
+    fn line(&self) -> u32 {
+        self.peek().map_or(0, |token| token.line)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        let found = self.peek_kind(0) == Some(&Kind::Punct(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", punct))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek_kind(0) {
+            Some(Kind::Ident(name)) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ => self.unexpected("an identifier"),
+        }
+    }
+
+    fn skip_past(&mut self, punct: char) -> Result<()> {
+        while !self.eat(punct) {
+            if self.peek().is_none() {
+                return self.unexpected(&format!("`{}`", punct));
+            }
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Skips a balanced `{ ... }`
+    fn skip_block(&mut self) -> Result<()> {
+        self.expect('{')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek_kind(0) {
+                Some(Kind::Punct('{')) => depth += 1,
+                Some(Kind::Punct('}')) => depth -= 1,
+                Some(_) => {}
+                None => return self.unexpected("`}`"),
+            }
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Tokens up to the matching `close`, joined without whitespace
+    fn balanced(&mut self, open: char, close: char) -> Result<String> {
+        self.expect(open)?;
+        let mut text = String::new();
+        let mut depth = 1;
+        loop {
+            let kind = match self.peek_kind(0) {
+                Some(kind) => kind,
+                None => return self.unexpected(&format!("`{}`", close)),
+            };
+            self.pos += 1;
+            match kind {
+                Kind::Punct(c) if *c == open => depth += 1,
+                Kind::Punct(c) if *c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(text);
+                    }
+                }
+                _ => {}
+            }
+            let word = |kind: &Kind| matches!(kind, Kind::Ident(_) | Kind::Number(_));
+            if word(kind) && text.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+                text.push(' ');
+            }
+            text.push_str(&kind.to_string());
+        }
+    }
+
+    fn attribute(&mut self) -> Result<String> {
+        self.balanced('[', ']')
+    }
+
+    fn modifiers(&mut self) -> Vec<String> {
+        let mut modifiers = Vec::new();
+        while let (Some(Kind::Ident(word)), Some(Kind::Ident(_))) =
+            (self.peek_kind(0), self.peek_kind(1))
+        {
+            if !MODIFIERS.contains(&word.as_str()) {
+                break;
+            }
+            modifiers.push(word.clone());
+            self.pos += 1;
+        }
+        modifiers
+    }
+
+    /// Type name with its template arguments
+    fn type_name(&mut self) -> Result<String> {
+        let mut ty = self.ident()?;
+        if self.peek_kind(0) == Some(&Kind::Punct('<')) {
+            ty = format!("{}<{}>", ty, self.balanced('<', '>')?);
+        }
+        Ok(ty)
+    }
+
+    fn register(&mut self) -> Result<Register> {
+        self.expect('(')?;
+        let mut slot = None;
+        let mut space = 0;
+        loop {
+            let word = self.ident()?;
+            let (prefix, digits) = word.split_at(1);
+            if let Some(n) = word.strip_prefix("space").and_then(|n| n.parse().ok()) {
+                space = n;
+            } else if let (Some(class), Ok(index)) = (
+                RegisterClass::from_prefix(prefix.chars().next().unwrap_or(' ')),
+                digits.parse(),
+            ) {
+                slot = Some((class, index));
+            }
+            // Anything else is a shader profile, e.g. `register(ps_5_0, t0)`
+            if !self.eat(',') {
+                break;
+            }
+        }
+        match slot {
+            Some((class, index)) => {
+                self.expect(')')?;
+                Ok(Register {
+                    class,
+                    index,
+                    space,
+                })
+            }
+            None => self.unexpected("a register such as `t0`"),
+        }
+    }
+
+    /// Array sizes, semantic, register and initializer after the name
+    fn variable(&mut self, modifiers: Vec<String>, ty: String, name: String) -> Result<Variable> {
+        let line = self.line();
+        let mut variable = Variable {
+            ty,
+            name,
+            array_size: None,
+            semantic: None,
+            register: None,
+            modifiers,
+            line,
+        };
+        while self.peek_kind(0) == Some(&Kind::Punct('[')) {
+            let size = self.balanced('[', ']')?;
+            let size = match size.parse::<u32>() {
+                Ok(size) => size,
+                Err(_) if size.is_empty() => 0,
+                Err(_) => return self.error(format!("array size `{}` isn't a number", size)),
+            };
+            variable.array_size = Some(variable.array_size.unwrap_or(1) * size);
+        }
+        while self.eat(':') {
+            match self.ident()?.as_str() {
+                "register" => variable.register = Some(self.register()?),
+                "packoffset" => {
+                    self.balanced('(', ')')?;
+                }
+                semantic => variable.semantic = Some(Semantic::parse(semantic)),
+            }
+        }
+        if self.peek_kind(0) == Some(&Kind::Punct('<')) {
+            // Annotations
+            self.balanced('<', '>')?;
+        }
+        if self.eat('=') {
+            self.initializer()?;
+        }
+        Ok(variable)
+    }
+
+    /// Skips to the `,`, `;` or `)` ending an initializer
+    fn initializer(&mut self) -> Result<()> {
+        let mut depth = 0;
+        loop {
+            match self.peek_kind(0) {
+                Some(Kind::Punct('(')) | Some(Kind::Punct('{')) | Some(Kind::Punct('[')) => {
+                    depth += 1
+                }
+                Some(Kind::Punct(')')) | Some(Kind::Punct('}')) | Some(Kind::Punct(']'))
+                    if depth > 0 =>
+                {
+                    depth -= 1
+                }
+                Some(Kind::Punct(',')) | Some(Kind::Punct(';')) | Some(Kind::Punct(')'))
+                    if depth == 0 =>
+                {
+                    return Ok(())
+                }
+                Some(_) => {}
+                None => return self.unexpected("`;`"),
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Declarations up to the closing `}` of a struct or cbuffer
+    fn members(&mut self) -> Result<Vec<Variable>> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+            let modifiers = self.modifiers();
+            let ty = self.type_name()?;
+            loop {
+                let name = self.ident()?;
+                members.push(self.variable(modifiers.clone(), ty.clone(), name)?);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(';')?;
+        }
+        Ok(members)
+    }
+
+    fn structure(&mut self) -> Result<Struct> {
+        let line = self.line();
+        self.pos += 1;
+        let name = self.ident()?;
+        let fields = self.members()?;
+        self.expect(';')?;
+        Ok(Struct { name, fields, line })
+    }
+
+    fn cbuffer(&mut self) -> Result<ConstantBuffer> {
+        let line = self.line();
+        self.pos += 1;
+        let name = self.ident()?;
+        let mut register = None;
+        if self.eat(':') {
+            match self.ident()?.as_str() {
+                "register" => register = Some(self.register()?),
+                _ => {
+                    self.pos -= 1;
+                    return self.unexpected("`register`");
+                }
+            }
+        }
+        let members = self.members()?;
+        Ok(ConstantBuffer {
+            name,
+            register,
+            members,
+            line,
+        })
+    }
+
+    /// Function, resource or global variable
+    fn declaration(&mut self, attributes: Vec<String>) -> Result<()> {
+        let line = self.line();
+        let modifiers = self.modifiers();
+        let ty = self.type_name()?;
+        let name = self.ident()?;
+        if self.eat('(') {
+            return self.function(attributes, ty, name, line);
+        }
+        let mut name = name;
+        loop {
+            let variable = self.variable(modifiers.clone(), ty.clone(), name)?;
+            if variable
+                .modifiers
+                .iter()
+                .any(|m| m == "static" || m == "groupshared")
+            {
+                // Not bound to the pipeline
+            } else if let Some(class) = RegisterClass::of_type(&variable.ty) {
+                self.reflection.resources.push(Resource {
+                    name: variable.name,
+                    ty: variable.ty,
+                    class,
+                    register: variable.register,
+                    count: variable.array_size.unwrap_or(1),
+                    line: variable.line,
+                });
+            } else {
+                self.reflection.globals.push(variable);
+            }
+            if !self.eat(',') {
+                break;
+            }
+            name = self.ident()?;
+        }
+        self.expect(';')
+    }
+
+    fn function(
+        &mut self,
+        attributes: Vec<String>,
+        return_type: String,
+        name: String,
+        line: u32,
+    ) -> Result<()> {
+        let mut parameters = Vec::new();
+        // `f(void)` has no parameters
+        if self.peek_kind(0) == Some(&Kind::Ident("void".into()))
+            && self.peek_kind(1) == Some(&Kind::Punct(')'))
+        {
+            self.pos += 1;
+        }
+        if !self.eat(')') {
+            loop {
+                let modifiers = self.modifiers();
+                let has = |m: &str| modifiers.iter().any(|modifier| modifier == m);
+                let direction = if has("inout") || (has("in") && has("out")) {
+                    Direction::InOut
+                } else if has("out") {
+                    Direction::Out
+                } else if has("uniform") {
+                    Direction::Uniform
+                } else {
+                    Direction::In
+                };
+                let ty = self.type_name()?;
+                let name = self.ident()?;
+                let variable = self.variable(modifiers, ty, name)?;
+                parameters.push(Parameter {
+                    direction,
+                    variable,
+                });
+                if self.eat(')') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        let mut return_semantic = None;
+        if self.eat(':') {
+            return_semantic = Some(Semantic::parse(&self.ident()?));
+        }
+        if self.eat(';') {
+            // Prototype, the definition follows
+            return Ok(());
+        }
+        self.skip_block()?;
+        self.reflection.functions.push(Function {
+            name,
+            return_type,
+            return_semantic,
+            parameters,
+            attributes,
+            line,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    fn element(
+        name: &str,
+        index: u32,
+        register: u32,
+        ty: ComponentType,
+        mask: u8,
+    ) -> SignatureElement {
+        SignatureElement {
+            semantic_name: name.into(),
+            semantic_index: index,
+            register,
+            component_type: ty,
+            mask,
+        }
+    }
+
+    #[test]
+    fn reflects_hello_triangle() {
+        let reflection = Reflection::parse(SIMPLE).unwrap();
+        let input = reflection.structure("PSInput").unwrap();
+        let semantics: Vec<_> = input
+            .fields
+            .iter()
+            .map(|f| f.semantic.clone().unwrap())
+            .collect();
+        assert_eq!(
+            semantics,
+            [Semantic::parse("SV_POSITION"), Semantic::parse("COLOR")]
+        );
+
+        let vs = reflection.function("VSMain").unwrap();
+        assert_eq!(vs.return_type, "PSInput");
+        assert_eq!(vs.parameters.len(), 2);
+        assert_eq!(
+            vs.parameters[0].variable.semantic,
+            Some(Semantic::parse("POSITION"))
+        );
+        let ps = reflection.function("PSMain").unwrap();
+        assert_eq!(ps.return_semantic, Some(Semantic::parse("SV_TARGET")));
+
+        let float4 = |name, register| element(name, 0, register, ComponentType::Float32, 0b1111);
+        assert_eq!(
+            reflection.input_signature("VSMain").unwrap(),
+            [float4("POSITION", 0), float4("COLOR", 1)]
+        );
+        assert_eq!(
+            reflection.output_signature("VSMain").unwrap(),
+            [float4("SV_POSITION", 0), float4("COLOR", 1)]
+        );
+        assert_eq!(
+            reflection.input_signature("PSMain").unwrap(),
+            reflection.output_signature("VSMain").unwrap()
+        );
+        assert_eq!(
+            reflection.output_signature("PSMain").unwrap(),
+            [float4("SV_TARGET", 0)]
+        );
+        assert!(reflection.bindings().is_empty());
+    }
+
+    #[test]
+    fn reflects_bindings_and_signatures() {
+        let source = r#"
+            #define MAX_LIGHTS 4
+            #include "common.hlsli"
+
+            /* Per-draw constants */
+            cbuffer Constants : register(b1, space2)
+            {
+                row_major float4x4 transform;
+                float3 tint; float scale;
+            };
+            struct Light { float3 direction; float intensity; };
+            ConstantBuffer<Light> light : register(b0);
+            Texture2D<float4> albedo : register(t0);
+            Texture2D shadows[4] : register(t1);
+            Texture2D bindless[] : register(t0, space1);
+            SamplerState linearSampler : register(s0);
+            RWStructuredBuffer<uint> counters : register(u1);
+            static const float PI = 3.14159;
+            groupshared float cache[64];
+            float exposure = 1.0f;
+
+            struct VSInput
+            {
+                float3 position : POSITION;
+                float4x4 world : WORLD;
+                nointerpolation uint id : TEXCOORD3;
+            };
+
+            float4 Shade(float3 n, Light l) { return dot(n, -l.direction) * l.intensity; }
+
+            void VSMain(VSInput input, uint vertex : SV_VertexID,
+                        out float4 position : SV_Position, out float2 uv : TEXCOORD)
+            {
+                position = mul(float4(input.position, 1), transform);
+                uv = float2(vertex & 1, vertex >> 1);
+            }
+
+            [numthreads(8, 8, 1)]
+            void CSMain(uint3 id : SV_DispatchThreadID) { counters[0] = id.x; }
+        "#;
+        let reflection = Reflection::parse(source).unwrap();
+
+        let cbuffer = &reflection.cbuffers[0];
+        assert_eq!(cbuffer.name, "Constants");
+        let names: Vec<_> = cbuffer.members.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["transform", "tint", "scale"]);
+        assert_eq!(cbuffer.members[0].modifiers, ["row_major"]);
+
+        let bindings: Vec<_> = reflection
+            .bindings()
+            .into_iter()
+            .map(|b| (b.name, b.register.unwrap().to_string(), b.count))
+            .collect();
+        let binding =
+            |name: &str, register: &str, count| (name.to_string(), register.to_string(), count);
+        assert_eq!(
+            bindings,
+            [
+                binding("Constants", "b1, space2", 1),
+                binding("light", "b0, space0", 1),
+                binding("albedo", "t0, space0", 1),
+                binding("shadows", "t1, space0", 4),
+                binding("bindless", "t0, space1", 0),
+                binding("linearSampler", "s0, space0", 1),
+                binding("counters", "u1, space0", 1),
+            ]
+        );
+        assert_eq!(reflection.resources[0].class, RegisterClass::ConstantBuffer);
+        assert_eq!(
+            reflection.resources[5].class,
+            RegisterClass::UnorderedAccess
+        );
+        let globals: Vec<_> = reflection.globals.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(globals, ["exposure"]);
+
+        let float = ComponentType::Float32;
+        let uint = ComponentType::Uint32;
+        let mut inputs = vec![element("POSITION", 0, 0, float, 0b111)];
+        inputs.extend((0..4).map(|i| element("WORLD", i, 1 + i, float, 0b1111)));
+        inputs.push(element("TEXCOORD", 3, 5, uint, 0b1));
+        inputs.push(element("SV_VERTEXID", 0, 6, uint, 0b1));
+        assert_eq!(reflection.input_signature("VSMain").unwrap(), inputs);
+        assert_eq!(
+            reflection.output_signature("VSMain").unwrap(),
+            [
+                element("SV_POSITION", 0, 0, float, 0b1111),
+                element("TEXCOORD", 0, 1, float, 0b11)
+            ]
+        );
+
+        let cs = reflection.function("CSMain").unwrap();
+        assert_eq!(cs.attributes, ["numthreads(8,8,1)"]);
+        assert_eq!(reflection.functions.len(), 3);
+    }
+
+    #[test]
+    fn parses_numeric_types() {
+        let ty = |component_type, rows, columns| {
+            Some(NumericType {
+                component_type,
+                rows,
+                columns,
+            })
+        };
+        assert_eq!(
+            NumericType::parse("float"),
+            ty(ComponentType::Float32, 1, 1)
+        );
+        assert_eq!(NumericType::parse("int3"), ty(ComponentType::Sint32, 1, 3));
+        assert_eq!(
+            NumericType::parse("uint2x3"),
+            ty(ComponentType::Uint32, 2, 3)
+        );
+        assert_eq!(
+            NumericType::parse("vector<half, 2>"),
+            ty(ComponentType::Float32, 1, 2)
+        );
+        assert_eq!(
+            NumericType::parse("matrix<float,3,4>"),
+            ty(ComponentType::Float32, 3, 4)
+        );
+        assert_eq!(NumericType::parse("float5"), None);
+        assert_eq!(NumericType::parse("PSInput"), None);
+    }
+
+    #[test]
+    fn reports_positions() {
+        let error = Reflection::parse("struct A\n{\n    float4 a : COLOR\n};").unwrap_err();
+        assert_eq!(
+            error,
+            HlslError::Syntax {
+                line: 4,
+                column: 1,
+                message: "expected `;`, found `}`".into()
+            }
+        );
+        let error = Reflection::parse("/* open").unwrap_err();
+        assert_eq!(error.to_string(), "1:1: unterminated comment");
+
+        let reflection =
+            Reflection::parse("float4 main(float4 p) : SV_Target { return p; }").unwrap();
+        assert_eq!(
+            reflection.input_signature("main"),
+            Err(HlslError::MissingSemantic {
+                name: "p".into(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            reflection.input_signature("VSMain"),
+            Err(HlslError::NoFunction("VSMain".into()))
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Input layout checks against the vertex shader input signature
+//!
+//! The same mismatches the debug layer reports at pipeline creation, found
+//! from the layout and a signature reflected from source or read from the
+//! compiled blob.
+use super::signature::{ComponentType, SignatureElement};
+use crate::format::Format;
+use crate::vertex::InputElement;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The shader reads a semantic no element provides
+    Missing { semantic: String },
+    /// Two elements provide the same semantic
+    Duplicate {
+        semantic: String,
+        first: usize,
+        second: usize,
+    },
+    /// `SV_` semantics come from the pipeline, not from vertex buffers
+    SystemValue { element: usize, semantic: String },
+    /// Format isn't readable by the input assembler, e.g. a depth format
+    NotVertexFormat { element: usize, format: Format },
+    /// Float format read as an integer or the other way around
+    ComponentType {
+        element: usize,
+        semantic: String,
+        format: Format,
+        expected: ComponentType,
+    },
+    /// Valid, missing components read as 0 and `w` as 1, extra ones are
+    /// dropped
+    ComponentCount {
+        element: usize,
+        semantic: String,
+        provided: u32,
+        expected: u32,
+    },
+}
+
+impl LayoutError {
+    /// Pipeline creation succeeds with only warnings
+    pub fn is_warning(&self) -> bool {
+        matches!(self, LayoutError::ComponentCount { .. })
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::Missing { semantic } => {
+                write!(
+                    f,
+                    "shader reads {} but no input element provides it",
+                    semantic
+                )
+            }
+            LayoutError::Duplicate {
+                semantic,
+                first,
+                second,
+            } => write!(
+                f,
+                "input elements {} and {} both provide {}",
+                first, second, semantic
+            ),
+            LayoutError::SystemValue { element, semantic } => write!(
+                f,
+                "input element {} uses system value semantic {}",
+                element, semantic
+            ),
+            LayoutError::NotVertexFormat { element, format } => {
+                write!(
+                    f,
+                    "input element {} has non-vertex format {:?}",
+                    element, format
+                )
+            }
+            LayoutError::ComponentType {
+                element,
+                semantic,
+                format,
+                expected,
+            } => write!(
+                f,
+                "input element {} ({}) has format {:?} but the shader reads {:?}",
+                element, semantic, format, expected
+            ),
+            LayoutError::ComponentCount {
+                element,
+                semantic,
+                provided,
+                expected,
+            } => write!(
+                f,
+                "input element {} ({}) has {} components, the shader declares {}",
+                element, semantic, provided, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// All mismatches between the layout and the input signature, in element
+/// order followed by semantics the layout lacks
+pub fn validate(layout: &[InputElement], signature: &[SignatureElement]) -> Vec<LayoutError> {
+    let mut errors = Vec::new();
+    for (i, element) in layout.iter().enumerate() {
+        let semantic = semantic(element.semantic_name, element.semantic_index);
+        if element
+            .semantic_name
+            .to_ascii_uppercase()
+            .starts_with("SV_")
+        {
+            errors.push(LayoutError::SystemValue {
+                element: i,
+                semantic,
+            });
+            continue;
+        }
+        if let Some(first) = layout[..i]
+            .iter()
+            .position(|other| provides(other, element))
+        {
+            errors.push(LayoutError::Duplicate {
+                semantic,
+                first,
+                second: i,
+            });
+            continue;
+        }
+        let component_type = match component_type(element.format) {
+            Some(component_type) => component_type,
+            None => {
+                errors.push(LayoutError::NotVertexFormat {
+                    element: i,
+                    format: element.format,
+                });
+                continue;
+            }
+        };
+        // Elements the shader doesn't read are ignored
+        let input = match signature.iter().find(|input| reads(input, element)) {
+            Some(input) => input,
+            None => continue,
+        };
+        if component_type != input.component_type {
+            errors.push(LayoutError::ComponentType {
+                element: i,
+                semantic: semantic.clone(),
+                format: element.format,
+                expected: input.component_type,
+            });
+        }
+        if element.format.component_count() != input.component_count() {
+            errors.push(LayoutError::ComponentCount {
+                element: i,
+                semantic,
+                provided: element.format.component_count(),
+                expected: input.component_count(),
+            });
+        }
+    }
+    for input in signature.iter().filter(|input| !input.is_system_value()) {
+        if !layout.iter().any(|element| reads(input, element)) {
+            errors.push(LayoutError::Missing {
+                semantic: input.to_string(),
+            });
+        }
+    }
+    errors
+}
+
+/// Mismatches pipeline creation would reject, warnings left out
+pub fn check(
+    layout: &[InputElement],
+    signature: &[SignatureElement],
+) -> Result<(), Vec<LayoutError>> {
+    let errors: Vec<_> = validate(layout, signature)
+        .into_iter()
+        .filter(|error| !error.is_warning())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn semantic(name: &str, index: u32) -> String {
+    if index == 0 {
+        name.to_string()
+    } else {
+        format!("{}{}", name, index)
+    }
+}
+
+fn provides(a: &InputElement, b: &InputElement) -> bool {
+    a.semantic_name.eq_ignore_ascii_case(b.semantic_name) && a.semantic_index == b.semantic_index
+}
+
+fn reads(input: &SignatureElement, element: &InputElement) -> bool {
+    input
+        .semantic_name
+        .eq_ignore_ascii_case(element.semantic_name)
+        && input.semantic_index == element.semantic_index
+}
+
+/// Register type the shader sees, normalized formats read as floats
+fn component_type(format: Format) -> Option<ComponentType> {
+    match format {
+        Format::R32G32B32A32Float
+        | Format::R32G32B32Float
+        | Format::R16G16B16A16Float
+        | Format::R32G32Float
+        | Format::R32Float
+        | Format::R10G10B10A2Unorm
+        | Format::R8G8B8A8Unorm
+        | Format::R8G8B8A8UnormSrgb
+        | Format::B8G8R8A8Unorm
+        | Format::B8G8R8A8UnormSrgb => Some(ComponentType::Float32),
+        Format::R32G32B32A32Uint
+        | Format::R32G32B32Uint
+        | Format::R32G32Uint
+        | Format::R32Uint
+        | Format::R16Uint => Some(ComponentType::Uint32),
+        Format::R32G32B32A32Sint | Format::R32G32B32Sint | Format::R32G32Sint | Format::R32Sint => {
+            Some(ComponentType::Sint32)
+        }
+        Format::Unknown | Format::D32Float | Format::D24UnormS8Uint => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::container::{fixture, Container};
+    use crate::shader::hlsl::Reflection;
+    use crate::vertex::{Vertex, VertexLayout};
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    #[test]
+    fn hello_triangle_layout_matches() {
+        let signature = Reflection::parse(SIMPLE)
+            .unwrap()
+            .input_signature("VSMain")
+            .unwrap();
+        assert_eq!(check(Vertex::LAYOUT, &signature), Ok(()));
+        // `float3` position read as `float4`, `w` is 1
+        assert_eq!(
+            validate(Vertex::LAYOUT, &signature),
+            [LayoutError::ComponentCount {
+                element: 0,
+                semantic: "POSITION".into(),
+                provided: 3,
+                expected: 4
+            }]
+        );
+
+        // Same signature from the blob `D3DCompile` returns
+        let isgn: Vec<_> = signature
+            .iter()
+            .map(|e| (e.semantic_name.as_str(), e))
+            .collect();
+        let blob = fixture::container(&[(b"ISGN", fixture::signature(&isgn))]);
+        let compiled = Container::parse(&blob).unwrap().input_signature().unwrap();
+        assert_eq!(compiled, signature);
+    }
+
+    #[test]
+    fn reports_each_mismatch() {
+        let source = "
+            float4 main(float3 position : POSITION, uint id : BLENDINDICES,
+                        float2 uv : TEXCOORD1, uint vertex : SV_VertexID) : SV_Position
+            { return float4(position, 1); }
+        ";
+        let signature = Reflection::parse(source)
+            .unwrap()
+            .input_signature("main")
+            .unwrap();
+        let layout = [
+            InputElement::per_vertex("position", Format::R32G32B32Float, 0),
+            InputElement::per_vertex("BLENDINDICES", Format::R8G8B8A8Unorm, 12),
+            InputElement::per_vertex("TEXCOORD", Format::R32G32Float, 16),
+            InputElement::per_vertex("POSITION", Format::R32G32B32Float, 24),
+            InputElement::per_vertex("SV_InstanceID", Format::R32Uint, 36),
+            InputElement::per_vertex("DEPTH", Format::D32Float, 40),
+        ];
+        let errors = check(&layout, &signature).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                LayoutError::ComponentType {
+                    element: 1,
+                    semantic: "BLENDINDICES".into(),
+                    format: Format::R8G8B8A8Unorm,
+                    expected: ComponentType::Uint32
+                },
+                LayoutError::Duplicate {
+                    semantic: "POSITION".into(),
+                    first: 0,
+                    second: 3
+                },
+                LayoutError::SystemValue {
+                    element: 4,
+                    semantic: "SV_InstanceID".into()
+                },
+                LayoutError::NotVertexFormat {
+                    element: 5,
+                    format: Format::D32Float
+                },
+                LayoutError::Missing {
+                    semantic: "TEXCOORD1".into()
+                },
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "input element 1 (BLENDINDICES) has format R8G8B8A8Unorm but the shader reads Uint32"
+        );
+        assert_eq!(
+            errors[4].to_string(),
+            "shader reads TEXCOORD1 but no input element provides it"
+        );
+        // BLENDINDICES also gets a component count warning
+        assert_eq!(validate(&layout, &signature).len(), errors.len() + 1);
+    }
+}
@@ -0,0 +1,153 @@
+//! List of shaders to compile ahead of time
+//!
+//! One entry point per line, the file, entry point, profile and then the
+//! defines it reacts to. A bare name toggles the define, `NAME=a|b` gives its
+//! values:
+//!
+//! ```text
+//! # file        entry   profile  defines
+//! simple.hlsl   VSMain  vs_6_0   VERTEX_COLOR
+//! simple.hlsl   PSMain  ps_6_0   PREMULTIPLIED QUALITY=0|1|2
+//! ```
+use super::permutation::{Axis, Permutations};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// Permutations of each source file, in the order first listed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: Vec<(String, Permutations)>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest, ManifestError> {
+        let mut manifest = Manifest::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            let error = |message: String| ManifestError {
+                line: line_number,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let file = match words.next() {
+                Some(file) => file,
+                None => continue,
+            };
+            let (entry_point, profile) = match (words.next(), words.next()) {
+                (Some(entry_point), Some(profile)) => (entry_point, profile),
+                _ => {
+                    return Err(error(format!(
+                        "{} needs an entry point and a profile",
+                        file
+                    )))
+                }
+            };
+            let mut axes: Vec<Axis> = Vec::new();
+            for word in words {
+                let axis = match word.split_once('=') {
+                    Some((name, values)) => {
+                        Axis::values(name, &values.split('|').collect::<Vec<_>>())
+                    }
+                    None => Axis::toggle(word),
+                };
+                if axis.name.is_empty() || axis.values.iter().any(|v| v.as_deref() == Some("")) {
+                    return Err(error(format!("empty define in {}", word)));
+                }
+                if axes.iter().any(|other| other.name == axis.name) {
+                    return Err(error(format!("{} listed twice", axis.name)));
+                }
+                axes.push(axis);
+            }
+
+            let index = match manifest.files.iter().position(|(name, _)| name == file) {
+                Some(index) => index,
+                None => {
+                    manifest.files.push((file.to_string(), Permutations::new()));
+                    manifest.files.len() - 1
+                }
+            };
+            let permutations = &mut manifest.files[index].1;
+            if permutations
+                .entry_points
+                .iter()
+                .any(|e| e.name == entry_point)
+            {
+                return Err(error(format!("{} of {} listed twice", entry_point, file)));
+            }
+            *permutations = std::mem::take(permutations).entry_point(entry_point, profile, axes);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Name of the compiled library of a source file, `simple.hlsl` gives
+/// `simple.dxlib`
+pub fn library_name(file: &str) -> String {
+    let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    format!("{}.dxlib", stem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::permutation;
+
+    #[test]
+    fn parses_entry_points() {
+        let manifest = Manifest::parse(
+            "
+            # Hello triangle
+            simple.hlsl  VSMain  vs_5_0  VERTEX_COLOR
+            post/tonemap.hlsl main ps_6_0 QUALITY=0|1|2 # per preset
+            simple.hlsl  PSMain  ps_5_0  PREMULTIPLIED WIREFRAME_TINT
+            ",
+        )
+        .unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.files[0].0, "simple.hlsl");
+        assert_eq!(manifest.files[0].1, permutation::hello_triangle("5_0"));
+        let tonemap = &manifest.files[1].1;
+        assert_eq!(tonemap.variants().len(), 3);
+        assert_eq!(
+            tonemap.entry_points[0].axes[0],
+            Axis::values("QUALITY", &["0", "1", "2"])
+        );
+
+        assert_eq!(library_name("simple.hlsl"), "simple.dxlib");
+        assert_eq!(library_name("post/tonemap.hlsl"), "tonemap.dxlib");
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let errors = [
+            (
+                "simple.hlsl VSMain",
+                "line 1: simple.hlsl needs an entry point and a profile",
+            ),
+            ("a.hlsl main ps_5_0 FOG FOG", "line 1: FOG listed twice"),
+            ("a.hlsl main ps_5_0 Q=1|", "line 1: empty define in Q=1|"),
+            (
+                "a.hlsl main ps_5_0\n\na.hlsl main vs_5_0",
+                "line 3: main of a.hlsl listed twice",
+            ),
+        ];
+        for (text, message) in &errors {
+            assert_eq!(Manifest::parse(text).unwrap_err().to_string(), *message);
+        }
+    }
+}
@@ -0,0 +1,593 @@
+//! Shader variants keyed on preprocessor defines
+//!
+//! Each entry point lists the defines it reacts to and their values, every
+//! combination is a variant. Variants are preprocessed first so ones with the
+//! same source compile once, identical blobs are then stored once.
+use super::preprocess::{IncludeResolver, PreprocessError, Preprocessed, Preprocessor};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::iter::FromIterator;
+
+/// Sorted set of `NAME=value` defines
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Defines(BTreeMap<String, String>);
+
+impl Defines {
+    pub fn new() -> Self {
+        Defines::default()
+    }
+
+    pub fn set(mut self, name: &str, value: &str) -> Self {
+        self.0.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> FromIterator<(&'a str, &'a str)> for Defines {
+    fn from_iter<I: IntoIterator<Item = (&'a str, &'a str)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Defines::new(), |defines, (name, value)| {
+                defines.set(name, value)
+            })
+    }
+}
+
+impl fmt::Display for Defines {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value)) in self.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{}{}={}", separator, name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Define an entry point reacts to and the values it takes, `None` leaves it
+/// undefined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis {
+    pub name: String,
+    pub values: Vec<Option<String>>,
+}
+
+impl Axis {
+    /// Undefined or `1`
+    pub fn toggle(name: &str) -> Self {
+        Axis {
+            name: name.to_string(),
+            values: vec![None, Some("1".to_string())],
+        }
+    }
+
+    pub fn values(name: &str, values: &[&str]) -> Self {
+        Axis {
+            name: name.to_string(),
+            values: values.iter().map(|value| Some(value.to_string())).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub name: String,
+    /// Target profile, e.g. `vs_5_0`
+    pub profile: String,
+    pub axes: Vec<Axis>,
+}
+
+/// One combination of an entry point's defines
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub entry_point: String,
+    pub profile: String,
+    pub defines: Defines,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.entry_point, self.profile)?;
+        if !self.defines.is_empty() {
+            write!(f, " {}", self.defines)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermutationError<E> {
+    Preprocess(Box<Variant>, PreprocessError),
+    Compile(Box<Variant>, E),
+}
+
+impl<E: fmt::Display> fmt::Display for PermutationError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PermutationError::Preprocess(variant, error) => write!(f, "{}: {}", variant, error),
+            PermutationError::Compile(variant, error) => write!(f, "{}: {}", variant, error),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for PermutationError<E> {}
+
+/// Entry points of one source file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Permutations {
+    pub entry_points: Vec<EntryPoint>,
+}
+
+impl Permutations {
+    pub fn new() -> Self {
+        Permutations::default()
+    }
+
+    pub fn entry_point(mut self, name: &str, profile: &str, axes: Vec<Axis>) -> Self {
+        self.entry_points.push(EntryPoint {
+            name: name.to_string(),
+            profile: profile.to_string(),
+            axes,
+        });
+        self
+    }
+
+    /// Every combination of every entry point
+    pub fn variants(&self) -> Vec<Variant> {
+        let mut variants = Vec::new();
+        for entry_point in &self.entry_points {
+            let mut combinations = vec![Defines::new()];
+            for axis in &entry_point.axes {
+                combinations = combinations
+                    .iter()
+                    .flat_map(|defines| {
+                        axis.values.iter().map(move |value| match value {
+                            Some(value) => defines.clone().set(&axis.name, value),
+                            None => defines.clone(),
+                        })
+                    })
+                    .collect();
+            }
+            variants.extend(combinations.into_iter().map(|defines| Variant {
+                entry_point: entry_point.name.clone(),
+                profile: entry_point.profile.clone(),
+                defines,
+            }));
+        }
+        variants
+    }
+
+    /// Preprocesses every variant of `source` and compiles the distinct ones
+    ///
+    /// `compile` gets the preprocessed source, the defines are already
+    /// applied.
+    pub fn compile<E>(
+        &self,
+        file: &str,
+        source: &str,
+        resolver: &dyn IncludeResolver,
+        mut compile: impl FnMut(&Variant, &Preprocessed) -> Result<Vec<u8>, E>,
+    ) -> Result<ShaderLibrary, PermutationError<E>> {
+        let mut library = ShaderLibrary {
+            axes: self
+                .entry_points
+                .iter()
+                .map(|e| ((e.name.clone(), e.profile.clone()), e.axes.clone()))
+                .collect(),
+            ..ShaderLibrary::default()
+        };
+        let mut sources: HashMap<(String, String, String), usize> = HashMap::new();
+        let mut blobs: HashMap<Vec<u8>, usize> = HashMap::new();
+        for variant in self.variants() {
+            let mut preprocessor = Preprocessor::new(resolver);
+            for (name, value) in variant.defines.iter() {
+                preprocessor.define(name, value);
+            }
+            let preprocessed = preprocessor
+                .run(file, source)
+                .map_err(|e| PermutationError::Preprocess(Box::new(variant.clone()), e))?;
+            let key = (
+                variant.entry_point.clone(),
+                variant.profile.clone(),
+                preprocessed.source.clone(),
+            );
+            let index = match sources.get(&key) {
+                Some(&index) => index,
+                None => {
+                    let blob = compile(&variant, &preprocessed)
+                        .map_err(|e| PermutationError::Compile(Box::new(variant.clone()), e))?;
+                    library.compiles += 1;
+                    let index = *blobs.entry(blob).or_insert_with_key(|blob| {
+                        library.blobs.push(blob.clone());
+                        library.blobs.len() - 1
+                    });
+                    sources.insert(key, index);
+                    index
+                }
+            };
+            library.variants.insert(
+                (variant.entry_point, variant.profile, variant.defines),
+                index,
+            );
+        }
+        Ok(library)
+    }
+}
+
+/// Compiled variants, looked up by entry point, profile and defines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShaderLibrary {
+    /// Keyed by entry point and profile
+    axes: HashMap<(String, String), Vec<Axis>>,
+    blobs: Vec<Vec<u8>>,
+    variants: HashMap<(String, String, Defines), usize>,
+    compiles: usize,
+}
+
+impl ShaderLibrary {
+    /// Blob of the variant, defines the entry point doesn't react to are
+    /// ignored so one set of defines can select every stage
+    pub fn get(&self, entry_point: &str, profile: &str, defines: &Defines) -> Option<&[u8]> {
+        let key = (entry_point.to_string(), profile.to_string());
+        let axes = self.axes.get(&key)?;
+        let relevant = defines
+            .iter()
+            .filter(|(name, _)| axes.iter().any(|axis| axis.name == *name))
+            .collect();
+        let index = self.variants.get(&(key.0, key.1, relevant))?;
+        Some(&self.blobs[*index])
+    }
+
+    pub fn variant_count(&self) -> usize {
+        self.variants.len()
+    }
+
+    /// Distinct blobs, at most the number of variants
+    pub fn blob_count(&self) -> usize {
+        self.blobs.len()
+    }
+
+    /// Calls of the compile function, 0 for a library read from bytes
+    pub fn compile_count(&self) -> usize {
+        self.compiles
+    }
+
+    /// Stable byte form for embedding, entries sorted so equal libraries
+    /// give equal bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(LIBRARY_MAGIC);
+        put_u32(&mut out, LIBRARY_VERSION);
+
+        let mut axes: Vec<_> = self.axes.iter().collect();
+        axes.sort_by_key(|(key, _)| *key);
+        put_u32(&mut out, axes.len() as u32);
+        for ((entry_point, profile), axes) in axes {
+            put_str(&mut out, entry_point);
+            put_str(&mut out, profile);
+            put_u32(&mut out, axes.len() as u32);
+            for axis in axes {
+                put_str(&mut out, &axis.name);
+                put_u32(&mut out, axis.values.len() as u32);
+                for value in &axis.values {
+                    out.push(value.is_some() as u8);
+                    put_str(&mut out, value.as_deref().unwrap_or(""));
+                }
+            }
+        }
+
+        put_u32(&mut out, self.blobs.len() as u32);
+        for blob in &self.blobs {
+            put_u32(&mut out, blob.len() as u32);
+            out.extend_from_slice(blob);
+        }
+
+        let mut variants: Vec<_> = self.variants.iter().collect();
+        variants.sort();
+        put_u32(&mut out, variants.len() as u32);
+        for ((entry_point, profile, defines), index) in variants {
+            put_str(&mut out, entry_point);
+            put_str(&mut out, profile);
+            put_u32(&mut out, defines.0.len() as u32);
+            for (name, value) in defines.iter() {
+                put_str(&mut out, name);
+                put_str(&mut out, value);
+            }
+            put_u32(&mut out, *index as u32);
+        }
+        out
+    }
+
+    /// `None` when malformed or written by another version
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != LIBRARY_MAGIC || reader.u32()? != LIBRARY_VERSION {
+            return None;
+        }
+        let mut library = ShaderLibrary::default();
+        for _ in 0..reader.u32()? {
+            let entry_point = reader.string()?;
+            let profile = reader.string()?;
+            let mut axes = Vec::new();
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                let mut values = Vec::new();
+                for _ in 0..reader.u32()? {
+                    let present = reader.take(1)?[0] != 0;
+                    let value = reader.string()?;
+                    values.push(if present { Some(value) } else { None });
+                }
+                axes.push(Axis { name, values });
+            }
+            library.axes.insert((entry_point, profile), axes);
+        }
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            library.blobs.push(reader.take(len)?.to_vec());
+        }
+        for _ in 0..reader.u32()? {
+            let entry_point = reader.string()?;
+            let profile = reader.string()?;
+            let mut defines = Defines::new();
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                defines = defines.set(&name, &reader.string()?);
+            }
+            let index = reader.u32()? as usize;
+            if index >= library.blobs.len() {
+                return None;
+            }
+            library
+                .variants
+                .insert((entry_point, profile, defines), index);
+        }
+        if reader.0.is_empty() {
+            Some(library)
+        } else {
+            None
+        }
+    }
+}
+
+const LIBRARY_MAGIC: &[u8; 4] = b"DXSL";
+const LIBRARY_VERSION: u32 = 2;
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(word))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
+
+/// Variants of `simple.hlsl` for a shader model, `5_0` for `D3DCompile` and
+/// `6_0` for DXC
+pub fn hello_triangle(shader_model: &str) -> Permutations {
+    Permutations::new()
+        .entry_point(
+            "VSMain",
+            &format!("vs_{}", shader_model),
+            vec![Axis::toggle("VERTEX_COLOR")],
+        )
+        .entry_point(
+            "PSMain",
+            &format!("ps_{}", shader_model),
+            vec![
+                Axis::toggle("PREMULTIPLIED"),
+                Axis::toggle("WIREFRAME_TINT"),
+            ],
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::preprocess::Embedded;
+
+    const SIMPLE: &str = include_str!("../../../winapi-rs/src/simple.hlsl");
+
+    /// Stands in for the compiler, the code without blank and `#line` lines
+    fn compile(variant: &Variant, source: &Preprocessed) -> Result<Vec<u8>, String> {
+        let mut blob = format!("{} {}\n", variant.entry_point, variant.profile);
+        for line in source.source.lines() {
+            if !line.trim().is_empty() && !line.starts_with("#line") {
+                blob.push_str(line.trim());
+                blob.push('\n');
+            }
+        }
+        Ok(blob.into_bytes())
+    }
+
+    #[test]
+    fn enumerates_define_combinations() {
+        let variants: Vec<_> = hello_triangle("5_0")
+            .variants()
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            variants,
+            [
+                "VSMain (vs_5_0)",
+                "VSMain (vs_5_0) VERTEX_COLOR=1",
+                "PSMain (ps_5_0)",
+                "PSMain (ps_5_0) WIREFRAME_TINT=1",
+                "PSMain (ps_5_0) PREMULTIPLIED=1",
+                "PSMain (ps_5_0) PREMULTIPLIED=1 WIREFRAME_TINT=1",
+            ]
+        );
+
+        let quality = Permutations::new().entry_point(
+            "main",
+            "ps_5_0",
+            vec![
+                Axis::values("QUALITY", &["0", "1", "2"]),
+                Axis::toggle("FOG"),
+            ],
+        );
+        assert_eq!(quality.variants().len(), 6);
+    }
+
+    #[test]
+    fn compiles_and_looks_up_variants() {
+        let library = hello_triangle("5_0")
+            .compile("simple.hlsl", SIMPLE, &Embedded::default(), compile)
+            .unwrap();
+        assert_eq!(library.variant_count(), 6);
+        assert_eq!(library.compile_count(), 6);
+        assert_eq!(library.blob_count(), 6);
+
+        // One set of defines for the whole pipeline
+        let defines = Defines::new()
+            .set("VERTEX_COLOR", "1")
+            .set("PREMULTIPLIED", "1");
+        let vs =
+            String::from_utf8(library.get("VSMain", "vs_5_0", &defines).unwrap().to_vec()).unwrap();
+        assert!(vs.contains("result.color = color;"));
+        let ps =
+            String::from_utf8(library.get("PSMain", "ps_5_0", &defines).unwrap().to_vec()).unwrap();
+        assert!(ps.contains("color.rgb *= color.a;"));
+        assert!(!ps.contains("lerp"));
+
+        assert_eq!(
+            library.get("VSMain", "vs_5_0", &Defines::new().set("VERTEX_COLOR", "2")),
+            None
+        );
+        assert_eq!(library.get("CSMain", "cs_5_0", &defines), None);
+
+        // Embedded form, nothing is compiled when it's read back
+        let bytes = library.to_bytes();
+        let embedded = ShaderLibrary::from_bytes(&bytes).unwrap();
+        assert_eq!(embedded.to_bytes(), bytes);
+        assert_eq!(embedded.compile_count(), 0);
+        assert_eq!(
+            embedded.get("PSMain", "ps_5_0", &defines),
+            library.get("PSMain", "ps_5_0", &defines)
+        );
+        assert_eq!(ShaderLibrary::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(
+            ShaderLibrary::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            None
+        );
+    }
+
+    #[test]
+    fn deduplicates_sources_and_blobs() {
+        let source = "
+#if FAST
+float4 main() : SV_Target { return 0; }
+#else
+float4 main() : SV_Target { return 0; }
+#endif
+#if DEBUG
+#endif
+";
+        let permutations = Permutations::new().entry_point(
+            "main",
+            "ps_5_0",
+            vec![Axis::toggle("FAST"), Axis::toggle("DEBUG")],
+        );
+        let library = permutations
+            .compile("dedup.hlsl", source, &Embedded::default(), compile)
+            .unwrap();
+        assert_eq!(library.variant_count(), 4);
+        // `DEBUG` changes nothing after preprocessing
+        assert_eq!(library.compile_count(), 2);
+        // `FAST` moves the line but compiles the same
+        assert_eq!(library.blob_count(), 1);
+        let fast = Defines::new().set("FAST", "1").set("DEBUG", "1");
+        assert_eq!(
+            library.get("main", "ps_5_0", &fast),
+            library.get("main", "ps_5_0", &Defines::new())
+        );
+    }
+
+    #[test]
+    fn keeps_profiles_apart() {
+        let permutations = Permutations::new()
+            .entry_point("main", "ps_5_0", vec![Axis::toggle("FOG")])
+            .entry_point("main", "ps_6_0", Vec::new());
+        let source = "float4 main() : SV_Target { return 0; }\n";
+        let library = permutations
+            .compile("profiles.hlsl", source, &Embedded::default(), compile)
+            .unwrap();
+        assert_eq!(library.variant_count(), 3);
+
+        let fog = Defines::new().set("FOG", "1");
+        let blob = |profile| String::from_utf8(library.get("main", profile, &fog)?.to_vec()).ok();
+        assert!(blob("ps_5_0").unwrap().starts_with("main ps_5_0\n"));
+        // Defines only select among the profile's own axes
+        assert!(blob("ps_6_0").unwrap().starts_with("main ps_6_0\n"));
+        assert_eq!(blob("ps_5_1"), None);
+
+        let embedded = ShaderLibrary::from_bytes(&library.to_bytes()).unwrap();
+        assert_eq!(
+            embedded.get("main", "ps_6_0", &fog),
+            library.get("main", "ps_6_0", &fog)
+        );
+    }
+
+    #[test]
+    fn reports_failing_variant() {
+        let permutations =
+            Permutations::new().entry_point("main", "ps_5_0", vec![Axis::toggle("BROKEN")]);
+        let source = "#if BROKEN\n#error not yet\n#endif\n";
+        let error = permutations
+            .compile("broken.hlsl", source, &Embedded::default(), compile)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "main (ps_5_0) BROKEN=1: broken.hlsl:2: #error not yet"
+        );
+
+        let error = permutations
+            .compile("ok.hlsl", "", &Embedded::default(), |variant, _| {
+                Err::<Vec<u8>, _>(format!("no compiler for {}", variant.profile))
+            })
+            .unwrap_err();
+        assert_eq!(
+            error,
+            PermutationError::Compile(
+                Box::new(permutations.variants().remove(0)),
+                "no compiler for ps_5_0".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,1103 @@
+//! C-style preprocessor for HLSL sources
+//!
+//! Resolves `#include` through an [`IncludeResolver`], expands object and
+//! function-like macros and evaluates `#if`, `#ifdef` and friends. The output
+//! keeps the line numbers of each file, `#line` directives mark where files
+//! start and resume, so any compiler reports positions in the original files.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Finds included files
+pub trait IncludeResolver {
+    /// Canonical name and contents of `name` included from the file `from`
+    fn resolve(&self, name: &str, from: &str) -> Option<(String, String)>;
+}
+
+/// Includes next to the including file first, then in the directories
+#[derive(Debug, Clone, Default)]
+pub struct SearchPath {
+    pub dirs: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    pub fn new(dirs: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        SearchPath {
+            dirs: dirs.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl IncludeResolver for SearchPath {
+    fn resolve(&self, name: &str, from: &str) -> Option<(String, String)> {
+        let local = PathBuf::from(join(from, name));
+        std::iter::once(local)
+            .chain(self.dirs.iter().map(|dir| dir.join(name)))
+            .find_map(|path| {
+                let source = std::fs::read_to_string(&path).ok()?;
+                Some((path.to_string_lossy().replace('\\', "/"), source))
+            })
+    }
+}
+
+/// Files compiled into the binary, e.g. with `include_str!`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Embedded<'a> {
+    /// Paths relative to a common root and their contents
+    pub files: &'a [(&'a str, &'a str)],
+}
+
+impl IncludeResolver for Embedded<'_> {
+    fn resolve(&self, name: &str, from: &str) -> Option<(String, String)> {
+        let find = |path: &str| {
+            self.files
+                .iter()
+                .find(|(file, _)| *file == path)
+                .map(|(file, source)| (file.to_string(), source.to_string()))
+        };
+        find(&join(from, name)).or_else(|| find(&join("", name)))
+    }
+}
+
+/// `name` relative to the directory of `from`, `.` and `..` resolved
+fn join(from: &str, name: &str) -> String {
+    let mut parts: Vec<&str> = from.split('/').collect();
+    parts.pop();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "." | "" => {}
+            ".." if parts.last().is_some_and(|p| *p != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.retain(|p| !p.is_empty());
+    let joined = parts.join("/");
+    if from.starts_with('/') {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Macro {
+    /// `None` for object-like macros
+    params: Option<Vec<String>>,
+    body: String,
+}
+
+/// Output of [`Preprocessor::run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// Included files in the order first seen, the root file not included
+    pub dependencies: Vec<String>,
+    /// Root file first, then the dependencies
+    files: Vec<String>,
+    /// File index and line of each output line
+    origins: Vec<(usize, u32)>,
+}
+
+impl Preprocessed {
+    /// File and line an output line (1-based) came from
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = *self.origins.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Root file first, then the dependencies
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// What the compiler sees of a line of an input file, macros expanded
+    pub fn line(&self, file: &str, line: u32) -> Option<&str> {
+        self.source
+            .lines()
+            .zip(&self.origins)
+            .find(|(text, &(index, origin))| {
+                origin == line && self.files[index] == file && !text.starts_with("#line ")
+            })
+            .map(|(text, _)| text)
+    }
+}
+
+/// Macro definitions and the resolver for a run
+pub struct Preprocessor<'a> {
+    resolver: &'a dyn IncludeResolver,
+    macros: HashMap<String, Macro>,
+}
+
+/// One `#if` group
+struct Conditional {
+    /// Lines of the current branch are kept
+    active: bool,
+    /// A branch was taken, later `#elif`/`#else` are skipped
+    taken: bool,
+    /// The enclosing group is active
+    outer: bool,
+    seen_else: bool,
+    line: u32,
+}
+
+/// State of a run through the root file and its includes
+struct Run {
+    output: Vec<String>,
+    origins: Vec<(usize, u32)>,
+    files: Vec<String>,
+    stack: Vec<String>,
+    once: Vec<String>,
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(resolver: &'a dyn IncludeResolver) -> Self {
+        Preprocessor {
+            resolver,
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Like `/D NAME=value`, an empty value defines the name as `1`
+    pub fn define(&mut self, name: &str, value: &str) -> &mut Self {
+        let body = if value.is_empty() { "1" } else { value };
+        self.macros.insert(
+            name.to_string(),
+            Macro {
+                params: None,
+                body: body.to_string(),
+            },
+        );
+        self
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// Expansion of an object-like macro, e.g. the root signature a
+    /// `rootsig_1_1` target names with `-E`
+    pub fn value(&self, name: &str) -> Option<String> {
+        match self.macros.get(name)?.params {
+            None => self.expand(name, &mut Vec::new()).ok(),
+            Some(_) => None,
+        }
+    }
+
+    /// Preprocesses `source` of the file `name`, macros defined by it stay
+    /// defined for the next run
+    pub fn run(&mut self, name: &str, source: &str) -> Result<Preprocessed, PreprocessError> {
+        let mut run = Run {
+            output: Vec::new(),
+            origins: Vec::new(),
+            files: Vec::new(),
+            stack: Vec::new(),
+            once: Vec::new(),
+        };
+        self.file(&mut run, name, source)?;
+        let mut source = run.output.join("\n");
+        source.push('\n');
+        Ok(Preprocessed {
+            source,
+            dependencies: run.files[1..].to_vec(),
+            files: run.files,
+            origins: run.origins,
+        })
+    }
+
+    fn file(&mut self, run: &mut Run, name: &str, source: &str) -> Result<(), PreprocessError> {
+        let index = match run.files.iter().position(|file| file == name) {
+            Some(index) => index,
+            None => {
+                run.files.push(name.to_string());
+                run.files.len() - 1
+            }
+        };
+        run.stack.push(name.to_string());
+        let error = |line, message: String| PreprocessError {
+            file: name.to_string(),
+            line,
+            message,
+        };
+        let emit = |run: &mut Run, line: u32, text: String| {
+            run.output.push(text);
+            run.origins.push((index, line));
+        };
+        emit(run, 1, format!("#line 1 \"{}\"", name));
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut lines = logical_lines(&strip_comments(source))
+            .into_iter()
+            .peekable();
+        while let Some((line, mut text, mut physical)) = lines.next() {
+            let active = conditionals.last().is_none_or(|c| c.active);
+            let directive = match text.trim_start().strip_prefix('#') {
+                Some(directive) => directive.trim(),
+                None => {
+                    // Calls take their arguments from the following lines
+                    // too, the joined text is emitted on the first line
+                    while let Some(name) = self.open_call(&text).filter(|_| active) {
+                        match lines.next_if(|(_, next, _)| !next.trim_start().starts_with('#')) {
+                            Some((_, next, count)) => {
+                                text.push(' ');
+                                text.push_str(&next);
+                                physical += count;
+                            }
+                            None => {
+                                return Err(error(line, format!("unterminated call to `{}`", name)))
+                            }
+                        }
+                    }
+                    let text = if active {
+                        self.expand(&text, &mut Vec::new())
+                            .map_err(|m| error(line, m))?
+                    } else {
+                        String::new()
+                    };
+                    emit(run, line, text);
+                    for i in 1..physical {
+                        emit(run, line + i, String::new());
+                    }
+                    continue;
+                }
+            };
+            let (keyword, rest) = split_word(directive);
+            let rest = rest.trim();
+            let mut text = String::new();
+            match keyword {
+                "if" | "ifdef" | "ifndef" => {
+                    let value = active
+                        && match keyword {
+                            "if" => self.evaluate(rest).map_err(|m| error(line, m))?,
+                            "ifdef" => self.is_defined(split_word(rest).0),
+                            _ => !self.is_defined(split_word(rest).0),
+                        };
+                    conditionals.push(Conditional {
+                        active: value,
+                        taken: value,
+                        outer: active,
+                        seen_else: false,
+                        line,
+                    });
+                }
+                "elif" | "else" => {
+                    let conditional = match conditionals.last_mut() {
+                        Some(c) if c.seen_else => {
+                            return Err(error(line, format!("#{} after #else", keyword)))
+                        }
+                        Some(c) => c,
+                        None => return Err(error(line, format!("#{} without #if", keyword))),
+                    };
+                    let value = if !conditional.outer || conditional.taken {
+                        false
+                    } else if keyword == "elif" {
+                        self.evaluate(rest).map_err(|m| error(line, m))?
+                    } else {
+                        true
+                    };
+                    conditional.seen_else = keyword == "else";
+                    conditional.active = value;
+                    conditional.taken |= value;
+                }
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(error(line, "#endif without #if".into()));
+                    }
+                }
+                // Anything else only matters in active groups
+                _ if !active => {}
+                "define" => {
+                    let (name, definition) = parse_define(rest).map_err(|m| error(line, m))?;
+                    self.macros.insert(name, definition);
+                }
+                "undef" => {
+                    self.macros.remove(split_word(rest).0);
+                }
+                "include" => {
+                    let target = include_name(rest)
+                        .ok_or_else(|| error(line, format!("bad #include {}", rest)))?;
+                    let (resolved, contents) = self
+                        .resolver
+                        .resolve(target, name)
+                        .ok_or_else(|| error(line, format!("can't find include {:?}", target)))?;
+                    if run.stack.contains(&resolved) {
+                        return Err(error(line, format!("{} includes itself", resolved)));
+                    }
+                    if !run.once.contains(&resolved) {
+                        self.file(run, &resolved, &contents)?;
+                        text = format!("#line {} \"{}\"", line + 1, name);
+                    }
+                }
+                "pragma" if rest == "once" => run.once.push(name.to_string()),
+                // Left for the compiler, e.g. `#pragma pack_matrix`
+                "pragma" => text = format!("#{}", directive),
+                "error" => return Err(error(line, format!("#error {}", rest))),
+                "" => {}
+                _ => return Err(error(line, format!("unknown directive #{}", keyword))),
+            }
+            let resumed = !text.is_empty() && text.starts_with("#line");
+            emit(run, if resumed { line + 1 } else { line }, text);
+            for i in 1..physical {
+                emit(run, line + i, String::new());
+            }
+        }
+        if let Some(conditional) = conditionals.last() {
+            return Err(error(conditional.line, "#if without #endif".into()));
+        }
+        run.stack.pop();
+        Ok(())
+    }
+
+    /// Name of a function-like macro whose call `text` leaves open
+    fn open_call<'t>(&self, text: &'t str) -> Option<&'t str> {
+        let tokens = tokenize(text);
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = tokens[i];
+            i += 1;
+            if self.macros.get(token).is_none_or(|m| m.params.is_none()) {
+                continue;
+            }
+            let open = tokens[i..].iter().position(|t| !t.trim().is_empty());
+            if let Some(open) = open.filter(|&open| tokens[i + open] == "(") {
+                match arguments(&tokens[i + open + 1..]) {
+                    Some((_, used)) => i += open + 1 + used,
+                    None => return Some(token),
+                }
+            }
+        }
+        None
+    }
+
+    /// Macros of `text` expanded, `disabled` ones are being expanded already
+    fn expand(&self, text: &str, disabled: &mut Vec<String>) -> Result<String, String> {
+        let tokens = tokenize(text);
+        let mut out = String::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = tokens[i];
+            i += 1;
+            let definition = match self.macros.get(token) {
+                Some(definition) if !disabled.iter().any(|d| d == token) => definition,
+                _ => {
+                    out.push_str(token);
+                    continue;
+                }
+            };
+            let body = match &definition.params {
+                None => definition.body.clone(),
+                Some(params) => {
+                    // Not an invocation without `(`, the name stays
+                    let open = tokens[i..].iter().position(|t| !t.trim().is_empty());
+                    let args = match open {
+                        Some(open) if tokens[i + open] == "(" => {
+                            match arguments(&tokens[i + open + 1..]) {
+                                Some((args, used)) => {
+                                    i += open + 1 + used;
+                                    args
+                                }
+                                None => {
+                                    out.push_str(token);
+                                    continue;
+                                }
+                            }
+                        }
+                        _ => {
+                            out.push_str(token);
+                            continue;
+                        }
+                    };
+                    // `F()` passes one empty argument
+                    let given = if args.is_empty() && params.len() == 1 {
+                        1
+                    } else {
+                        args.len()
+                    };
+                    if given != params.len() {
+                        return Err(format!(
+                            "`{}` takes {} argument{}, given {}",
+                            token,
+                            params.len(),
+                            if params.len() == 1 { "" } else { "s" },
+                            given
+                        ));
+                    }
+                    self.substitute(params, &definition.body, &args, disabled)?
+                }
+            };
+            disabled.push(token.to_string());
+            out.push_str(&self.expand(&body, disabled)?);
+            disabled.pop();
+        }
+        Ok(out)
+    }
+
+    /// Body with the parameters replaced, `#` stringifies and `##` pastes
+    fn substitute(
+        &self,
+        params: &[String],
+        body: &str,
+        args: &[String],
+        disabled: &mut Vec<String>,
+    ) -> Result<String, String> {
+        let tokens = tokenize(body);
+        let arg = |token: &str| {
+            params
+                .iter()
+                .position(|p| p == token)
+                .map(|i| args.get(i).map_or("", |a| a.trim()))
+        };
+        // Index of the first token from `i` that isn't whitespace
+        let skip_space = |mut i: usize| {
+            while tokens.get(i).is_some_and(|t| t.trim().is_empty()) {
+                i += 1;
+            }
+            i
+        };
+        let mut out = String::new();
+        let mut paste = false;
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = tokens[i];
+            let pastes = |i: usize| tokens.get(i) == Some(&"#") && tokens.get(i + 1) == Some(&"#");
+            if pastes(i) {
+                out.truncate(out.trim_end().len());
+                paste = true;
+                i = skip_space(i + 2);
+                continue;
+            }
+            if token == "#" {
+                let next = skip_space(i + 1);
+                if let Some(value) = tokens.get(next).and_then(|t| arg(t)) {
+                    // Whitespace between tokens becomes one space, as in C
+                    let spaced: String = tokenize(value)
+                        .into_iter()
+                        .map(|t| if t.trim().is_empty() { " " } else { t })
+                        .collect();
+                    out.push_str(&format!("{:?}", spaced));
+                    i = next + 1;
+                    continue;
+                }
+            }
+            if token.trim().is_empty() {
+                out.push_str(token);
+                i += 1;
+                continue;
+            }
+            match arg(token) {
+                // Arguments next to `##` aren't expanded first
+                Some(value) if paste || pastes(skip_space(i + 1)) => out.push_str(value),
+                Some(value) => out.push_str(&self.expand(value, disabled)?),
+                None => out.push_str(token),
+            }
+            paste = false;
+            i += 1;
+        }
+        Ok(out)
+    }
+
+    /// Value of an `#if` expression
+    fn evaluate(&self, expression: &str) -> Result<bool, String> {
+        // `defined` is resolved before the macros are expanded
+        let tokens = tokenize(expression);
+        let mut resolved = String::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens[i] != "defined" {
+                resolved.push_str(tokens[i]);
+                i += 1;
+                continue;
+            }
+            let rest: Vec<&str> = tokens[i + 1..]
+                .iter()
+                .copied()
+                .filter(|t| !t.trim().is_empty())
+                .collect();
+            let (name, used) = match rest[..] {
+                ["(", name, ")", ..] => (name, 3),
+                ["(", ..] => return Err("missing `)` after `defined(`".into()),
+                [name, ..] => (name, 1),
+                [] => return Err("`defined` without a name".into()),
+            };
+            resolved.push_str(if self.is_defined(name) { " 1 " } else { " 0 " });
+            // Skip past the consumed tokens and whitespace between them
+            let mut seen = 0;
+            i += 1;
+            while seen < used {
+                if !tokens[i].trim().is_empty() {
+                    seen += 1;
+                }
+                i += 1;
+            }
+        }
+        let expanded = self.expand(&resolved, &mut Vec::new())?;
+        let tokens: Vec<&str> = tokenize(&expanded)
+            .into_iter()
+            .filter(|t| !t.trim().is_empty())
+            .collect();
+        let mut parser = Expression { tokens, pos: 0 };
+        let value = parser.conditional()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(value != 0),
+            Some(token) => Err(format!("unexpected `{}` in #if", token)),
+        }
+    }
+}
+
+/// Comments replaced by a space, newlines inside them kept
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                out.push(c);
+                while let Some(d) = chars.next() {
+                    out.push(d);
+                    if d == '\\' {
+                        out.extend(chars.next());
+                    } else if d == c || d == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&d| d != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                out.push(' ');
+                let mut last = ' ';
+                for d in chars.by_ref() {
+                    if d == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && d == '/' {
+                        break;
+                    }
+                    last = d;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Lines with `\` continuations joined: first line number, text and the
+/// number of physical lines
+fn logical_lines(source: &str) -> Vec<(u32, String, u32)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(u32, String, u32)> = None;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (start, mut text, count) = pending.take().unwrap_or((i as u32 + 1, String::new(), 0));
+        match line.strip_suffix('\\') {
+            Some(continued) => {
+                text.push_str(continued);
+                pending = Some((start, text, count + 1));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((start, text, count + 1));
+            }
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// Identifiers, numbers, string and character literals, whitespace runs and single characters,
+/// concatenating them gives back the text
+fn tokenize(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        if word(c) {
+            // Numbers take their suffixes and decimals along
+            while i < bytes.len() && (word(bytes[i]) || (c.is_ascii_digit() && bytes[i] == b'.')) {
+                i += 1;
+            }
+        } else if c.is_ascii_whitespace() {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+        } else if c == b'"' || c == b'\'' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != c {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+        } else {
+            // Whole UTF-8 characters
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+        tokens.push(&text[start..i]);
+    }
+    tokens
+}
+
+/// Arguments of an invocation after its `(` and the tokens they took
+fn arguments(tokens: &[&str]) -> Option<(Vec<String>, usize)> {
+    let mut args = vec![String::new()];
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            ")" if depth == 0 => {
+                if args.len() == 1 && args[0].trim().is_empty() {
+                    args.clear();
+                }
+                return Some((args, i + 1));
+            }
+            "," if depth == 0 => args.push(String::new()),
+            _ => {
+                match *token {
+                    "(" => depth += 1,
+                    ")" => depth -= 1,
+                    _ => {}
+                }
+                args.last_mut()?.push_str(token);
+            }
+        }
+    }
+    None
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+fn include_name(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+    rest.strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .or_else(|| rest.strip_prefix('<').and_then(|r| r.strip_suffix('>')))
+}
+
+/// `NAME body` or `NAME(a, b) body`
+fn parse_define(rest: &str) -> Result<(String, Macro), String> {
+    let (name, after) = split_word(rest);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("#define needs a name".into());
+    }
+    // Parameters only when `(` follows the name directly
+    let (params, body) = match after.strip_prefix('(') {
+        Some(after) => {
+            let close = after.find(')').ok_or("missing `)` in #define")?;
+            let params: Vec<String> = after[..close]
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect();
+            (Some(params), &after[close + 1..])
+        }
+        None => (None, after),
+    };
+    Ok((
+        name.to_string(),
+        Macro {
+            params,
+            body: body.trim().to_string(),
+        },
+    ))
+}
+
+/// Integer expression of `#if`, unknown identifiers are 0
+struct Expression<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Expression<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    /// Two character operators are two tokens
+    fn operator(&mut self) -> Option<&'static str> {
+        const OPERATORS: &[&str] = &[
+            "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*",
+            "/", "%",
+        ];
+        let first = self.peek()?;
+        let second = self.tokens.get(self.pos + 1).copied().unwrap_or("");
+        let two = format!("{}{}", first, second);
+        OPERATORS
+            .iter()
+            .find(|op| op.len() == 2 && **op == two)
+            .or_else(|| OPERATORS.iter().find(|op| op.len() == 1 && **op == first))
+            .copied()
+    }
+
+    fn conditional(&mut self) -> Result<i64, String> {
+        let condition = self.binary(0)?;
+        if self.peek() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.conditional()?;
+        if self.peek() != Some(":") {
+            return Err("expected `:` in #if".into());
+        }
+        self.pos += 1;
+        let otherwise = self.conditional()?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.operator() {
+            let precedence = match op {
+                "||" => 1,
+                "&&" => 2,
+                "|" => 3,
+                "^" => 4,
+                "&" => 5,
+                "==" | "!=" => 6,
+                "<" | ">" | "<=" | ">=" => 7,
+                "<<" | ">>" => 8,
+                "+" | "-" => 9,
+                _ => 10,
+            };
+            if precedence < min_precedence.max(1) {
+                break;
+            }
+            self.pos += op.len();
+            let rhs = self.binary(precedence + 1)?;
+            let bool = |b: bool| b as i64;
+            lhs = match op {
+                "||" => bool(lhs != 0 || rhs != 0),
+                "&&" => bool(lhs != 0 && rhs != 0),
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => bool(lhs == rhs),
+                "!=" => bool(lhs != rhs),
+                "<" => bool(lhs < rhs),
+                ">" => bool(lhs > rhs),
+                "<=" => bool(lhs <= rhs),
+                ">=" => bool(lhs >= rhs),
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero in #if".into()),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.peek().ok_or("expected a value in #if")?;
+        self.pos += 1;
+        match token {
+            "!" => Ok((self.unary()? == 0) as i64),
+            "~" => Ok(!self.unary()?),
+            "-" => Ok(self.unary()?.wrapping_neg()),
+            "+" => self.unary(),
+            "(" => {
+                let value = self.conditional()?;
+                if self.peek() != Some(")") {
+                    return Err("expected `)` in #if".into());
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                let digits = token.trim_end_matches(['u', 'U', 'l', 'L']);
+                let value = if let Some(hex) = digits
+                    .strip_prefix("0x")
+                    .or_else(|| digits.strip_prefix("0X"))
+                {
+                    i64::from_str_radix(hex, 16)
+                } else if digits.len() > 1 && digits.starts_with('0') {
+                    i64::from_str_radix(&digits[1..], 8)
+                } else {
+                    digits.parse()
+                };
+                value.map_err(|_| format!("bad number `{}` in #if", token))
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => Ok(0),
+            _ => Err(format!("unexpected `{}` in #if", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMON: &str =
+        "#pragma once\n#define PI 3.14159\nfloat Square(float x) { return x * x; }\n";
+    const LIGHTING: &str = "#pragma once\n#include \"../common.hlsli\"\nfloat3 Lambert(float3 n, float3 l) { return max(dot(n, l), 0) / PI; }\n";
+
+    fn files() -> Embedded<'static> {
+        Embedded {
+            files: &[
+                ("common.hlsli", COMMON),
+                ("lighting/lambert.hlsli", LIGHTING),
+            ],
+        }
+    }
+
+    fn lines(source: &str) -> Vec<&str> {
+        source.lines().collect()
+    }
+
+    #[test]
+    fn includes_once_with_line_markers() {
+        let source = "#include \"common.hlsli\"\n#include \"lighting/lambert.hlsli\"\nfloat4 main() : SV_Target { return PI; }\n";
+        let resolver = files();
+        let output = Preprocessor::new(&resolver)
+            .run("main.hlsl", source)
+            .unwrap();
+        assert_eq!(
+            lines(&output.source),
+            [
+                "#line 1 \"main.hlsl\"",
+                "#line 1 \"common.hlsli\"",
+                "",
+                "",
+                "float Square(float x) { return x * x; }",
+                "#line 2 \"main.hlsl\"",
+                "#line 1 \"lighting/lambert.hlsli\"",
+                "",
+                // Already included once
+                "",
+                "float3 Lambert(float3 n, float3 l) { return max(dot(n, l), 0) / 3.14159; }",
+                "#line 3 \"main.hlsl\"",
+                "float4 main() : SV_Target { return 3.14159; }",
+            ]
+        );
+        assert_eq!(
+            output.dependencies,
+            ["common.hlsli", "lighting/lambert.hlsli"]
+        );
+        assert_eq!(output.origin(5), Some(("common.hlsli", 3)));
+        assert_eq!(output.origin(10), Some(("lighting/lambert.hlsli", 3)));
+        assert_eq!(output.origin(12), Some(("main.hlsl", 3)));
+        assert_eq!(output.origin(13), None);
+    }
+
+    #[test]
+    fn expands_macros() {
+        let source = r#"
+#define SCALE 2
+#define MUL(a, b) ((a) * (b))
+#define NAME(x) #x
+#define EMPTY(x) [x]
+#define GLUE(a, b) a##b
+#define TWICE(x) MUL(x, SCALE)
+#define SELF SELF + 1
+float a = TWICE(MUL(1, 2)); float b = NAME(hello world);
+float GLUE(tex, coord) = SELF; int MUL = 3; // MUL without arguments
+float c = MUL( (1, 2), \
+    3);
+float d = NAME(  a  "q"   b ) EMPTY();
+"#;
+        let resolver = files();
+        let mut preprocessor = Preprocessor::new(&resolver);
+        let output = preprocessor.run("macros.hlsl", source).unwrap();
+        let lines = lines(&output.source);
+        assert_eq!(
+            lines[9],
+            "float a = ((((1) * (2))) * (2)); float b = \"hello world\";"
+        );
+        assert_eq!(lines[10], "float texcoord = SELF + 1; int MUL = 3; ");
+        assert_eq!(lines[11], "float c = (((1, 2)) * (3));");
+        // The continuation line stays so later lines keep their numbers
+        assert_eq!(lines[12], "");
+        assert_eq!(output.origin(13), Some(("macros.hlsl", 12)));
+        assert_eq!(lines[13], r#"float d = "a \"q\" b" [];"#);
+        assert!(preprocessor.is_defined("TWICE"));
+    }
+
+    #[test]
+    fn collects_arguments_across_lines() {
+        let source =
+            "#define F(a, b) a + b\n#define C(c) c\nx = F(\n1,\n2) * F(3, 4);\ny = C('(');\nz;\n";
+        let resolver = files();
+        let output = Preprocessor::new(&resolver)
+            .run("lines.hlsl", source)
+            .unwrap();
+        let lines = lines(&output.source);
+        assert_eq!(lines[3], "x = 1 + 2 * 3 + 4;");
+        assert_eq!(lines[4..6], ["", ""]);
+        assert_eq!(lines[6], "y = '(';");
+        assert_eq!(output.origin(8), Some(("lines.hlsl", 7)));
+    }
+
+    #[test]
+    fn evaluates_conditionals() {
+        let source = "
+#if defined(USE_SHADOWS) && SHADOW_CASCADES >= 2
+shadows
+#elif defined USE_SHADOWS
+single
+#else
+none
+#endif
+#ifdef USE_FOG
+fog
+#endif
+#if 0
+#unknown directives are skipped
+#endif
+#if (1 << 3) == 0x8 && !(7 % 4 - 3) && (0 ? 0 : 012 == 10)
+math
+#endif
+";
+        let resolver = files();
+        let run = |defines: &[(&str, &str)]| {
+            let mut preprocessor = Preprocessor::new(&resolver);
+            for (name, value) in defines {
+                preprocessor.define(name, value);
+            }
+            let output = preprocessor.run("if.hlsl", source).unwrap();
+            output
+                .source
+                .lines()
+                .skip(1)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(&[]), ["none", "math"]);
+        assert_eq!(run(&[("USE_SHADOWS", "")]), ["single", "math"]);
+        assert_eq!(
+            run(&[
+                ("USE_SHADOWS", ""),
+                ("SHADOW_CASCADES", "4"),
+                ("USE_FOG", "")
+            ]),
+            ["shadows", "fog", "math"]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        let resolver = files();
+        let error = |source: &str| {
+            Preprocessor::new(&resolver)
+                .run("bad.hlsl", source)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("\n#include \"missing.hlsli\""),
+            "bad.hlsl:2: can't find include \"missing.hlsli\""
+        );
+        assert_eq!(error("#ifdef A\n\n"), "bad.hlsl:1: #if without #endif");
+        assert_eq!(error("#endif"), "bad.hlsl:1: #endif without #if");
+        assert_eq!(
+            error("#if 1\n#else\n#else\n#endif"),
+            "bad.hlsl:3: #else after #else"
+        );
+        assert_eq!(
+            error("/* a\n */ #error no way"),
+            "bad.hlsl:2: #error no way"
+        );
+        assert_eq!(
+            error("#if 1 +\n#endif"),
+            "bad.hlsl:1: expected a value in #if"
+        );
+        assert_eq!(
+            error("#if defined(A\n#endif"),
+            "bad.hlsl:1: missing `)` after `defined(`"
+        );
+        assert_eq!(
+            error("#define F(a, b) a b\n\nF(1)"),
+            "bad.hlsl:3: `F` takes 2 arguments, given 1"
+        );
+        assert_eq!(
+            error("#define G(a) a\n#if G(1, 2)\n#endif"),
+            "bad.hlsl:2: `G` takes 1 argument, given 2"
+        );
+        assert_eq!(
+            error("#define F(a, b) a b\nF(\n1,\n2,\n3)"),
+            "bad.hlsl:2: `F` takes 2 arguments, given 3"
+        );
+        assert_eq!(
+            error("#define F(a) a\nF(1,\n#define X\n)"),
+            "bad.hlsl:2: unterminated call to `F`"
+        );
+        assert_eq!(
+            error("#define F(a) a\nF(1"),
+            "bad.hlsl:2: unterminated call to `F`"
+        );
+
+        let recursive = Embedded {
+            files: &[
+                ("a.hlsli", "#include \"b.hlsli\""),
+                ("b.hlsli", "\n#include \"a.hlsli\""),
+            ],
+        };
+        let error = Preprocessor::new(&recursive)
+            .run("main.hlsl", "#include \"a.hlsli\"")
+            .unwrap_err();
+        assert_eq!(
+            error,
+            PreprocessError {
+                file: "b.hlsli".into(),
+                line: 2,
+                message: "a.hlsli includes itself".into()
+            }
+        );
+    }
+
+    #[test]
+    fn searches_directories() {
+        let dir = std::env::temp_dir().join(format!("preprocess-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+        std::fs::write(dir.join("include/common.hlsli"), COMMON).unwrap();
+        std::fs::write(dir.join("local.hlsli"), "#define LOCAL 1\n").unwrap();
+
+        let resolver = SearchPath::new([dir.join("include")]);
+        let root = dir.join("main.hlsl").to_string_lossy().replace('\\', "/");
+        let source = "#include \"local.hlsli\"\n#include <common.hlsli>\nfloat x = LOCAL * PI;\n";
+        let output = Preprocessor::new(&resolver).run(&root, source);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let output = output.unwrap();
+        assert_eq!(output.dependencies.len(), 2);
+        assert!(output.dependencies[0].ends_with("/local.hlsli"));
+        assert!(output.dependencies[1].ends_with("include/common.hlsli"));
+        assert!(output.source.contains("float x = 1 * 3.14159;"));
+    }
+
+    #[test]
+    fn joins_relative_paths() {
+        assert_eq!(join("a/b/main.hlsl", "../c.hlsli"), "a/c.hlsli");
+        assert_eq!(join("main.hlsl", "./x/y.hlsli"), "x/y.hlsli");
+        assert_eq!(join("/abs/main.hlsl", "y.hlsli"), "/abs/y.hlsli");
+        assert_eq!(join("main.hlsl", "../up.hlsli"), "../up.hlsli");
+    }
+}
@@ -0,0 +1,46 @@
+//! Input and output signatures of shader entry points
+use std::fmt;
+
+/// `D3D_REGISTER_COMPONENT_TYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ComponentType {
+    Unknown = 0,
+    Uint32 = 1,
+    Sint32 = 2,
+    Float32 = 3,
+}
+
+/// `D3D12_SIGNATURE_PARAMETER_DESC` without the stream and precision
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignatureElement {
+    /// Upper case, e.g. `"TEXCOORD"`
+    pub semantic_name: String,
+    pub semantic_index: u32,
+    pub register: u32,
+    pub component_type: ComponentType,
+    /// Components of the register, `0b1111` for `xyzw`
+    pub mask: u8,
+}
+
+impl SignatureElement {
+    /// `SV_` semantics are produced or consumed by the pipeline, not by
+    /// the input assembler or render targets
+    pub fn is_system_value(&self) -> bool {
+        self.semantic_name.starts_with("SV_")
+    }
+
+    pub fn component_count(&self) -> u32 {
+        self.mask.count_ones()
+    }
+}
+
+impl fmt::Display for SignatureElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.semantic_index == 0 {
+            write!(f, "{}", self.semantic_name)
+        } else {
+            write!(f, "{}{}", self.semantic_name, self.semantic_index)
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! CPU backend rasterizing into images, runs without Windows or a GPU
+//!
+//! The pipeline is fixed to `simple.hlsl`: `VSMain` passes `POSITION` and
+//! `COLOR` through and `PSMain` returns the color. Commands are recorded and
+//! run on `execute_command_list`, so errors in the recorded commands are
+//! reported there. Only triangle lists and strips are drawn, and barriers are
+//! accepted but have nothing to do.
+use crate::backend::{Backend, CommandQueueDesc, SwapChainDesc, VertexBufferView};
+use crate::descriptor::{CpuDescriptorHandle, DescriptorHeap, DescriptorHeapDesc};
+use crate::format::Format;
+use crate::image::Image;
+use crate::mock::{self, Call};
+use crate::pipeline::{BlendDesc, PrimitiveTopology, RasterizerDesc};
+use crate::raster::{self, ClipVertex, RasterState};
+use crate::resource::{Barrier, ResourceId};
+use crate::scene::{Rect, Viewport};
+use crate::vertex::{InputClassification, InputElement, Vertex, VertexLayout};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SoftwareError {
+    NoCommandQueue,
+    NoSwapChain,
+    NoCommandList,
+    NoCommandAllocator,
+    /// Reset while recording, or executed before closing
+    ListOpen,
+    /// Closed twice
+    ListClosed,
+    /// Descriptor without a render target view
+    UnknownDescriptor(CpuDescriptorHandle),
+    UnknownResource(ResourceId),
+    /// Vertex fetch outside of every vertex buffer
+    UnknownAddress(u64),
+    /// Vertex element format that is not 32-bit float
+    UnsupportedFormat(Format),
+    NoRenderTarget,
+}
+
+/// Backend drawing with `raster`
+#[derive(Debug, Clone)]
+pub struct SoftwareBackend {
+    /// Input layout of the pipeline state
+    pub input_layout: Vec<InputElement>,
+    pub rasterizer: RasterizerDesc,
+    pub blend: BlendDesc,
+    back_buffers: Vec<Image>,
+    back_buffer: usize,
+    last_presented: Option<usize>,
+    queue: bool,
+    rtvs: HashMap<CpuDescriptorHandle, ResourceId>,
+    buffers: Vec<(u64, Vec<u8>)>,
+    next_address: u64,
+    allocators: usize,
+    list: Option<Vec<Call>>,
+    recording: bool,
+    completed: u64,
+}
+
+impl Default for SoftwareBackend {
+    fn default() -> Self {
+        SoftwareBackend {
+            input_layout: Vertex::LAYOUT.to_vec(),
+            rasterizer: RasterizerDesc::default(),
+            blend: BlendDesc::default(),
+            back_buffers: Vec::new(),
+            back_buffer: 0,
+            last_presented: None,
+            queue: false,
+            rtvs: HashMap::new(),
+            buffers: Vec::new(),
+            next_address: 0x1000,
+            allocators: 0,
+            list: None,
+            recording: false,
+            completed: 0,
+        }
+    }
+}
+
+/// Bound state while executing a command list
+#[derive(Default)]
+struct State {
+    viewports: Vec<Viewport>,
+    scissors: Vec<Rect>,
+    render_targets: Vec<CpuDescriptorHandle>,
+    topology: Option<PrimitiveTopology>,
+    vertex_buffers: HashMap<u32, VertexBufferView>,
+}
+
+impl SoftwareBackend {
+    /// Back buffer of the swap chain
+    pub fn back_buffer(&self, index: usize) -> Option<&Image> {
+        self.back_buffers.get(index)
+    }
+
+    /// Index of the back buffer presented last
+    pub fn last_presented(&self) -> Option<usize> {
+        self.last_presented
+    }
+
+    /// Back buffer presented last
+    pub fn presented_image(&self) -> Option<&Image> {
+        self.last_presented.and_then(|i| self.back_buffer(i))
+    }
+
+    fn record(&mut self, call: Call) {
+        if let Some(list) = self.list.as_mut() {
+            if self.recording {
+                list.push(call);
+            }
+        }
+    }
+
+    fn target(&mut self, rtv: CpuDescriptorHandle) -> Result<&mut Image, SoftwareError> {
+        let resource = *self
+            .rtvs
+            .get(&rtv)
+            .ok_or(SoftwareError::UnknownDescriptor(rtv))?;
+        self.back_buffers
+            .get_mut(resource.0 as usize)
+            .ok_or(SoftwareError::UnknownResource(resource))
+    }
+
+    fn read(&self, address: u64, len: usize) -> Result<&[u8], SoftwareError> {
+        self.buffers
+            .iter()
+            .find(|(start, data)| {
+                address >= *start && address + len as u64 <= *start + data.len() as u64
+            })
+            .map(|(start, data)| {
+                let offset = (address - start) as usize;
+                &data[offset..offset + len]
+            })
+            .ok_or(SoftwareError::UnknownAddress(address))
+    }
+
+    /// Input assembler and `VSMain`
+    fn fetch(
+        &self,
+        state: &State,
+        vertex: u32,
+        instance: u32,
+    ) -> Result<ClipVertex, SoftwareError> {
+        let mut out = ClipVertex {
+            position: [0.0, 0.0, 0.0, 1.0],
+            color: [0.0, 0.0, 0.0, 1.0],
+        };
+        for element in &self.input_layout {
+            let target = match (element.semantic_name, element.semantic_index) {
+                ("POSITION", 0) => &mut out.position,
+                ("COLOR", 0) => &mut out.color,
+                _ => continue,
+            };
+            let component_size = match element.format {
+                Format::R32G32B32A32Float
+                | Format::R32G32B32Float
+                | Format::R32G32Float
+                | Format::R32Float => 4,
+                format => return Err(SoftwareError::UnsupportedFormat(format)),
+            };
+            let view = state
+                .vertex_buffers
+                .get(&element.input_slot)
+                .ok_or(SoftwareError::UnknownAddress(0))?;
+            let index = match element.input_slot_class {
+                InputClassification::PerVertexData => vertex,
+                InputClassification::PerInstanceData => {
+                    instance / element.instance_data_step_rate.max(1)
+                }
+            };
+            let address = view.buffer_location
+                + index as u64 * view.stride_in_bytes as u64
+                + element.aligned_byte_offset as u64;
+            let count = element.format.component_count() as usize;
+            let bytes = self.read(address, count * component_size)?;
+            for (c, value) in bytes.chunks_exact(component_size).enumerate() {
+                target[c] = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+            }
+        }
+        Ok(out)
+    }
+
+    fn draw(
+        &mut self,
+        state: &State,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) -> Result<(), SoftwareError> {
+        let rtv = *state
+            .render_targets
+            .first()
+            .ok_or(SoftwareError::NoRenderTarget)?;
+        let (viewport, scissor) = match (state.viewports.first(), state.scissors.first()) {
+            (Some(viewport), Some(scissor)) => (*viewport, *scissor),
+            // Without a viewport and scissor nothing is visible
+            _ => return Ok(()),
+        };
+
+        let mut triangles = Vec::new();
+        for instance in start_instance..start_instance + instance_count {
+            let mut vertices = Vec::with_capacity(vertex_count as usize);
+            for vertex in start_vertex..start_vertex + vertex_count {
+                vertices.push(self.fetch(state, vertex, instance)?);
+            }
+            match state.topology {
+                Some(PrimitiveTopology::TriangleList) => {
+                    for t in vertices.chunks_exact(3) {
+                        triangles.push([t[0], t[1], t[2]]);
+                    }
+                }
+                Some(PrimitiveTopology::TriangleStrip) => {
+                    for (i, t) in vertices.windows(3).enumerate() {
+                        // Every other triangle is flipped to keep the winding
+                        if i % 2 == 0 {
+                            triangles.push([t[0], t[1], t[2]]);
+                        } else {
+                            triangles.push([t[1], t[0], t[2]]);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let rasterizer = self.rasterizer;
+        let blend = self.blend.render_target[0];
+        let state = RasterState {
+            viewport,
+            scissor,
+            rasterizer: &rasterizer,
+            blend: &blend,
+        };
+        let target = self.target(rtv)?;
+        for triangle in &triangles {
+            raster::draw_triangle(target, &state, triangle);
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, calls: &[Call]) -> Result<(), SoftwareError> {
+        let mut state = State::default();
+        for call in calls {
+            match call {
+                Call::SetViewports(viewports) => state.viewports = viewports.clone(),
+                Call::SetScissorRects(rects) => state.scissors = rects.clone(),
+                Call::SetRenderTargets(rtvs) => state.render_targets = rtvs.clone(),
+                Call::SetPrimitiveTopology(topology) => state.topology = Some(*topology),
+                Call::SetVertexBuffers { start_slot, views } => {
+                    for (i, view) in views.iter().enumerate() {
+                        state.vertex_buffers.insert(start_slot + i as u32, *view);
+                    }
+                }
+                Call::ClearRenderTargetView { rtv, color } => self.target(*rtv)?.fill(*color),
+                Call::DrawInstanced {
+                    vertex_count,
+                    instance_count,
+                    start_vertex,
+                    start_instance,
+                } => self.draw(
+                    &state,
+                    *vertex_count,
+                    *instance_count,
+                    *start_vertex,
+                    *start_instance,
+                )?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for SoftwareBackend {
+    type Error = SoftwareError;
+
+    fn create_command_queue(&mut self, _desc: &CommandQueueDesc) -> Result<(), Self::Error> {
+        self.queue = true;
+        Ok(())
+    }
+
+    fn create_swap_chain(&mut self, desc: &SwapChainDesc) -> Result<(), Self::Error> {
+        if !self.queue {
+            return Err(SoftwareError::NoCommandQueue);
+        }
+        if desc.format != Image::FORMAT {
+            return Err(SoftwareError::UnsupportedFormat(desc.format));
+        }
+        self.back_buffers = (0..desc.buffer_count)
+            .map(|_| Image::new(desc.width, desc.height))
+            .collect();
+        self.back_buffer = 0;
+        Ok(())
+    }
+
+    fn create_descriptor_heap(
+        &mut self,
+        desc: &DescriptorHeapDesc,
+    ) -> Result<DescriptorHeap, Self::Error> {
+        let increment = 32;
+        let start = self.next_address;
+        self.next_address += (increment * desc.num_descriptors as u64 + 0xffff) & !0xffff;
+        Ok(DescriptorHeap {
+            desc: *desc,
+            start: CpuDescriptorHandle(start as _),
+            increment: increment as _,
+        })
+    }
+
+    fn create_render_target_view(
+        &mut self,
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    ) -> Result<(), Self::Error> {
+        if resource.0 as usize >= self.back_buffers.len() {
+            return Err(SoftwareError::UnknownResource(resource));
+        }
+        self.rtvs.insert(handle, resource);
+        Ok(())
+    }
+
+    /// Commands run on execute, so allocators are free right after
+    fn create_command_allocators(&mut self, count: usize) -> Result<(), Self::Error> {
+        self.allocators = count;
+        Ok(())
+    }
+
+    fn create_command_list(&mut self) -> Result<(), Self::Error> {
+        if self.allocators == 0 {
+            return Err(SoftwareError::NoCommandAllocator);
+        }
+        self.list = Some(Vec::new());
+        self.recording = false;
+        Ok(())
+    }
+
+    fn create_vertex_buffer(
+        &mut self,
+        data: &[u8],
+        stride: u32,
+    ) -> Result<VertexBufferView, Self::Error> {
+        let address = self.next_address;
+        self.next_address += (data.len() as u64 + 0xffff) & !0xffff;
+        self.buffers.push((address, data.to_vec()));
+        Ok(VertexBufferView {
+            buffer_location: address,
+            size_in_bytes: data.len() as _,
+            stride_in_bytes: stride,
+        })
+    }
+
+    /// Draws read the buffer when executed, like the GPU would
+    fn create_upload_buffer(&mut self, size: u64) -> Result<u64, Self::Error> {
+        let address = self.next_address;
+        self.next_address += (size + 0xffff) & !0xffff;
+        self.buffers.push((address, vec![0; size as usize]));
+        Ok(address)
+    }
+
+    fn upload_memory(&mut self, address: u64) -> Option<&mut [u8]> {
+        mock::upload_memory(&mut self.buffers, address)
+    }
+
+    fn reset_command_list(&mut self, allocator: usize) -> Result<(), Self::Error> {
+        if allocator >= self.allocators {
+            return Err(SoftwareError::NoCommandAllocator);
+        }
+        match self.list.as_mut() {
+            None => Err(SoftwareError::NoCommandList),
+            Some(_) if self.recording => Err(SoftwareError::ListOpen),
+            Some(list) => {
+                list.clear();
+                self.recording = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn set_graphics_root_signature(&mut self) {
+        self.record(Call::SetGraphicsRootSignature);
+    }
+
+    fn set_viewports(&mut self, viewports: &[Viewport]) {
+        self.record(Call::SetViewports(viewports.to_vec()));
+    }
+
+    fn set_scissor_rects(&mut self, rects: &[Rect]) {
+        self.record(Call::SetScissorRects(rects.to_vec()));
+    }
+
+    fn resource_barrier(&mut self, barriers: &[Barrier]) {
+        self.record(Call::ResourceBarrier(barriers.to_vec()));
+    }
+
+    fn set_render_targets(&mut self, rtvs: &[CpuDescriptorHandle]) {
+        self.record(Call::SetRenderTargets(rtvs.to_vec()));
+    }
+
+    fn clear_render_target_view(&mut self, rtv: CpuDescriptorHandle, color: [f32; 4]) {
+        self.record(Call::ClearRenderTargetView { rtv, color });
+    }
+
+    fn set_primitive_topology(&mut self, topology: PrimitiveTopology) {
+        self.record(Call::SetPrimitiveTopology(topology));
+    }
+
+    fn set_vertex_buffers(&mut self, start_slot: u32, views: &[VertexBufferView]) {
+        self.record(Call::SetVertexBuffers {
+            start_slot,
+            views: views.to_vec(),
+        });
+    }
+
+    fn draw_instanced(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) {
+        self.record(Call::DrawInstanced {
+            vertex_count,
+            instance_count,
+            start_vertex,
+            start_instance,
+        });
+    }
+
+    fn close_command_list(&mut self) -> Result<(), Self::Error> {
+        match self.list {
+            None => Err(SoftwareError::NoCommandList),
+            Some(_) if !self.recording => Err(SoftwareError::ListClosed),
+            Some(_) => {
+                self.recording = false;
+                Ok(())
+            }
+        }
+    }
+
+    fn execute_command_list(&mut self) -> Result<(), Self::Error> {
+        if self.recording {
+            return Err(SoftwareError::ListOpen);
+        }
+        let calls = self.list.take().ok_or(SoftwareError::NoCommandList)?;
+        let result = self.run(&calls);
+        self.list = Some(calls);
+        result
+    }
+
+    /// Work is done by the time it is executed, so values complete at once
+    fn signal(&mut self, value: u64) -> Result<(), Self::Error> {
+        self.completed = self.completed.max(value);
+        Ok(())
+    }
+
+    fn completed_value(&self) -> u64 {
+        self.completed
+    }
+
+    fn wait_for_value(&mut self, value: u64) -> Result<(), Self::Error> {
+        self.completed = self.completed.max(value);
+        Ok(())
+    }
+
+    fn current_back_buffer_index(&self) -> usize {
+        self.back_buffer
+    }
+
+    fn present(&mut self, _sync_interval: u32, _flags: u32) -> Result<(), Self::Error> {
+        if self.back_buffers.is_empty() {
+            return Err(SoftwareError::NoSwapChain);
+        }
+        self.last_presented = Some(self.back_buffer);
+        self.back_buffer = (self.back_buffer + 1) % self.back_buffers.len();
+        Ok(())
+    }
+
+    fn read_back_buffer(&mut self, index: usize) -> Result<Image, Self::Error> {
+        self.back_buffer(index)
+            .cloned()
+            .ok_or(SoftwareError::UnknownResource(ResourceId::back_buffer(
+                index,
+            )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Renderer;
+    use crate::scene::Scene;
+
+    #[test]
+    fn renders_hello_triangle() {
+        let mut renderer =
+            Renderer::new(SoftwareBackend::default(), Scene::hello_triangle(16, 16)).unwrap();
+        renderer.render().unwrap();
+        let backend = renderer.backend();
+        assert_eq!(backend.last_presented(), Some(0));
+        let image = backend.presented_image().unwrap();
+
+        // Clear color in the corners
+        let clear = [102, 51, 255, 128];
+        assert_eq!(image.pixel(0, 0), clear);
+        assert_eq!(image.pixel(15, 0), clear);
+
+        // Bottom corners are inside, near the blue and green vertices
+        let [b, g, r, _] = image.pixel(0, 15);
+        assert!(b > 200 && g < 50 && r < 50);
+        let [b, g, r, _] = image.pixel(15, 15);
+        assert!(g > 200 && b < 50 && r < 50);
+
+        // The triangle covers the bottom middle with a mix of all three colors
+        let [b, g, r, a] = image.pixel(8, 12);
+        assert!(r > 0 && g > 0 && b > 0);
+        assert!(a > 128 && a < 255);
+        // The red top vertex dominates near the top
+        let [b, g, r, _] = image.pixel(8, 2);
+        assert!(r > 200 && g < 50 && b < 50);
+
+        // The other back buffer is untouched
+        assert_eq!(backend.back_buffer(1).unwrap().pixel(0, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reports_unknown_descriptor() {
+        let mut backend = SoftwareBackend::default();
+        backend
+            .create_command_queue(&CommandQueueDesc {
+                list_type: crate::backend::CommandListType::Direct,
+                priority: crate::backend::CommandQueuePriority::Normal,
+            })
+            .unwrap();
+        backend.create_command_allocators(1).unwrap();
+        backend.create_command_list().unwrap();
+        backend.reset_command_list(0).unwrap();
+        backend.clear_render_target_view(CpuDescriptorHandle(1), [0.0; 4]);
+        backend.close_command_list().unwrap();
+        assert_eq!(
+            backend.execute_command_list(),
+            Err(SoftwareError::UnknownDescriptor(CpuDescriptorHandle(1)))
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Resource state tracking and transition barrier generation
+//!
+//! Resources are registered with their initial state, after that uses are
+//! declared with `transition` and the barriers are taken in one batch with
+//! `flush` right before the commands that need the new states.
+use crate::resource::{Barrier, ResourceId, ResourceStates, ALL_SUBRESOURCES};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    UnknownResource(ResourceId),
+    SubresourceOutOfRange {
+        resource: ResourceId,
+        subresource: u32,
+        count: u32,
+    },
+    /// Write state combined with other states
+    InvalidState {
+        resource: ResourceId,
+        state: ResourceStates,
+    },
+    /// Barrier whose before state is not the tracked state
+    Mismatch {
+        resource: ResourceId,
+        subresource: u32,
+        tracked: ResourceStates,
+        before: ResourceStates,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::UnknownResource(resource) => {
+                write!(f, "resource {} is not tracked", resource.0)
+            }
+            StateError::SubresourceOutOfRange {
+                resource,
+                subresource,
+                count,
+            } => write!(
+                f,
+                "subresource {} of resource {} is out of range, it has {}",
+                subresource, resource.0, count
+            ),
+            StateError::InvalidState { resource, state } => write!(
+                f,
+                "state {:#x} of resource {} combines a write state",
+                state.0, resource.0
+            ),
+            StateError::Mismatch {
+                resource,
+                subresource,
+                tracked,
+                before,
+            } => write!(
+                f,
+                "barrier of resource {} subresource {} is from {:#x} but the state is {:#x}",
+                resource.0, subresource, before.0, tracked.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Current state of each subresource and the barriers waiting to be recorded
+#[derive(Debug, Default, Clone)]
+pub struct StateTracker {
+    resources: HashMap<ResourceId, Vec<ResourceStates>>,
+    pending: Vec<Barrier>,
+}
+
+impl StateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a resource, replaces an earlier registration
+    pub fn register(&mut self, resource: ResourceId, subresources: u32, state: ResourceStates) {
+        self.resources
+            .insert(resource, vec![state; subresources.max(1) as usize]);
+    }
+
+    /// Stop tracking, e.g. when the resource is released
+    pub fn unregister(&mut self, resource: ResourceId) {
+        self.resources.remove(&resource);
+        self.pending.retain(|b| match b {
+            Barrier::Transition { resource: r, .. } => *r != resource,
+        });
+    }
+
+    /// Tracked state of a subresource, including pending transitions
+    pub fn state(&self, resource: ResourceId, subresource: u32) -> Option<ResourceStates> {
+        self.resources
+            .get(&resource)?
+            .get(subresource as usize)
+            .copied()
+    }
+
+    /// Use a subresource, or all with `ALL_SUBRESOURCES`, in a new state
+    ///
+    /// No barrier is needed when a read state is already part of the
+    /// current read states.
+    pub fn transition(
+        &mut self,
+        resource: ResourceId,
+        subresource: u32,
+        after: ResourceStates,
+    ) -> Result<(), StateError> {
+        if !after.is_valid() {
+            return Err(StateError::InvalidState {
+                resource,
+                state: after,
+            });
+        }
+        let states = self
+            .resources
+            .get_mut(&resource)
+            .ok_or(StateError::UnknownResource(resource))?;
+        let mut barriers = Vec::new();
+        if subresource == ALL_SUBRESOURCES {
+            let first = states[0];
+            if states.iter().all(|&s| s == first) {
+                if needs_barrier(first, after) {
+                    barriers.push((ALL_SUBRESOURCES, first, after));
+                    states.iter_mut().for_each(|s| *s = after);
+                }
+            } else {
+                for (i, state) in states.iter_mut().enumerate() {
+                    if needs_barrier(*state, after) {
+                        barriers.push((i as u32, *state, after));
+                        *state = after;
+                    }
+                }
+            }
+        } else {
+            let count = states.len() as u32;
+            let state =
+                states
+                    .get_mut(subresource as usize)
+                    .ok_or(StateError::SubresourceOutOfRange {
+                        resource,
+                        subresource,
+                        count,
+                    })?;
+            if needs_barrier(*state, after) {
+                barriers.push((subresource, *state, after));
+                *state = after;
+            }
+        }
+        for (subresource, before, after) in barriers {
+            self.push(resource, subresource, before, after);
+        }
+        Ok(())
+    }
+
+    /// Add to the batch, a transition continuing a pending one replaces it
+    fn push(
+        &mut self,
+        resource: ResourceId,
+        subresource: u32,
+        before: ResourceStates,
+        after: ResourceStates,
+    ) {
+        let pending = self.pending.iter().position(|b| match *b {
+            Barrier::Transition {
+                resource: r,
+                subresource: s,
+                ..
+            } => r == resource && s == subresource,
+        });
+        if let Some(i) = pending {
+            let Barrier::Transition { before: first, .. } = self.pending[i];
+            if first == after {
+                self.pending.remove(i);
+            } else {
+                self.pending[i] = Barrier::Transition {
+                    resource,
+                    subresource,
+                    before: first,
+                    after,
+                };
+            }
+            return;
+        }
+        self.pending.push(Barrier::Transition {
+            resource,
+            subresource,
+            before,
+            after,
+        });
+    }
+
+    /// Barriers to record before the next command, empties the batch
+    pub fn flush(&mut self) -> Vec<Barrier> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Apply barriers recorded elsewhere, e.g. from a command stream,
+    /// checking that they start from the tracked states
+    pub fn apply(&mut self, barriers: &[Barrier]) -> Result<(), StateError> {
+        for barrier in barriers {
+            let Barrier::Transition {
+                resource,
+                subresource,
+                before,
+                after,
+            } = *barrier;
+            if !after.is_valid() {
+                return Err(StateError::InvalidState {
+                    resource,
+                    state: after,
+                });
+            }
+            let states = self
+                .resources
+                .get_mut(&resource)
+                .ok_or(StateError::UnknownResource(resource))?;
+            let count = states.len() as u32;
+            let range = if subresource == ALL_SUBRESOURCES {
+                0..states.len()
+            } else if subresource < count {
+                subresource as usize..subresource as usize + 1
+            } else {
+                return Err(StateError::SubresourceOutOfRange {
+                    resource,
+                    subresource,
+                    count,
+                });
+            };
+            for i in range {
+                if states[i] != before {
+                    return Err(StateError::Mismatch {
+                        resource,
+                        subresource: i as u32,
+                        tracked: states[i],
+                        before,
+                    });
+                }
+                states[i] = after;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn needs_barrier(current: ResourceStates, after: ResourceStates) -> bool {
+    current != after && !(current.is_read_only() && after.is_read_only() && current.contains(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Renderer;
+    use crate::mock::{Call, MockBackend};
+    use crate::scene::Scene;
+
+    const TEXTURE: ResourceId = ResourceId(7);
+
+    fn tracker() -> StateTracker {
+        let mut tracker = StateTracker::new();
+        tracker.register(TEXTURE, 3, ResourceStates::COMMON);
+        tracker
+    }
+
+    fn barrier(subresource: u32, before: ResourceStates, after: ResourceStates) -> Barrier {
+        Barrier::Transition {
+            resource: TEXTURE,
+            subresource,
+            before,
+            after,
+        }
+    }
+
+    #[test]
+    fn transitions_whole_resource_once() {
+        let mut tracker = tracker();
+        tracker
+            .transition(TEXTURE, ALL_SUBRESOURCES, ResourceStates::COPY_DEST)
+            .unwrap();
+        tracker
+            .transition(TEXTURE, ALL_SUBRESOURCES, ResourceStates::COPY_DEST)
+            .unwrap();
+        assert_eq!(
+            tracker.flush(),
+            [Barrier::transition(
+                TEXTURE,
+                ResourceStates::COMMON,
+                ResourceStates::COPY_DEST
+            )]
+        );
+        assert!(tracker.flush().is_empty());
+    }
+
+    #[test]
+    fn tracks_subresources() {
+        let mut tracker = tracker();
+        tracker
+            .transition(TEXTURE, 1, ResourceStates::RENDER_TARGET)
+            .unwrap();
+        assert_eq!(tracker.state(TEXTURE, 0), Some(ResourceStates::COMMON));
+        assert_eq!(
+            tracker.state(TEXTURE, 1),
+            Some(ResourceStates::RENDER_TARGET)
+        );
+        tracker.flush();
+
+        // Differing subresources get a barrier each
+        tracker
+            .transition(TEXTURE, ALL_SUBRESOURCES, ResourceStates::COMMON)
+            .unwrap();
+        assert_eq!(
+            tracker.flush(),
+            [barrier(
+                1,
+                ResourceStates::RENDER_TARGET,
+                ResourceStates::COMMON
+            )]
+        );
+        tracker
+            .transition(TEXTURE, ALL_SUBRESOURCES, ResourceStates::UNORDERED_ACCESS)
+            .unwrap();
+        assert_eq!(tracker.flush().len(), 1);
+    }
+
+    #[test]
+    fn merges_pending_transitions() {
+        let mut tracker = tracker();
+        tracker
+            .transition(TEXTURE, 0, ResourceStates::COPY_DEST)
+            .unwrap();
+        tracker
+            .transition(TEXTURE, 0, ResourceStates::PIXEL_SHADER_RESOURCE)
+            .unwrap();
+        tracker
+            .transition(TEXTURE, 2, ResourceStates::COPY_DEST)
+            .unwrap();
+        tracker
+            .transition(TEXTURE, 2, ResourceStates::COMMON)
+            .unwrap();
+        assert_eq!(
+            tracker.flush(),
+            [barrier(
+                0,
+                ResourceStates::COMMON,
+                ResourceStates::PIXEL_SHADER_RESOURCE
+            )]
+        );
+    }
+
+    #[test]
+    fn combines_read_states() {
+        let mut tracker = tracker();
+        let read = ResourceStates::PIXEL_SHADER_RESOURCE | ResourceStates::COPY_SOURCE;
+        tracker.transition(TEXTURE, 0, read).unwrap();
+        tracker.flush();
+        tracker
+            .transition(TEXTURE, 0, ResourceStates::COPY_SOURCE)
+            .unwrap();
+        assert!(tracker.flush().is_empty());
+        assert_eq!(tracker.state(TEXTURE, 0), Some(read));
+    }
+
+    #[test]
+    fn reports_invalid_use() {
+        let mut tracker = tracker();
+        let state = ResourceStates::RENDER_TARGET | ResourceStates::COPY_SOURCE;
+        assert_eq!(
+            tracker.transition(TEXTURE, 0, state),
+            Err(StateError::InvalidState {
+                resource: TEXTURE,
+                state
+            })
+        );
+        assert_eq!(
+            tracker.transition(TEXTURE, 3, ResourceStates::COMMON),
+            Err(StateError::SubresourceOutOfRange {
+                resource: TEXTURE,
+                subresource: 3,
+                count: 3
+            })
+        );
+        assert_eq!(
+            tracker.transition(ResourceId(1), 0, ResourceStates::COMMON),
+            Err(StateError::UnknownResource(ResourceId(1)))
+        );
+        assert_eq!(
+            tracker.apply(&[barrier(
+                2,
+                ResourceStates::COPY_DEST,
+                ResourceStates::COMMON
+            )]),
+            Err(StateError::Mismatch {
+                resource: TEXTURE,
+                subresource: 2,
+                tracked: ResourceStates::COMMON,
+                before: ResourceStates::COPY_DEST
+            })
+        );
+    }
+
+    #[test]
+    fn recorded_frames_are_consistent() {
+        let mut renderer =
+            Renderer::new(MockBackend::default(), Scene::hello_triangle(64, 64)).unwrap();
+        for _ in 0..3 {
+            renderer.render().unwrap();
+        }
+
+        let mut tracker = StateTracker::new();
+        for i in 0..crate::NUM_OF_FRAMES {
+            tracker.register(ResourceId::back_buffer(i), 1, ResourceStates::PRESENT);
+        }
+        for call in &renderer.backend().calls {
+            if let Call::ResourceBarrier(barriers) = call {
+                tracker.apply(barriers).unwrap();
+            }
+        }
+        for i in 0..crate::NUM_OF_FRAMES {
+            let state = tracker.state(ResourceId::back_buffer(i), 0);
+            assert_eq!(state, Some(ResourceStates::PRESENT));
+        }
+    }
+}
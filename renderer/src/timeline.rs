@@ -0,0 +1,388 @@
+//! Fence timeline, hands out points of the queue to wait for or `.await`
+//!
+//! Fence values only grow. A point is complete once the completed value of
+//! the fence reaches it, which means the GPU is done with everything
+//! submitted before the point was signaled.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// `ID3D12Fence` signaled by a queue, with the event it waits on
+pub trait Fence {
+    type Error;
+
+    /// `Signal` the value from the queue after the submitted work
+    fn signal(&mut self, value: u64) -> Result<(), Self::Error>;
+
+    /// `GetCompletedValue`
+    fn completed_value(&self) -> u64;
+
+    /// `SetEventOnCompletion` and `WaitForSingleObject`
+    fn wait(&self, value: u64) -> Result<(), Self::Error>;
+
+    /// Wakes the waker once the fence reaches the value, possibly right away.
+    /// `Completion` calls it once however often it is polled
+    fn wake_on_completion(&self, value: u64, waker: Waker) -> Result<(), Self::Error>;
+}
+
+/// Fence value signaled on the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FencePoint(pub u64);
+
+/// Hands out increasing fence points
+#[derive(Debug)]
+pub struct Timeline<F: Fence> {
+    fence: F,
+    last: u64,
+}
+
+impl<F: Fence> Timeline<F> {
+    /// Continues from the completed value of the fence
+    pub fn new(fence: F) -> Self {
+        let last = fence.completed_value();
+        Timeline { fence, last }
+    }
+
+    pub fn fence(&self) -> &F {
+        &self.fence
+    }
+
+    /// Signal the next point after the work submitted so far
+    pub fn signal(&mut self) -> Result<FencePoint, F::Error> {
+        let value = self.last + 1;
+        self.fence.signal(value)?;
+        self.last = value;
+        Ok(FencePoint(value))
+    }
+
+    /// Once complete the queue is idle
+    pub fn last_signaled(&self) -> FencePoint {
+        FencePoint(self.last)
+    }
+
+    pub fn completed(&self) -> FencePoint {
+        FencePoint(self.fence.completed_value())
+    }
+
+    /// Polls without blocking
+    pub fn is_complete(&self, point: FencePoint) -> bool {
+        self.fence.completed_value() >= point.0
+    }
+
+    /// Blocks until the point completes
+    pub fn wait(&self, point: FencePoint) -> Result<(), F::Error> {
+        if self.is_complete(point) {
+            return Ok(());
+        }
+        self.fence.wait(point.0)
+    }
+
+    /// Future that resolves once the point completes
+    pub fn completion(&self, point: FencePoint) -> Completion<'_, F> {
+        Completion {
+            fence: &self.fence,
+            value: point.0,
+            waker: None,
+        }
+    }
+}
+
+/// Completion of a fence point, see `Timeline::completion`
+#[derive(Debug)]
+pub struct Completion<'a, F: Fence> {
+    fence: &'a F,
+    value: u64,
+    /// Registered with the fence on the first pending poll, later polls
+    /// only swap the waker it forwards to
+    waker: Option<Arc<LatestWaker>>,
+}
+
+/// Wakes whichever waker the future was polled with last
+#[derive(Debug)]
+struct LatestWaker(Mutex<Waker>);
+
+impl Wake for LatestWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.lock().unwrap().wake_by_ref();
+    }
+}
+
+impl<'a, F: Fence> Future for Completion<'a, F> {
+    type Output = Result<(), F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        // Swapped before checking the fence so a completion in between
+        // wakes the current waker
+        if let Some(latest) = &this.waker {
+            let mut waker = latest.0.lock().unwrap();
+            if !waker.will_wake(cx.waker()) {
+                *waker = cx.waker().clone();
+            }
+        }
+        if this.fence.completed_value() >= this.value {
+            return Poll::Ready(Ok(()));
+        }
+        if this.waker.is_some() {
+            return Poll::Pending;
+        }
+        let latest = Arc::new(LatestWaker(Mutex::new(cx.waker().clone())));
+        match this
+            .fence
+            .wake_on_completion(this.value, Waker::from(latest.clone()))
+        {
+            Ok(()) => {
+                this.waker = Some(latest);
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockFenceError {
+    /// The value is never signaled so waiting for it would block forever
+    NotSignaled(u64),
+}
+
+/// Fence whose completed value is advanced by hand, clones share the value
+#[derive(Debug, Clone, Default)]
+pub struct MockFence {
+    shared: Arc<MockShared>,
+}
+
+#[derive(Debug, Default)]
+struct MockShared {
+    state: Mutex<MockState>,
+    completed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    signaled: u64,
+    completed: u64,
+    wakers: Vec<(u64, Waker)>,
+}
+
+impl MockFenceError {
+    fn check(state: &MockState, value: u64) -> Result<(), Self> {
+        if value > state.signaled.max(state.completed) {
+            Err(MockFenceError::NotSignaled(value))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl MockFence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest value signaled from the queue
+    pub fn signaled_value(&self) -> u64 {
+        self.shared.state.lock().unwrap().signaled
+    }
+
+    /// Wakers waiting for values that have not completed
+    pub fn waiters(&self) -> usize {
+        self.shared.state.lock().unwrap().wakers.len()
+    }
+
+    /// GPU finishes the work up to the value, waking whoever waits for it
+    pub fn complete(&self, value: u64) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.completed = state.completed.max(value);
+        let completed = state.completed;
+        let (ready, waiting) = state
+            .wakers
+            .drain(..)
+            .partition::<Vec<_>, _>(|&(value, _)| value <= completed);
+        state.wakers = waiting;
+        drop(state);
+        self.shared.completed.notify_all();
+        for (_, waker) in ready {
+            waker.wake();
+        }
+    }
+
+    /// GPU finishes all the signaled work
+    pub fn complete_all(&self) {
+        let signaled = self.signaled_value();
+        self.complete(signaled);
+    }
+}
+
+impl Fence for MockFence {
+    type Error = MockFenceError;
+
+    fn signal(&mut self, value: u64) -> Result<(), MockFenceError> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.signaled = state.signaled.max(value);
+        Ok(())
+    }
+
+    fn completed_value(&self) -> u64 {
+        self.shared.state.lock().unwrap().completed
+    }
+
+    /// Blocks until another thread completes the value
+    fn wait(&self, value: u64) -> Result<(), MockFenceError> {
+        let mut state = self.shared.state.lock().unwrap();
+        MockFenceError::check(&state, value)?;
+        while state.completed < value {
+            state = self.shared.completed.wait(state).unwrap();
+        }
+        Ok(())
+    }
+
+    fn wake_on_completion(&self, value: u64, waker: Waker) -> Result<(), MockFenceError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.completed >= value {
+            drop(state);
+            waker.wake();
+            return Ok(());
+        }
+        MockFenceError::check(&state, value)?;
+        state.wakers.push((value, waker));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::{self, Thread};
+
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn hands_out_increasing_points() {
+        let fence = MockFence::new();
+        let mut timeline = Timeline::new(fence.clone());
+        let first = timeline.signal().unwrap();
+        let second = timeline.signal().unwrap();
+        assert_eq!((first, second), (FencePoint(1), FencePoint(2)));
+        assert_eq!(timeline.last_signaled(), second);
+        assert_eq!(fence.signaled_value(), 2);
+
+        assert!(!timeline.is_complete(first));
+        fence.complete(1);
+        assert!(timeline.is_complete(first));
+        assert!(!timeline.is_complete(second));
+        assert_eq!(timeline.completed(), first);
+
+        // Continues after the completed value of an existing fence
+        fence.complete_all();
+        assert_eq!(Timeline::new(fence).signal().unwrap(), FencePoint(3));
+    }
+
+    #[test]
+    fn completion_wakes_once_fence_advances() {
+        let fence = MockFence::new();
+        let mut timeline = Timeline::new(fence.clone());
+        let point = timeline.signal().unwrap();
+
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut completion = timeline.completion(point);
+        assert!(Pin::new(&mut completion).poll(&mut cx).is_pending());
+        assert_eq!(fence.waiters(), 1);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        fence.complete(1);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(fence.waiters(), 0);
+        assert_eq!(Pin::new(&mut completion).poll(&mut cx), Poll::Ready(Ok(())));
+    }
+
+    #[test]
+    fn completion_registers_once() {
+        let fence = MockFence::new();
+        let mut timeline = Timeline::new(fence.clone());
+        let point = timeline.signal().unwrap();
+
+        let first = Arc::new(CountWakes::default());
+        let last = Arc::new(CountWakes::default());
+        let mut completion = timeline.completion(point);
+        for wakes in &[&first, &first, &last] {
+            let waker = Waker::from(Arc::clone(wakes));
+            let mut cx = Context::from_waker(&waker);
+            assert!(Pin::new(&mut completion).poll(&mut cx).is_pending());
+        }
+        assert_eq!(fence.waiters(), 1);
+
+        // Only the waker of the last poll is woken
+        fence.complete(1);
+        assert_eq!(first.0.load(Ordering::SeqCst), 0);
+        assert_eq!(last.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn waits_for_work_completed_on_another_thread() {
+        let fence = MockFence::new();
+        let mut timeline = Timeline::new(fence.clone());
+        let first = timeline.signal().unwrap();
+        let second = timeline.signal().unwrap();
+
+        let gpu = thread::spawn(move || {
+            fence.complete(1);
+            fence.complete(2);
+        });
+        let result = block_on(async {
+            timeline.completion(first).await?;
+            timeline.completion(second).await
+        });
+        assert_eq!(result, Ok(()));
+        timeline.wait(second).unwrap();
+        gpu.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_waits_that_never_complete() {
+        let fence = MockFence::new();
+        let timeline = Timeline::new(fence.clone());
+        let error = Err(MockFenceError::NotSignaled(1));
+        assert_eq!(timeline.wait(FencePoint(1)), error);
+        assert_eq!(block_on(timeline.completion(FencePoint(1))), error);
+
+        // Completed values are fine even if they were not signaled
+        fence.complete(1);
+        assert_eq!(timeline.wait(FencePoint(1)), Ok(()));
+    }
+}
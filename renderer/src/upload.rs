@@ -0,0 +1,258 @@
+//! Ring of persistently mapped upload memory for per-frame data
+//!
+//! Each frame sub-allocates vertex, index and constant data after the
+//! previous one. A frame's region is reused once its fence value completes.
+use crate::backend::VertexBufferView;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// `D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT`, also the size granularity
+/// of constant buffer views
+pub const CONSTANT_BUFFER_ALIGNMENT: u64 = 256;
+
+/// Size of the renderer's upload ring
+pub const UPLOAD_RING_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadError {
+    /// Not enough free space before the frames in flight retire
+    Overflow { requested: u64, available: u64 },
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::Overflow {
+                requested,
+                available,
+            } => write!(
+                f,
+                "upload ring overflow, requested {} bytes with {} available",
+                requested, available
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+/// Region of the ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    /// Offset from the start of the ring
+    pub offset: u64,
+    pub size: u64,
+    pub gpu_address: u64,
+}
+
+impl Allocation {
+    pub fn vertex_buffer_view(&self, stride: u32) -> VertexBufferView {
+        VertexBufferView {
+            buffer_location: self.gpu_address,
+            size_in_bytes: self.size as _,
+            stride_in_bytes: stride,
+        }
+    }
+}
+
+/// Byte ring over an upload buffer at `gpu_address`
+#[derive(Debug, Clone)]
+pub struct UploadRing {
+    gpu_address: u64,
+    capacity: u64,
+    head: u64,
+    tail: u64,
+    used: u64,
+    /// Bytes used by the current frame, including padding
+    frame_used: u64,
+    /// Ended frames as `(fence value, head, used)`
+    frames: VecDeque<(u64, u64, u64)>,
+}
+
+impl UploadRing {
+    pub fn new(gpu_address: u64, capacity: u64) -> Self {
+        UploadRing {
+            gpu_address,
+            capacity,
+            head: 0,
+            tail: 0,
+            used: 0,
+            frame_used: 0,
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Sub-allocate `size` bytes at a power of two alignment, never wraps
+    /// a region around the end
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Result<Allocation, UploadError> {
+        assert!(
+            alignment.is_power_of_two(),
+            "alignment must be a power of two"
+        );
+        let overflow = UploadError::Overflow {
+            requested: size,
+            available: self.available(),
+        };
+        // Queued frames, even empty ones, hold a head that `retire` moves
+        // the tail to, so only an idle ring starts over
+        if self.used == 0 && self.frames.is_empty() {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let start = align(self.head, alignment);
+        let (offset, taken) = if self.head >= self.tail && self.used < self.capacity {
+            if start + size <= self.capacity {
+                (start, start + size - self.head)
+            } else if size <= self.tail {
+                (0, self.capacity - self.head + size)
+            } else {
+                return Err(overflow);
+            }
+        } else if start + size <= self.tail {
+            (start, start + size - self.head)
+        } else {
+            return Err(overflow);
+        };
+        self.head = offset + size;
+        self.used += taken;
+        self.frame_used += taken;
+        Ok(Allocation {
+            offset,
+            size,
+            gpu_address: self.gpu_address + offset,
+        })
+    }
+
+    /// Constant buffer data, aligned and padded to 256 bytes
+    pub fn allocate_constants(&mut self, size: u64) -> Result<Allocation, UploadError> {
+        let size = align(size.max(1), CONSTANT_BUFFER_ALIGNMENT);
+        self.allocate(size, CONSTANT_BUFFER_ALIGNMENT)
+    }
+
+    /// Close the frame, its regions are reused once `fence_value` completes
+    pub fn end_frame(&mut self, fence_value: u64) {
+        self.frames
+            .push_back((fence_value, self.head, self.frame_used));
+        self.frame_used = 0;
+    }
+
+    /// Free the frames whose fence value is at most `completed`
+    pub fn retire(&mut self, completed: u64) {
+        while let Some(&(value, head, used)) = self.frames.front() {
+            if value > completed {
+                break;
+            }
+            self.tail = head;
+            self.used -= used;
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Free bytes, padding at the end may make less usable
+    pub fn available(&self) -> u64 {
+        self.capacity - self.used
+    }
+
+    /// Frames waiting for their fence value
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_constants_to_256_bytes() {
+        let mut ring = UploadRing::new(0x10000, 4096);
+        let vertices = ring.allocate(84, 4).unwrap();
+        let constants = ring.allocate_constants(64).unwrap();
+        let more = ring.allocate_constants(300).unwrap();
+        assert_eq!((vertices.offset, vertices.gpu_address), (0, 0x10000));
+        assert_eq!((constants.offset, constants.size), (256, 256));
+        assert_eq!((more.offset, more.size), (512, 512));
+        assert_eq!(more.gpu_address % CONSTANT_BUFFER_ALIGNMENT, 0);
+        assert_eq!(ring.available(), 4096 - 1024);
+
+        let view = vertices.vertex_buffer_view(28);
+        assert_eq!(view.buffer_location, 0x10000);
+        assert_eq!(view.size_in_bytes, 84);
+    }
+
+    #[test]
+    fn retires_frames_when_fence_completes() {
+        let mut ring = UploadRing::new(0, 1024);
+        ring.allocate(512, 4).unwrap();
+        ring.end_frame(1);
+        ring.allocate(256, 4).unwrap();
+        ring.end_frame(2);
+        assert_eq!(ring.frames_in_flight(), 2);
+
+        ring.retire(0);
+        assert_eq!(ring.available(), 256);
+        ring.retire(1);
+        assert_eq!(ring.available(), 768);
+        assert_eq!(ring.frames_in_flight(), 1);
+
+        // Too big for the end, wraps to the start freed by the first frame
+        let wrapped = ring.allocate(384, 4).unwrap();
+        assert_eq!(wrapped.offset, 0);
+        assert_eq!(ring.available(), 1024 - 256 - 256 - 384);
+        ring.end_frame(3);
+        ring.retire(3);
+        assert_eq!(ring.available(), 1024);
+    }
+
+    #[test]
+    fn keeps_tail_after_empty_frames() {
+        let mut ring = UploadRing::new(0, 1024);
+        ring.allocate(100, 4).unwrap();
+        ring.end_frame(1);
+        ring.end_frame(2);
+        ring.retire(1);
+        let second = ring.allocate(200, 4).unwrap();
+        assert_eq!(second.offset, 100);
+        ring.end_frame(3);
+        ring.retire(2);
+
+        // Frame 3 holds 100..300, neither allocation may overlap it
+        assert!(ring.allocate(800, 4).is_err());
+        assert_eq!(ring.allocate(60, 4).unwrap().offset, 300);
+        ring.end_frame(4);
+        ring.retire(4);
+        assert_eq!(ring.available(), 1024);
+        assert_eq!(ring.allocate(1024, 4).unwrap().offset, 0);
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let mut ring = UploadRing::new(0, 1024);
+        ring.allocate(768, 4).unwrap();
+        ring.end_frame(1);
+        assert_eq!(
+            ring.allocate(512, 4),
+            Err(UploadError::Overflow {
+                requested: 512,
+                available: 256
+            })
+        );
+
+        // Padding counts, 256 bytes free but the aligned start doesn't fit
+        ring.allocate(4, 4).unwrap();
+        assert!(ring.allocate_constants(16).is_err());
+        assert!(ring.allocate(2048, 4).is_err());
+
+        ring.end_frame(2);
+        ring.retire(2);
+        assert!(ring.allocate(1024, 4).is_ok());
+    }
+}
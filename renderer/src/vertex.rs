@@ -0,0 +1,185 @@
+use crate::format::Format;
+
+/// Generates `VertexLayout` from the fields of a `#[repr(C)]` struct
+///
+/// ```
+/// use renderer::vertex::VertexLayout;
+///
+/// #[derive(VertexLayout)]
+/// #[repr(C)]
+/// struct Vertex {
+///     position: [f32; 3],
+///     texcoord1: [f32; 2],
+///     #[vertex(semantic = "COLOR", format = "R8G8B8A8Unorm")]
+///     tint: [u8; 4],
+/// }
+///
+/// assert_eq!(Vertex::LAYOUT[1].semantic_name, "TEXCOORD");
+/// assert_eq!(Vertex::LAYOUT[1].semantic_index, 1);
+/// assert_eq!(Vertex::LAYOUT[2].aligned_byte_offset, 20);
+/// assert_eq!(Vertex::STRIDE, 24);
+/// ```
+///
+/// Fields need a type with a known format
+///
+/// ```compile_fail
+/// # use renderer::vertex::VertexLayout;
+/// #[derive(VertexLayout)]
+/// #[repr(C)]
+/// struct Vertex {
+///     position: [f64; 3],
+/// }
+/// ```
+///
+/// The field order must be fixed
+///
+/// ```compile_fail
+/// # use renderer::vertex::VertexLayout;
+/// #[derive(VertexLayout)]
+/// struct Vertex {
+///     position: [f32; 3],
+/// }
+/// ```
+///
+/// A format override must match the field size
+///
+/// ```compile_fail
+/// # use renderer::vertex::VertexLayout;
+/// #[derive(VertexLayout)]
+/// #[repr(C)]
+/// struct Vertex {
+///     #[vertex(format = "R32G32B32A32Float")]
+///     position: [f32; 3],
+/// }
+/// ```
+pub use renderer_derive::VertexLayout;
+
+/// Vertex struct whose fields give its input layout
+pub trait VertexLayout {
+    /// Input elements in field order
+    const LAYOUT: &'static [InputElement];
+    /// `StrideInBytes` of the vertex buffer view
+    const STRIDE: u32;
+}
+
+/// Type of a vertex field and the format the input assembler reads it as
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no vertex format",
+    label = "add `#[vertex(format = \"...\")]` or use a supported type"
+)]
+pub trait VertexFormat {
+    const FORMAT: Format;
+}
+
+macro_rules! vertex_formats {
+    ($($ty:ty => $format:ident,)*) => {
+        $(impl VertexFormat for $ty {
+            const FORMAT: Format = Format::$format;
+        })*
+    };
+}
+
+vertex_formats! {
+    f32 => R32Float,
+    [f32; 1] => R32Float,
+    [f32; 2] => R32G32Float,
+    [f32; 3] => R32G32B32Float,
+    [f32; 4] => R32G32B32A32Float,
+    u32 => R32Uint,
+    [u32; 1] => R32Uint,
+    [u32; 2] => R32G32Uint,
+    [u32; 3] => R32G32B32Uint,
+    [u32; 4] => R32G32B32A32Uint,
+    i32 => R32Sint,
+    [i32; 1] => R32Sint,
+    [i32; 2] => R32G32Sint,
+    [i32; 3] => R32G32B32Sint,
+    [i32; 4] => R32G32B32A32Sint,
+    u16 => R16Uint,
+    [u8; 4] => R8G8B8A8Unorm,
+}
+
+/// Vertex of `VSMain(float4 position : POSITION, float4 color : COLOR)`
+#[derive(Debug, Clone, Copy, PartialEq, VertexLayout)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    pub const fn new(position: [f32; 3], color: [f32; 4]) -> Self {
+        Self { position, color }
+    }
+}
+
+/// Vertices as bytes for uploading
+pub fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+    // Vertex is `repr(C)` and has only `f32` fields, so there is no padding
+    unsafe {
+        std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(vertices),
+        )
+    }
+}
+
+/// `D3D12_INPUT_CLASSIFICATION`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum InputClassification {
+    PerVertexData = 0,
+    PerInstanceData = 1,
+}
+
+/// Plain data version of `D3D12_INPUT_ELEMENT_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputElement {
+    /// Semantic name without the index, e.g. `"COLOR"`
+    pub semantic_name: &'static str,
+    pub semantic_index: u32,
+    pub format: Format,
+    pub input_slot: u32,
+    pub aligned_byte_offset: u32,
+    pub input_slot_class: InputClassification,
+    pub instance_data_step_rate: u32,
+}
+
+impl InputElement {
+    pub const fn per_vertex(semantic_name: &'static str, format: Format, offset: u32) -> Self {
+        InputElement {
+            semantic_name,
+            semantic_index: 0,
+            format,
+            input_slot: 0,
+            aligned_byte_offset: offset,
+            input_slot_class: InputClassification::PerVertexData,
+            instance_data_step_rate: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn layout_matches_struct() {
+        let v = Vertex::new([0.0; 3], [0.0; 4]);
+        let base = &v as *const _ as usize;
+        assert_eq!(
+            Vertex::LAYOUT[0].aligned_byte_offset as usize,
+            &v.position as *const _ as usize - base
+        );
+        assert_eq!(
+            Vertex::LAYOUT[1].aligned_byte_offset as usize,
+            &v.color as *const _ as usize - base
+        );
+        let size: u32 = Vertex::LAYOUT
+            .iter()
+            .map(|e| e.format.size_in_bytes())
+            .sum();
+        assert_eq!(size as usize, mem::size_of::<Vertex>());
+    }
+}
@@ -0,0 +1,34 @@
+//! Scenes drawn with the CPU backend against the reference images in
+//! `tests/golden`, run with `UPDATE_GOLDEN=1` to accept new output
+use renderer::frame::Renderer;
+use renderer::golden::{Golden, Tolerance};
+use renderer::headless;
+use renderer::image::Image;
+use renderer::scene::Scene;
+use renderer::software::SoftwareBackend;
+
+fn golden() -> Golden {
+    Golden::new(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/golden"),
+    )
+}
+
+fn render(scene: Scene) -> Image {
+    let mut renderer = Renderer::new(SoftwareBackend::default(), scene).unwrap();
+    headless::render_frames(&mut renderer, 1).unwrap()
+}
+
+/// Room for rounding differences of other rasterizers along the edges
+const EDGES: Tolerance = Tolerance {
+    channel: 2,
+    max_pixels: 64,
+};
+
+#[test]
+fn hello_triangle() {
+    let image = render(Scene::hello_triangle(128, 128));
+    if let Err(err) = golden().check("hello_triangle", &image, EDGES) {
+        panic!("{}", err);
+    }
+}
@@ -0,0 +1,8 @@
+@echo off
+rem Replaces the hand assembled fixtures with compiler output, see README.md
+cd /d "%~dp0"
+fxc /nologo /T vs_5_0 /E VSMain /Fo simple_vs.dxbc ..\..\..\winapi-rs\src\simple.hlsl || exit /b 1
+fxc /nologo /T ps_5_0 /E PSMain /Fo simple_ps.dxbc ..\..\..\winapi-rs\src\simple.hlsl || exit /b 1
+fxc /nologo /T ps_5_0 /E PSMain /Fo textured_ps.dxbc textured.hlsl || exit /b 1
+dxc -nologo -T vs_6_0 -E VSMain -Fo simple_vs.dxil ..\..\..\winapi-rs\src\simple.hlsl || exit /b 1
+cargo test --manifest-path ..\..\Cargo.toml shader::container -- --include-ignored
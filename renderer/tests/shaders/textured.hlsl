@@ -0,0 +1,19 @@
+cbuffer Material : register(b0)
+{
+    float4 tint;
+    float exposure;
+};
+
+Texture2D albedo : register(t0);
+SamplerState linearSampler : register(s0);
+
+struct PSInput
+{
+    float4 position : SV_POSITION;
+    float2 uv : TEXCOORD;
+};
+
+float4 PSMain(PSInput input) : SV_TARGET
+{
+    return albedo.Sample(linearSampler, input.uv) * tint * exposure;
+}
@@ -0,0 +1,124 @@
+//! Input layouts generated by `#[derive(VertexLayout)]`
+use renderer::format::Format;
+use renderer::vertex::{InputClassification, InputElement, Vertex, VertexLayout};
+
+#[derive(VertexLayout)]
+#[repr(C)]
+#[allow(dead_code)]
+struct Skinned {
+    position: [f32; 3],
+    normal: [f32; 3],
+    texcoord0: [f32; 2],
+    texcoord1: [f32; 2],
+    #[vertex(semantic = "BLENDINDICES", format = "R8G8B8A8Unorm")]
+    joints: [u8; 4],
+    #[vertex(semantic = "BLENDWEIGHT")]
+    weights: [f32; 4],
+}
+
+#[derive(VertexLayout)]
+#[repr(C)]
+#[vertex(slot = 1, instance_step_rate = 1)]
+#[allow(dead_code)]
+struct Instance {
+    #[vertex(semantic = "TRANSFORM", index = 0)]
+    row0: [f32; 4],
+    #[vertex(semantic = "TRANSFORM", index = 1)]
+    row1: [f32; 4],
+    #[vertex(skip)]
+    padding: [u32; 3],
+    id: u32,
+}
+
+#[derive(VertexLayout)]
+#[repr(C)]
+#[allow(dead_code)]
+struct Packed(
+    #[vertex(semantic = "POSITION")] [f32; 2],
+    #[vertex(semantic = "COLOR", format = "R8G8B8A8UnormSrgb")] [u8; 4],
+);
+
+// Checked while compiling, a drifting layout fails the build
+const _: () = {
+    assert!(Vertex::STRIDE == 28);
+    assert!(Vertex::LAYOUT.len() == 2);
+    assert!(Vertex::LAYOUT[1].aligned_byte_offset == 12);
+    assert!(Skinned::STRIDE == 60);
+    assert!(Skinned::LAYOUT[5].aligned_byte_offset == 44);
+    assert!(Instance::LAYOUT.len() == 3);
+    assert!(Instance::LAYOUT[2].aligned_byte_offset == 44);
+    assert!(Packed::STRIDE == 12);
+};
+
+fn element(
+    semantic_name: &'static str,
+    semantic_index: u32,
+    format: Format,
+    aligned_byte_offset: u32,
+) -> InputElement {
+    InputElement {
+        semantic_name,
+        semantic_index,
+        format,
+        input_slot: 0,
+        aligned_byte_offset,
+        input_slot_class: InputClassification::PerVertexData,
+        instance_data_step_rate: 0,
+    }
+}
+
+#[test]
+fn hello_triangle_vertex() {
+    assert_eq!(
+        Vertex::LAYOUT,
+        &[
+            element("POSITION", 0, Format::R32G32B32Float, 0),
+            element("COLOR", 0, Format::R32G32B32A32Float, 12),
+        ]
+    );
+}
+
+#[test]
+fn semantics_formats_and_offsets() {
+    assert_eq!(
+        Skinned::LAYOUT,
+        &[
+            element("POSITION", 0, Format::R32G32B32Float, 0),
+            element("NORMAL", 0, Format::R32G32B32Float, 12),
+            element("TEXCOORD", 0, Format::R32G32Float, 24),
+            element("TEXCOORD", 1, Format::R32G32Float, 32),
+            element("BLENDINDICES", 0, Format::R8G8B8A8Unorm, 40),
+            element("BLENDWEIGHT", 0, Format::R32G32B32A32Float, 44),
+        ]
+    );
+    assert_eq!(Skinned::STRIDE as usize, std::mem::size_of::<Skinned>());
+}
+
+#[test]
+fn per_instance_slot() {
+    let instance = |semantic_name, semantic_index, format, aligned_byte_offset| InputElement {
+        input_slot: 1,
+        input_slot_class: InputClassification::PerInstanceData,
+        instance_data_step_rate: 1,
+        ..element(semantic_name, semantic_index, format, aligned_byte_offset)
+    };
+    assert_eq!(
+        Instance::LAYOUT,
+        &[
+            instance("TRANSFORM", 0, Format::R32G32B32A32Float, 0),
+            instance("TRANSFORM", 1, Format::R32G32B32A32Float, 16),
+            instance("ID", 0, Format::R32Uint, 44),
+        ]
+    );
+}
+
+#[test]
+fn tuple_struct() {
+    assert_eq!(
+        Packed::LAYOUT,
+        &[
+            element("POSITION", 0, Format::R32G32Float, 0),
+            element("COLOR", 0, Format::R8G8B8A8UnormSrgb, 8),
+        ]
+    );
+}
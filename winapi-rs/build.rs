@@ -0,0 +1,55 @@
+//! Compiles `permutation::hello_triangle` with DXC for Windows release builds,
+//! debug builds compile the same variants at startup instead. Also serializes
+//! the `HelloTriangleRS` root signature of simple.hlsl
+use renderer::root_signature::{hlsl, RootSignatureVersion};
+use renderer::shader::compiler::{Build, Dxc, OPTIMIZATION_LEVEL3};
+use renderer::shader::manifest::Manifest;
+use renderer::shader::permutation;
+use renderer::shader::preprocess::{Preprocessor, SearchPath};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=DXC");
+    // Elsewhere the CPU rasterizer draws, without shaders
+    if env::var_os("CARGO_CFG_WINDOWS").is_none()
+        || env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some()
+    {
+        return;
+    }
+
+    let manifest = Manifest {
+        files: vec![(
+            "simple.hlsl".to_string(),
+            permutation::hello_triangle("6_0"),
+        )],
+    };
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let dxc = Dxc::from_env();
+    let (_, warnings) = Build::new(&dxc)
+        .flags(OPTIMIZATION_LEVEL3)
+        .cache(out_dir.join("shader-cache"))
+        .manifest(&manifest, Path::new("src"), &out_dir)
+        .unwrap_or_else(|e| panic!("{}", e));
+    for (variant, warning) in warnings {
+        for line in format!("{}: {}", variant, warning).lines() {
+            println!("cargo:warning={}", line);
+        }
+    }
+
+    // HelloTriangleRS serialized to RTS0, so the binary needs neither the
+    // HLSL it is declared in nor a preprocessor
+    let source = fs::read_to_string("src/simple.hlsl").unwrap_or_else(|e| panic!("{}", e));
+    let resolver = SearchPath::new(vec!["src"]);
+    let mut preprocessor = Preprocessor::new(&resolver);
+    preprocessor
+        .run("src/simple.hlsl", &source)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let root = hlsl::from_define(&preprocessor, "HelloTriangleRS", RootSignatureVersion::V1_0)
+        .unwrap_or_else(|e| panic!("Invalid root signature\n{}", e))
+        .to_bytes()
+        .unwrap_or_else(|e| panic!("Invalid root signature\n{}", e));
+    fs::write(out_dir.join("simple.rts0"), root).unwrap_or_else(|e| panic!("{}", e));
+}
@@ -0,0 +1,100 @@
+//! `ID3D12Fence` of a queue for the timeline of the `renderer` crate
+use crate::window::check;
+use renderer::timeline::Fence;
+use std::ptr::{null, null_mut};
+use std::task::Waker;
+use std::thread;
+use winapi::shared::minwindef::FALSE;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d12::*;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::synchapi::*;
+use winapi::um::winnt::HANDLE;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+const INFINITE: u32 = 0xFFFFFFFF;
+
+/// Fence signaled from a queue, waits block on an event
+pub struct D3D12Fence {
+    fence: ComPtr<ID3D12Fence>,
+    queue: ComPtr<ID3D12CommandQueue>,
+    event: HANDLE,
+}
+
+impl D3D12Fence {
+    pub fn new(
+        device: &ComPtr<ID3D12Device>,
+        queue: ComPtr<ID3D12CommandQueue>,
+    ) -> Result<Self, HRESULT> {
+        let fence = unsafe {
+            let mut ptr = null_mut::<ID3D12Fence>();
+            check(device.CreateFence(
+                0,
+                D3D12_FENCE_FLAG_NONE,
+                &ID3D12Fence::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+        let event = create_event();
+        Ok(D3D12Fence {
+            fence,
+            queue,
+            event,
+        })
+    }
+}
+
+impl Drop for D3D12Fence {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.event) };
+    }
+}
+
+fn create_event() -> HANDLE {
+    let event = unsafe { CreateEventA(null_mut(), FALSE, FALSE, null()) };
+    if event.is_null() {
+        panic!("Unable to create fence event");
+    }
+    event
+}
+
+impl Fence for D3D12Fence {
+    type Error = HRESULT;
+
+    fn signal(&mut self, value: u64) -> Result<(), HRESULT> {
+        unsafe { check(self.queue.Signal(self.fence.as_raw(), value)) }
+    }
+
+    fn completed_value(&self) -> u64 {
+        unsafe { self.fence.GetCompletedValue() }
+    }
+
+    fn wait(&self, value: u64) -> Result<(), HRESULT> {
+        unsafe {
+            check(self.fence.SetEventOnCompletion(value, self.event))?;
+            WaitForSingleObject(self.event, INFINITE);
+        }
+        Ok(())
+    }
+
+    /// Event of its own, set by the fence and waited for on another thread,
+    /// once per `Completion`
+    fn wake_on_completion(&self, value: u64, waker: Waker) -> Result<(), HRESULT> {
+        let event = create_event();
+        if let Err(hr) = unsafe { check(self.fence.SetEventOnCompletion(value, event)) } {
+            unsafe { CloseHandle(event) };
+            return Err(hr);
+        }
+
+        // Handles are plain values, the thread owns this one
+        let event = event as usize;
+        thread::spawn(move || unsafe {
+            WaitForSingleObject(event as HANDLE, INFINITE);
+            CloseHandle(event as HANDLE);
+            waker.wake();
+        });
+        Ok(())
+    }
+}
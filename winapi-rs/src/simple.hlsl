@@ -21,12 +21,27 @@ PSInput VSMain(float4 position : POSITION, float4 color : COLOR)
     PSInput result;
 
     result.position = position;
+#if VERTEX_COLOR
     result.color = color;
+#else
+    result.color = float4(1.0, 1.0, 1.0, 1.0);
+#endif
 
     return result;
 }
 
 float4 PSMain(PSInput input) : SV_TARGET
 {
-    return input.color;
-}
\ No newline at end of file
+    float4 color = input.color;
+#ifdef WIREFRAME_TINT
+    color.rgb = lerp(color.rgb, float3(1.0, 0.5, 0.0), 0.5);
+#endif
+#if PREMULTIPLIED
+    color.rgb *= color.a;
+#endif
+    return color;
+}
+
+// Root signature of both entry points, what DXC compiles with
+// -T rootsig_1_0 -E HelloTriangleRS
+#define HelloTriangleRS "RootFlags(ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT)"
@@ -0,0 +1,1292 @@
+//! D3D12 backend of the window, the frame flow comes from the `renderer` crate
+use crate::fence::D3D12Fence;
+use ptr::{null, null_mut};
+use renderer::backend::{Backend, CommandQueueDesc, SwapChainDesc, VertexBufferView};
+use renderer::descriptor::{CpuDescriptorHandle, DescriptorHeap, DescriptorHeapDesc};
+use renderer::format::Format;
+use renderer::frame::Renderer;
+use renderer::image::Image;
+use renderer::memory::{AllocationInfo, HeapAllocator, HeapType, ResourceKind, DEFAULT_HEAP_SIZE};
+use renderer::pipeline::definition::{Definitions, PipelineDefinition};
+#[cfg(debug_assertions)]
+use renderer::pipeline::reload::Reloader;
+use renderer::pipeline::{
+    BlendDesc, DepthStencilDesc, DepthStencilOpDesc, GraphicsPipelineDesc, PrimitiveTopology,
+    RasterizerDesc,
+};
+use renderer::resource::{Barrier, ResourceId, ResourceStates};
+#[cfg(debug_assertions)]
+use renderer::root_signature::{hlsl, RootSignatureVersion};
+use renderer::scene::{Rect, Scene, Viewport, BACK_BUFFER_FORMAT};
+#[cfg(debug_assertions)]
+use renderer::shader::cache::{self, Compiled, Request};
+#[cfg(debug_assertions)]
+use renderer::shader::compiler::{Build, CompileError, Compiler, Dxc};
+use renderer::shader::container::Container;
+use renderer::shader::layout;
+#[cfg(debug_assertions)]
+use renderer::shader::permutation;
+use renderer::shader::permutation::ShaderLibrary;
+#[cfg(debug_assertions)]
+use renderer::shader::preprocess::{Embedded, IncludeResolver, Preprocessor, SearchPath};
+use renderer::timeline::{Fence, Timeline};
+use renderer::vertex::{Vertex, VertexLayout};
+#[cfg(debug_assertions)]
+use std::path::Path;
+#[cfg(debug_assertions)]
+use std::slice;
+use std::{convert::TryInto, ffi::CString, mem, ptr};
+use winapi::shared::dxgi::*;
+use winapi::shared::dxgi1_2::*;
+use winapi::shared::dxgi1_3::*;
+use winapi::shared::dxgi1_4::*;
+use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
+use winapi::shared::minwindef::*;
+use winapi::shared::windef::*;
+use winapi::shared::winerror::HRESULT;
+use winapi::um::d3d12::*;
+use winapi::um::d3d12sdklayers::*;
+use winapi::um::d3dcommon::*;
+#[cfg(debug_assertions)]
+use winapi::um::d3dcompiler::*;
+use winapi::um::dcomp::*;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+pub type Window = Renderer<D3D12>;
+
+/// Create drawing resources for the window
+pub fn create_window(hwnd: HWND) -> Window {
+    let scene = Scene::hello_triangle(1024, 1024);
+    Renderer::new(D3D12::new(Some(hwnd)), scene).expect("Unable to create renderer")
+}
+
+/// Create drawing resources without a window, back buffers are plain textures
+pub fn create_headless() -> Window {
+    let scene = Scene::hello_triangle(1024, 1024);
+    Renderer::new(D3D12::new(None), scene).expect("Unable to create renderer")
+}
+
+/// Pipeline of `simple.pipelines.toml` the scene is drawn with
+const PIPELINE: &str = "triangle";
+
+/// Rebuilds the pipeline after its definition or shader was edited, the
+/// current one stays when the edit doesn't compile. Whether it was rebuilt
+#[cfg(debug_assertions)]
+pub fn reload_pipelines(window: &mut Window) -> bool {
+    let d3d = window.backend_mut();
+    let rebuilt = match d3d.reloader.poll() {
+        Ok(changed) if changed.iter().any(|name| name == PIPELINE) => d3d.rebuild_pipeline(),
+        Ok(_) => return false,
+        Err(e) => Err(e.to_string()),
+    };
+    let (pipeline_state, shaders) = match rebuilt {
+        Ok(rebuilt) => rebuilt,
+        Err(e) => {
+            eprintln!("Pipeline {} not reloaded\n{}", PIPELINE, e);
+            return false;
+        }
+    };
+    // Frames in flight still use the previous pipeline
+    window.wait_for_gpu().expect("Unable to wait for GPU");
+    let d3d = window.backend_mut();
+    d3d.pipeline_state = pipeline_state;
+    d3d.shaders = shaders;
+    true
+}
+
+pub(crate) fn check(hr: HRESULT) -> Result<(), HRESULT> {
+    if hr < 0 {
+        Err(hr)
+    } else {
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+pub struct D3D12 {
+    /// Without a window the back buffers are offscreen textures
+    hwnd: Option<HWND>,
+    factory: ComPtr<IDXGIFactory4>,
+    adapter: ComPtr<IDXGIAdapter1>,
+    device: ComPtr<ID3D12Device>,
+    comp_device: ComPtr<IDCompositionDevice>,
+    pipeline_state: ComPtr<ID3D12PipelineState>,
+    root_signature: ComPtr<ID3D12RootSignature>,
+    shaders: ShaderLibrary,
+    /// Edits of the definitions and shaders in `src` rebuild the pipeline
+    #[cfg(debug_assertions)]
+    reloader: Reloader,
+
+    // Created by the renderer
+    queue: Option<ComPtr<ID3D12CommandQueue>>,
+    swap_chain: Option<ComPtr<IDXGISwapChain3>>,
+    comp_target: Option<ComPtr<IDCompositionTarget>>,
+    comp_visual: Option<ComPtr<IDCompositionVisual>>,
+    /// Command allocator per frame in flight
+    allocators: Vec<ComPtr<ID3D12CommandAllocator>>,
+    list: Option<ComPtr<ID3D12GraphicsCommandList>>,
+    desc_heaps: Vec<ComPtr<ID3D12DescriptorHeap>>,
+
+    /// Back buffers, indexed by `ResourceId`
+    resources: Vec<ComPtr<ID3D12Resource>>,
+    back_buffer_size: (u32, u32),
+
+    /// Current offscreen back buffer
+    back_buffer: usize,
+
+    /// Vertex buffers, kept alive for the GPU
+    buffers: Vec<ComPtr<ID3D12Resource>>,
+
+    /// Upload buffers mapped for their lifetime, with address and memory
+    uploads: Vec<(ComPtr<ID3D12Resource>, u64, *mut u8, usize)>,
+
+    /// Heaps of the placed buffers, dropped after them
+    memory: HeapAllocator<ComPtr<ID3D12Heap>>,
+
+    /// Fence of the queue, the renderer chooses the values
+    fence: Option<D3D12Fence>,
+}
+
+impl D3D12 {
+    /// Pipeline state and shaders of the current definition, with the shader
+    /// read from disk
+    #[cfg(debug_assertions)]
+    fn rebuild_pipeline(&self) -> Result<(ComPtr<ID3D12PipelineState>, ShaderLibrary), String> {
+        let definition = self
+            .reloader
+            .definitions()
+            .get(PIPELINE)
+            .ok_or_else(|| format!("no {} pipeline", PIPELINE))?;
+        let path = self.reloader.shader_path(&definition.shader);
+        let source =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let shaders = compile_shaders(&source, &SearchPath::new(Some(dir)))?;
+        let pipeline_state =
+            create_pipeline_state(&self.device, &self.root_signature, &shaders, definition)?;
+        Ok((pipeline_state, shaders))
+    }
+
+    /// Create the device and the pipeline state
+    pub fn new(hwnd: Option<HWND>) -> Self {
+        if let Some(hwnd) = hwnd {
+            println!("HWND {}", hwnd as u32);
+        }
+
+        // Start "DebugView" to listen errors
+        // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
+        let debug = unsafe {
+            let mut ptr = null_mut::<ID3D12Debug>();
+            let hr = D3D12GetDebugInterface(
+                &ID3D12Debug::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            );
+            (hr == 0).then(|| ComPtr::from_raw(ptr))
+        }
+        .expect("Unable to create debug layer");
+
+        unsafe {
+            debug.EnableDebugLayer();
+        }
+
+        // Create Factory4
+        let factory = unsafe {
+            let mut ptr = null_mut::<IDXGIFactory4>();
+            let hr = CreateDXGIFactory2(
+                0,
+                &IDXGIFactory4::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            );
+            (hr == 0).then(|| ComPtr::from_raw(ptr))
+        }
+        .expect("Unable to create factory4");
+
+        // Get any D3D adapter
+        let adapter = (0..99)
+            .into_iter()
+            .find_map(|i| unsafe {
+                let mut ptr = null_mut::<IDXGIAdapter1>();
+                let hr = factory.EnumAdapters1(i, &mut ptr as *mut *mut _ as *mut *mut _);
+                (hr == 0).then(|| ComPtr::from_raw(ptr))
+            })
+            .expect("Could not find d3d adapter");
+
+        // Create device
+        let device = unsafe {
+            let mut ptr = null_mut::<ID3D12Device>();
+            let hr = D3D12CreateDevice(
+                adapter.as_raw() as *mut _,
+                D3D_FEATURE_LEVEL_11_0,
+                &ID3D12Device::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            );
+            (hr == 0).then(|| ComPtr::from_raw(ptr))
+        }
+        .expect("Unable to create device");
+
+        // Composition device
+        let comp_device = unsafe {
+            let mut ptr = null_mut::<IDCompositionDevice>();
+            let hr = DCompositionCreateDevice(
+                0 as _,
+                &IDCompositionDevice::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            );
+            (hr == 0).then(|| ComPtr::from_raw(ptr))
+        }
+        .expect("Unable to create composition device");
+
+        // Declared in simple.hlsl and serialized in Rust, the same blob
+        // D3D12SerializeRootSignature gives
+        let root = root_signature();
+        let root_signature = unsafe {
+            let mut ptr = null_mut::<ID3D12RootSignature>();
+            let hr = device.CreateRootSignature(
+                0,
+                root.as_ptr() as _,
+                root.len(),
+                &ID3D12RootSignature::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            );
+            (hr == 0).then(|| ComPtr::from_raw(ptr))
+        }
+        .expect("Unable to create root signature");
+
+        let shaders = shader_library();
+        let definitions = Definitions::parse(include_str!("./simple.pipelines.toml"))
+            .unwrap_or_else(|e| panic!("Invalid simple.pipelines.toml\n{}", e));
+        let definition = definitions
+            .get(PIPELINE)
+            .expect("simple.pipelines.toml has no triangle pipeline");
+        let pipeline_state = create_pipeline_state(&device, &root_signature, &shaders, definition)
+            .unwrap_or_else(|e| panic!("Unable to create pipeline state: {}", e));
+
+        D3D12 {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            comp_device,
+            pipeline_state,
+            root_signature,
+            shaders,
+            #[cfg(debug_assertions)]
+            reloader: Reloader::new(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("src/simple.pipelines.toml"),
+                definitions,
+            ),
+            queue: None,
+            swap_chain: None,
+            comp_target: None,
+            comp_visual: None,
+            allocators: Vec::new(),
+            list: None,
+            desc_heaps: Vec::new(),
+            resources: Vec::new(),
+            back_buffer_size: (0, 0),
+            back_buffer: 0,
+            buffers: Vec::new(),
+            uploads: Vec::new(),
+            memory: HeapAllocator::new(DEFAULT_HEAP_SIZE),
+            fence: None,
+        }
+    }
+
+    fn queue(&self) -> &ComPtr<ID3D12CommandQueue> {
+        self.queue.as_ref().expect("Command queue is not created")
+    }
+
+    fn fence(&mut self) -> &mut D3D12Fence {
+        self.fence.as_mut().expect("Command queue is not created")
+    }
+
+    fn list(&self) -> &ComPtr<ID3D12GraphicsCommandList> {
+        self.list.as_ref().expect("Command list is not created")
+    }
+
+    /// Buffer placed in a shared heap of the type
+    fn create_placed_buffer(
+        &mut self,
+        heap_type: HeapType,
+        size: u64,
+    ) -> Result<ComPtr<ID3D12Resource>, HRESULT> {
+        let desc = buffer_desc(size);
+        let info = unsafe { self.device.GetResourceAllocationInfo(0, 1, &desc) };
+        let device = &self.device;
+        let allocation = self.memory.allocate(
+            heap_type,
+            ResourceKind::Buffer,
+            &AllocationInfo {
+                size: info.SizeInBytes,
+                alignment: info.Alignment,
+            },
+            |heap| -> Result<_, HRESULT> {
+                let desc = D3D12_HEAP_DESC {
+                    SizeInBytes: heap.size,
+                    Properties: heap_properties(heap.heap_type as _),
+                    Alignment: heap.alignment,
+                    Flags: heap.kind as _,
+                };
+                let mut ptr = null_mut::<ID3D12Heap>();
+                unsafe {
+                    check(device.CreateHeap(
+                        &desc,
+                        &ID3D12Heap::uuidof(),
+                        &mut ptr as *mut *mut _ as *mut *mut _,
+                    ))?;
+                    Ok(ComPtr::from_raw(ptr))
+                }
+            },
+        )?;
+        let heap = &self
+            .memory
+            .heap(allocation.heap)
+            .expect("Heap is allocated")
+            .heap;
+        let state = match heap_type {
+            HeapType::Default => D3D12_RESOURCE_STATE_COMMON,
+            HeapType::Upload => D3D12_RESOURCE_STATE_GENERIC_READ,
+            HeapType::Readback => D3D12_RESOURCE_STATE_COPY_DEST,
+        };
+        unsafe {
+            let mut ptr = null_mut::<ID3D12Resource>();
+            check(self.device.CreatePlacedResource(
+                heap.as_raw(),
+                allocation.offset,
+                &desc,
+                state,
+                null(),
+                &ID3D12Resource::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            Ok(ComPtr::from_raw(ptr))
+        }
+    }
+
+    /// Render targets standing in for the swap chain buffers
+    fn create_offscreen_buffers(&mut self, desc: &SwapChainDesc) -> Result<(), HRESULT> {
+        let props = heap_properties(D3D12_HEAP_TYPE_DEFAULT);
+        let texture = texture_desc(desc.width, desc.height, desc.format);
+        self.resources = (0..desc.buffer_count)
+            .map(|_| unsafe {
+                let mut ptr = null_mut::<ID3D12Resource>();
+                check(self.device.CreateCommittedResource(
+                    &props,
+                    D3D12_HEAP_FLAG_NONE,
+                    &texture,
+                    D3D12_RESOURCE_STATE_PRESENT,
+                    null(),
+                    &ID3D12Resource::uuidof(),
+                    &mut ptr as *mut *mut _ as *mut *mut _,
+                ))?;
+                Ok(ComPtr::from_raw(ptr))
+            })
+            .collect::<Result<Vec<_>, HRESULT>>()?;
+        self.back_buffer = 0;
+        Ok(())
+    }
+}
+
+impl Backend for D3D12 {
+    type Error = HRESULT;
+
+    fn create_command_queue(&mut self, desc: &CommandQueueDesc) -> Result<(), HRESULT> {
+        let queue = unsafe {
+            let mut ptr = null_mut::<ID3D12CommandQueue>();
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                Type: desc.list_type as _,
+                Priority: desc.priority as _,
+                Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            check(self.device.CreateCommandQueue(
+                &desc,
+                &ID3D12CommandQueue::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+        self.fence = Some(D3D12Fence::new(&self.device, queue.clone())?);
+        self.queue = Some(queue);
+        Ok(())
+    }
+
+    fn create_swap_chain(&mut self, desc: &SwapChainDesc) -> Result<(), HRESULT> {
+        self.back_buffer_size = (desc.width, desc.height);
+        let hwnd = match self.hwnd {
+            Some(hwnd) => hwnd,
+            None => return self.create_offscreen_buffers(desc),
+        };
+
+        // Create swap chain for composition
+        let swap_chain = unsafe {
+            let desc = DXGI_SWAP_CHAIN_DESC1 {
+                AlphaMode: desc.alpha_mode as _,
+                BufferCount: desc.buffer_count,
+                Width: desc.width,
+                Height: desc.height,
+                Format: desc.format as _,
+                Flags: 0,
+                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Scaling: DXGI_SCALING_STRETCH,
+                Stereo: TRUE,
+                SwapEffect: DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+            };
+            let mut ptr = null_mut::<IDXGISwapChain1>();
+            check(self.factory.CreateSwapChainForComposition(
+                self.queue().as_raw() as _,
+                &desc,
+                null_mut(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        }
+        .cast::<IDXGISwapChain3>()?;
+
+        // Create IDCompositionTarget for the window
+        let comp_target = unsafe {
+            let mut ptr = null_mut::<IDCompositionTarget>();
+            check(self.comp_device.CreateTargetForHwnd(
+                hwnd as _,
+                1,
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+
+        // Create IDCompositionVisual for the window
+        let comp_visual = unsafe {
+            let mut ptr = null_mut::<IDCompositionVisual>();
+            check(
+                self.comp_device
+                    .CreateVisual(&mut ptr as *mut *mut _ as *mut *mut _),
+            )?;
+            ComPtr::from_raw(ptr)
+        };
+
+        // Set swap_chain and the root visual and commit
+        unsafe {
+            check(comp_visual.SetContent(swap_chain.as_raw() as _))?;
+            check(comp_target.SetRoot(comp_visual.as_raw() as _))?;
+            check(self.comp_device.Commit())?;
+        }
+
+        // Back buffers are the first resources
+        self.resources = (0..desc.buffer_count)
+            .map(|i| unsafe {
+                let mut ptr = null_mut::<ID3D12Resource>();
+                check(swap_chain.GetBuffer(
+                    i,
+                    &ID3D12Resource::uuidof(),
+                    &mut ptr as *mut *mut _ as *mut *mut _,
+                ))?;
+                Ok(ComPtr::from_raw(ptr))
+            })
+            .collect::<Result<Vec<_>, HRESULT>>()?;
+
+        self.swap_chain = Some(swap_chain);
+        self.comp_target = Some(comp_target);
+        self.comp_visual = Some(comp_visual);
+        Ok(())
+    }
+
+    fn create_descriptor_heap(
+        &mut self,
+        desc: &DescriptorHeapDesc,
+    ) -> Result<DescriptorHeap, HRESULT> {
+        let heap = unsafe {
+            let d3d_desc = D3D12_DESCRIPTOR_HEAP_DESC {
+                Type: desc.heap_type as _,
+                NumDescriptors: desc.num_descriptors,
+                Flags: if desc.shader_visible {
+                    D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE
+                } else {
+                    D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+                },
+                NodeMask: 0,
+            };
+            let mut ptr = null_mut::<ID3D12DescriptorHeap>();
+            check(self.device.CreateDescriptorHeap(
+                &d3d_desc,
+                &ID3D12DescriptorHeap::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+        let (start, increment) = unsafe {
+            (
+                heap.GetCPUDescriptorHandleForHeapStart().ptr,
+                self.device
+                    .GetDescriptorHandleIncrementSize(desc.heap_type as _) as usize,
+            )
+        };
+        self.desc_heaps.push(heap);
+        Ok(DescriptorHeap {
+            desc: *desc,
+            start: CpuDescriptorHandle(start),
+            increment,
+        })
+    }
+
+    fn create_render_target_view(
+        &mut self,
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    ) -> Result<(), HRESULT> {
+        unsafe {
+            // let desc = D3D12_TEX2D_RTV {
+            //     Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
+            //     ViewDimension: 0,
+            // };
+            self.device.CreateRenderTargetView(
+                self.resources[resource.0 as usize].as_raw(),
+                0 as _,
+                D3D12_CPU_DESCRIPTOR_HANDLE { ptr: handle.0 },
+            );
+        }
+        Ok(())
+    }
+
+    fn create_command_allocators(&mut self, count: usize) -> Result<(), HRESULT> {
+        self.allocators.clear();
+        for _ in 0..count {
+            let allocator = unsafe {
+                let mut ptr = null_mut::<ID3D12CommandAllocator>();
+                check(self.device.CreateCommandAllocator(
+                    D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    &ID3D12CommandAllocator::uuidof(),
+                    &mut ptr as *mut *mut _ as *mut *mut _,
+                ))?;
+                ComPtr::from_raw(ptr)
+            };
+            self.allocators.push(allocator);
+        }
+        Ok(())
+    }
+
+    fn create_command_list(&mut self) -> Result<(), HRESULT> {
+        let list = unsafe {
+            let mut ptr = null_mut::<ID3D12GraphicsCommandList>();
+            check(self.device.CreateCommandList(
+                0,
+                D3D12_COMMAND_LIST_TYPE_DIRECT,
+                self.allocators[0].as_raw(),
+                self.pipeline_state.as_raw(),
+                &ID3D12GraphicsCommandList::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            let ptr = ComPtr::from_raw(ptr);
+            check(ptr.Close())?;
+            ptr
+        };
+        self.list = Some(list);
+        Ok(())
+    }
+
+    fn create_vertex_buffer(
+        &mut self,
+        data: &[u8],
+        stride: u32,
+    ) -> Result<VertexBufferView, HRESULT> {
+        let vertex_buffer = self.create_placed_buffer(HeapType::Upload, data.len() as _)?;
+        let vertex_buffer_view = unsafe {
+            let mut gpu_data = null_mut::<u8>();
+            check(vertex_buffer.Map(
+                0,
+                &D3D12_RANGE { Begin: 0, End: 0 },
+                &mut gpu_data as *mut *mut _ as *mut *mut _,
+            ))?;
+            if gpu_data.is_null() {
+                panic!("Nullptr");
+            }
+            std::ptr::copy_nonoverlapping(data.as_ptr(), gpu_data, data.len());
+            vertex_buffer.Unmap(0, null());
+            VertexBufferView {
+                buffer_location: vertex_buffer.GetGPUVirtualAddress(),
+                stride_in_bytes: stride,
+                size_in_bytes: data.len() as _,
+            }
+        };
+        self.buffers.push(vertex_buffer);
+        Ok(vertex_buffer_view)
+    }
+
+    fn create_upload_buffer(&mut self, size: u64) -> Result<u64, HRESULT> {
+        let buffer = self.create_placed_buffer(HeapType::Upload, size)?;
+        unsafe {
+            // Stays mapped, the CPU never reads it back
+            let mut data = null_mut::<u8>();
+            check(buffer.Map(
+                0,
+                &D3D12_RANGE { Begin: 0, End: 0 },
+                &mut data as *mut *mut _ as *mut *mut _,
+            ))?;
+            let address = buffer.GetGPUVirtualAddress();
+            self.uploads.push((buffer, address, data, size as _));
+            Ok(address)
+        }
+    }
+
+    fn upload_memory(&mut self, address: u64) -> Option<&mut [u8]> {
+        self.uploads
+            .iter()
+            .find(|(_, start, _, size)| address >= *start && address < *start + *size as u64)
+            .map(|&(_, start, data, size)| unsafe {
+                let offset = (address - start) as usize;
+                std::slice::from_raw_parts_mut(data.add(offset), size - offset)
+            })
+    }
+
+    fn reset_command_list(&mut self, allocator: usize) -> Result<(), HRESULT> {
+        let allocator = &self.allocators[allocator];
+        unsafe {
+            check(allocator.Reset())?;
+            check(
+                self.list()
+                    .Reset(allocator.as_raw(), self.pipeline_state.as_raw()),
+            )
+        }
+    }
+
+    fn set_graphics_root_signature(&mut self) {
+        unsafe {
+            self.list()
+                .SetGraphicsRootSignature(self.root_signature.as_raw())
+        }
+    }
+
+    fn set_viewports(&mut self, viewports: &[Viewport]) {
+        let viewports = viewports.iter().map(viewport).collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .RSSetViewports(viewports.len() as _, viewports.as_ptr())
+        }
+    }
+
+    fn set_scissor_rects(&mut self, rects: &[Rect]) {
+        let rects = rects.iter().map(rect).collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .RSSetScissorRects(rects.len() as _, rects.as_ptr())
+        }
+    }
+
+    fn resource_barrier(&mut self, barriers: &[Barrier]) {
+        let barriers = barriers
+            .iter()
+            .map(|b| match *b {
+                Barrier::Transition {
+                    resource,
+                    subresource,
+                    before,
+                    after,
+                } => {
+                    let mut barrier = D3D12_RESOURCE_BARRIER {
+                        Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                        Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                        ..unsafe { mem::zeroed() }
+                    };
+                    unsafe {
+                        *barrier.u.Transition_mut() = D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: self.resources[resource.0 as usize].as_raw(),
+                            Subresource: subresource,
+                            StateBefore: before.0,
+                            StateAfter: after.0,
+                        };
+                    }
+                    barrier
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .ResourceBarrier(barriers.len() as _, barriers.as_ptr())
+        }
+    }
+
+    fn set_render_targets(&mut self, rtvs: &[CpuDescriptorHandle]) {
+        let rtvs = rtvs
+            .iter()
+            .map(|h| D3D12_CPU_DESCRIPTOR_HANDLE { ptr: h.0 })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .OMSetRenderTargets(rtvs.len() as _, rtvs.as_ptr(), 0, ptr::null())
+        }
+    }
+
+    fn clear_render_target_view(&mut self, rtv: CpuDescriptorHandle, color: [f32; 4]) {
+        unsafe {
+            self.list().ClearRenderTargetView(
+                D3D12_CPU_DESCRIPTOR_HANDLE { ptr: rtv.0 },
+                &color,
+                0,
+                null(),
+            )
+        }
+    }
+
+    fn set_primitive_topology(&mut self, topology: PrimitiveTopology) {
+        unsafe { self.list().IASetPrimitiveTopology(topology as _) }
+    }
+
+    fn set_vertex_buffers(&mut self, start_slot: u32, views: &[VertexBufferView]) {
+        let views = views
+            .iter()
+            .map(|v| D3D12_VERTEX_BUFFER_VIEW {
+                BufferLocation: v.buffer_location,
+                StrideInBytes: v.stride_in_bytes,
+                SizeInBytes: v.size_in_bytes,
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .IASetVertexBuffers(start_slot, views.len() as _, views.as_ptr())
+        }
+    }
+
+    fn draw_instanced(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) {
+        unsafe {
+            self.list()
+                .DrawInstanced(vertex_count, instance_count, start_vertex, start_instance)
+        }
+    }
+
+    fn close_command_list(&mut self) -> Result<(), HRESULT> {
+        unsafe { check(self.list().Close()) }
+    }
+
+    fn execute_command_list(&mut self) -> Result<(), HRESULT> {
+        unsafe {
+            let lists = [self.list().as_raw().cast::<ID3D12CommandList>()];
+            self.queue()
+                .ExecuteCommandLists(lists.len() as _, lists.as_ptr());
+        }
+        Ok(())
+    }
+
+    fn signal(&mut self, value: u64) -> Result<(), HRESULT> {
+        self.fence().signal(value)
+    }
+
+    fn completed_value(&self) -> u64 {
+        self.fence
+            .as_ref()
+            .map_or(0, |fence| fence.completed_value())
+    }
+
+    fn wait_for_value(&mut self, value: u64) -> Result<(), HRESULT> {
+        self.fence().wait(value)
+    }
+
+    fn current_back_buffer_index(&self) -> usize {
+        match &self.swap_chain {
+            Some(swap_chain) => unsafe { swap_chain.GetCurrentBackBufferIndex() as usize },
+            None => self.back_buffer,
+        }
+    }
+
+    fn present(&mut self, sync_interval: u32, flags: u32) -> Result<(), HRESULT> {
+        match &self.swap_chain {
+            Some(swap_chain) => unsafe { check(swap_chain.Present(sync_interval, flags)) }?,
+            // Offscreen buffers take turns like the swap chain ones
+            None => self.back_buffer = (self.back_buffer + 1) % self.resources.len(),
+        }
+        println!("Render");
+        Ok(())
+    }
+
+    fn read_back_buffer(&mut self, index: usize) -> Result<Image, HRESULT> {
+        let (width, height) = self.back_buffer_size;
+        let texture = texture_desc(width, height, BACK_BUFFER_FORMAT);
+        let mut footprint = unsafe { mem::zeroed::<D3D12_PLACED_SUBRESOURCE_FOOTPRINT>() };
+        let mut total = 0u64;
+        unsafe {
+            self.device.GetCopyableFootprints(
+                &texture,
+                0,
+                1,
+                0,
+                &mut footprint,
+                null_mut(),
+                null_mut(),
+                &mut total,
+            );
+        }
+
+        let readback = unsafe {
+            let mut ptr = null_mut::<ID3D12Resource>();
+            check(self.device.CreateCommittedResource(
+                &heap_properties(D3D12_HEAP_TYPE_READBACK),
+                D3D12_HEAP_FLAG_NONE,
+                &buffer_desc(total),
+                D3D12_RESOURCE_STATE_COPY_DEST,
+                null(),
+                &ID3D12Resource::uuidof(),
+                &mut ptr as *mut *mut _ as *mut *mut _,
+            ))?;
+            ComPtr::from_raw(ptr)
+        };
+
+        // Copy with the command list, the frame has been waited for already
+        let back_buffer = ResourceId::back_buffer(index);
+        unsafe {
+            let allocator = &self.allocators[0];
+            check(allocator.Reset())?;
+            check(self.list().Reset(allocator.as_raw(), null_mut()))?;
+        }
+        self.resource_barrier(&[Barrier::transition(
+            back_buffer,
+            ResourceStates::PRESENT,
+            ResourceStates::COPY_SOURCE,
+        )]);
+        unsafe {
+            let mut dest = D3D12_TEXTURE_COPY_LOCATION {
+                pResource: readback.as_raw(),
+                Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+                ..mem::zeroed()
+            };
+            *dest.u.PlacedFootprint_mut() = footprint;
+            let mut source = D3D12_TEXTURE_COPY_LOCATION {
+                pResource: self.resources[index].as_raw(),
+                Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+                ..mem::zeroed()
+            };
+            *source.u.SubresourceIndex_mut() = 0;
+            self.list()
+                .CopyTextureRegion(&dest, 0, 0, 0, &source, null());
+        }
+        self.resource_barrier(&[Barrier::transition(
+            back_buffer,
+            ResourceStates::COPY_SOURCE,
+            ResourceStates::PRESENT,
+        )]);
+        self.close_command_list()?;
+        self.execute_command_list()?;
+
+        // Own timeline, the values of the frame fence belong to the renderer
+        let mut timeline = Timeline::new(D3D12Fence::new(&self.device, self.queue().clone())?);
+        let copied = timeline.signal()?;
+        timeline.wait(copied)?;
+
+        // Rows of the footprint are padded to 256 bytes
+        let mut image = Image::new(width, height);
+        let row = width as usize * 4;
+        let pitch = footprint.Footprint.RowPitch as usize;
+        unsafe {
+            let mut data = null_mut::<u8>();
+            check(readback.Map(
+                0,
+                &D3D12_RANGE {
+                    Begin: 0,
+                    End: total as _,
+                },
+                &mut data as *mut *mut _ as *mut *mut _,
+            ))?;
+            for y in 0..height as usize {
+                ptr::copy_nonoverlapping(
+                    data.add(footprint.Offset as usize + y * pitch),
+                    image.data[y * row..].as_mut_ptr(),
+                    row,
+                );
+            }
+            readback.Unmap(0, &D3D12_RANGE { Begin: 0, End: 0 });
+        }
+        Ok(image)
+    }
+}
+
+unsafe impl Send for D3D12 {}
+unsafe impl Sync for D3D12 {}
+
+fn heap_properties(heap_type: D3D12_HEAP_TYPE) -> D3D12_HEAP_PROPERTIES {
+    D3D12_HEAP_PROPERTIES {
+        Type: heap_type,
+        CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        CreationNodeMask: 1,
+        VisibleNodeMask: 1,
+        MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+    }
+}
+
+fn buffer_desc(width: u64) -> D3D12_RESOURCE_DESC {
+    D3D12_RESOURCE_DESC {
+        Alignment: 0,
+        Flags: D3D12_RESOURCE_FLAG_NONE,
+        Dimension: D3D12_RESOURCE_DIMENSION_BUFFER,
+        DepthOrArraySize: 1,
+        Format: DXGI_FORMAT_UNKNOWN,
+        Height: 1,
+        Width: width,
+        Layout: D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        MipLevels: 1,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+    }
+}
+
+fn texture_desc(width: u32, height: u32, format: Format) -> D3D12_RESOURCE_DESC {
+    D3D12_RESOURCE_DESC {
+        Alignment: 0,
+        Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+        Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        DepthOrArraySize: 1,
+        Format: format as _,
+        Height: height,
+        Width: width as u64,
+        Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+        MipLevels: 1,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+    }
+}
+
+fn viewport(v: &Viewport) -> D3D12_VIEWPORT {
+    D3D12_VIEWPORT {
+        Width: v.width,
+        Height: v.height,
+        MaxDepth: v.max_depth,
+        MinDepth: v.min_depth,
+        TopLeftX: v.top_left_x,
+        TopLeftY: v.top_left_y,
+    }
+}
+
+fn rect(r: &Rect) -> D3D12_RECT {
+    D3D12_RECT {
+        top: r.top,
+        left: r.left,
+        bottom: r.bottom,
+        right: r.right,
+    }
+}
+
+/// `HelloTriangleRS` that `build.rs` serialized, release builds don't
+/// carry the HLSL it is declared in
+#[cfg(not(debug_assertions))]
+fn root_signature() -> Vec<u8> {
+    include_bytes!(concat!(env!("OUT_DIR"), "/simple.rts0")).to_vec()
+}
+
+/// Read from simple.hlsl so edits to the declaration show up without a rebuild
+#[cfg(debug_assertions)]
+fn root_signature() -> Vec<u8> {
+    let resolver = Embedded::default();
+    let mut preprocessor = Preprocessor::new(&resolver);
+    preprocessor
+        .run("simple.hlsl", include_str!("./simple.hlsl"))
+        .expect("Unable to preprocess simple.hlsl");
+    hlsl::from_define(&preprocessor, "HelloTriangleRS", RootSignatureVersion::V1_0)
+        .unwrap_or_else(|e| panic!("Invalid root signature\n{}", e))
+        .to_bytes()
+        .expect("Unable to serialize root signature")
+}
+
+/// Shader model of the embedded library, `build.rs` compiles with DXC
+#[cfg(not(debug_assertions))]
+fn shader_model() -> &'static str {
+    "6_0"
+}
+
+/// Shader model of the compiler `compile_shaders` picks
+#[cfg(debug_assertions)]
+fn shader_model() -> &'static str {
+    if std::env::var_os("DXC").is_some() {
+        "6_0"
+    } else {
+        "5_0"
+    }
+}
+
+/// Blobs `build.rs` compiled, no HLSL or runtime compiler in release builds
+#[cfg(not(debug_assertions))]
+fn shader_library() -> ShaderLibrary {
+    ShaderLibrary::from_bytes(include_bytes!(concat!(env!("OUT_DIR"), "/simple.dxlib")))
+        .expect("Embedded shader library is malformed")
+}
+
+/// Compiled at startup so shader edits show up without a rebuild
+#[cfg(debug_assertions)]
+fn shader_library() -> ShaderLibrary {
+    compile_shaders(include_str!("./simple.hlsl"), &Embedded::default())
+        .unwrap_or_else(|e| panic!("Could not compile shaders\n{}", e))
+}
+
+/// Variants of `simple.hlsl` with `D3DCompile`, or with DXC when `DXC`
+/// names it. Only ones whose preprocessed source changed are recompiled
+#[cfg(debug_assertions)]
+fn compile_shaders(source: &str, resolver: &dyn IncludeResolver) -> Result<ShaderLibrary, String> {
+    let compiler: Box<dyn Compiler> = match std::env::var_os("DXC") {
+        Some(_) => Box::new(Dxc::from_env()),
+        None => Box::new(D3DCompiler),
+    };
+    let compilation = Build::new(&*compiler)
+        .cache(cache::default_dir())
+        .compile(
+            &permutation::hello_triangle(shader_model()),
+            "simple.hlsl",
+            source,
+            resolver,
+        )
+        .map_err(|e| e.to_string())?;
+    for (variant, warning) in &compilation.warnings {
+        eprintln!("{}: {}", variant, warning);
+    }
+    Ok(compilation.library)
+}
+
+fn create_pipeline_state(
+    device: &ComPtr<ID3D12Device>,
+    root_signature: &ComPtr<ID3D12RootSignature>,
+    shaders: &ShaderLibrary,
+    definition: &PipelineDefinition,
+) -> Result<ComPtr<ID3D12PipelineState>, String> {
+    let shader = |entry_point: &str, stage: &str| {
+        let profile = format!("{}_{}", stage, shader_model());
+        shaders
+            .get(entry_point, &profile, &definition.defines)
+            .ok_or_else(|| {
+                format!(
+                    "{} has no {} ({}) variant with {}",
+                    definition.shader, entry_point, profile, definition.defines
+                )
+            })
+    };
+    let vertex_shader = shader(&definition.vertex_entry_point, "vs")?;
+    let pixel_shader = shader(&definition.pixel_entry_point, "ps")?;
+
+    // The scene's vertex buffers hold `Vertex`
+    if definition.input_layout != Vertex::LAYOUT {
+        return Err(format!(
+            "{} input is not the layout of Vertex",
+            definition.name
+        ));
+    }
+    // Layout drift would otherwise only show up in the debug layer
+    let signature = Container::parse(vertex_shader)
+        .and_then(|container| container.input_signature())
+        .map_err(|e| {
+            format!(
+                "{} has no input signature: {}",
+                definition.vertex_entry_point, e
+            )
+        })?;
+    if let Err(errors) = layout::check(&definition.input_layout, &signature) {
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        return Err(format!(
+            "Input layout does not match {}: {}",
+            definition.vertex_entry_point,
+            errors.join(", ")
+        ));
+    }
+    let pipeline = definition.desc(vertex_shader, pixel_shader);
+
+    // Semantic names must outlive the pipeline creation
+    let semantic_names = pipeline
+        .input_layout
+        .iter()
+        .map(|el| CString::new(el.semantic_name).unwrap())
+        .collect::<Vec<_>>();
+    let els = pipeline
+        .input_layout
+        .iter()
+        .zip(semantic_names.iter())
+        .map(|(el, name)| D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: name.as_ptr(),
+            SemanticIndex: el.semantic_index,
+            Format: el.format as _,
+            InputSlot: el.input_slot,
+            InstanceDataStepRate: el.instance_data_step_rate,
+            InputSlotClass: el.input_slot_class as _,
+            AlignedByteOffset: el.aligned_byte_offset,
+        })
+        .collect::<Vec<_>>();
+    let pso_desc = graphics_pipeline_desc(&pipeline, root_signature.as_raw(), &els);
+
+    unsafe {
+        let mut ptr = null_mut::<ID3D12PipelineState>();
+        let hr = device.CreateGraphicsPipelineState(
+            &pso_desc,
+            &ID3D12PipelineState::uuidof(),
+            &mut ptr as *mut *mut _ as *mut *mut _,
+        );
+        check(hr).map_err(|hr| format!("CreateGraphicsPipelineState failed {:#x}", hr))?;
+        Ok(ComPtr::from_raw(ptr))
+    }
+}
+
+fn graphics_pipeline_desc(
+    desc: &GraphicsPipelineDesc,
+    root_signature: *mut ID3D12RootSignature,
+    input_elements: &[D3D12_INPUT_ELEMENT_DESC],
+) -> D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+    D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        pRootSignature: root_signature,
+        VS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: desc.vertex_shader.len(),
+            pShaderBytecode: desc.vertex_shader.as_ptr() as _,
+        },
+        PS: D3D12_SHADER_BYTECODE {
+            BytecodeLength: desc.pixel_shader.len(),
+            pShaderBytecode: desc.pixel_shader.as_ptr() as _,
+        },
+        BlendState: blend_desc(&desc.blend),
+        SampleMask: desc.sample_mask,
+        RasterizerState: rasterizer_desc(&desc.rasterizer),
+        DepthStencilState: depth_stencil_desc(&desc.depth_stencil),
+        InputLayout: D3D12_INPUT_LAYOUT_DESC {
+            NumElements: input_elements.len() as u32,
+            pInputElementDescs: input_elements.as_ptr(),
+        },
+        PrimitiveTopologyType: desc.primitive_topology_type as _,
+        NumRenderTargets: desc.render_target_formats.len() as u32,
+        RTVFormats: desc.rtv_formats().map(|format| format as _),
+        DSVFormat: desc.depth_stencil_format as _,
+        SampleDesc: DXGI_SAMPLE_DESC {
+            Count: desc.sample_desc.count,
+            Quality: desc.sample_desc.quality,
+        },
+        // No geometry or tessellation stages, stream output or cached blob
+        ..unsafe { mem::zeroed() }
+    }
+}
+
+fn rasterizer_desc(desc: &RasterizerDesc) -> D3D12_RASTERIZER_DESC {
+    D3D12_RASTERIZER_DESC {
+        FillMode: desc.fill_mode as _,
+        CullMode: desc.cull_mode as _,
+        FrontCounterClockwise: desc.front_counter_clockwise as _,
+        DepthBias: desc.depth_bias,
+        DepthBiasClamp: desc.depth_bias_clamp,
+        SlopeScaledDepthBias: desc.slope_scaled_depth_bias,
+        DepthClipEnable: desc.depth_clip_enable as _,
+        MultisampleEnable: desc.multisample_enable as _,
+        AntialiasedLineEnable: desc.antialiased_line_enable as _,
+        ForcedSampleCount: desc.forced_sample_count,
+        ConservativeRaster: desc.conservative_raster as _,
+    }
+}
+
+fn blend_desc(desc: &BlendDesc) -> D3D12_BLEND_DESC {
+    D3D12_BLEND_DESC {
+        AlphaToCoverageEnable: desc.alpha_to_coverage_enable as _,
+        IndependentBlendEnable: desc.independent_blend_enable as _,
+        RenderTarget: desc
+            .render_target
+            .iter()
+            .map(|rt| D3D12_RENDER_TARGET_BLEND_DESC {
+                BlendEnable: rt.blend_enable as _,
+                LogicOpEnable: rt.logic_op_enable as _,
+                SrcBlend: rt.src_blend as _,
+                DestBlend: rt.dest_blend as _,
+                BlendOp: rt.blend_op as _,
+                SrcBlendAlpha: rt.src_blend_alpha as _,
+                DestBlendAlpha: rt.dest_blend_alpha as _,
+                BlendOpAlpha: rt.blend_op_alpha as _,
+                LogicOp: rt.logic_op as _,
+                RenderTargetWriteMask: rt.render_target_write_mask,
+            })
+            .collect::<Vec<_>>()
+            .as_slice()
+            .try_into()
+            .unwrap(),
+    }
+}
+
+fn depth_stencil_desc(desc: &DepthStencilDesc) -> D3D12_DEPTH_STENCIL_DESC {
+    let face = |op: &DepthStencilOpDesc| D3D12_DEPTH_STENCILOP_DESC {
+        StencilFailOp: op.stencil_fail_op as _,
+        StencilDepthFailOp: op.stencil_depth_fail_op as _,
+        StencilPassOp: op.stencil_pass_op as _,
+        StencilFunc: op.stencil_func as _,
+    };
+    D3D12_DEPTH_STENCIL_DESC {
+        DepthEnable: desc.depth_enable as _,
+        DepthWriteMask: desc.depth_write_mask as _,
+        DepthFunc: desc.depth_func as _,
+        StencilEnable: desc.stencil_enable as _,
+        StencilReadMask: desc.stencil_read_mask,
+        StencilWriteMask: desc.stencil_write_mask,
+        FrontFace: face(&desc.front_face),
+        BackFace: face(&desc.back_face),
+    }
+}
+
+/// `D3DCompile` of `d3dcompiler_47.dll`, shader model 5.1 and below
+#[cfg(debug_assertions)]
+struct D3DCompiler;
+
+#[cfg(debug_assertions)]
+impl Compiler for D3DCompiler {
+    fn name(&self) -> &str {
+        "d3dcompiler_47"
+    }
+
+    fn compile(&self, request: &Request) -> Result<Compiled, CompileError> {
+        let entry_point = CString::new(request.entry_point).unwrap();
+        let profile = CString::new(request.profile).unwrap();
+        unsafe {
+            let mut err = null_mut::<ID3DBlob>();
+            let mut ptr = null_mut::<ID3DBlob>();
+            // File names come from the `#line` directives of the source
+            let hr = D3DCompile(
+                request.source.as_ptr() as LPCVOID,
+                request.source.len(),
+                null(),
+                null(),
+                null_mut(),
+                entry_point.as_ptr(),
+                profile.as_ptr(),
+                request.flags,
+                0,
+                &mut ptr,
+                &mut err,
+            );
+            // Errors on failure, warnings otherwise
+            let output = if err.is_null() {
+                String::new()
+            } else {
+                let err = ComPtr::from_raw(err);
+                String::from_utf8_lossy(blob_bytes(&err))
+                    .trim_end_matches('\0')
+                    .to_string()
+            };
+            if hr < 0 {
+                let output = if output.is_empty() {
+                    format!("D3DCompile failed with HRESULT {:#x}", hr)
+                } else {
+                    output
+                };
+                return Err(CompileError::failed(&output));
+            }
+            let blob = ComPtr::from_raw(ptr);
+            Ok(Compiled {
+                blob: blob_bytes(&blob).to_vec(),
+                diagnostics: output,
+            })
+        }
+    }
+}
+
+/// Contents of a blob, the blob keeps owning the memory
+#[cfg(debug_assertions)]
+fn blob_bytes(blob: &ID3DBlob) -> &[u8] {
+    unsafe { slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize()) }
+}
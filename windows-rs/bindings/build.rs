@@ -4,18 +4,19 @@ fn main() {
         windows::win32::direct3d_hlsl::*,
         windows::win32::dxgi::*,
         windows::win32::display_devices::RECT,
-        windows::win32::gdi::ValidateRect,
+        windows::win32::gdi::{InvalidateRect, ValidateRect},
         windows::win32::menus_and_resources::{HMENU, HICON},
         windows::win32::windows_and_messaging::{
             CreateWindowExA, DefWindowProcA, DispatchMessageA, GetMessageA, PostQuitMessage,
             TranslateMessage,
             RegisterClassA, LoadCursorA, HWND, LPARAM, MSG, WNDCLASSA, WPARAM,
-            IDC_ARROW, WM_CREATE,
-            WM_DESTROY, WM_PAINT, WINDOWS_STYLE, WINDOWS_EX_STYLE,
+            IDC_ARROW, WM_CREATE, SetTimer,
+            WM_DESTROY, WM_PAINT, WM_TIMER, WINDOWS_STYLE, WINDOWS_EX_STYLE,
         },
         windows::win32::system_services::{
             GetModuleHandleA, HINSTANCE, LRESULT, WINDOWSCLASS_STYLES, CreateEventA, WaitForSingleObject
         },
+        windows::win32::windows_programming::CloseHandle,
         windows::win32::direct_composition::{IDCompositionDevice, IDCompositionTarget, IDCompositionVisual, DCompositionCreateDevice}
     );
 }
@@ -3,60 +3,145 @@ use bindings::{
     windows::win32::direct_composition::*, windows::win32::display_devices::*,
     windows::win32::dxgi::*, windows::win32::gdi::*, windows::win32::menus_and_resources::*,
     windows::win32::system_services::*, windows::win32::windows_and_messaging::*,
+    windows::win32::windows_programming::CloseHandle,
 };
+use renderer::backend::{Backend, CommandQueueDesc, SwapChainDesc, VertexBufferView};
+use renderer::descriptor::{CpuDescriptorHandle, DescriptorHeap, DescriptorHeapDesc};
+use renderer::format::Format;
+use renderer::frame::Renderer;
+use renderer::headless::Headless;
+use renderer::image::Image;
+use renderer::memory::{AllocationInfo, HeapAllocator, HeapType, ResourceKind, DEFAULT_HEAP_SIZE};
+use renderer::pipeline::definition::{Definitions, PipelineDefinition};
+#[cfg(debug_assertions)]
+use renderer::pipeline::reload::Reloader;
+use renderer::pipeline::{
+    BlendDesc, DepthStencilDesc, DepthStencilOpDesc, GraphicsPipelineDesc, PrimitiveTopology,
+    RasterizerDesc,
+};
+use renderer::resource::{Barrier, ResourceId, ResourceStates};
+use renderer::scene::{Rect, Scene, Viewport, BACK_BUFFER_FORMAT};
+use renderer::shader::container::Container;
+use renderer::shader::layout;
+use renderer::shader::permutation::ShaderLibrary;
+use renderer::timeline::{Fence, Timeline};
+use renderer::vertex::{Vertex, VertexLayout};
+#[cfg(debug_assertions)]
+use std::path::Path;
 use std::ptr::null_mut;
+use std::task::Waker;
 use std::{convert::TryInto, ffi::CString};
 use windows::{Abi, Interface};
 
-const NUM_OF_FRAMES: usize = 2;
+type Window = Renderer<D3D12>;
 
-#[derive(Debug)]
-#[repr(C)]
-struct Vertex {
-    position: [f32; 3],
-    color: [f32; 4],
+fn create_window(hwnd: HWND) -> windows::Result<Window> {
+    let scene = Scene::hello_triangle(1024, 1024);
+    Renderer::new(D3D12::new(Some(hwnd))?, scene)
 }
-impl Vertex {
-    const fn new(position: [f32; 3], color: [f32; 4]) -> Self {
-        Self { position, color }
-    }
+
+/// Create drawing resources without a window, back buffers are plain textures
+fn create_headless() -> windows::Result<Window> {
+    let scene = Scene::hello_triangle(1024, 1024);
+    Renderer::new(D3D12::new(None)?, scene)
+}
+
+/// Pipeline of `simple.pipelines.toml` the scene is drawn with
+const PIPELINE: &str = "triangle";
+
+/// Rebuilds the pipeline after its definition or shader was edited, the
+/// current one stays when the edit doesn't compile. Whether it was rebuilt
+#[cfg(debug_assertions)]
+fn reload_pipelines(window: &mut Window) -> bool {
+    let d3d = window.backend_mut();
+    let rebuilt = match d3d.reloader.poll() {
+        Ok(changed) if changed.iter().any(|name| name == PIPELINE) => d3d.rebuild_pipeline(),
+        Ok(_) => return false,
+        Err(e) => Err(e.to_string()),
+    };
+    let (pipeline_state, shaders) = match rebuilt {
+        Ok(rebuilt) => rebuilt,
+        Err(e) => {
+            eprintln!("Pipeline {} not reloaded\n{}", PIPELINE, e);
+            return false;
+        }
+    };
+    // Frames in flight still use the previous pipeline
+    window.wait_for_gpu().expect("Unable to wait for GPU");
+    let d3d = window.backend_mut();
+    d3d.pipeline_state = pipeline_state;
+    d3d.shaders = shaders;
+    true
 }
 
 #[allow(dead_code)]
-struct Window {
-    hwnd: HWND,
+struct D3D12 {
+    /// Without a window the back buffers are offscreen textures
+    hwnd: Option<HWND>,
     factory: IDXGIFactory4,
     adapter: IDXGIAdapter1,
     device: ID3D12Device,
-    queue: ID3D12CommandQueue,
-    allocator: ID3D12CommandAllocator,
     comp_device: IDCompositionDevice,
-    swap_chain: IDXGISwapChain3,
-    comp_target: IDCompositionTarget,
-    comp_visual: IDCompositionVisual,
-    rtv_desc_heap: ID3D12DescriptorHeap,
-    rtv_desc_size: usize,
-    resources: [ID3D12Resource; NUM_OF_FRAMES],
     root_signature: ID3D12RootSignature,
-    list: ID3D12GraphicsCommandList,
-    vertex_shader: ID3DBlob,
-    pixel_shader: ID3DBlob,
+    shaders: ShaderLibrary,
     pipeline_state: ID3D12PipelineState,
-    viewport: D3D12_VIEWPORT,
-    scissor: RECT,
+    /// Edits of the definitions and shaders in `src` rebuild the pipeline
+    #[cfg(debug_assertions)]
+    reloader: Reloader,
 
-    // Synchronization
-    fence: ID3D12Fence,
-    fence_value: u64,
-    fence_event: HANDLE,
+    // Created by the renderer
+    queue: Option<ID3D12CommandQueue>,
+    swap_chain: Option<IDXGISwapChain3>,
+    comp_target: Option<IDCompositionTarget>,
+    comp_visual: Option<IDCompositionVisual>,
+    /// Command allocator per frame in flight
+    allocators: Vec<ID3D12CommandAllocator>,
+    list: Option<ID3D12GraphicsCommandList>,
+    desc_heaps: Vec<ID3D12DescriptorHeap>,
+
+    /// Back buffers, indexed by `ResourceId`
+    resources: Vec<ID3D12Resource>,
+    back_buffer_size: (u32, u32),
+
+    /// Current offscreen back buffer
+    back_buffer: usize,
+
+    /// Vertex buffers, kept alive for the GPU
+    buffers: Vec<ID3D12Resource>,
+
+    /// Upload buffers mapped for their lifetime, with address and memory
+    uploads: Vec<(ID3D12Resource, u64, *mut u8, usize)>,
+
+    /// Heaps of the placed buffers, dropped after them
+    memory: HeapAllocator<ID3D12Heap>,
 
-    // Resources
-    vertex_buffer: ID3D12Resource,
-    vertex_buffer_view: D3D12_VERTEX_BUFFER_VIEW,
+    /// Fence of the queue, the renderer chooses the values
+    fence: Option<D3D12Fence>,
 }
 
-impl Window {
-    pub fn new(hwnd: HWND) -> windows::Result<Self> {
+impl D3D12 {
+    /// Pipeline state and shaders of the current definition, with the shader
+    /// read from disk
+    #[cfg(debug_assertions)]
+    fn rebuild_pipeline(&self) -> Result<(ID3D12PipelineState, ShaderLibrary), String> {
+        use renderer::shader::preprocess::SearchPath;
+
+        let definition = self
+            .reloader
+            .definitions()
+            .get(PIPELINE)
+            .ok_or_else(|| format!("no {} pipeline", PIPELINE))?;
+        let path = self.reloader.shader_path(&definition.shader);
+        let source =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let shaders = compile_shaders(&source, &SearchPath::new(Some(dir)))?;
+        let pipeline_state =
+            create_pipeline_state(&self.device, &self.root_signature, &shaders, definition)?;
+        Ok((pipeline_state, shaders))
+    }
+
+    pub fn new(hwnd: Option<HWND>) -> windows::Result<Self> {
         // Start "DebugView" to listen errors
         // https://docs.microsoft.com/en-us/sysinternals/downloads/debugview
         let debug = unsafe {
@@ -93,44 +178,271 @@ impl Window {
             .and_some(ptr)
         }?;
 
-        let queue = unsafe {
-            let mut ptr: Option<ID3D12CommandQueue> = None;
-            let desc = D3D12_COMMAND_QUEUE_DESC {
-                r#type: D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
-                priority: D3D12_COMMAND_QUEUE_PRIORITY::D3D12_COMMAND_QUEUE_PRIORITY_HIGH.0,
-                flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
-                node_mask: 0,
-            };
+        // Composition device
+        let comp_device = unsafe {
+            let mut ptr: Option<IDCompositionDevice> = None;
+            DCompositionCreateDevice(None, &IDCompositionDevice::IID, ptr.set_abi()).and_some(ptr)
+        }?;
+
+        // Create root signature, declared in simple.hlsl and serialized in Rust
+        let root = root_signature();
+        let root_signature = unsafe {
+            let mut ptr: Option<ID3D12RootSignature> = None;
             device
-                .CreateCommandQueue(&desc, &ID3D12CommandQueue::IID, ptr.set_abi())
+                .CreateRootSignature(
+                    0,
+                    root.as_ptr() as _,
+                    root.len(),
+                    &ID3D12RootSignature::IID,
+                    ptr.set_abi(),
+                )
                 .and_some(ptr)
         }?;
 
-        let allocator = unsafe {
-            let mut ptr: Option<ID3D12CommandAllocator> = None;
+        let shaders = shader_library();
+        let definitions = Definitions::parse(include_str!("./simple.pipelines.toml"))
+            .unwrap_or_else(|e| panic!("Invalid simple.pipelines.toml\n{}", e));
+        let definition = definitions
+            .get(PIPELINE)
+            .expect("simple.pipelines.toml has no triangle pipeline");
+        let pipeline_state = create_pipeline_state(&device, &root_signature, &shaders, definition)
+            .unwrap_or_else(|e| panic!("Unable to create pipeline state: {}", e));
+
+        Ok(D3D12 {
+            hwnd,
+            factory,
+            adapter,
+            device,
+            comp_device,
+            root_signature,
+            pipeline_state,
+            shaders,
+            #[cfg(debug_assertions)]
+            reloader: Reloader::new(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("src/simple.pipelines.toml"),
+                definitions,
+            ),
+            queue: None,
+            swap_chain: None,
+            comp_target: None,
+            comp_visual: None,
+            allocators: Vec::new(),
+            list: None,
+            desc_heaps: Vec::new(),
+            resources: Vec::new(),
+            back_buffer_size: (0, 0),
+            back_buffer: 0,
+            buffers: Vec::new(),
+            uploads: Vec::new(),
+            memory: HeapAllocator::new(DEFAULT_HEAP_SIZE),
+            fence: None,
+        })
+    }
+
+    fn queue(&self) -> &ID3D12CommandQueue {
+        self.queue.as_ref().expect("Command queue is not created")
+    }
+
+    fn fence(&mut self) -> &mut D3D12Fence {
+        self.fence.as_mut().expect("Command queue is not created")
+    }
+
+    fn list(&self) -> &ID3D12GraphicsCommandList {
+        self.list.as_ref().expect("Command list is not created")
+    }
+
+    /// Buffer placed in a shared heap of the type
+    fn create_placed_buffer(
+        &mut self,
+        heap_type: HeapType,
+        size: u64,
+    ) -> windows::Result<ID3D12Resource> {
+        let desc = buffer_desc(size);
+        let info = unsafe { self.device.GetResourceAllocationInfo(0, 1, &desc) };
+        let device = &self.device;
+        let allocation = self.memory.allocate(
+            heap_type,
+            ResourceKind::Buffer,
+            &AllocationInfo {
+                size: info.size_in_bytes,
+                alignment: info.alignment,
+            },
+            |heap| {
+                let desc = D3D12_HEAP_DESC {
+                    size_in_bytes: heap.size,
+                    properties: heap_properties(D3D12_HEAP_TYPE(heap.heap_type as _)),
+                    alignment: heap.alignment,
+                    flags: D3D12_HEAP_FLAGS(heap.kind as _),
+                };
+                let mut ptr: Option<ID3D12Heap> = None;
+                unsafe { device.CreateHeap(&desc, &ID3D12Heap::IID, ptr.set_abi()) }.and_some(ptr)
+            },
+        )?;
+        let heap = &self
+            .memory
+            .heap(allocation.heap)
+            .expect("Heap is allocated")
+            .heap;
+        let state = match heap_type {
+            HeapType::Default => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COMMON,
+            HeapType::Upload => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+            HeapType::Readback => D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
+        };
+        unsafe {
+            let mut ptr: Option<ID3D12Resource> = None;
+            self.device
+                .CreatePlacedResource(
+                    heap,
+                    allocation.offset,
+                    &desc,
+                    state,
+                    null_mut(),
+                    &ID3D12Resource::IID,
+                    ptr.set_abi(),
+                )
+                .and_some(ptr)
+        }
+    }
+
+    /// Render targets standing in for the swap chain buffers
+    fn create_offscreen_buffers(&mut self, desc: &SwapChainDesc) -> windows::Result<()> {
+        let props = heap_properties(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_DEFAULT);
+        let texture = texture_desc(desc.width, desc.height, desc.format);
+        self.resources = (0..desc.buffer_count)
+            .map(|_| unsafe {
+                let mut ptr: Option<ID3D12Resource> = None;
+                self.device
+                    .CreateCommittedResource(
+                        &props,
+                        D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
+                        &texture,
+                        D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT,
+                        null_mut(),
+                        &ID3D12Resource::IID,
+                        ptr.set_abi(),
+                    )
+                    .and_some(ptr)
+            })
+            .collect::<Result<Vec<_>, windows::ErrorCode>>()?;
+        self.back_buffer = 0;
+        Ok(())
+    }
+}
+
+/// Fence signaled from a queue, waits block on an event
+struct D3D12Fence {
+    fence: ID3D12Fence,
+    queue: ID3D12CommandQueue,
+    event: HANDLE,
+}
+
+impl D3D12Fence {
+    fn new(device: &ID3D12Device, queue: ID3D12CommandQueue) -> windows::Result<Self> {
+        let fence = unsafe {
+            let mut ptr: Option<ID3D12Fence> = None;
             device
-                .CreateCommandAllocator(
-                    D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
-                    &ID3D12CommandAllocator::IID,
+                .CreateFence(
+                    0,
+                    D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE,
+                    &ID3D12Fence::IID,
                     ptr.set_abi(),
                 )
                 .and_some(ptr)
         }?;
+        Ok(D3D12Fence {
+            fence,
+            queue,
+            event: create_event(),
+        })
+    }
+}
 
-        // Composition device
-        let comp_device = unsafe {
-            let mut ptr: Option<IDCompositionDevice> = None;
-            DCompositionCreateDevice(None, &IDCompositionDevice::IID, ptr.set_abi()).and_some(ptr)
+impl Drop for D3D12Fence {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.event) };
+    }
+}
+
+fn create_event() -> HANDLE {
+    let event = unsafe { CreateEventA(null_mut(), false, false, PSTR(null_mut())) };
+    if event.0 == 0 {
+        panic!("Unable to create fence event");
+    }
+    event
+}
+
+impl Fence for D3D12Fence {
+    type Error = windows::Error;
+
+    fn signal(&mut self, value: u64) -> windows::Result<()> {
+        unsafe { self.queue.Signal(&self.fence, value).ok() }
+    }
+
+    fn completed_value(&self) -> u64 {
+        unsafe { self.fence.GetCompletedValue() }
+    }
+
+    fn wait(&self, value: u64) -> windows::Result<()> {
+        unsafe {
+            self.fence.SetEventOnCompletion(value, self.event).ok()?;
+            WaitForSingleObject(self.event, 0xFFFFFFFF);
+        }
+        Ok(())
+    }
+
+    /// Event of its own, set by the fence and waited for on another thread,
+    /// once per `Completion`
+    fn wake_on_completion(&self, value: u64, waker: Waker) -> windows::Result<()> {
+        let event = create_event();
+        if let Err(err) = unsafe { self.fence.SetEventOnCompletion(value, event).ok() } {
+            unsafe { CloseHandle(event) };
+            return Err(err);
+        }
+        std::thread::spawn(move || unsafe {
+            WaitForSingleObject(event, 0xFFFFFFFF);
+            CloseHandle(event);
+            waker.wake();
+        });
+        Ok(())
+    }
+}
+
+impl Backend for D3D12 {
+    type Error = windows::ErrorCode;
+
+    fn create_command_queue(&mut self, desc: &CommandQueueDesc) -> windows::Result<()> {
+        let queue = unsafe {
+            let mut ptr: Option<ID3D12CommandQueue> = None;
+            let desc = D3D12_COMMAND_QUEUE_DESC {
+                r#type: D3D12_COMMAND_LIST_TYPE(desc.list_type as _),
+                priority: desc.priority as _,
+                flags: D3D12_COMMAND_QUEUE_FLAGS::D3D12_COMMAND_QUEUE_FLAG_NONE,
+                node_mask: 0,
+            };
+            self.device
+                .CreateCommandQueue(&desc, &ID3D12CommandQueue::IID, ptr.set_abi())
+                .and_some(ptr)
         }?;
+        self.fence = Some(D3D12Fence::new(&self.device, queue.clone())?);
+        self.queue = Some(queue);
+        Ok(())
+    }
+
+    fn create_swap_chain(&mut self, desc: &SwapChainDesc) -> windows::Result<()> {
+        self.back_buffer_size = (desc.width, desc.height);
+        let hwnd = match self.hwnd {
+            Some(hwnd) => hwnd,
+            None => return self.create_offscreen_buffers(desc),
+        };
 
         // Create swap chain for composition
         let swap_chain = unsafe {
             let desc = DXGI_SWAP_CHAIN_DESC1 {
-                alpha_mode: DXGI_ALPHA_MODE::DXGI_ALPHA_MODE_PREMULTIPLIED,
-                buffer_count: NUM_OF_FRAMES as _,
-                width: 1024,
-                height: 1024,
-                format: DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM,
+                alpha_mode: DXGI_ALPHA_MODE(desc.alpha_mode as _),
+                buffer_count: desc.buffer_count,
+                width: desc.width,
+                height: desc.height,
+                format: DXGI_FORMAT(desc.format as _),
                 flags: 0,
                 buffer_usage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
                 sample_desc: DXGI_SAMPLE_DESC {
@@ -142,8 +454,8 @@ impl Window {
                 swap_effect: DXGI_SWAP_EFFECT::DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
             };
             let mut ptr: Option<IDXGISwapChain1> = None;
-            factory
-                .CreateSwapChainForComposition(&queue, &desc, None, &mut ptr)
+            self.factory
+                .CreateSwapChainForComposition(self.queue(), &desc, None, &mut ptr)
                 .and_some(ptr)
         }?
         .cast::<IDXGISwapChain3>()?;
@@ -151,7 +463,7 @@ impl Window {
         // Create IDCompositionTarget for the window
         let comp_target = unsafe {
             let mut ptr = None;
-            comp_device
+            self.comp_device
                 .CreateTargetForHwnd(hwnd, BOOL(1), &mut ptr)
                 .and_some(ptr)
         }?;
@@ -159,530 +471,835 @@ impl Window {
         // Create IDCompositionVisual for the window
         let comp_visual = unsafe {
             let mut ptr = None;
-            comp_device.CreateVisual(&mut ptr).and_some(ptr)
+            self.comp_device.CreateVisual(&mut ptr).and_some(ptr)
         }?;
 
         // Set swap_chain and the root visual and commit
         unsafe {
             comp_visual.SetContent(&swap_chain).ok()?;
             comp_target.SetRoot(&comp_visual).ok()?;
-            comp_device.Commit().ok()?;
+            self.comp_device.Commit().ok()?;
         }
 
-        // Create descriptor heap for render target views
-        let rtv_desc_heap = unsafe {
-            let desc = D3D12_DESCRIPTOR_HEAP_DESC {
-                r#type: D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-                num_descriptors: NUM_OF_FRAMES as _,
-                flags: D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE,
+        // Back buffers are the first resources
+        self.resources = (0..desc.buffer_count)
+            .map(|i| unsafe {
+                let mut ptr: Option<ID3D12Resource> = None;
+                swap_chain
+                    .GetBuffer(i, &ID3D12Resource::IID, ptr.set_abi())
+                    .and_some(ptr)
+            })
+            .collect::<Result<Vec<_>, windows::ErrorCode>>()?;
+
+        self.swap_chain = Some(swap_chain);
+        self.comp_target = Some(comp_target);
+        self.comp_visual = Some(comp_visual);
+        Ok(())
+    }
+
+    fn create_descriptor_heap(
+        &mut self,
+        desc: &DescriptorHeapDesc,
+    ) -> windows::Result<DescriptorHeap> {
+        let heap_type = D3D12_DESCRIPTOR_HEAP_TYPE(desc.heap_type as _);
+        let heap = unsafe {
+            let d3d_desc = D3D12_DESCRIPTOR_HEAP_DESC {
+                r#type: heap_type,
+                num_descriptors: desc.num_descriptors,
+                flags: if desc.shader_visible {
+                    D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_SHADER_VISIBLE
+                } else {
+                    D3D12_DESCRIPTOR_HEAP_FLAGS::D3D12_DESCRIPTOR_HEAP_FLAG_NONE
+                },
                 node_mask: 0,
             };
             let mut ptr: Option<ID3D12DescriptorHeap> = None;
-            device
-                .CreateDescriptorHeap(&desc, &ID3D12DescriptorHeap::IID, ptr.set_abi())
+            self.device
+                .CreateDescriptorHeap(&d3d_desc, &ID3D12DescriptorHeap::IID, ptr.set_abi())
                 .and_some(ptr)
         }?;
-
-        // Create resource per frame
-        let mut descriptor = unsafe { rtv_desc_heap.GetCPUDescriptorHandleForHeapStart() };
-        let rtv_desc_size = unsafe {
-            device.GetDescriptorHandleIncrementSize(
-                D3D12_DESCRIPTOR_HEAP_TYPE::D3D12_DESCRIPTOR_HEAP_TYPE_RTV,
-            ) as usize
+        let (start, increment) = unsafe {
+            (
+                heap.GetCPUDescriptorHandleForHeapStart().ptr,
+                self.device.GetDescriptorHandleIncrementSize(heap_type) as usize,
+            )
         };
-        let resources = (0..NUM_OF_FRAMES)
-            .map(|i| {
-                let resource = unsafe {
-                    let mut ptr: Option<ID3D12Resource> = None;
-                    swap_chain
-                        .GetBuffer(i as _, &ID3D12Resource::IID, ptr.set_abi())
-                        .and_some(ptr)
-                }?;
-
-                unsafe {
-                    // let desc = D3D12_TEX2D_RTV {
-                    //     Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                    //     u: D3D12_RTV_DIMENSION_UNKNOWN as _,
-                    //     ViewDimension: 0,
-                    // };
-                    device.CreateRenderTargetView(&resource, 0 as _, &descriptor);
-                    descriptor.ptr += rtv_desc_size;
-                }
+        self.desc_heaps.push(heap);
+        Ok(DescriptorHeap {
+            desc: *desc,
+            start: CpuDescriptorHandle(start),
+            increment,
+        })
+    }
 
-                Ok(resource)
-            })
-            .collect::<Result<Vec<_>, windows::ErrorCode>>()?
-            .try_into()
-            .expect("Unable to create resources");
+    fn create_render_target_view(
+        &mut self,
+        resource: ResourceId,
+        handle: CpuDescriptorHandle,
+    ) -> windows::Result<()> {
+        unsafe {
+            self.device.CreateRenderTargetView(
+                &self.resources[resource.0 as usize],
+                0 as _,
+                &D3D12_CPU_DESCRIPTOR_HANDLE { ptr: handle.0 },
+            );
+        }
+        Ok(())
+    }
 
-        // Create root signature
-        let root_signature = unsafe {
-            let root = {
-                let mut blob: Option<ID3DBlob> = None;
-                let mut error: Option<ID3DBlob> = None;
-
-                let desc = D3D12_ROOT_SIGNATURE_DESC {
-                    num_parameters: 0,
-                    p_parameters: null_mut() as _,
-                    num_static_samplers: 0,
-                    p_static_samplers: null_mut() as _,
-                    flags: D3D12_ROOT_SIGNATURE_FLAGS::D3D12_ROOT_SIGNATURE_FLAG_ALLOW_INPUT_ASSEMBLER_INPUT_LAYOUT,
-                };
-                D3D12SerializeRootSignature(
-                    &desc,
-                    D3D_ROOT_SIGNATURE_VERSION::D3D_ROOT_SIGNATURE_VERSION_1_0,
-                    &mut blob as _,
-                    &mut error as _,
-                )
-                .and_then(|| {
-                    if error.is_none() {
-                        blob.unwrap()
-                    } else {
-                        panic!("Root signature failed, error blob contains the error")
-                    }
-                })
+    fn create_command_allocators(&mut self, count: usize) -> windows::Result<()> {
+        self.allocators.clear();
+        for _ in 0..count {
+            let allocator = unsafe {
+                let mut ptr: Option<ID3D12CommandAllocator> = None;
+                self.device
+                    .CreateCommandAllocator(
+                        D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                        &ID3D12CommandAllocator::IID,
+                        ptr.set_abi(),
+                    )
+                    .and_some(ptr)
             }?;
+            self.allocators.push(allocator);
+        }
+        Ok(())
+    }
 
-            let mut ptr: Option<ID3D12RootSignature> = None;
-            device
-                .CreateRootSignature(
+    fn create_command_list(&mut self) -> windows::Result<()> {
+        // Create direct command list
+        let list = unsafe {
+            let mut ptr: Option<ID3D12GraphicsCommandList> = None;
+            self.device
+                .CreateCommandList(
                     0,
-                    root.GetBufferPointer(),
-                    root.GetBufferSize(),
-                    &ID3D12RootSignature::IID,
+                    D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
+                    &self.allocators[0],
+                    &self.pipeline_state,
+                    &ID3D12GraphicsCommandList::IID,
                     ptr.set_abi(),
                 )
                 .and_some(ptr)
         }?;
+        unsafe { list.Close() }.ok()?;
+        self.list = Some(list);
+        Ok(())
+    }
 
-        let vertex_shader = unsafe {
-            let data = include_bytes!("./simple.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
+    fn create_vertex_buffer(
+        &mut self,
+        data: &[u8],
+        stride: u32,
+    ) -> windows::Result<VertexBufferView> {
+        let vertex_buffer = self.create_placed_buffer(HeapType::Upload, data.len() as _)?;
+        let vertex_buffer_view = unsafe {
+            let mut gpu_data = null_mut::<u8>();
+            vertex_buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { begin: 0, end: 0 },
+                    &mut gpu_data as *mut *mut _ as *mut *mut _,
+                )
+                .ok()?;
 
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("simple.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("VSMain\0".as_ptr() as _),
-                PSTR("vs_5_0\0".as_ptr() as _),
-                0,
-                0,
-                &mut ptr,
-                &mut err,
-            )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
+            if gpu_data.is_null() {
+                panic!("Nullptr");
             }
+            std::ptr::copy_nonoverlapping(data.as_ptr(), gpu_data, data.len());
+
+            vertex_buffer.Unmap(0, null_mut());
+            let vertex_buffer_view = VertexBufferView {
+                buffer_location: vertex_buffer.GetGPUVirtualAddress(),
+                stride_in_bytes: stride,
+                size_in_bytes: data.len() as _,
+            };
+            (vertex_buffer, vertex_buffer_view)
         };
+        self.buffers.push(vertex_buffer);
+        Ok(vertex_buffer_view)
+    }
 
-        let pixel_shader = unsafe {
-            let data = include_bytes!("./simple.hlsl");
-            let mut err: Option<ID3DBlob> = None;
-            let mut ptr: Option<ID3DBlob> = None;
+    fn create_upload_buffer(&mut self, size: u64) -> windows::Result<u64> {
+        let buffer = self.create_placed_buffer(HeapType::Upload, size)?;
+        unsafe {
+            // Stays mapped, the CPU never reads it back
+            let mut data = null_mut::<u8>();
+            buffer
+                .Map(
+                    0,
+                    &D3D12_RANGE { begin: 0, end: 0 },
+                    &mut data as *mut *mut _ as *mut *mut _,
+                )
+                .ok()?;
+            let address = buffer.GetGPUVirtualAddress();
+            self.uploads.push((buffer, address, data, size as _));
+            Ok(address)
+        }
+    }
 
-            D3DCompile(
-                data.as_ptr() as *mut _,
-                data.len(),
-                PSTR("simple.hlsl\0".as_ptr() as _),
-                null_mut(),
-                None,
-                PSTR("PSMain\0".as_ptr() as _),
-                PSTR("ps_5_0\0".as_ptr() as _),
-                0,
+    fn upload_memory(&mut self, address: u64) -> Option<&mut [u8]> {
+        self.uploads
+            .iter()
+            .find(|(_, start, _, size)| address >= *start && address < *start + *size as u64)
+            .map(|&(_, start, data, size)| unsafe {
+                let offset = (address - start) as usize;
+                std::slice::from_raw_parts_mut(data.add(offset), size - offset)
+            })
+    }
+
+    fn reset_command_list(&mut self, allocator: usize) -> windows::Result<()> {
+        let allocator = &self.allocators[allocator];
+        unsafe {
+            // Reset allocator
+            allocator.Reset().ok()?;
+
+            // Reset list
+            self.list().Reset(allocator, &self.pipeline_state).ok()
+        }
+    }
+
+    fn set_graphics_root_signature(&mut self) {
+        unsafe { self.list().SetGraphicsRootSignature(&self.root_signature) }
+    }
+
+    fn set_viewports(&mut self, viewports: &[Viewport]) {
+        let viewports = viewports.iter().map(viewport).collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .RSSetViewports(viewports.len() as _, viewports.as_ptr())
+        }
+    }
+
+    fn set_scissor_rects(&mut self, rects: &[Rect]) {
+        let rects = rects.iter().map(rect).collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .RSSetScissorRects(rects.len() as _, rects.as_ptr())
+        }
+    }
+
+    fn resource_barrier(&mut self, barriers: &[Barrier]) {
+        let barriers = barriers
+            .iter()
+            .map(|b| match *b {
+                Barrier::Transition {
+                    resource,
+                    subresource,
+                    before,
+                    after,
+                } => unsafe {
+                    let mut barrier = D3D12_RESOURCE_BARRIER {
+                        r#type: D3D12_RESOURCE_BARRIER_TYPE::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                        flags: D3D12_RESOURCE_BARRIER_FLAGS::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                        ..std::mem::zeroed()
+                    };
+                    barrier.anonymous.transition.subresource = subresource;
+                    barrier.anonymous.transition.p_resource =
+                        self.resources[resource.0 as usize].abi();
+                    barrier.anonymous.transition.state_before = D3D12_RESOURCE_STATES(before.0);
+                    barrier.anonymous.transition.state_after = D3D12_RESOURCE_STATES(after.0);
+                    barrier
+                },
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .ResourceBarrier(barriers.len() as _, barriers.as_ptr())
+        }
+    }
+
+    fn set_render_targets(&mut self, rtvs: &[CpuDescriptorHandle]) {
+        let rtvs = rtvs
+            .iter()
+            .map(|h| D3D12_CPU_DESCRIPTOR_HANDLE { ptr: h.0 })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .OMSetRenderTargets(rtvs.len() as _, rtvs.as_ptr(), false, null_mut())
+        }
+    }
+
+    fn clear_render_target_view(&mut self, rtv: CpuDescriptorHandle, color: [f32; 4]) {
+        unsafe {
+            self.list().ClearRenderTargetView(
+                D3D12_CPU_DESCRIPTOR_HANDLE { ptr: rtv.0 },
+                color.as_ptr(),
                 0,
-                &mut ptr,
-                &mut err,
+                null_mut(),
             )
-            .ok()?;
-
-            match ptr {
-                Some(v) => v,
-                None => {
-                    panic!(
-                        "Shader creation failed with error {}",
-                        CString::from_raw(err.unwrap().GetBufferPointer() as _).to_string_lossy()
-                    )
-                }
-            }
-        };
+        }
+    }
 
-        let mut els = [
-            D3D12_INPUT_ELEMENT_DESC {
-                semantic_name: PSTR("POSITION\0".as_ptr() as _),
-                semantic_index: 0,
-                format: DXGI_FORMAT::DXGI_FORMAT_R32G32B32_FLOAT,
-                input_slot: 0,
-                instance_data_step_rate: 0,
-                input_slot_class:
-                    D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
-                aligned_byte_offset: 0,
-            },
-            D3D12_INPUT_ELEMENT_DESC {
-                semantic_name: PSTR("COLOR\0".as_ptr() as _),
-                semantic_index: 0,
-                format: DXGI_FORMAT::DXGI_FORMAT_R32G32B32A32_FLOAT,
-                input_slot: 0,
-                instance_data_step_rate: 0,
-                input_slot_class:
-                    D3D12_INPUT_CLASSIFICATION::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA,
-                aligned_byte_offset: 12,
-            },
-        ];
-
-        let pso_desc = D3D12_GRAPHICS_PIPELINE_STATE_DESC {
-            // TODO: Can I get rid of this clone? Or do I even have to?
-            p_root_signature: Some(root_signature.clone()),
-            // unsafe { std::mem::transmute(root_signature.abi()) },
-            input_layout: D3D12_INPUT_LAYOUT_DESC {
-                num_elements: els.len() as u32,
-                p_input_element_descs: els.as_mut_ptr(),
-            },
-            vs: D3D12_SHADER_BYTECODE {
-                bytecode_length: unsafe { vertex_shader.GetBufferSize() },
-                p_shader_bytecode: unsafe { vertex_shader.GetBufferPointer() },
-            },
-            ps: D3D12_SHADER_BYTECODE {
-                bytecode_length: unsafe { pixel_shader.GetBufferSize() },
-                p_shader_bytecode: unsafe { pixel_shader.GetBufferPointer() },
-            },
-            // CD3DX12_RASTERIZER_DESC( CD3DX12_DEFAULT )
-            rasterizer_state: D3D12_RASTERIZER_DESC {
-                fill_mode: D3D12_FILL_MODE::D3D12_FILL_MODE_SOLID,
-                cull_mode: D3D12_CULL_MODE::D3D12_CULL_MODE_BACK,
-                front_counter_clockwise: BOOL(0),
-                depth_bias: D3D12_DEFAULT_DEPTH_BIAS as _,
-                depth_bias_clamp: D3D12_DEFAULT_DEPTH_BIAS_CLAMP,
-                slope_scaled_depth_bias: D3D12_DEFAULT_SLOPE_SCALED_DEPTH_BIAS,
-                depth_clip_enable: BOOL(1),
-                multisample_enable: BOOL(0),
-                antialiased_line_enable: BOOL(0),
-                forced_sample_count: 0,
-                conservative_raster:
-                    D3D12_CONSERVATIVE_RASTERIZATION_MODE::D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF,
-            },
-            // CD3DX12_BLEND_DESC(D3D12_DEFAULT)
-            blend_state: D3D12_BLEND_DESC {
-                alpha_to_coverage_enable: BOOL(0),
-                independent_blend_enable: BOOL(0),
-                render_target: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
-                    .map(|_| D3D12_RENDER_TARGET_BLEND_DESC {
-                        blend_enable: false.into(),
-                        logic_op_enable: false.into(),
-                        dest_blend: D3D12_BLEND::D3D12_BLEND_ZERO,
-                        src_blend: D3D12_BLEND::D3D12_BLEND_ZERO,
-                        dest_blend_alpha: D3D12_BLEND::D3D12_BLEND_ONE,
-                        src_blend_alpha: D3D12_BLEND::D3D12_BLEND_ONE,
-                        blend_op: D3D12_BLEND_OP::D3D12_BLEND_OP_ADD,
-                        logic_op: D3D12_LOGIC_OP::D3D12_LOGIC_OP_NOOP,
-                        blend_op_alpha: D3D12_BLEND_OP::D3D12_BLEND_OP_ADD,
-                        render_target_write_mask:
-                            D3D12_COLOR_WRITE_ENABLE::D3D12_COLOR_WRITE_ENABLE_ALL.0 as _,
-                    })
-                    .collect::<Vec<_>>()
-                    .as_slice()
-                    .try_into()
-                    .unwrap(),
-            },
-            sample_mask: 0xffffffff,
-            primitive_topology_type:
-                D3D12_PRIMITIVE_TOPOLOGY_TYPE::D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE,
-            num_render_targets: 1,
-            rtv_formats: (0..D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT)
-                .map(|i| {
-                    if i == 0 {
-                        DXGI_FORMAT::DXGI_FORMAT_B8G8R8A8_UNORM
-                    } else {
-                        DXGI_FORMAT::DXGI_FORMAT_UNKNOWN
-                    }
-                })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-            sample_desc: DXGI_SAMPLE_DESC {
-                count: 1,
-                quality: 0,
-            },
-            ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
-        };
+    fn set_primitive_topology(&mut self, topology: PrimitiveTopology) {
+        unsafe {
+            self.list()
+                .IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY(topology as _))
+        }
+    }
 
-        let pipeline_state = unsafe {
-            let mut ptr: Option<ID3D12PipelineState> = None;
-            device
-                .CreateGraphicsPipelineState(&pso_desc, &ID3D12PipelineState::IID, ptr.set_abi())
-                .and_some(ptr)
+    fn set_vertex_buffers(&mut self, start_slot: u32, views: &[VertexBufferView]) {
+        let views = views
+            .iter()
+            .map(|v| D3D12_VERTEX_BUFFER_VIEW {
+                buffer_location: v.buffer_location,
+                stride_in_bytes: v.stride_in_bytes,
+                size_in_bytes: v.size_in_bytes,
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.list()
+                .IASetVertexBuffers(start_slot, views.len() as _, views.as_ptr())
         }
-        .expect("Unable to create pipeline state");
+    }
 
-        // Create direct command list
-        let list = unsafe {
-            let mut ptr: Option<ID3D12GraphicsCommandList> = None;
-            device
-                .CreateCommandList(
-                    0,
-                    D3D12_COMMAND_LIST_TYPE::D3D12_COMMAND_LIST_TYPE_DIRECT,
-                    &allocator,
-                    &pipeline_state,
-                    &ID3D12GraphicsCommandList::IID,
-                    ptr.set_abi(),
-                )
-                .and_then(|| {
-                    let ptr = ptr.unwrap();
-                    ptr.Close().unwrap();
-                    ptr
-                })
-        }?;
+    fn draw_instanced(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) {
+        unsafe {
+            self.list()
+                .DrawInstanced(vertex_count, instance_count, start_vertex, start_instance)
+        }
+    }
 
-        // Create fence
-        let (fence, fence_value, fence_event) = unsafe {
-            let mut ptr: Option<ID3D12Fence> = None;
-            let fence = device
-                .CreateFence(
-                    0,
-                    D3D12_FENCE_FLAGS::D3D12_FENCE_FLAG_NONE,
-                    &ID3D12Fence::IID,
-                    ptr.set_abi(),
-                )
-                .and_some(ptr)?;
-            let fence_event = CreateEventA(null_mut(), false, false, PSTR(null_mut()));
-            if fence_event.0 == 0 {
-                panic!("Unable to create fence event");
+    fn close_command_list(&mut self) -> windows::Result<()> {
+        unsafe { self.list().Close().ok() }
+    }
+
+    fn execute_command_list(&mut self) -> windows::Result<()> {
+        unsafe {
+            let mut lists = [Some(self.list().cast::<ID3D12CommandList>()?)];
+            self.queue()
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        }
+        Ok(())
+    }
+
+    fn signal(&mut self, value: u64) -> windows::Result<()> {
+        self.fence().signal(value)
+    }
+
+    fn completed_value(&self) -> u64 {
+        self.fence
+            .as_ref()
+            .map_or(0, |fence| fence.completed_value())
+    }
+
+    fn wait_for_value(&mut self, value: u64) -> windows::Result<()> {
+        self.fence().wait(value)
+    }
+
+    fn current_back_buffer_index(&self) -> usize {
+        match &self.swap_chain {
+            Some(swap_chain) => unsafe { swap_chain.GetCurrentBackBufferIndex() as usize },
+            None => self.back_buffer,
+        }
+    }
+
+    fn present(&mut self, sync_interval: u32, flags: u32) -> windows::Result<()> {
+        match &self.swap_chain {
+            Some(swap_chain) => unsafe { swap_chain.Present(sync_interval, flags).ok() },
+            // Offscreen buffers take turns like the swap chain ones
+            None => {
+                self.back_buffer = (self.back_buffer + 1) % self.resources.len();
+                Ok(())
             }
-            (fence, 0, fence_event)
-        };
+        }
+    }
 
-        let (vertex_buffer, vertex_buffer_view) = unsafe {
-            // Blue end of the triangle is semi transparent
-            let ar = 1.0;
-            let scale = 1.0;
-            let cpu_triangle: [Vertex; 3] = [
-                Vertex::new([0.0, scale * ar, 0.0], [1.0, 0.0, 0.0, 1.0]),
-                Vertex::new([scale, -scale * ar, 0.0], [0.0, 1.0, 0.0, 1.0]),
-                Vertex::new([-scale, -scale * ar, 0.0], [0.0, 0.0, 1.0, 0.5]),
-            ];
-            let triangle_size_bytes = std::mem::size_of_val(&cpu_triangle);
-            let props = D3D12_HEAP_PROPERTIES {
-                r#type: D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_UPLOAD,
-                cpu_page_property: D3D12_CPU_PAGE_PROPERTY::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
-                creation_node_mask: 1,
-                visible_node_mask: 1,
-                memory_pool_preference: D3D12_MEMORY_POOL::D3D12_MEMORY_POOL_UNKNOWN,
-            };
-            let desc = D3D12_RESOURCE_DESC {
-                alignment: 0,
-                flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE,
-                dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER,
-                depth_or_array_size: 1,
-                format: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
-                height: 1,
-                width: triangle_size_bytes as u64,
-                layout: D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                mip_levels: 1,
-                sample_desc: DXGI_SAMPLE_DESC {
-                    count: 1,
-                    quality: 0,
-                },
-            };
-            // let clr = D3D12_CLEAR_VALUE {
-            //     Format: DXGI_FORMAT_UNKNOWN,
+    fn read_back_buffer(&mut self, index: usize) -> windows::Result<Image> {
+        let (width, height) = self.back_buffer_size;
+        let texture = texture_desc(width, height, BACK_BUFFER_FORMAT);
+        let mut footprint = D3D12_PLACED_SUBRESOURCE_FOOTPRINT::default();
+        let mut total = 0u64;
+        unsafe {
+            self.device.GetCopyableFootprints(
+                &texture,
+                0,
+                1,
+                0,
+                &mut footprint,
+                null_mut(),
+                null_mut(),
+                &mut total,
+            );
+        }
 
-            // };
+        let readback = unsafe {
             let mut ptr: Option<ID3D12Resource> = None;
-            let vertex_buffer = device
+            self.device
                 .CreateCommittedResource(
-                    &props,
+                    &heap_properties(D3D12_HEAP_TYPE::D3D12_HEAP_TYPE_READBACK),
                     D3D12_HEAP_FLAGS::D3D12_HEAP_FLAG_NONE,
-                    &desc,
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_GENERIC_READ,
+                    &buffer_desc(total),
+                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_COPY_DEST,
                     null_mut(),
                     &ID3D12Resource::IID,
                     ptr.set_abi(),
                 )
-                .and_some(ptr)?;
+                .and_some(ptr)
+        }?;
 
-            let mut gpu_triangle = null_mut::<Vertex>();
-            vertex_buffer
+        // Copy with the command list, the frame has been waited for already
+        let back_buffer = ResourceId::back_buffer(index);
+        unsafe {
+            let allocator = &self.allocators[0];
+            allocator.Reset().ok()?;
+            self.list().Reset(allocator, None).ok()?;
+        }
+        self.resource_barrier(&[Barrier::transition(
+            back_buffer,
+            ResourceStates::PRESENT,
+            ResourceStates::COPY_SOURCE,
+        )]);
+        unsafe {
+            let mut dest = D3D12_TEXTURE_COPY_LOCATION {
+                r#type: D3D12_TEXTURE_COPY_TYPE::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+                ..std::mem::zeroed()
+            };
+            dest.p_resource = readback.abi();
+            dest.anonymous.placed_footprint = footprint;
+            let mut source = D3D12_TEXTURE_COPY_LOCATION {
+                r#type: D3D12_TEXTURE_COPY_TYPE::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+                ..std::mem::zeroed()
+            };
+            source.p_resource = self.resources[index].abi();
+            source.anonymous.subresource_index = 0;
+            self.list()
+                .CopyTextureRegion(&dest, 0, 0, 0, &source, null_mut());
+        }
+        self.resource_barrier(&[Barrier::transition(
+            back_buffer,
+            ResourceStates::COPY_SOURCE,
+            ResourceStates::PRESENT,
+        )]);
+        self.close_command_list()?;
+        self.execute_command_list()?;
+
+        // Own timeline, the values of the frame fence belong to the renderer
+        let mut timeline = Timeline::new(D3D12Fence::new(&self.device, self.queue().clone())?);
+        let copied = timeline.signal()?;
+        timeline.wait(copied)?;
+
+        // Rows of the footprint are padded to 256 bytes
+        let mut image = Image::new(width, height);
+        let row = width as usize * 4;
+        let pitch = footprint.footprint.row_pitch as usize;
+        unsafe {
+            let mut data = null_mut::<u8>();
+            readback
                 .Map(
                     0,
-                    &D3D12_RANGE { begin: 0, end: 0 },
-                    &mut gpu_triangle as *mut *mut _ as *mut *mut _,
+                    &D3D12_RANGE {
+                        begin: 0,
+                        end: total as _,
+                    },
+                    &mut data as *mut *mut _ as *mut *mut _,
                 )
                 .ok()?;
-
-            if gpu_triangle.is_null() {
-                panic!("Nullptr");
+            for y in 0..height as usize {
+                std::ptr::copy_nonoverlapping(
+                    data.add(footprint.offset as usize + y * pitch),
+                    image.data[y * row..].as_mut_ptr(),
+                    row,
+                );
             }
-            std::ptr::copy_nonoverlapping(cpu_triangle.as_ptr(), gpu_triangle as *mut _, 3);
+            readback.Unmap(0, &D3D12_RANGE { begin: 0, end: 0 });
+        }
+        Ok(image)
+    }
+}
 
-            // Debug, if you want to see what was copied
-            // let gpu_slice = std::slice::from_raw_parts(gpu_triangle, 3);
-            // println!("{:?}", cpu_triangle);
-            // println!("{:?}", gpu_slice);
+fn heap_properties(heap_type: D3D12_HEAP_TYPE) -> D3D12_HEAP_PROPERTIES {
+    D3D12_HEAP_PROPERTIES {
+        r#type: heap_type,
+        cpu_page_property: D3D12_CPU_PAGE_PROPERTY::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+        creation_node_mask: 1,
+        visible_node_mask: 1,
+        memory_pool_preference: D3D12_MEMORY_POOL::D3D12_MEMORY_POOL_UNKNOWN,
+    }
+}
 
-            vertex_buffer.Unmap(0, null_mut());
-            let vertex_buffer_view = D3D12_VERTEX_BUFFER_VIEW {
-                buffer_location: vertex_buffer.GetGPUVirtualAddress(),
-                stride_in_bytes: std::mem::size_of::<Vertex>() as _,
-                size_in_bytes: triangle_size_bytes as _,
-            };
-            (vertex_buffer, vertex_buffer_view)
-        };
+fn buffer_desc(width: u64) -> D3D12_RESOURCE_DESC {
+    D3D12_RESOURCE_DESC {
+        alignment: 0,
+        flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_NONE,
+        dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_BUFFER,
+        depth_or_array_size: 1,
+        format: DXGI_FORMAT::DXGI_FORMAT_UNKNOWN,
+        height: 1,
+        width,
+        layout: D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        mip_levels: 1,
+        sample_desc: DXGI_SAMPLE_DESC {
+            count: 1,
+            quality: 0,
+        },
+    }
+}
 
-        let viewport = D3D12_VIEWPORT {
-            width: 1024.0,
-            height: 1024.0,
-            max_depth: D3D12_MAX_DEPTH,
-            min_depth: D3D12_MIN_DEPTH,
-            top_leftx: 0.0,
-            top_lefty: 0.0,
-        };
+fn texture_desc(width: u32, height: u32, format: Format) -> D3D12_RESOURCE_DESC {
+    D3D12_RESOURCE_DESC {
+        alignment: 0,
+        flags: D3D12_RESOURCE_FLAGS::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+        dimension: D3D12_RESOURCE_DIMENSION::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+        depth_or_array_size: 1,
+        format: DXGI_FORMAT(format as _),
+        height,
+        width: width as u64,
+        layout: D3D12_TEXTURE_LAYOUT::D3D12_TEXTURE_LAYOUT_UNKNOWN,
+        mip_levels: 1,
+        sample_desc: DXGI_SAMPLE_DESC {
+            count: 1,
+            quality: 0,
+        },
+    }
+}
 
-        let scissor = RECT {
-            top: 0,
-            left: 0,
-            bottom: 1024,
-            right: 1024,
-        };
+fn viewport(v: &Viewport) -> D3D12_VIEWPORT {
+    D3D12_VIEWPORT {
+        width: v.width,
+        height: v.height,
+        max_depth: v.max_depth,
+        min_depth: v.min_depth,
+        top_leftx: v.top_left_x,
+        top_lefty: v.top_left_y,
+    }
+}
 
-        Ok(Window {
-            hwnd,
-            factory,
-            adapter,
-            device,
-            queue,
-            allocator,
-            comp_device,
-            swap_chain,
-            comp_target,
-            comp_visual,
-            rtv_desc_heap,
-            rtv_desc_size,
-            resources,
-            root_signature,
-            list,
-            pipeline_state,
-            vertex_shader,
-            pixel_shader,
-            viewport,
-            scissor,
-            fence,
-            fence_event,
-            fence_value,
-            vertex_buffer,
-            vertex_buffer_view,
+fn rect(r: &Rect) -> RECT {
+    RECT {
+        top: r.top,
+        left: r.left,
+        bottom: r.bottom,
+        right: r.right,
+    }
+}
+
+fn create_pipeline_state(
+    device: &ID3D12Device,
+    root_signature: &ID3D12RootSignature,
+    shaders: &ShaderLibrary,
+    definition: &PipelineDefinition,
+) -> Result<ID3D12PipelineState, String> {
+    let shader = |entry_point: &str, stage: &str| {
+        let profile = format!("{}_{}", stage, shader_model());
+        shaders
+            .get(entry_point, &profile, &definition.defines)
+            .ok_or_else(|| {
+                format!(
+                    "{} has no {} ({}) variant with {}",
+                    definition.shader, entry_point, profile, definition.defines
+                )
+            })
+    };
+    let vertex_shader = shader(&definition.vertex_entry_point, "vs")?;
+    let pixel_shader = shader(&definition.pixel_entry_point, "ps")?;
+
+    // The scene's vertex buffers hold `Vertex`
+    if definition.input_layout != Vertex::LAYOUT {
+        return Err(format!(
+            "{} input is not the layout of Vertex",
+            definition.name
+        ));
+    }
+    // Layout drift would otherwise only show up in the debug layer
+    let signature = Container::parse(vertex_shader)
+        .and_then(|container| container.input_signature())
+        .map_err(|e| {
+            format!(
+                "{} has no input signature: {}",
+                definition.vertex_entry_point, e
+            )
+        })?;
+    if let Err(errors) = layout::check(&definition.input_layout, &signature) {
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        return Err(format!(
+            "Input layout does not match {}: {}",
+            definition.vertex_entry_point,
+            errors.join(", ")
+        ));
+    }
+    let pipeline = definition.desc(vertex_shader, pixel_shader);
+
+    // Semantic names must outlive the pipeline creation
+    let semantic_names = pipeline
+        .input_layout
+        .iter()
+        .map(|el| CString::new(el.semantic_name).unwrap())
+        .collect::<Vec<_>>();
+    let mut els = pipeline
+        .input_layout
+        .iter()
+        .zip(semantic_names.iter())
+        .map(|(el, name)| D3D12_INPUT_ELEMENT_DESC {
+            semantic_name: PSTR(name.as_ptr() as _),
+            semantic_index: el.semantic_index,
+            format: DXGI_FORMAT(el.format as _),
+            input_slot: el.input_slot,
+            instance_data_step_rate: el.instance_data_step_rate,
+            input_slot_class: D3D12_INPUT_CLASSIFICATION(el.input_slot_class as _),
+            aligned_byte_offset: el.aligned_byte_offset,
         })
+        .collect::<Vec<_>>();
+    // TODO: Can I get rid of this clone? Or do I even have to?
+    let pso_desc = graphics_pipeline_desc(&pipeline, root_signature.clone(), &mut els);
+
+    unsafe {
+        let mut ptr: Option<ID3D12PipelineState> = None;
+        device
+            .CreateGraphicsPipelineState(&pso_desc, &ID3D12PipelineState::IID, ptr.set_abi())
+            .and_some(ptr)
     }
+    .map_err(|e| format!("CreateGraphicsPipelineState failed {:?}", e))
+}
 
-    fn populate_command_list(&mut self) -> ::windows::Result<()> {
-        unsafe {
-            // Get the current backbuffer on which to draw
-            let current_frame = self.swap_chain.GetCurrentBackBufferIndex() as usize;
-            let current_resource = &self.resources[current_frame];
-            let rtv = {
-                let mut ptr = self.rtv_desc_heap.GetCPUDescriptorHandleForHeapStart();
-                ptr.ptr += self.rtv_desc_size * current_frame;
-                ptr
-            };
+fn graphics_pipeline_desc(
+    desc: &GraphicsPipelineDesc,
+    root_signature: ID3D12RootSignature,
+    input_elements: &mut [D3D12_INPUT_ELEMENT_DESC],
+) -> D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+    D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        p_root_signature: Some(root_signature),
+        vs: D3D12_SHADER_BYTECODE {
+            bytecode_length: desc.vertex_shader.len(),
+            p_shader_bytecode: desc.vertex_shader.as_ptr() as _,
+        },
+        ps: D3D12_SHADER_BYTECODE {
+            bytecode_length: desc.pixel_shader.len(),
+            p_shader_bytecode: desc.pixel_shader.as_ptr() as _,
+        },
+        blend_state: blend_desc(&desc.blend),
+        sample_mask: desc.sample_mask,
+        rasterizer_state: rasterizer_desc(&desc.rasterizer),
+        depth_stencil_state: depth_stencil_desc(&desc.depth_stencil),
+        input_layout: D3D12_INPUT_LAYOUT_DESC {
+            num_elements: input_elements.len() as u32,
+            p_input_element_descs: input_elements.as_mut_ptr(),
+        },
+        primitive_topology_type: D3D12_PRIMITIVE_TOPOLOGY_TYPE(desc.primitive_topology_type as _),
+        num_render_targets: desc.render_target_formats.len() as u32,
+        rtv_formats: desc.rtv_formats().map(|format| DXGI_FORMAT(format as _)),
+        dsv_format: DXGI_FORMAT(desc.depth_stencil_format as _),
+        sample_desc: DXGI_SAMPLE_DESC {
+            count: desc.sample_desc.count,
+            quality: desc.sample_desc.quality,
+        },
+        // No geometry or tessellation stages, stream output or cached blob
+        ..D3D12_GRAPHICS_PIPELINE_STATE_DESC::default()
+    }
+}
 
-            // Reset allocator
-            self.allocator.Reset().ok()?;
+fn rasterizer_desc(desc: &RasterizerDesc) -> D3D12_RASTERIZER_DESC {
+    D3D12_RASTERIZER_DESC {
+        fill_mode: D3D12_FILL_MODE(desc.fill_mode as _),
+        cull_mode: D3D12_CULL_MODE(desc.cull_mode as _),
+        front_counter_clockwise: desc.front_counter_clockwise.into(),
+        depth_bias: desc.depth_bias,
+        depth_bias_clamp: desc.depth_bias_clamp,
+        slope_scaled_depth_bias: desc.slope_scaled_depth_bias,
+        depth_clip_enable: desc.depth_clip_enable.into(),
+        multisample_enable: desc.multisample_enable.into(),
+        antialiased_line_enable: desc.antialiased_line_enable.into(),
+        forced_sample_count: desc.forced_sample_count,
+        conservative_raster: D3D12_CONSERVATIVE_RASTERIZATION_MODE(desc.conservative_raster as _),
+    }
+}
 
-            // Reset list
-            self.list
-                .Reset(&self.allocator, &self.pipeline_state)
-                .ok()?;
+fn blend_desc(desc: &BlendDesc) -> D3D12_BLEND_DESC {
+    D3D12_BLEND_DESC {
+        alpha_to_coverage_enable: desc.alpha_to_coverage_enable.into(),
+        independent_blend_enable: desc.independent_blend_enable.into(),
+        render_target: desc
+            .render_target
+            .iter()
+            .map(|rt| D3D12_RENDER_TARGET_BLEND_DESC {
+                blend_enable: rt.blend_enable.into(),
+                logic_op_enable: rt.logic_op_enable.into(),
+                src_blend: D3D12_BLEND(rt.src_blend as _),
+                dest_blend: D3D12_BLEND(rt.dest_blend as _),
+                blend_op: D3D12_BLEND_OP(rt.blend_op as _),
+                src_blend_alpha: D3D12_BLEND(rt.src_blend_alpha as _),
+                dest_blend_alpha: D3D12_BLEND(rt.dest_blend_alpha as _),
+                blend_op_alpha: D3D12_BLEND_OP(rt.blend_op_alpha as _),
+                logic_op: D3D12_LOGIC_OP(rt.logic_op as _),
+                render_target_write_mask: rt.render_target_write_mask,
+            })
+            .collect::<Vec<_>>()
+            .as_slice()
+            .try_into()
+            .unwrap(),
+    }
+}
 
-            // Set root signature, viewport and scissor rect
-            self.list.SetGraphicsRootSignature(&self.root_signature);
-            self.list.RSSetViewports(1, &self.viewport);
-            self.list.RSSetScissorRects(1, &self.scissor);
-
-            // Direct the draw commands to the render target resource
-            let barriers = {
-                let mut barrier = D3D12_RESOURCE_BARRIER {
-                    r#type: D3D12_RESOURCE_BARRIER_TYPE::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-                    flags: D3D12_RESOURCE_BARRIER_FLAGS::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                    ..std::mem::zeroed()
-                };
-                barrier.anonymous.transition.subresource = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
-                barrier.anonymous.transition.p_resource = current_resource.abi();
-                barrier.anonymous.transition.state_before =
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT;
-                barrier.anonymous.transition.state_after =
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET;
-                [barrier]
-            };
-            self.list.ResourceBarrier(1, barriers.as_ptr());
+fn depth_stencil_desc(desc: &DepthStencilDesc) -> D3D12_DEPTH_STENCIL_DESC {
+    let face = |op: &DepthStencilOpDesc| D3D12_DEPTH_STENCILOP_DESC {
+        stencil_fail_op: D3D12_STENCIL_OP(op.stencil_fail_op as _),
+        stencil_depth_fail_op: D3D12_STENCIL_OP(op.stencil_depth_fail_op as _),
+        stencil_pass_op: D3D12_STENCIL_OP(op.stencil_pass_op as _),
+        stencil_func: D3D12_COMPARISON_FUNC(op.stencil_func as _),
+    };
+    D3D12_DEPTH_STENCIL_DESC {
+        depth_enable: desc.depth_enable.into(),
+        depth_write_mask: D3D12_DEPTH_WRITE_MASK(desc.depth_write_mask as _),
+        depth_func: D3D12_COMPARISON_FUNC(desc.depth_func as _),
+        stencil_enable: desc.stencil_enable.into(),
+        stencil_read_mask: desc.stencil_read_mask,
+        stencil_write_mask: desc.stencil_write_mask,
+        front_face: face(&desc.front_face),
+        back_face: face(&desc.back_face),
+    }
+}
 
-            self.list.OMSetRenderTargets(1, &rtv, false, null_mut());
+/// `HelloTriangleRS` that `build.rs` serialized, release builds don't
+/// carry the HLSL it is declared in
+#[cfg(not(debug_assertions))]
+fn root_signature() -> Vec<u8> {
+    include_bytes!(concat!(env!("OUT_DIR"), "/simple.rts0")).to_vec()
+}
 
-            self.list
-                .ClearRenderTargetView(rtv, [1.0f32, 0.2, 0.4, 0.5].as_ptr(), 0, null_mut());
-            self.list.IASetPrimitiveTopology(
-                D3D_PRIMITIVE_TOPOLOGY::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST,
-            );
-            self.list.IASetVertexBuffers(0, 1, &self.vertex_buffer_view);
-            self.list.DrawInstanced(3, 1, 0, 0);
-
-            // Direct the draw commands to the render target resource
-            let barriers = {
-                let mut barrier = D3D12_RESOURCE_BARRIER {
-                    r#type: D3D12_RESOURCE_BARRIER_TYPE::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-                    flags: D3D12_RESOURCE_BARRIER_FLAGS::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                    ..std::mem::zeroed()
-                };
-                barrier.anonymous.transition.subresource = D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES;
-                barrier.anonymous.transition.p_resource = current_resource.abi();
-                barrier.anonymous.transition.state_before =
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_RENDER_TARGET;
-                barrier.anonymous.transition.state_after =
-                    D3D12_RESOURCE_STATES::D3D12_RESOURCE_STATE_PRESENT;
-                [barrier]
-            };
-            self.list.ResourceBarrier(1, barriers.as_ptr());
+/// Read from simple.hlsl so edits to the declaration show up without a rebuild
+#[cfg(debug_assertions)]
+fn root_signature() -> Vec<u8> {
+    use renderer::root_signature::{hlsl, RootSignatureVersion};
+    use renderer::shader::preprocess::{Embedded, Preprocessor};
 
-            // Close list
-            self.list.Close().ok()?;
-            Ok(())
-        }
+    let resolver = Embedded::default();
+    let mut preprocessor = Preprocessor::new(&resolver);
+    preprocessor
+        .run("simple.hlsl", include_str!("./simple.hlsl"))
+        .expect("Unable to preprocess simple.hlsl");
+    hlsl::from_define(&preprocessor, "HelloTriangleRS", RootSignatureVersion::V1_0)
+        .unwrap_or_else(|e| panic!("Invalid root signature\n{}", e))
+        .to_bytes()
+        .expect("Unable to serialize root signature")
+}
+
+/// Shader model of the embedded library, `build.rs` compiles with DXC
+#[cfg(not(debug_assertions))]
+fn shader_model() -> &'static str {
+    "6_0"
+}
+
+/// Shader model of the compiler `compile_shaders` picks
+#[cfg(debug_assertions)]
+fn shader_model() -> &'static str {
+    if std::env::var_os("DXC").is_some() {
+        "6_0"
+    } else {
+        "5_0"
     }
+}
 
-    pub fn wait_for_previous_frame(&mut self) -> windows::Result<()> {
-        // This is bad practice says Microsoft's C++ example
-        unsafe {
-            let old_fence_value = self.fence_value;
-            self.queue.Signal(&self.fence, old_fence_value).ok()?;
-            self.fence_value += 1;
-            if self.fence.GetCompletedValue() < old_fence_value {
-                self.fence
-                    .SetEventOnCompletion(old_fence_value, self.fence_event)
-                    .ok()?;
-                WaitForSingleObject(self.fence_event, 0xFFFFFFFF);
+/// Blobs `build.rs` compiled, no HLSL or runtime compiler in release builds
+#[cfg(not(debug_assertions))]
+fn shader_library() -> ShaderLibrary {
+    ShaderLibrary::from_bytes(include_bytes!(concat!(env!("OUT_DIR"), "/simple.dxlib")))
+        .expect("Embedded shader library is malformed")
+}
+
+/// Compiled at startup so shader edits show up without a rebuild
+#[cfg(debug_assertions)]
+fn shader_library() -> ShaderLibrary {
+    use renderer::shader::preprocess::Embedded;
+
+    compile_shaders(include_str!("./simple.hlsl"), &Embedded::default())
+        .unwrap_or_else(|e| panic!("Could not compile shaders\n{}", e))
+}
+
+/// Variants of `simple.hlsl` with `D3DCompile`, or with DXC when `DXC`
+/// names it. Only ones whose preprocessed source changed are recompiled
+#[cfg(debug_assertions)]
+fn compile_shaders(
+    source: &str,
+    resolver: &dyn renderer::shader::preprocess::IncludeResolver,
+) -> Result<ShaderLibrary, String> {
+    use renderer::shader::cache;
+    use renderer::shader::compiler::{Build, Compiler, Dxc};
+    use renderer::shader::permutation;
+
+    let compiler: Box<dyn Compiler> = match std::env::var_os("DXC") {
+        Some(_) => Box::new(Dxc::from_env()),
+        None => Box::new(runtime::D3DCompiler),
+    };
+    let compilation = Build::new(&*compiler)
+        .cache(cache::default_dir())
+        .compile(
+            &permutation::hello_triangle(shader_model()),
+            "simple.hlsl",
+            source,
+            resolver,
+        )
+        .map_err(|e| e.to_string())?;
+    for (variant, warning) in &compilation.warnings {
+        eprintln!("{}: {}", variant, warning);
+    }
+    Ok(compilation.library)
+}
+
+#[cfg(debug_assertions)]
+mod runtime {
+    use super::*;
+    use renderer::shader::cache::{Compiled, Request};
+    use renderer::shader::compiler::{CompileError, Compiler};
+    use std::slice;
+
+    /// `D3DCompile` of `d3dcompiler_47.dll`, shader model 5.1 and below
+    pub struct D3DCompiler;
+
+    impl Compiler for D3DCompiler {
+        fn name(&self) -> &str {
+            "d3dcompiler_47"
+        }
+
+        fn compile(&self, request: &Request) -> Result<Compiled, CompileError> {
+            let entry_point = CString::new(request.entry_point).unwrap();
+            let profile = CString::new(request.profile).unwrap();
+            unsafe {
+                let mut err: Option<ID3DBlob> = None;
+                let mut ptr: Option<ID3DBlob> = None;
+
+                // File names come from the `#line` directives of the source
+                let hr = D3DCompile(
+                    request.source.as_ptr() as *mut _,
+                    request.source.len(),
+                    PSTR(null_mut()),
+                    null_mut(),
+                    None,
+                    PSTR(entry_point.as_ptr() as _),
+                    PSTR(profile.as_ptr() as _),
+                    request.flags,
+                    0,
+                    &mut ptr,
+                    &mut err,
+                );
+
+                // Errors on failure, warnings otherwise
+                let output = err.map_or_else(String::new, |err| {
+                    String::from_utf8_lossy(blob_bytes(&err))
+                        .trim_end_matches('\0')
+                        .to_string()
+                });
+                match ptr {
+                    Some(blob) if hr.is_ok() => Ok(Compiled {
+                        blob: blob_bytes(&blob).to_vec(),
+                        diagnostics: output,
+                    }),
+                    _ if output.is_empty() => Err(CompileError::failed(&format!(
+                        "D3DCompile failed with {:?}",
+                        hr
+                    ))),
+                    _ => Err(CompileError::failed(&output)),
+                }
             }
-            Ok(())
         }
     }
 
-    pub fn render(&mut self) -> windows::Result<()> {
-        self.populate_command_list()?;
-        unsafe {
-            let mut lists = [Some(self.list.cast::<ID3D12CommandList>()?)];
-            self.queue
-                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
-            self.swap_chain.Present(1, 0).ok()?;
-        }
-        self.wait_for_previous_frame()?;
-        Ok(())
+    /// Contents of a blob, the blob keeps owning the memory
+    fn blob_bytes(blob: &ID3DBlob) -> &[u8] {
+        unsafe { slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize()) }
     }
 }
 
@@ -692,17 +1309,31 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
         static mut WINDOW: Option<Window> = None;
         match msg {
             WM_CREATE => {
-                WINDOW = Some(Window::new(hwnd).unwrap());
+                WINDOW = Some(create_window(hwnd).unwrap());
+                // Repaints pick up pipeline edits
+                #[cfg(debug_assertions)]
+                SetTimer(hwnd, 1, 500, None);
                 DefWindowProcA(hwnd, msg, wparam, lparam)
             }
             WM_PAINT => {
                 if let Some(window) = WINDOW.as_mut() {
+                    #[cfg(debug_assertions)]
+                    reload_pipelines(window);
                     window.render().unwrap();
                 }
                 ValidateRect(hwnd, std::ptr::null());
                 LRESULT(0)
             }
+            #[cfg(debug_assertions)]
+            WM_TIMER => {
+                InvalidateRect(hwnd, std::ptr::null(), BOOL(0));
+                LRESULT(0)
+            }
             WM_DESTROY => {
+                // GPU may still use the resources of the frames in flight
+                if let Some(window) = WINDOW.as_mut() {
+                    window.wait_for_gpu().unwrap();
+                }
                 WINDOW = None;
                 PostQuitMessage(0);
                 LRESULT(0)
@@ -712,7 +1343,27 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
     }
 }
 
+/// Render offscreen when started with `--headless <file.png|file.ppm>`
+fn run_headless() -> bool {
+    let headless = match Headless::from_args(std::env::args().skip(1)) {
+        Ok(Some(headless)) => headless,
+        Ok(None) => return false,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let image = create_headless()
+        .and_then(|mut renderer| headless.run(&mut renderer))
+        .expect("Unable to render");
+    headless.save(&image).expect("Unable to write image");
+    true
+}
+
 fn main() {
+    if run_headless() {
+        return;
+    }
     unsafe {
         let instance = HINSTANCE(GetModuleHandleA(PSTR(null_mut())));
         let cursor = LoadCursorA(HINSTANCE(0), PSTR(IDC_ARROW as _));